@@ -0,0 +1,41 @@
+// cargo bench --features bench
+use batadase::{AssocTable, DbName, Env, IndexTable, Index, Transaction, bench::{Case, run}, env::EnvBuilder};
+use criterion::{Criterion, criterion_group, criterion_main};
+
+#[derive(DbName)]
+#[table(AssocTable<'tx, TX, u64, Vec<u8>>)]
+struct Assoc;
+
+#[derive(DbName)]
+#[table(IndexTable<'tx, TX, Vec<u8>>)]
+struct Indexed;
+
+const ENTRIES: u64 = 10_000;
+const VALUE_SIZES: [usize; 3] = [16, 256, 4096];
+
+fn env() -> EnvBuilder { Env::builder().unwrap().mapsize(1 << 30).unwrap().with::<Assoc>().with::<Indexed>() }
+
+fn tables(c: &mut Criterion) {
+	for size in VALUE_SIZES {
+		let value = vec![0xab; size];
+		run(c, &Case {
+			name: &format!("assoc/{size}b"),
+			env: &env,
+			entries: ENTRIES,
+			put: &|tx, i| tx.get::<Assoc>().put(&i, &value),
+			get: &|tx, i| tx.get::<Assoc>().get(&i).map(drop),
+			scan: &|tx| Ok(tx.get::<Assoc>().iter()?.count()),
+		});
+		run(c, &Case {
+			name: &format!("index/{size}b"),
+			env: &env,
+			entries: ENTRIES,
+			put: &|tx, i| tx.get::<Indexed>().put(Index::from(i), &value),
+			get: &|tx, i| tx.get::<Indexed>().get(Index::from(i)).map(drop),
+			scan: &|tx| Ok(tx.get::<Indexed>().iter()?.count()),
+		});
+	}
+}
+
+criterion_group!(benches, tables);
+criterion_main!(benches);
@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use batadase::Transaction;
+use crate::{Args, Register, Result};
+
+// roughly what the compacting copy writes: 2 meta pages plus every page in use by a db
+fn estimate_size(env: &batadase::Env) -> Result<u64> {
+	let root = env.stat()?;
+	let tx = env.read_tx()?;
+	let mut pages = 2 + root.branch_pages + root.leaf_pages + root.overflow_pages;
+	for (_, dbi) in env.dbs() {
+		let stat = tx.stat(dbi)?;
+		pages += stat.branch_pages + stat.leaf_pages + stat.overflow_pages;
+	}
+	Ok(pages as u64 * u64::from(root.page_size))
+}
+
+fn copy_with_progress(env: &batadase::Env, dst: &Path) -> Result<()> {
+	let expected = estimate_size(env)?.max(1);
+	let dst_c = std::ffi::CString::new(dst.as_os_str().as_encoded_bytes())?;
+	let data_file = dst.join("data.mdb");
+	std::thread::scope(|s| {
+		let copy = s.spawn(|| env.copy(&dst_c, true));
+		while !copy.is_finished() {
+			std::thread::sleep(Duration::from_millis(250));
+			let written = std::fs::metadata(&data_file).map_or(0, |x| x.len());
+			eprint!("\rcopied {:.1} / ~{:.1} MiB ({:.0}%)", mib(written), mib(expected), (written as f64 / expected as f64 * 100.0).min(100.0));
+		}
+		eprintln!();
+		Ok(copy.join().expect("copy thread panicked")?)
+	})
+}
+
+fn mib(bytes: u64) -> f64 { bytes as f64 / f64::from(1 << 20) }
+
+// Swapping the data file under other processes loses what they write to the old one, and those keeping the env open
+// keep reading it. LMDB can't tell who has the env open, so this goes by what it can tell - no reader slots taken and
+// the advisory writer lock free - and then checks that nothing was committed while copying.
+fn exclude_others(env: &batadase::Env) -> Result<(batadase::writer_lock::WriterLock, usize)> {
+	let readers = env.readers_in_use()?.in_use;
+	if readers > 0 {
+		return Err(format!("{readers} reader slots are taken, close the env in every other process first - slots of dead processes are freed once it's opened with nothing else using it").into());
+	}
+	Ok((env.lock_writer(Duration::ZERO)?, env.info()?.last_txn_id))
+}
+
+fn ensure_empty_dir(path: &Path) -> Result<()> {
+	std::fs::create_dir_all(path)?;
+	if std::fs::read_dir(path)?.next().is_some() { return Err(format!("{} isn't empty", path.display()).into()); }
+	Ok(())
+}
+
+pub(crate) fn run(args: &Args, register: Register) -> Result<()> {
+	let in_place = args.flag("in-place");
+	let (src, dst) = if in_place {
+		let [src] = args.expect_positional(1)? else { unreachable!() };
+		(PathBuf::from(src), PathBuf::from(format!("{src}.compacting")))
+	} else {
+		let [src, dst] = args.expect_positional(2)? else { unreachable!() };
+		(PathBuf::from(src), PathBuf::from(dst))
+	};
+	let before = std::fs::metadata(src.join("data.mdb"))?.len();
+
+	ensure_empty_dir(&dst)?;
+	let env = crate::open_read_only(&src.to_string_lossy(), register)?;
+	let excluded = if in_place { Some(exclude_others(&env)?) } else { None };
+	copy_with_progress(&env, &dst)?;
+	let after = std::fs::metadata(dst.join("data.mdb"))?.len();
+
+	if let Some((_lock, txn_id)) = excluded {
+		if env.info()?.last_txn_id != txn_id || env.readers_in_use()?.in_use > 0 {
+			std::fs::remove_dir_all(&dst)?;
+			return Err("another process used the env while it was copied, left it as it was".into());
+		}
+		drop(env);
+		std::fs::rename(dst.join("data.mdb"), src.join("data.mdb"))?;
+		std::fs::remove_dir(&dst)?;
+	}
+	eprintln!("{:.1} MiB -> {:.1} MiB", mib(before), mib(after));
+	Ok(())
+}
@@ -0,0 +1,117 @@
+//! batadase-cli: tooling for batadase environments.
+//!
+//! Envs are opened read-only with every named db they contain, so this works on any LMDB env,
+//! not just ones created by batadase.
+//!
+//! The `batadase-cli` binary doesn't know about any of your types, so `verify` can only check key ordering
+//! and there are no migrations to run. Make your own binary registering your tables and migrations, e.g.
+//! ```ignore
+//! fn main() {
+//!     batadase_cli::Cli::new()
+//!         .tables(|env| env.with::<MyTable>().with::<MyOtherTable>())
+//!         .migrations(MIGRATIONS)
+//!         .run();
+//! }
+//! ```
+
+use batadase::{Env, env::EnvBuilder, migrate::Migration};
+
+mod compact;
+mod migrate;
+mod stats;
+mod verify;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// adds tables to the builder, see `EnvBuilder::with`
+pub type Register = fn(EnvBuilder) -> EnvBuilder;
+
+const USAGE: &str = "\
+usage: batadase-cli <command> [args]
+
+commands:
+	stats <path> [--json]     env info and per-db entry counts, depths, page counts and sizes
+	verify <path> [--json]    walk every db checking key ordering and registered tables' archives
+	compact <src> <dst>       compacting copy of src into dst, which has to be empty or not exist
+	compact <src> --in-place  compacting copy next to src, then swap it in place of src's data file -
+	                          the env has to be closed in every other process, it refuses while
+	                          reader slots are taken or the writer lock is held and gives up if
+	                          anything was committed meanwhile
+	migrate <path>            apply pending migrations, taking a before-migration snapshot
+	                          into <path>/snapshots first
+	migrate <path> --no-snapshot  apply pending migrations without the snapshot
+	migrate <path> --dry-run  run pending migrations in a transaction that is then aborted
+	migrate <path> --status   applied version and pending migrations
+";
+
+/// positional args and `--flags`, in order
+pub(crate) struct Args<'a> {
+	pub(crate) positional: Vec<&'a str>,
+	pub(crate) flags: Vec<&'a str>,
+}
+
+impl<'a> Args<'a> {
+	fn parse(args: &'a [String]) -> Self {
+		let (flags, positional) = args.iter().map(String::as_str).partition(|x| x.starts_with("--"));
+		Self { positional, flags }
+	}
+
+	pub(crate) fn flag(&self, name: &str) -> bool { self.flags.iter().any(|x| x.strip_prefix("--") == Some(name)) }
+
+	pub(crate) fn expect_positional(&self, n: usize) -> Result<&[&'a str]> {
+		if self.positional.len() != n { return Err(format!("expected {n} argument(s)\n\n{USAGE}").into()); }
+		Ok(&self.positional)
+	}
+}
+
+pub(crate) fn open_read_only(path: &str, register: Register) -> Result<Env> {
+	let path = std::ffi::CString::new(path)?;
+	Ok(register(Env::builder()?).read_only().build_existing(&path)?)
+}
+
+#[derive(Default)]
+pub struct Cli {
+	register: Option<Register>,
+	migrations: &'static [Migration],
+}
+
+impl Cli {
+	pub fn new() -> Self { Self::default() }
+
+	#[must_use]
+	pub fn tables(mut self, register: Register) -> Self {
+		self.register = Some(register);
+		self
+	}
+
+	#[must_use]
+	pub fn migrations(mut self, migrations: &'static [Migration]) -> Self {
+		self.migrations = migrations;
+		self
+	}
+
+	/// Parse `std::env::args` and run the command, exits the process on failure.
+	pub fn run(self) {
+		let register = self.register.unwrap_or(std::convert::identity);
+		let args = std::env::args().skip(1).collect::<Vec<_>>();
+		let Some((command, rest)) = args.split_first() else { eprint!("{USAGE}"); std::process::exit(2); };
+		let args = Args::parse(rest);
+		let res = match command.as_str() {
+			"stats" => stats::run(&args, register),
+			"verify" => verify::run(&args, register),
+			"compact" => compact::run(&args, register),
+			"migrate" => migrate::run(&args, register, self.migrations),
+			_ => { eprint!("{USAGE}"); std::process::exit(2); },
+		};
+		if let Err(e) = res {
+			let mut message = format!("error: {e}");
+			let mut source = e.source();
+			while let Some(e) = source {
+				message += &format!(": {e}");
+				source = e.source();
+			}
+			eprintln!("{message}");
+			std::process::exit(1);
+		}
+	}
+}
@@ -0,0 +1,3 @@
+fn main() {
+	batadase_cli::Cli::new().run();
+}
@@ -0,0 +1,32 @@
+use batadase::{Env, Meta, migrate::{self, Migration}};
+use crate::{Args, Register, Result};
+
+pub(crate) fn run(args: &Args, register: Register, migrations: &'static [Migration]) -> Result<()> {
+	let [path] = args.expect_positional(1)? else { unreachable!() };
+	let path = std::ffi::CString::new(*path)?;
+	// migrations write to the app's tables, so those (and Meta) get opened - and created if missing - like the app would
+	let env: &'static Env = Box::leak(Box::new(register(Env::builder()?).with::<Meta>().build(&path)?));
+
+	if args.flag("status") {
+		let tx = env.read_tx()?;
+		println!("applied version: {}", migrate::version(&tx)?);
+		let pending = migrate::pending(&tx, migrations)?;
+		if pending.is_empty() { println!("no pending migrations"); }
+		for migration in pending { println!("pending: {} {}", migration.version, migration.name); }
+		return Ok(());
+	}
+
+	let runtime = tokio::runtime::Builder::new_current_thread().build()?;
+	if args.flag("dry-run") {
+		let applied = runtime.block_on(migrate::dry_run(env, migrations))?;
+		println!("{} migration(s) would be applied: {applied:?}, nothing was changed", applied.len());
+	} else {
+		if !args.flag("no-snapshot") && !migrate::pending(&env.read_tx()?, migrations)?.is_empty() {
+			let snapshot = env.snapshot("before-migration")?;
+			println!("took snapshot {}", snapshot.path.display());
+		}
+		let applied = runtime.block_on(migrate::run_pending(env, migrations))?;
+		println!("{} migration(s) applied: {applied:?}", applied.len());
+	}
+	Ok(())
+}
@@ -0,0 +1,78 @@
+use batadase::lmdb::Stat;
+use crate::{Args, Register, Result};
+
+#[derive(serde::Serialize)]
+struct EnvStats {
+	path: String,
+	map_size: usize,
+	page_size: u32,
+	last_page: usize,
+	last_txn_id: usize,
+	max_readers: u32,
+	num_readers: u32,
+	databases: Vec<DbStats>,
+}
+
+#[derive(serde::Serialize)]
+struct DbStats {
+	name: String,
+	entries: usize,
+	depth: u32,
+	branch_pages: usize,
+	leaf_pages: usize,
+	overflow_pages: usize,
+	size_bytes: usize,
+}
+
+impl DbStats {
+	fn new(name: &[u8], stat: Stat) -> Self {
+		Self {
+			name: batadase::db_name_lossy(name),
+			entries: stat.entries,
+			depth: stat.depth,
+			branch_pages: stat.branch_pages,
+			leaf_pages: stat.leaf_pages,
+			overflow_pages: stat.overflow_pages,
+			size_bytes: stat.size_bytes(),
+		}
+	}
+}
+
+pub(crate) fn run(args: &Args, register: Register) -> Result<()> {
+	let [path] = args.expect_positional(1)? else { unreachable!() };
+	let env = crate::open_read_only(path, register)?;
+	let info = env.info()?;
+	let stat = env.stat_all()?;
+	let databases = stat.dbs.into_iter().map(|(name, stat)| DbStats::new(&name, stat)).collect();
+
+	let stats = EnvStats {
+		path: (*path).to_owned(),
+		map_size: info.map_size,
+		page_size: stat.root.page_size,
+		last_page: info.last_page,
+		last_txn_id: info.last_txn_id,
+		max_readers: info.max_readers,
+		num_readers: info.num_readers,
+		databases,
+	};
+
+	if args.flag("json") {
+		println!("{}", serde_json::to_string_pretty(&stats)?);
+		return Ok(());
+	}
+
+	println!("path:        {}", stats.path);
+	println!("map size:    {} ({:.1}% used)", stats.map_size, env.usage()?.percent);
+	println!("page size:   {}", stats.page_size);
+	println!("last page:   {}", stats.last_page);
+	println!("last txn id: {}", stats.last_txn_id);
+	println!("readers:     {}/{}", stats.num_readers, stats.max_readers);
+	println!();
+
+	let name_width = stats.databases.iter().map(|x| x.name.len()).max().unwrap_or(0).max("database".len());
+	println!("{:<name_width$}  {:>10}  {:>5}  {:>8}  {:>8}  {:>8}  {:>12}", "database", "entries", "depth", "branch", "leaf", "overflow", "size");
+	for db in &stats.databases {
+		println!("{:<name_width$}  {:>10}  {:>5}  {:>8}  {:>8}  {:>8}  {:>12}", db.name, db.entries, db.depth, db.branch_pages, db.leaf_pages, db.overflow_pages, db.size_bytes);
+	}
+	Ok(())
+}
@@ -0,0 +1,33 @@
+use crate::{Args, Register, Result};
+
+pub(crate) fn run(args: &Args, register: Register) -> Result<()> {
+	let [path] = args.expect_positional(1)? else { unreachable!() };
+	let env = crate::open_read_only(path, register)?;
+	let report = env.verify()?;
+	let errors = report.errors();
+	let reports = report.dbs;
+
+	if args.flag("json") {
+		let reports = reports.iter().map(|x| serde_json::json!({
+			"name": x.name,
+			"entries": x.entries,
+			"misordered": x.misordered,
+			"invalid": x.invalid,
+			"checked": x.checked,
+			"first_error": x.first_error,
+		})).collect::<Vec<_>>();
+		println!("{}", serde_json::to_string_pretty(&serde_json::json!({ "path": path, "txn_id": report.txn_id, "errors": errors, "databases": reports }))?);
+	} else {
+		let name_width = reports.iter().map(|x| x.name.len()).max().unwrap_or(0).max("database".len());
+		println!("{:<name_width$}  {:>10}  {:>10}  {:>10}  {:>7}", "database", "entries", "misordered", "invalid", "checked");
+		for report in &reports {
+			println!("{:<name_width$}  {:>10}  {:>10}  {:>10}  {:>7}", report.name, report.entries, report.misordered, report.invalid, if report.checked { "yes" } else { "no" });
+		}
+		for report in &reports {
+			if let Some(e) = &report.first_error { println!("{}: {e}", report.name); }
+		}
+	}
+
+	if errors > 0 { return Err(format!("{errors} error(s) found").into()); }
+	Ok(())
+}
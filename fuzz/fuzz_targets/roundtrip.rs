@@ -0,0 +1,13 @@
+#![no_main]
+
+static DB: std::sync::LazyLock<batadase::Db> = std::sync::LazyLock::new(|| {
+	let path = std::env::temp_dir().join(format!("batadase-fuzz-{}", std::process::id()));
+	batadase::Db::open(path).expect("opening the db failed")
+});
+
+// first byte is the key length, the rest is split into key and value
+libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+	let Some((&split, rest)) = data.split_first() else { return };
+	let (key, value) = rest.split_at(usize::from(split).min(rest.len()));
+	batadase::fuzz::roundtrip(&DB.tree("fuzz").expect("opening the tree failed"), key, value);
+});
@@ -2,7 +2,7 @@
 
 use proc_quote::quote;
 
-#[proc_macro_derive(DbName, attributes(name, flags, table))]
+#[proc_macro_derive(DbName, attributes(name, flags, table, dup_order))]
 pub fn derive_db_name(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
 	let input = syn::parse_macro_input!(input as syn::DeriveInput);
 	let name = &input.ident;
@@ -12,6 +12,7 @@ pub fn derive_db_name(input: proc_macro::TokenStream) -> proc_macro::TokenStream
 	let mut db_name = None;
 	let mut flags = None;
 	let mut table = None;
+	let mut dup_order = None;
 	for attr in input.attrs {
 		let args = attr.meta.require_list().unwrap();
 		match &attr.path().get_ident().unwrap().to_string() as &str {
@@ -21,11 +22,13 @@ pub fn derive_db_name(input: proc_macro::TokenStream) -> proc_macro::TokenStream
 			},
 			"flags" => { flags = Some(args.parse_args::<syn::Expr>().unwrap()); },
 			"table" => { table = Some(args.parse_args::<syn::Type>().unwrap()); },
+			"dup_order" => { dup_order = Some(args.parse_args::<syn::Type>().unwrap()); },
 			_ => unreachable!(),
 		}
 	}
 
 	let flags = flags.map_or_else(|| quote!(), |x| quote!(fn flags() -> #crate_name::enumflags2::BitFlags<#crate_name::lmdb::DbFlags> { #x.into() }));
+	let dup_order = dup_order.map_or_else(|| quote!(), |x| quote!(fn dup_order() -> ::std::option::Option<#crate_name::lmdb::DupOrder> { ::std::option::Option::Some(#crate_name::lmdb::DupOrder::of::<#x>()) }));
 	let db_name = db_name.map_or_else(|| quote!(&::std::concat!(::std::module_path!(), "::", ::std::stringify!(#name), "\0").as_bytes()), |x| quote!(#x));//syn::LitByteStr::new(format!("{}\0", name).as_bytes(), name.span()));
 
 	quote!(
@@ -33,6 +36,7 @@ pub fn derive_db_name(input: proc_macro::TokenStream) -> proc_macro::TokenStream
 			type Table<'tx, 'env: 'tx, TX: #crate_name::Transaction<'env> + 'tx> = #table;
 			const NAME: &'static [u8] = #db_name;
 			#flags
+			#dup_order
 		}
 	).into()
 }
@@ -8,4 +8,6 @@ fn main() {
 	assert!(Command::new("cargo").arg("publish").current_dir("macros").status().unwrap().success());
 	sleep(Duration::from_secs(60));
 	assert!(Command::new("cargo").arg("publish").status().unwrap().success());
+	sleep(Duration::from_secs(60));
+	assert!(Command::new("cargo").arg("publish").current_dir("cli").status().unwrap().success());
 }
@@ -1,28 +1,86 @@
-use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, RkyvDe, Error, lmdb};
+use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, RkyvDe, Error, lmdb, error::Context};
 use culpa::throws;
 use enumflags2::BitFlag;
 use std::marker::PhantomData;
 
-pub struct AssocPolyTable<'tx, TX, K> {
+pub struct AssocPolyTable<'tx, TX, K, T = Untagged> {
 	tx: &'tx TX,
 	dbi: lmdb_sys::MDB_dbi,
-	_pd: PhantomData<K>,
+	_pd: PhantomData<(K, T)>,
+	_borrow: crate::borrows::Borrow,
 }
 
-impl<'tx, 'env: 'tx, TX, K> Table<'tx, 'env, TX> for AssocPolyTable<'tx, TX, K> where
+/// Whether values record the type they were put as, picked with the table's last type parameter.
+/// Without a tag a value read as the wrong type only fails if it happens not to bytecheck.
+pub trait Tagging {
+	type Tagged: AsMut<[u8]>;
+
+	fn tag<V: rkyv::Archive>(archive: rkyv::util::AlignedVec) -> Self::Tagged;
+	/// the archive out of the stored bytes, if they were put as a `V`
+	fn untag<V: rkyv::Archive>(stored: &[u8]) -> Result<&[u8], Error>;
+}
+
+/// the archive as-is, the default
+pub struct Untagged;
+
+impl Tagging for Untagged {
+	type Tagged = rkyv::util::AlignedVec;
+
+	fn tag<V: rkyv::Archive>(archive: rkyv::util::AlignedVec) -> Self::Tagged { archive }
+	fn untag<V: rkyv::Archive>(stored: &[u8]) -> Result<&[u8], Error> { Ok(stored) }
+}
+
+/// Little-endian [`type_tag`] of the value's type followed by the archive, reads as another type fail with
+/// [`Error::TypeMismatch`]. Changing a table to or from tagging changes its on-disk format.
+pub struct TypeTagged;
+
+impl Tagging for TypeTagged {
+	type Tagged = Vec<u8>;
+
+	fn tag<V: rkyv::Archive>(archive: rkyv::util::AlignedVec) -> Self::Tagged {
+		let mut tagged = Vec::with_capacity(8 + archive.len());
+		tagged.extend_from_slice(&type_tag::<V>().to_le_bytes());
+		tagged.extend_from_slice(&archive);
+		tagged
+	}
+
+	fn untag<V: rkyv::Archive>(stored: &[u8]) -> Result<&[u8], Error> {
+		match stored.split_first_chunk::<8>() {
+			Some((tag, archive)) if u64::from_le_bytes(*tag) == type_tag::<V>() => Ok(archive),
+			_ => Err(Error::TypeMismatch(std::any::type_name::<V>())),
+		}
+	}
+}
+
+/// FNV-1a of the type's name and archived size and alignment. Type names aren't guaranteed to stay the same across
+/// compiler versions, so a toolchain upgrade can make existing values mismatch.
+pub fn type_tag<V: rkyv::Archive>() -> u64 {
+	let layout = std::alloc::Layout::new::<rkyv::Archived<V>>();
+	crate::fnv1a(std::any::type_name::<V>().bytes().chain((layout.size() as u64).to_le_bytes()).chain((layout.align() as u64).to_le_bytes()))
+}
+
+impl<'tx, 'env: 'tx, TX, K, T> Table<'tx, 'env, TX> for AssocPolyTable<'tx, TX, K, T> where
 	TX: Transaction<'env>,
+	T: Tagging,
 	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
 	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 {
 	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.dbi }
 	fn txn(&self) -> &TX { self.tx }
 	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
-		Self::build(tx, tx.env().db(name).unwrap())
+		Self::build(tx, tx.env().db(name).expect("table isn't registered in the env, add it with EnvBuilder::with"))
+	}
+
+	// values can be anything
+	#[throws]
+	fn check_entry(key: &[u8], _value: &[u8]) {
+		rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key)?;
 	}
 }
 
 // RwTxn only, so all methods mutate
-impl<'tx, K> AssocPolyTable<'tx, RwTxn<'tx>, K> where
+impl<'tx, K, T> AssocPolyTable<'tx, RwTxn<'tx>, K, T> where
+	T: Tagging,
 	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
 {
 	#[throws]
@@ -30,8 +88,8 @@ impl<'tx, K> AssocPolyTable<'tx, RwTxn<'tx>, K> where
 		V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
 	{
 		let mut key_bytes = rkyv::to_bytes(key)?;
-		let mut value_bytes = rkyv::to_bytes(value)?;
-		lmdb::put(self.tx, self.dbi, &mut key_bytes, &mut value_bytes, lmdb::PutFlags::empty())?;
+		let mut value_bytes = T::tag::<V>(rkyv::to_bytes(value)?);
+		lmdb::put(self.tx, self.dbi, &mut key_bytes, &mut value_bytes, lmdb::PutFlags::empty()).context(self.tx, self.dbi, "put", Some(&key_bytes))?;
 	}
 
 	#[throws]
@@ -39,28 +97,29 @@ impl<'tx, K> AssocPolyTable<'tx, RwTxn<'tx>, K> where
 		V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
 	{
 		let mut key_bytes = rkyv::to_bytes(key)?;
-		let mut value_bytes = rkyv::to_bytes(value)?;
-		lmdb::put(self.tx, self.dbi, &mut key_bytes, &mut value_bytes, lmdb::PutFlags::NoOverwrite.into())?;
+		let mut value_bytes = T::tag::<V>(rkyv::to_bytes(value)?);
+		lmdb::put(self.tx, self.dbi, &mut key_bytes, &mut value_bytes, lmdb::PutFlags::NoOverwrite.into()).context(self.tx, self.dbi, "put_no_overwrite", Some(&key_bytes))?;
 	}
 
 	#[throws]
 	pub fn delete(&self, key: &K) -> bool {
 		let mut key_bytes = rkyv::to_bytes(key)?;
-		lmdb::del(self.tx, self.dbi, &mut key_bytes)?
+		lmdb::del(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "delete", Some(&key_bytes))?
 	}
 
 	#[throws]
-	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi)?; }
+	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi).context(self.tx, self.dbi, "clear", None)?; }
 }
 
 // both RoTxn and RwTxn, so all methods are read-only
-impl<'tx, 'env: 'tx, TX, K> AssocPolyTable<'tx, TX, K> where
+impl<'tx, 'env: 'tx, TX, K, T> AssocPolyTable<'tx, TX, K, T> where
 	TX: Transaction<'env>,
+	T: Tagging,
 	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
 	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 {
 	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self {
-		Self { tx, dbi, _pd: PhantomData }
+		Self { tx, dbi, _pd: PhantomData, _borrow: crate::borrows::Borrow::of(tx) }
 	}
 
 	#[throws]
@@ -69,8 +128,47 @@ impl<'tx, 'env: 'tx, TX, K> AssocPolyTable<'tx, TX, K> where
 		rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe>,
 	{
 		let mut key_bytes = rkyv::to_bytes(key)?;
-		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut key_bytes)? else { return None; };
-		Some(rkyv::access::<rkyv::Archived<V>, _>(value_bytes)?)
+		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "get", Some(&key_bytes))? else { return None; };
+		let archive = T::untag::<V>(value_bytes).context(self.tx, self.dbi, "get", Some(&key_bytes))?;
+		Some(rkyv::access::<rkyv::Archived<V>, rkyv::rancor::Error>(archive).context(self.tx, self.dbi, "get", Some(&key_bytes))?)
+	}
+
+	/// whether `key` has a value, which isn't read or validated
+	#[throws]
+	pub fn contains_key(&self, key: &K) -> bool {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "contains_key", Some(&key_bytes))?.is_some()
+	}
+
+	/// Like [`AssocPolyTable::get`] but without bytecheck validation, for hot paths over trusted data.
+	/// A [`TypeTagged`] table still checks the tag.
+	///
+	/// # Safety
+	/// The stored value has to be a valid archive of `V`, so it has to have been put as a `V` and not corrupted since.
+	#[throws]
+	pub unsafe fn get_unchecked<V>(&self, key: &K) -> Option<&'tx rkyv::Archived<V>> where
+		V: rkyv::Archive,
+	{
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "get", Some(&key_bytes))? else { return None; };
+		let archive = T::untag::<V>(value_bytes).context(self.tx, self.dbi, "get", Some(&key_bytes))?;
+		Some(unsafe { rkyv::access_unchecked::<rkyv::Archived<V>>(archive) })
+	}
+
+	/// the smallest key, its value isn't read
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn min_key(&self) -> Option<&'tx rkyv::Archived<K>> { self.edge_key(lmdb::CursorOp::First, "min_key")? }
+
+	/// the largest key, its value isn't read
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn max_key(&self) -> Option<&'tx rkyv::Archived<K>> { self.edge_key(lmdb::CursorOp::Last, "max_key")? }
+
+	#[throws]
+	fn edge_key(&self, op: lmdb::CursorOp, name: &'static str) -> Option<&'tx rkyv::Archived<K>> {
+		let Some((key_bytes, _)) = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, name, None)?.get(op)? else { return None; };
+		Some(rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key_bytes).context(self.tx, self.dbi, name, Some(key_bytes))?)
 	}
 
 	#[throws]
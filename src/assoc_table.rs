@@ -1,16 +1,18 @@
-use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, RkyvDe, Error, lmdb};
+use crate::{Transaction, RoTxn, RwTxn, Table, RkyvSer, RkyvVal, RkyvDe, Error, lmdb, bookmark::Bookmark, error::Context, framing::{Framing, Plain}};
 use culpa::throws;
 use enumflags2::BitFlag;
-use std::marker::PhantomData;
+use std::{marker::PhantomData, ops::Bound};
 
-pub struct AssocTable<'tx, TX, K, V> {
-	tx: &'tx TX,
-	dbi: lmdb_sys::MDB_dbi,
-	_pd: PhantomData<(K, V)>,
+pub struct AssocTable<'tx, TX, K, V, F = Plain> {
+	pub(crate) tx: &'tx TX,
+	pub(crate) dbi: lmdb_sys::MDB_dbi,
+	_pd: PhantomData<(K, V, F)>,
+	_borrow: crate::borrows::Borrow,
 }
 
-impl<'tx, 'env: 'tx, TX, K, V> Table<'tx, 'env, TX> for AssocTable<'tx, TX, K, V> where
+impl<'tx, 'env: 'tx, TX, K, V, F> Table<'tx, 'env, TX> for AssocTable<'tx, TX, K, V, F> where
 	TX: Transaction<'env>,
+	F: Framing,
 	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
 	V: rkyv::Archive,
 	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
@@ -19,89 +21,450 @@ impl<'tx, 'env: 'tx, TX, K, V> Table<'tx, 'env, TX> for AssocTable<'tx, TX, K, V
 	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.dbi }
 	fn txn(&self) -> &TX { self.tx }
 	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
-		Self::build(tx, tx.env().db(name).unwrap())
+		Self::build(tx, tx.env().db(name).expect("table isn't registered in the env, add it with EnvBuilder::with"))
+	}
+
+	#[throws]
+	fn check_entry(key: &[u8], value: &[u8]) {
+		rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key)?;
+		rkyv::access::<rkyv::Archived<V>, rkyv::rancor::Error>(F::unframe(value)?)?;
+	}
+}
+
+// ranges with up to this many entries are counted exactly by estimate_range_count
+const RANGE_PROBE: usize = 1000;
+// keys par_scan samples per shard to pick where to split
+const SPLIT_SAMPLES: usize = 16;
+
+// a key and its value as archives in the map
+pub(crate) type ArchivedEntry<'tx, K, V> = (&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>);
+
+// key ranges as serialized bounds, compared by the db's comparator so key flags like ReverseKey are respected
+pub(crate) struct KeyRange {
+	start: Bound<Vec<u8>>,
+	end: Bound<Vec<u8>>,
+}
+
+impl KeyRange {
+	#[throws(rkyv::rancor::Error)]
+	pub(crate) fn new<K>(range: &impl std::ops::RangeBounds<K>) -> Self where
+		K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	{
+		let bytes = |bound: Bound<&K>| Ok::<_, rkyv::rancor::Error>(match bound {
+			Bound::Included(key) => Bound::Included(rkyv::to_bytes(key)?.to_vec()),
+			Bound::Excluded(key) => Bound::Excluded(rkyv::to_bytes(key)?.to_vec()),
+			Bound::Unbounded => Bound::Unbounded,
+		});
+		Self { start: bytes(range.start_bound())?, end: bytes(range.end_bound())? }
+	}
+
+	pub(crate) fn before_end<'env>(&self, tx: &impl Transaction<'env>, dbi: lmdb_sys::MDB_dbi, key: &[u8]) -> bool {
+		match &self.end {
+			Bound::Included(end) => lmdb::cmp(tx, dbi, key, end).is_le(),
+			Bound::Excluded(end) => lmdb::cmp(tx, dbi, key, end).is_lt(),
+			Bound::Unbounded => true,
+		}
+	}
+
+	// moves cursor to the first entry at or after start, which can be past the end
+	#[throws(lmdb::Error)]
+	pub(crate) fn seek<'tx, 'env: 'tx, TX: Transaction<'env>>(&self, cursor: &mut lmdb::Cursor<'tx, TX>) -> Option<(&'tx [u8], &'tx [u8])> {
+		match &self.start {
+			Bound::Included(start) => cursor.get_with_key(&mut start.clone(), lmdb::CursorOp::SetRange)?,
+			Bound::Excluded(start) => match cursor.get_with_key(&mut start.clone(), lmdb::CursorOp::SetRange)? {
+				Some((key, _)) if key == start.as_slice() => cursor.get(lmdb::CursorOp::Next)?,
+				x => x,
+			},
+			Bound::Unbounded => cursor.get(lmdb::CursorOp::First)?,
+		}
 	}
 }
 
-fn archived_from_cursor_get<'tx, K, V>(get: Option<(&'tx [u8], &'tx [u8])>) -> Option<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> where
+#[throws]
+pub(crate) fn access<V, F: Framing>(stored: &[u8]) -> &rkyv::Archived<V> where
+	V: rkyv::Archive,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	rkyv::access::<rkyv::Archived<V>, rkyv::rancor::Error>(F::unframe(stored)?)?
+}
+
+// the archives of what a cursor got, ending iteration at the first invalid entry and passing cursor errors on
+pub(crate) fn archived_from_cursor_get<'tx, K, V, F: Framing>(get: Result<Option<lmdb::Entry<'tx>>, lmdb::Error>) -> Option<Result<ArchivedEntry<'tx, K, V>, Error>> where
 	K: rkyv::Archive,
 	V: rkyv::Archive,
 	rkyv::Archived<K>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 	rkyv::Archived<V>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 {
-	let (key_bytes, value_bytes) = get?;
+	let (key_bytes, value_bytes) = match get {
+		Ok(entry) => entry?,
+		Err(e) => return Some(Err(e.into())),
+	};
 	let key = match rkyv::access::<rkyv::Archived<K>, _>(key_bytes) {
 		Ok(x) => x,
 		Err(e) => { log::error!("Error deserializing key in cursor: {e:?}"); return None; }
 	};
+	let value_bytes = match F::unframe(value_bytes) {
+		Ok(x) => x,
+		Err(e) => { log::error!("Error unframing value in cursor: {e:?}"); return None; }
+	};
 	let value = match rkyv::access::<rkyv::Archived<V>, _>(value_bytes) {
 		Ok(x) => x,
 		Err(e) => { log::error!("Error deserializing value in cursor: {e:?}"); return None; }
 	};
-	Some((key, value))
+	Some(Ok((key, value)))
+}
+
+// cursors are dropped once they fail, their ops keep failing once the transaction has
+struct Cursor<'tx, TX, K, V, F>(Option<lmdb::Cursor<'tx, TX>>, lmdb::CursorOp, PhantomData<(K, V, F)>);
+impl<'tx, 'env: 'tx, TX, K, V, F> Iterator for Cursor<'tx, TX, K, V, F> where
+	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	type Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let get = self.0.as_mut()?.get(self.1);
+		if get.is_err() { self.0 = None; }
+		archived_from_cursor_get::<'tx, K, V, F>(get)
+	}
 }
 
-struct Cursor<'tx, TX, K, V>(lmdb::Cursor<'tx, TX>, lmdb::CursorOpFlags, PhantomData<(K, V)>);
-impl<'tx, 'env: 'tx, TX, K, V> Iterator for Cursor<'tx, TX, K, V> where
+// the values after the current one of the cursor's key
+struct DupValues<'tx, TX, K, V, F>(Option<lmdb::DupCursor<'tx, TX>>, PhantomData<(K, V, F)>);
+impl<'tx, 'env: 'tx, TX, K, V, F> Iterator for DupValues<'tx, TX, K, V, F> where
 	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	type Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let get = self.0.as_mut()?.get_dup(lmdb::DupCursorOp::NextDup);
+		if get.is_err() { self.0 = None; }
+		archived_from_cursor_get::<'tx, K, V, F>(get)
+	}
+}
+
+// leaves its cursor idle for the next scan once dropped
+struct ReusingCursor<'tx, 'env, 'c, K, V, F> {
+	cursor: Option<lmdb::Cursor<'tx, RoTxn<'env>>>,
+	idle: &'c mut Option<lmdb::IdleCursor<'env>>,
+	_pd: PhantomData<(K, V, F)>,
+}
+
+impl<'tx, 'env: 'tx, K, V, F> Iterator for ReusingCursor<'tx, 'env, '_, K, V, F> where
+	F: Framing,
 	K: rkyv::Archive,
 	V: rkyv::Archive,
 	rkyv::Archived<K>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 	rkyv::Archived<V>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 {
-	type Item = (&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>);
+	type Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>;
 
-	fn next(&mut self) -> Option<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> {
-		archived_from_cursor_get::<'tx, K, V>(self.0.get(self.1))
+	fn next(&mut self) -> Option<Self::Item> {
+		let get = self.cursor.as_mut()?.get(lmdb::CursorOp::Next);
+		if get.is_err() { self.cursor = None; }
+		archived_from_cursor_get::<'tx, K, V, F>(get)
 	}
 }
 
+impl<K, V, F> Drop for ReusingCursor<'_, '_, '_, K, V, F> {
+	fn drop(&mut self) { *self.idle = self.cursor.take().map(lmdb::Cursor::into_idle); }
+}
+
 // RwTxn only, so all methods mutate
-impl<'tx, K, V> AssocTable<'tx, RwTxn<'tx>, K, V> where
+impl<'tx, K, V, F> AssocTable<'tx, RwTxn<'tx>, K, V, F> where
+	F: Framing,
 	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
 	V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
 {
 	#[throws]
 	pub fn put(&self, key: &K, value: &V) {
 		let mut key_bytes = rkyv::to_bytes(key)?;
-		let mut value_bytes = rkyv::to_bytes(value)?;
-		lmdb::put(self.tx, self.dbi, &mut key_bytes, &mut value_bytes, lmdb::PutFlags::empty())?;
+		let mut value_bytes = F::frame(rkyv::to_bytes(value)?);
+		lmdb::put(self.tx, self.dbi, &mut key_bytes, &mut value_bytes, lmdb::PutFlags::empty()).context(self.tx, self.dbi, "put", Some(&key_bytes))?;
 	}
 
 	#[throws]
 	pub fn put_no_overwrite(&self, key: &K, value: &V) {
 		let mut key_bytes = rkyv::to_bytes(key)?;
-		let mut value_bytes = rkyv::to_bytes(value)?;
-		lmdb::put(self.tx, self.dbi, &mut key_bytes, &mut value_bytes, lmdb::PutFlags::NoOverwrite.into())?;
+		let mut value_bytes = F::frame(rkyv::to_bytes(value)?);
+		lmdb::put(self.tx, self.dbi, &mut key_bytes, &mut value_bytes, lmdb::PutFlags::NoOverwrite.into()).context(self.tx, self.dbi, "put_no_overwrite", Some(&key_bytes))?;
+	}
+
+	/// Put `new` only if the stored value is `expected`, or if there's none for `None`, returns whether it was put.
+	/// Values are compared as serialized bytes, so `V` has to serialize the same way every time (e.g. no `HashMap`s).
+	#[throws]
+	pub fn put_if_equals(&self, key: &K, expected: Option<&V>, new: &V) -> bool {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let stored = lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "put_if_equals", Some(&key_bytes))?;
+		let equal = match (stored, expected) {
+			(None, None) => true,
+			(Some(stored), Some(expected)) => F::unframe(stored).context(self.tx, self.dbi, "put_if_equals", Some(&key_bytes))? == rkyv::to_bytes(expected)?.as_slice(),
+			_ => false,
+		};
+		if !equal { return false; }
+		let mut value_bytes = F::frame(rkyv::to_bytes(new)?);
+		lmdb::put(self.tx, self.dbi, &mut key_bytes, &mut value_bytes, lmdb::PutFlags::empty()).context(self.tx, self.dbi, "put_if_equals", Some(&key_bytes))?;
+		true
 	}
 
 	#[throws]
 	pub fn delete(&self, key: &K) -> bool {
 		let mut key_bytes = rkyv::to_bytes(key)?;
-		lmdb::del(self.tx, self.dbi, &mut key_bytes)?
+		lmdb::del(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "delete", Some(&key_bytes))?
+	}
+
+	/// Delete the entry if `condition` holds for its value, returns whether it was deleted.
+	#[throws]
+	pub fn delete_if(&self, key: &K, condition: impl FnOnce(&rkyv::Archived<V>) -> bool) -> bool where
+		rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	{
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "delete_if", Some(&key_bytes))? else { return false; };
+		if !condition(access::<V, F>(value_bytes).context(self.tx, self.dbi, "delete_if", Some(&key_bytes))?) { return false; }
+		lmdb::del(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "delete_if", Some(&key_bytes))?
+	}
+
+	/// Replace the value with what `update` makes of it, deleting the entry for `None`, and return the previous value.
+	#[throws]
+	pub fn fetch_update(&self, key: &K, update: impl FnOnce(Option<V>) -> Option<V>) -> Option<V> where
+		V: Clone,
+		rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe>,
+	{
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let previous = match lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "fetch_update", Some(&key_bytes))? {
+			Some(value_bytes) => Some(rkyv::deserialize::<V, rkyv::rancor::Error>(access::<V, F>(value_bytes).context(self.tx, self.dbi, "fetch_update", Some(&key_bytes))?)
+				.context(self.tx, self.dbi, "fetch_update", Some(&key_bytes))?),
+			None => None,
+		};
+		match update(previous.clone()) {
+			Some(value) => {
+				let mut value_bytes = F::frame(rkyv::to_bytes(&value)?);
+				lmdb::put(self.tx, self.dbi, &mut key_bytes, &mut value_bytes, lmdb::PutFlags::empty()).context(self.tx, self.dbi, "fetch_update", Some(&key_bytes))?;
+			},
+			None => if previous.is_some() { lmdb::del(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "fetch_update", Some(&key_bytes))?; },
+		}
+		previous
+	}
+
+	#[throws]
+	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi).context(self.tx, self.dbi, "clear", None)?; }
+
+	/// Move the entries with keys in `range` into `dst`, replacing entries there with the same keys, returns how many were moved.
+	/// Keys are in the table's order, so as serialized bytes unless it has key flags like `IntegerKey`.
+	#[throws]
+	pub fn move_entries(&self, dst: &Self, range: impl std::ops::RangeBounds<K>) -> usize {
+		// nothing to do, and deleting after putting would lose the entries
+		if dst.dbi == self.dbi { return 0; }
+		let range = KeyRange::new(&range)?;
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "move_entries", None)?;
+		let mut entry = range.seek(&mut cursor)?;
+		let mut moved = 0;
+		while let Some((key, value)) = entry && range.before_end(self.tx, self.dbi, key) {
+			let mut key = key.to_vec();
+			lmdb::put(self.tx, dst.dbi, &mut key, &mut value.to_vec(), lmdb::PutFlags::empty()).context(self.tx, dst.dbi, "move_entries", Some(&key))?;
+			// leaves the cursor so that Next gets the entry after the deleted one
+			cursor.del_current().context(self.tx, self.dbi, "move_entries", Some(&key))?;
+			moved += 1;
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+		moved
+	}
+
+	/// remove and return the entry with the smallest key
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn pop_first(&self) -> Option<(K, V)> where
+		rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<K, RkyvDe>,
+		rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe>,
+	{ self.pop(lmdb::CursorOp::First, "pop_first")? }
+
+	/// remove and return the entry with the largest key
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn pop_last(&self) -> Option<(K, V)> where
+		rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<K, RkyvDe>,
+		rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe>,
+	{ self.pop(lmdb::CursorOp::Last, "pop_last")? }
+
+	#[throws]
+	fn pop(&self, op: lmdb::CursorOp, name: &'static str) -> Option<(K, V)> where
+		rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<K, RkyvDe>,
+		rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe>,
+	{
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, name, None)?;
+		let Some((key_bytes, value_bytes)) = cursor.get(op)? else { return None; };
+		let key = rkyv::from_bytes::<K, rkyv::rancor::Error>(key_bytes).context(self.tx, self.dbi, name, Some(key_bytes))?;
+		let value = rkyv::deserialize::<V, rkyv::rancor::Error>(access::<V, F>(value_bytes).context(self.tx, self.dbi, name, Some(key_bytes))?)
+			.context(self.tx, self.dbi, name, Some(key_bytes))?;
+		let key_bytes = key_bytes.to_vec(); // the slice goes stale with the delete
+		cursor.del_current().context(self.tx, self.dbi, name, Some(&key_bytes))?;
+		Some((key, value))
+	}
+
+	/// Put every entry of `parts` at once, for initial loads of big datasets: each part is serialized and sorted on a
+	/// thread of its own, and the sorted runs are merged into appends in this transaction. Keys have to come after the
+	/// table's last one and be distinct unless it's `DbFlags::DupSort`, or it fails with [`Error::OutOfOrder`] - it's
+	/// meant for empty tables. The serialized entries are all held in memory until they're written, a load too big for
+	/// that can be split by key prefix and imported prefix by prefix. Returns how many entries were put.
+	#[throws]
+	pub fn bulk_import_partitioned<I: Iterator<Item = (K, V)> + Send>(&self, parts: Vec<I>) -> usize {
+		let (tx, dbi) = (self.tx, self.dbi);
+		let dup_sort = lmdb::dbi_flags(tx.raw(), dbi)?.contains(lmdb::DbFlags::DupSort);
+		let order = move |a: &(Vec<u8>, Vec<u8>), b: &(Vec<u8>, Vec<u8>)| {
+			lmdb::cmp(tx, dbi, &a.0, &b.0).then_with(|| if dup_sort { lmdb::dcmp(tx, dbi, &a.1, &b.1) } else { std::cmp::Ordering::Equal })
+		};
+		let mut runs = std::thread::scope(|scope| {
+			let workers = parts.into_iter().map(|part| scope.spawn(move || {
+				let mut run = part.map(|(key, value)| Ok::<_, Error>((rkyv::to_bytes(&key)?.to_vec(), F::frame(rkyv::to_bytes(&value)?).as_mut().to_vec())))
+					.collect::<Result<Vec<_>, _>>()?;
+				run.sort_by(order);
+				Ok::<_, Error>(run)
+			})).collect::<Vec<_>>();
+			workers.into_iter().map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))).collect::<Result<Vec<_>, _>>()
+		})?;
+		let mut positions = vec![0; runs.len()];
+		let mut last_key = None::<Vec<u8>>;
+		let mut imported = 0;
+		// the smallest head of the runs, by a linear scan since there's a run per part
+		while let Some(i) = (0..runs.len()).filter(|&i| positions[i] < runs[i].len()).min_by(|&a, &b| order(&runs[a][positions[a]], &runs[b][positions[b]])) {
+			// taken out so their memory is freed as they're written
+			let (mut key, mut value) = std::mem::take(&mut runs[i][positions[i]]);
+			positions[i] += 1;
+			// MDB_APPEND fails with KeyExists for a key that's already there, more values of it need MDB_APPENDDUP alone
+			let flags = if dup_sort && last_key.as_ref() == Some(&key) { lmdb::PutFlags::AppendDup } else { lmdb::PutFlags::Append };
+			lmdb::put(tx, dbi, &mut key, &mut value, flags.into()).context(tx, dbi, "bulk_import_partitioned", Some(&key))?;
+			if dup_sort { last_key = Some(key); }
+			imported += 1;
+		}
+		log::info!("imported {imported} entries from {} parts", runs.len());
+		imported
+	}
+}
+
+// RwTxn and Plain framing only
+impl<'tx, K, V> AssocTable<'tx, RwTxn<'tx>, K, V, Plain> where
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+{
+	/// The stored value, for updating small fields (counters, flags) in place without a deserialize-reserialize-put cycle.
+	/// It's moved onto a dirty page with MDB_RESERVE first, so readers never see the write and
+	/// [`EnvBuilder::write_map`](crate::env::EnvBuilder::write_map) only makes it cheaper.
+	///
+	/// # Safety
+	/// The seal points into lmdb's dirty page, which the next write in the transaction can move, reuse or free. It has to
+	/// be dropped before anything else is written in the transaction, including another `get_mut`, and no reference from
+	/// an earlier read of the same entry can be used while it's alive.
+	#[throws]
+	pub unsafe fn get_mut(&self, key: &K) -> Option<rkyv::seal::Seal<'tx, rkyv::Archived<V>>> {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "get_mut", Some(&key_bytes))? else { return None; };
+		rkyv::access::<rkyv::Archived<V>, rkyv::rancor::Error>(value_bytes).context(self.tx, self.dbi, "get_mut", Some(&key_bytes))?;
+		let value_bytes = value_bytes.to_vec();
+		let reserved = lmdb::put_reserve(self.tx, self.dbi, &mut key_bytes, value_bytes.len()).context(self.tx, self.dbi, "get_mut", Some(&key_bytes))?;
+		reserved.copy_from_slice(&value_bytes);
+		// checked above
+		Some(unsafe { rkyv::access_unchecked_mut::<rkyv::Archived<V>>(reserved) })
 	}
+}
 
+impl<'tx, K> AssocTable<'tx, RwTxn<'tx>, K, Vec<u8>, Plain> where
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+{
+	/// Put a `len` byte value that's streamed into its place in the map, e.g. by a compressor, instead of being built in memory first.
+	///
+	/// # Safety
+	/// The writer points into lmdb's dirty page, which the next write in the transaction can move, reuse or free. It has
+	/// to be dropped before anything else is written in the transaction.
 	#[throws]
-	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi)?; }
+	pub unsafe fn put_writer(&self, key: &K, len: usize) -> lmdb::ValueWriter<'tx> {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let root_len = std::mem::size_of::<rkyv::vec::ArchivedVec<u8>>();
+		let reserved = lmdb::put_reserve(self.tx, self.dbi, &mut key_bytes, len + root_len).context(self.tx, self.dbi, "put_writer", Some(&key_bytes))?;
+		// the archive is the bytes followed by the vec pointing back at them, which only depends on the length
+		let (bytes, root) = reserved.split_at_mut(len);
+		root.fill(0);
+		// unaligned archives, so any address is aligned, and the bytes were just initialized
+		let out = unsafe { rkyv::Place::new_unchecked(len, root.as_mut_ptr().cast::<rkyv::vec::ArchivedVec<u8>>()) };
+		rkyv::vec::ArchivedVec::<u8>::resolve_from_len(len, rkyv::vec::VecResolver::from_pos(0), out);
+		lmdb::ValueWriter::new(bytes)
+	}
 }
 
 // both RoTxn and RwTxn, so all methods are read-only
-impl<'tx, 'env: 'tx, TX, K, V> AssocTable<'tx, TX, K, V> where
+impl<'tx, 'env: 'tx, TX, K, V, F> AssocTable<'tx, TX, K, V, F> where
 	TX: Transaction<'env>,
+	F: Framing,
 	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
 	V: rkyv::Archive,
 	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe> + 'tx,
 {
 	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self {
-		Self { tx, dbi, _pd: PhantomData }
+		Self { tx, dbi, _pd: PhantomData, _borrow: crate::borrows::Borrow::of(tx) }
 	}
 
 	#[throws]
 	pub fn get(&self, key: &K) -> Option<&'tx rkyv::Archived<V>> {
 		let mut key_bytes = rkyv::to_bytes(key)?;
-		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut key_bytes)? else { return None; };
-		Some(rkyv::access::<rkyv::Archived<V>, _>(value_bytes)?)
+		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "get", Some(&key_bytes))? else { return None; };
+		Some(access::<V, F>(value_bytes).context(self.tx, self.dbi, "get", Some(&key_bytes))?)
+	}
+
+	/// A reader over a byte value (`Vec<u8>`, `Box<[u8]>`...) reading straight from the map, so big values can be
+	/// streamed into a socket or decoder without copying them whole first. It's validated once like with `get`.
+	#[throws]
+	pub fn open_reader(&self, key: &K) -> Option<std::io::Cursor<&'tx [u8]>> where
+		rkyv::Archived<V>: std::ops::Deref<Target = [u8]>,
+	{
+		self.get(key)?.map(|value| std::io::Cursor::new(&**value))
+	}
+
+	/// whether `key` has a value, which isn't read or validated
+	#[throws]
+	pub fn contains_key(&self, key: &K) -> bool {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "contains_key", Some(&key_bytes))?.is_some()
+	}
+
+	/// Look up many keys with one cursor, walking them in key order, results are in the order of `keys`.
+	#[throws]
+	pub fn get_many<'k>(&self, keys: impl IntoIterator<Item = &'k K>) -> Vec<Option<&'tx rkyv::Archived<V>>> where
+		K: 'k,
+	{
+		let mut keys = keys.into_iter().enumerate().map(|(i, key)| Ok::<_, rkyv::rancor::Error>((i, rkyv::to_bytes(key)?))).collect::<Result<Vec<_>, _>>()?;
+		// lmdb's default key order, with IntegerKey or ReverseKey the cursor only has to jump around more
+		keys.sort_unstable_by(|(_, a), (_, b)| a.as_slice().cmp(b.as_slice()));
+		let mut values = vec![None; keys.len()];
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "get_many", None)?;
+		for (i, mut key_bytes) in keys {
+			let Some((found, value_bytes)) = cursor.get_with_key(&mut key_bytes, lmdb::CursorOp::SetRange)? else { continue };
+			if found != key_bytes.as_slice() { continue; }
+			values[i] = Some(access::<V, F>(value_bytes).context(self.tx, self.dbi, "get_many", Some(&key_bytes))?);
+		}
+		values
+	}
+
+	/// Like [`AssocTable::get`] but without bytecheck validation, for hot paths over trusted data.
+	/// Framing is still undone, so a `Crc32` checksum is still checked.
+	///
+	/// # Safety
+	/// The stored value has to be a valid archive of `V` - written by this table with the same `V` and framing, and not corrupted since.
+	#[throws]
+	pub unsafe fn get_unchecked(&self, key: &K) -> Option<&'tx rkyv::Archived<V>> {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "get", Some(&key_bytes))? else { return None; };
+		let value_bytes = F::unframe(value_bytes).context(self.tx, self.dbi, "get", Some(&key_bytes))?;
+		Some(unsafe { rkyv::access_unchecked::<rkyv::Archived<V>>(value_bytes) })
 	}
 
 	#[throws]
@@ -112,49 +475,428 @@ impl<'tx, 'env: 'tx, TX, K, V> AssocTable<'tx, TX, K, V> where
 
 	#[throws]
 	pub fn last(&self) -> Option<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> {
-		let Some((key_bytes, value_bytes)) = lmdb::Cursor::open(self.tx, self.dbi)?.get(lmdb::CursorOpFlags::Last) else { return None; };
-		Some((
-			rkyv::access::<rkyv::Archived<K>, _>(key_bytes)?,
-			rkyv::access::<rkyv::Archived<V>, _>(value_bytes)?,
-		))
+		let Some(entry) = lmdb::Cursor::open(self.tx, self.dbi)?.get(lmdb::CursorOp::Last)? else { return None; };
+		Some(self.entry(entry, "last")?)
+	}
+
+	/// the first entry with a key at or after `key`
+	#[throws]
+	pub fn get_ceiling(&self, key: &K) -> Option<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "get_ceiling", Some(&key_bytes))?;
+		let Some(entry) = cursor.get_with_key(&mut key_bytes, lmdb::CursorOp::SetRange)? else { return None; };
+		Some(self.entry(entry, "get_ceiling")?)
+	}
+
+	/// the last entry with a key at or before `key`
+	#[throws]
+	pub fn get_floor(&self, key: &K) -> Option<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "get_floor", Some(&key_bytes))?;
+		let entry = match cursor.get_with_key(&mut key_bytes, lmdb::CursorOp::SetRange)? {
+			Some(entry) if entry.0 == key_bytes.as_slice() => Some(entry),
+			// landed after key
+			Some(_) => cursor.get(lmdb::CursorOp::Prev)?,
+			// every key is before key
+			None => cursor.get(lmdb::CursorOp::Last)?,
+		};
+		let Some(entry) = entry else { return None; };
+		Some(self.entry(entry, "get_floor")?)
+	}
+
+	/// `n` keys picked by seeking to random key bytes from `rng`, possibly with repeats.
+	/// Only close to uniform when keys are spread evenly over their bytes, e.g. hashes or random ids.
+	#[throws]
+	pub fn sample_keys(&self, n: usize, mut rng: impl FnMut() -> u64) -> Vec<&'tx rkyv::Archived<K>> {
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "sample_keys", None)?;
+		// random keys as long as a real one, which is required with IntegerKey
+		let Some((first, _)) = cursor.get(lmdb::CursorOp::First)? else { return Vec::new(); };
+		let mut random = vec![0; first.len()];
+		let mut keys = Vec::with_capacity(n);
+		for _ in 0..n {
+			for chunk in random.chunks_mut(8) { chunk.copy_from_slice(&rng().to_ne_bytes()[..chunk.len()]); }
+			// past the last key wraps around
+			let sampled = match cursor.get_with_key(&mut random, lmdb::CursorOp::SetRange)? {
+				Some(entry) => Some(entry),
+				None => cursor.get(lmdb::CursorOp::First)?,
+			};
+			let Some((key_bytes, _)) = sampled else { break };
+			keys.push(rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key_bytes).context(self.tx, self.dbi, "sample_keys", Some(key_bytes))?);
+		}
+		keys
+	}
+
+	/// [`Table::warmup`] for just the entries with keys in `range`
+	#[throws]
+	pub fn warmup_range(&self, range: impl std::ops::RangeBounds<K>) -> usize {
+		let range = KeyRange::new(&range)?;
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "warmup_range", None)?;
+		let mut entry = range.seek(&mut cursor)?;
+		let mut bytes = 0;
+		while let Some((key, value)) = entry && range.before_end(self.tx, self.dbi, key) {
+			bytes += lmdb::touch(key) + lmdb::touch(value);
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+		bytes
+	}
+
+	/// Roughly how many entries have keys in `range`, counted exactly when there are at most 1000 of them.
+	/// Bigger ranges are interpolated over the first bytes where the smallest and largest keys differ,
+	/// so it's only close for keys spread evenly over those bytes.
+	#[throws]
+	pub fn estimate_range_count(&self, range: impl std::ops::RangeBounds<K>) -> usize {
+		let range = KeyRange::new(&range)?;
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "estimate_range_count", None)?;
+		let mut entry = range.seek(&mut cursor)?;
+		let mut count = 0;
+		while let Some((key, _)) = entry && range.before_end(self.tx, self.dbi, key) {
+			count += 1;
+			if count > RANGE_PROBE { break; }
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+		if count <= RANGE_PROBE { return count; }
+
+		let entries = self.entries()?;
+		let (Some((min, _)), Some((max, _))) = (cursor.get(lmdb::CursorOp::First)?, cursor.get(lmdb::CursorOp::Last)?) else { return count; };
+		let prefix = &min[..min.iter().zip(max).take_while(|(a, b)| a == b).count()];
+		// position in the key space as the 8 bytes after the common prefix
+		let position = |key: &[u8]| match key[..prefix.len().min(key.len())].cmp(prefix) {
+			std::cmp::Ordering::Less => 0.,
+			std::cmp::Ordering::Greater => u64::MAX as f64,
+			std::cmp::Ordering::Equal => {
+				let key = &key[prefix.len()..];
+				let mut x = [0; 8];
+				let n = key.len().min(8);
+				x[..n].copy_from_slice(&key[..n]);
+				u64::from_be_bytes(x) as f64
+			},
+		};
+		let (min, max) = (position(min), position(max));
+		let low = match &range.start { Bound::Included(x) | Bound::Excluded(x) => position(x).max(min), Bound::Unbounded => min };
+		let high = match &range.end { Bound::Included(x) | Bound::Excluded(x) => position(x).min(max), Bound::Unbounded => max };
+		if max <= min { return entries; }
+		((entries as f64 * (high - low) / (max - min)) as usize).clamp(count, entries)
+	}
+
+	/// Fold the entries with keys in `range` in key order, straight from the archives without collecting or deserializing.
+	#[throws]
+	pub fn fold_range<A>(&self, range: impl std::ops::RangeBounds<K>, init: A, mut f: impl FnMut(A, &'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>) -> A) -> A where
+		rkyv::Archived<K>: 'tx,
+	{
+		let range = KeyRange::new(&range)?;
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "fold_range", None)?;
+		let mut entry = range.seek(&mut cursor)?;
+		let mut acc = init;
+		while let Some((key_bytes, value_bytes)) = entry && range.before_end(self.tx, self.dbi, key_bytes) {
+			let key = rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key_bytes).context(self.tx, self.dbi, "fold_range", Some(key_bytes))?;
+			acc = f(acc, key, access::<V, F>(value_bytes).context(self.tx, self.dbi, "fold_range", Some(key_bytes))?);
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+		acc
+	}
+
+	/// how many entries have keys in `range`, walking all of them - see [`AssocTable::estimate_range_count`] for big ranges
+	#[throws]
+	pub fn count_range(&self, range: impl std::ops::RangeBounds<K>) -> usize {
+		let range = KeyRange::new(&range)?;
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "count_range", None)?;
+		let mut entry = range.seek(&mut cursor)?;
+		let mut count = 0;
+		while let Some((key, _)) = entry && range.before_end(self.tx, self.dbi, key) {
+			count += 1;
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+		count
+	}
+
+	/// the sum of `f` over the entries with keys in `range`
+	#[throws]
+	pub fn sum_by<S: std::ops::Add<Output = S> + Default>(&self, range: impl std::ops::RangeBounds<K>, mut f: impl FnMut(&rkyv::Archived<K>, &rkyv::Archived<V>) -> S) -> S where
+		rkyv::Archived<K>: 'tx,
+	{
+		self.fold_range(range, S::default(), |sum, key, value| sum + f(key, value))?
+	}
+
+	/// the entry with keys in `range` for which `f` is smallest, the first of them on ties
+	#[throws]
+	pub fn min_by_key<O: Ord>(&self, range: impl std::ops::RangeBounds<K>, mut f: impl FnMut(&rkyv::Archived<K>, &rkyv::Archived<V>) -> O) -> Option<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> where
+		rkyv::Archived<K>: 'tx,
+	{
+		self.fold_range(range, None, |min: Option<(O, _, _)>, key, value| {
+			let by = f(key, value);
+			match min {
+				Some(min) if min.0 <= by => Some(min),
+				_ => Some((by, key, value)),
+			}
+		})?.map(|(_, key, value)| (key, value))
+	}
+
+	/// the entry with keys in `range` for which `f` is largest, the last of them on ties
+	#[throws]
+	pub fn max_by_key<O: Ord>(&self, range: impl std::ops::RangeBounds<K>, mut f: impl FnMut(&rkyv::Archived<K>, &rkyv::Archived<V>) -> O) -> Option<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> where
+		rkyv::Archived<K>: 'tx,
+	{
+		self.fold_range(range, None, |max: Option<(O, _, _)>, key, value| {
+			let by = f(key, value);
+			match max {
+				Some(max) if max.0 > by => Some(max),
+				_ => Some((by, key, value)),
+			}
+		})?.map(|(_, key, value)| (key, value))
+	}
+
+	/// Write a `key => value` line with the archived types' `Debug` for each entry with a key in `range`, up to `limit` of them,
+	/// to look at a table while developing. Entries that don't validate are written in hex. Returns the number of lines.
+	#[throws]
+	pub fn dump_debug(&self, out: &mut impl std::io::Write, range: impl std::ops::RangeBounds<K>, limit: Option<usize>) -> usize where
+		rkyv::Archived<K>: std::fmt::Debug,
+		rkyv::Archived<V>: std::fmt::Debug,
+	{
+		let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{b:02x}")).collect::<String>();
+		let range = KeyRange::new(&range)?;
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "dump_debug", None)?;
+		let mut entry = range.seek(&mut cursor)?;
+		let mut written = 0;
+		while let Some((key, value)) = entry && range.before_end(self.tx, self.dbi, key) && limit.is_none_or(|limit| written < limit) {
+			match (rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key), access::<V, F>(value)) {
+				(Ok(key), Ok(value)) => writeln!(out, "{key:?} => {value:?}")?,
+				_ => writeln!(out, "{} => {} (invalid)", hex(key), hex(value))?,
+			}
+			written += 1;
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+		written
+	}
+
+	#[throws]
+	fn entry(&self, (key_bytes, value_bytes): (&'tx [u8], &'tx [u8]), name: &'static str) -> (&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>) {
+		(
+			rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key_bytes).context(self.tx, self.dbi, name, Some(key_bytes))?,
+			access::<V, F>(value_bytes).context(self.tx, self.dbi, name, Some(key_bytes))?,
+		)
+	}
+
+	/// the smallest key, its value isn't read
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn min_key(&self) -> Option<&'tx rkyv::Archived<K>> { self.edge_key(lmdb::CursorOp::First, "min_key")? }
+
+	/// the largest key, its value isn't read
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn max_key(&self) -> Option<&'tx rkyv::Archived<K>> { self.edge_key(lmdb::CursorOp::Last, "max_key")? }
+
+	#[throws]
+	fn edge_key(&self, op: lmdb::CursorOp, name: &'static str) -> Option<&'tx rkyv::Archived<K>> {
+		let Some((key_bytes, _)) = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, name, None)?.get(op)? else { return None; };
+		Some(rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key_bytes).context(self.tx, self.dbi, name, Some(key_bytes))?)
 	}
 
 	#[expect(clippy::iter_not_returning_iterator)]
 	#[throws]
-	pub fn iter(&self) -> impl Iterator<Item = (&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> + use<'tx, 'env, TX, K, V> where
+	pub fn iter(&self) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V, F> where
 		rkyv::Archived<K>: 'tx,
 		rkyv::Archived<V>: 'tx,
 	{
-		Cursor::<TX, K, V>(lmdb::Cursor::open(self.tx, self.dbi)?, lmdb::CursorOpFlags::Next, PhantomData)
+		Cursor::<TX, K, V, F>(Some(lmdb::Cursor::open(self.tx, self.dbi)?), lmdb::CursorOp::Next, PhantomData)
 	}
 
+	/// Like [`AssocTable::iter`] but without bytecheck validation of keys or values.
+	///
+	/// # Safety
+	/// Same as [`AssocTable::get_unchecked`], for every entry in the table.
 	#[throws]
-	pub fn iter_from(&self, key: &K) -> impl Iterator<Item = (&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> + use<'tx, 'env, TX, K, V> where
+	pub unsafe fn iter_unchecked(&self) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V, F> where
+		rkyv::Archived<K>: 'tx,
+		rkyv::Archived<V>: 'tx,
+	{
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi)?;
+		let mut entries = lmdb::steps(move || cursor.get(lmdb::CursorOp::Next));
+		std::iter::from_fn(move || {
+			let (key_bytes, value_bytes) = match entries.next()? {
+				Ok(entry) => entry,
+				Err(e) => return Some(Err(e)),
+			};
+			let value_bytes = match F::unframe(value_bytes) {
+				Ok(x) => x,
+				Err(e) => { log::error!("Error unframing value in cursor: {e:?}"); return None; }
+			};
+			Some(Ok(unsafe { (rkyv::access_unchecked::<rkyv::Archived<K>>(key_bytes), rkyv::access_unchecked::<rkyv::Archived<V>>(value_bytes)) }))
+		})
+	}
+
+	#[throws]
+	pub fn iter_from(&self, key: &K) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V, F> where
 		rkyv::Archived<K>: 'tx,
 		rkyv::Archived<V>: 'tx,
 	{
 		let mut key_bytes = rkyv::to_bytes(key)?;
 		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi)?;
-		archived_from_cursor_get::<'tx, K, V>(cursor.get_with_key(&mut key_bytes, lmdb::CursorOpFlags::SetRange)).into_iter()
-			.chain(Cursor::<TX, K, V>(cursor, lmdb::CursorOpFlags::Next, PhantomData))
+		let first = cursor.get_with_key(&mut key_bytes, lmdb::CursorOp::SetRange)?;
+		archived_from_cursor_get::<'tx, K, V, F>(Ok(first)).into_iter()
+			.chain(Cursor::<TX, K, V, F>(Some(cursor), lmdb::CursorOp::Next, PhantomData))
+	}
+
+	/// entries after `bookmark`, or all of them without one, see [`bookmark`](crate::bookmark)
+	#[throws]
+	pub fn iter_after(&self, bookmark: Option<&Bookmark>) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V, F> where
+		rkyv::Archived<K>: 'tx,
+		rkyv::Archived<V>: 'tx,
+	{
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "iter_after", None)?;
+		let first = match bookmark {
+			Some(bookmark) => bookmark.resume(self.tx, self.dbi, &mut cursor)?,
+			None => cursor.get(lmdb::CursorOp::First)?,
+		};
+		archived_from_cursor_get::<'tx, K, V, F>(Ok(first)).into_iter().chain(Cursor::<TX, K, V, F>(Some(cursor), lmdb::CursorOp::Next, PhantomData))
 	}
 
+	/// a bookmark of `key`, to resume after with [`AssocTable::iter_after`]
+	#[expect(unused_braces)]
 	#[throws]
-	pub fn iter_rev(&self) -> impl Iterator<Item = (&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> + use<'tx, 'env, TX, K, V> where
+	pub fn bookmark(&self, key: &K) -> Bookmark { Bookmark::key(&rkyv::to_bytes(key)?)? }
+
+	/// a bookmark of `value` under `key`, for `DbFlags::DupSort` tables
+	#[throws]
+	pub fn bookmark_dup(&self, key: &K, value: &V) -> Bookmark where
+		V: for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	{
+		Bookmark::entry(&rkyv::to_bytes(key)?, F::frame(rkyv::to_bytes(value)?).as_mut())?
+	}
+
+	/// Every value stored under `key` in order, for `DbFlags::DupSort` tables - other tables have at most one.
+	#[throws]
+	pub fn values_for(&self, key: &K) -> impl Iterator<Item = Result<&'tx rkyv::Archived<V>, Error>> + use<'tx, 'env, TX, K, V, F> where
 		rkyv::Archived<K>: 'tx,
 		rkyv::Archived<V>: 'tx,
 	{
-		Cursor::<TX, K, V>(lmdb::Cursor::open(self.tx, self.dbi)?, lmdb::CursorOpFlags::Prev, PhantomData)
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "values_for", Some(&key_bytes))?;
+		let first = archived_from_cursor_get::<'tx, K, V, F>(Ok(cursor.get_with_key(&mut key_bytes, lmdb::CursorOp::SetKey)?));
+		// NextDup stops at the next key, and only dupsort dbs have it
+		let rest = first.is_some().then(|| cursor.into_dup().ok()).flatten().map(|cursor| DupValues::<TX, K, V, F>(Some(cursor), PhantomData));
+		first.into_iter().chain(rest.into_iter().flatten()).map(|entry| entry.map(|(_, value)| value))
 	}
 
+	/// The values stored under `key` from the first one at or after `value` on, for `DbFlags::DupSort` tables.
+	/// Values are compared as stored, so with framing other than `Plain` the order isn't the archives' order.
 	#[throws]
-	pub fn iter_rev_from(&self, key: &K) -> impl Iterator<Item = (&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>)> + use<'tx, 'env, TX, K, V> where
+	pub fn seek_dup(&self, key: &K, value: &V) -> impl Iterator<Item = Result<&'tx rkyv::Archived<V>, Error>> + use<'tx, 'env, TX, K, V, F> where
+		V: for <'a> rkyv::Serialize<RkyvSer<'a>>,
+		rkyv::Archived<K>: 'tx,
+		rkyv::Archived<V>: 'tx,
+	{
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let mut value_bytes = F::frame(rkyv::to_bytes(value)?);
+		let cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "seek_dup", Some(&key_bytes))?;
+		let (first, rest) = match cursor.into_dup() {
+			Ok(mut cursor) => (cursor.get_with_key_value(&mut key_bytes, value_bytes.as_mut(), lmdb::DupCursorOp::GetBothRange)?, Some(cursor)),
+			// GetBothRange needs the dupsort comparator, the only value can be compared by hand
+			Err(mut cursor) => (cursor.get_with_key(&mut key_bytes, lmdb::CursorOp::SetKey)?.map(|(_, x)| x).filter(|x| **x >= *value_bytes.as_mut()), None),
+		};
+		let first = first.and_then(|x| match access::<V, F>(x) {
+			Ok(x) => Some(x),
+			Err(e) => { log::error!("Error deserializing value in cursor: {e:?}"); None },
+		});
+		let rest = rest.filter(|_| first.is_some()).map(|cursor| DupValues::<TX, K, V, F>(Some(cursor), PhantomData));
+		first.map(Ok).into_iter().chain(rest.into_iter().flatten().map(|entry| entry.map(|(_, value)| value)))
+	}
+
+	#[throws]
+	pub fn iter_rev(&self) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V, F> where
+		rkyv::Archived<K>: 'tx,
+		rkyv::Archived<V>: 'tx,
+	{
+		Cursor::<TX, K, V, F>(Some(lmdb::Cursor::open(self.tx, self.dbi)?), lmdb::CursorOp::Prev, PhantomData)
+	}
+
+	#[throws]
+	pub fn iter_rev_from(&self, key: &K) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V, F> where
 		rkyv::Archived<K>: 'tx,
 		rkyv::Archived<V>: 'tx,
 	{
 		let mut key_bytes = rkyv::to_bytes(key)?;
 		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi)?;
-		let _ = cursor.get_with_key(&mut key_bytes, lmdb::CursorOpFlags::SetRange);
-		Cursor::<TX, K, V>(cursor, lmdb::CursorOpFlags::Prev, PhantomData)
+		cursor.get_with_key(&mut key_bytes, lmdb::CursorOp::SetRange)?;
+		Cursor::<TX, K, V, F>(Some(cursor), lmdb::CursorOp::Prev, PhantomData)
+	}
+}
+
+// RoTxn only, since only read cursors outlive their transaction
+impl<'tx, 'env: 'tx, K, V, F> AssocTable<'tx, RoTxn<'env>, K, V, F> where
+	F: Framing,
+	K: rkyv::Archive,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	/// Like [`AssocTable::iter`], but renews the cursor an earlier scan of this table left in `idle` instead of opening one,
+	/// and leaves its own there once dropped - saves allocating and freeing a cursor per scan in hot read loops.
+	/// ```ignore
+	/// let mut idle = None;
+	/// loop {
+	///     let tx = ENV.read_tx()?;
+	///     let active = tx.get::<Sessions>().iter_reusing(&mut idle)?.filter(|entry| entry.as_ref().is_ok_and(|(_, session)| session.active)).count();
+	///     ...
+	/// }
+	/// ```
+	#[throws]
+	pub fn iter_reusing<'c>(&self, idle: &'c mut Option<lmdb::IdleCursor<'env>>) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, 'c, K, V, F> {
+		ReusingCursor::<K, V, F> { cursor: Some(lmdb::Cursor::renew_or_open(self.tx, self.dbi, idle)?), idle, _pd: PhantomData }
+	}
+
+	/// Scan the whole table on `shards` threads, each with its own read transaction over a slice of the key space, returning
+	/// what `scan` gives for each slice in key order - for recomputations over big tables. Slices are split at sampled keys
+	/// like [`AssocTable::sample_keys`]'s, so they're only about even for keys spread evenly over their bytes. The workers'
+	/// transactions begin after this one, with writes committing meanwhile they see a later snapshot than it.
+	#[throws]
+	pub fn par_scan<R: Send>(&self, shards: usize, scan: impl Fn(&mut dyn Iterator<Item = Result<(&rkyv::Archived<K>, &rkyv::Archived<V>), Error>>) -> R + Sync) -> Vec<R> {
+		let splits = self.split_keys(shards)?;
+		let starts = std::iter::once(Bound::Unbounded).chain(splits.iter().cloned().map(Bound::Included));
+		let ends = splits.iter().cloned().map(Bound::Excluded).chain(std::iter::once(Bound::Unbounded));
+		let ranges = starts.zip(ends).map(|(start, end)| KeyRange { start, end }).collect::<Vec<_>>();
+		let (env, dbi, scan) = (self.tx.env(), self.dbi, &scan);
+		std::thread::scope(|scope| {
+			let workers = ranges.into_iter().map(|range| scope.spawn(move || {
+				let tx = env.read_tx()?;
+				let mut cursor = lmdb::Cursor::open(&tx, dbi).context(&tx, dbi, "par_scan", None)?;
+				let mut entry = Some(range.seek(&mut cursor));
+				let mut entries = std::iter::from_fn(|| {
+					let current = archived_from_cursor_get::<K, V, F>(entry.take()?.map(|entry| entry.filter(|(key, _)| range.before_end(&tx, dbi, key))));
+					// nothing after the end or an error
+					if matches!(current, Some(Ok(_))) { entry = Some(cursor.get(lmdb::CursorOp::Next)); }
+					current
+				});
+				Ok::<_, Error>(scan(&mut entries))
+			})).collect::<Vec<_>>();
+			workers.into_iter().map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))).collect::<Result<Vec<_>, _>>()
+		})?
+	}
+
+	// up to shards - 1 distinct keys splitting the table into that many slices, in the db's order
+	#[throws]
+	fn split_keys(&self, shards: usize) -> Vec<Vec<u8>> {
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "par_scan", None)?;
+		let Some((first, _)) = cursor.get(lmdb::CursorOp::First)? else { return Vec::new(); };
+		if shards <= 1 { return Vec::new(); }
+		// seeks to random key bytes like sample_keys, xorshift is plenty for picking splits
+		let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+		let mut random = vec![0; first.len()];
+		let mut samples = Vec::with_capacity(shards * SPLIT_SAMPLES);
+		for _ in 0..shards * SPLIT_SAMPLES {
+			for chunk in random.chunks_mut(8) {
+				state ^= state << 13;
+				state ^= state >> 7;
+				state ^= state << 17;
+				chunk.copy_from_slice(&state.to_ne_bytes()[..chunk.len()]);
+			}
+			if let Some((key, _)) = cursor.get_with_key(&mut random, lmdb::CursorOp::SetRange)? { samples.push(key); }
+		}
+		samples.sort_by(|a, b| lmdb::cmp(self.tx, self.dbi, a, b));
+		samples.dedup();
+		if samples.is_empty() { return Vec::new(); }
+		let mut splits = (1..shards).map(|i| samples[i * samples.len() / shards]).filter(|&key| key != first).collect::<Vec<_>>();
+		splits.dedup();
+		splits.into_iter().map(<[u8]>::to_vec).collect()
 	}
 }
@@ -0,0 +1,123 @@
+//! Get, put, delete, clear and walking a db in key order, on byte strings, as traits, so code that only needs those
+//! runs on any store implementing them:
+//! ```ignore
+//! fn rename_user<B: Backend>(backend: &B, id: &[u8], name: &[u8]) -> Result<(), batadase::Error> {
+//!     let mut tx = backend.begin_write()?;
+//!     if tx.fetch(Users::NAME, id)?.is_none() { return Ok(()) }
+//!     tx.put(Users::NAME, id, name)?;
+//!     tx.finish()
+//! }
+//!
+//! rename_user(&*ENV, b"42", b"ferris")?; // LMDB
+//! ```
+//! [`Env`] is the default backend, with its [`RoTxn`] and [`RwTxn`] as the transactions, so the same writes go through
+//! the env's checks, quotas and changelog like a [`RawTable`](crate::RawTable)'s. Dbs are named by their
+//! [`DbName::NAME`](crate::DbName::NAME), nul included, and fail with [`Error::UnknownDb`] if an env doesn't have them,
+//! [`Error::UnregisteredDb`] for other backends. Every db holds one value per key, `DbFlags::DupSort` dbs of an env
+//! fail with `lmdb::Error::Incompatible`.
+//!
+//! That's all the traits offer: [`Transaction`] and the tables don't go through them and stay LMDB-only - they hand
+//! out `&'tx` references into the map, which the traits don't promise, values come back as [`Cow`]s borrowed where
+//! the backend can - and neither do dupsort dbs, cursors, nested transactions or the env's other features.
+
+use std::borrow::Cow;
+use culpa::{throw, throws};
+use enumflags2::BitFlag;
+use crate::{Env, RoTxn, RwTxn, Transaction, Error, lmdb, error::Context};
+
+/// the entries [`ReadTxn::iter_from`] walks
+pub type Entries<'tx> = Box<dyn Iterator<Item = Result<(Cow<'tx, [u8]>, Cow<'tx, [u8]>), Error>> + 'tx>;
+
+pub trait Backend {
+	type Read<'b>: ReadTxn where Self: 'b;
+	type Write<'b>: WriteTxn where Self: 'b;
+
+	/// a read transaction of the last commit, which it keeps seeing until it's dropped
+	fn begin_read(&self) -> Result<Self::Read<'_>, Error>;
+	/// the only write transaction, blocks while another one is open
+	fn begin_write(&self) -> Result<Self::Write<'_>, Error>;
+}
+
+pub trait ReadTxn {
+	fn fetch(&self, db: &[u8], key: &[u8]) -> Result<Option<Cow<'_, [u8]>>, Error>;
+	/// entries from the first key at or after `from` in key order, every entry for an empty `from`
+	fn iter_from(&self, db: &[u8], from: &[u8]) -> Result<Entries<'_>, Error>;
+}
+
+/// Writes are seen by the transaction's own reads right away and by others once it's finished,
+/// dropping it without finishing discards them.
+pub trait WriteTxn: ReadTxn {
+	fn put(&mut self, db: &[u8], key: &[u8], value: &[u8]) -> Result<(), Error>;
+	/// whether there was an entry to delete
+	fn delete(&mut self, db: &[u8], key: &[u8]) -> Result<bool, Error>;
+	/// delete every entry of the db
+	fn clear(&mut self, db: &[u8]) -> Result<(), Error>;
+	/// commit the writes
+	fn finish(self) -> Result<(), Error>;
+}
+
+impl Backend for Env {
+	type Read<'b> = RoTxn<'b>;
+	type Write<'b> = RwTxn<'b>;
+
+	fn begin_read(&self) -> Result<RoTxn<'_>, Error> { self.read_tx() }
+	// like a pipeline's, it doesn't wait for Env::write's semaphore
+	fn begin_write(&self) -> Result<RwTxn<'_>, Error> { self.write_tx() }
+}
+
+impl ReadTxn for RoTxn<'_> {
+	fn fetch(&self, db: &[u8], key: &[u8]) -> Result<Option<Cow<'_, [u8]>>, Error> { fetch(self, db, key) }
+	fn iter_from(&self, db: &[u8], from: &[u8]) -> Result<Entries<'_>, Error> { iter_from(self, db, from) }
+}
+
+impl ReadTxn for RwTxn<'_> {
+	fn fetch(&self, db: &[u8], key: &[u8]) -> Result<Option<Cow<'_, [u8]>>, Error> { fetch(self, db, key) }
+	fn iter_from(&self, db: &[u8], from: &[u8]) -> Result<Entries<'_>, Error> { iter_from(self, db, from) }
+}
+
+impl WriteTxn for RwTxn<'_> {
+	#[throws]
+	fn put(&mut self, db: &[u8], key: &[u8], value: &[u8]) {
+		let dbi = dbi(self, db)?;
+		lmdb::put(self, dbi, &mut key.to_vec(), &mut value.to_vec(), lmdb::PutFlags::empty()).context(self, dbi, "put", Some(key))?;
+	}
+
+	#[throws]
+	fn delete(&mut self, db: &[u8], key: &[u8]) -> bool {
+		let dbi = dbi(self, db)?;
+		lmdb::del(self, dbi, &mut key.to_vec()).context(self, dbi, "delete", Some(key))?
+	}
+
+	#[throws]
+	fn clear(&mut self, db: &[u8]) {
+		let dbi = dbi(self, db)?;
+		lmdb::drop(self, dbi).context(self, dbi, "clear", None)?;
+	}
+
+	fn finish(self) -> Result<(), Error> { Transaction::commit(self) }
+}
+
+// the db's handle, if it's one value per key
+#[throws]
+fn dbi<'env>(tx: &impl Transaction<'env>, db: &[u8]) -> lmdb_sys::MDB_dbi {
+	let Some(dbi) = tx.env().db(db) else { throw!(crate::dump::unknown_db(db)) };
+	if lmdb::dbi_flags(tx.raw(), dbi)?.contains(lmdb::DbFlags::DupSort) { throw!(lmdb::Error::Incompatible) }
+	dbi
+}
+
+#[throws]
+fn fetch<'tx, 'env: 'tx>(tx: &'tx impl Transaction<'env>, db: &[u8], key: &[u8]) -> Option<Cow<'tx, [u8]>> {
+	let dbi = dbi(tx, db)?;
+	lmdb::get(tx, dbi, &mut key.to_vec()).context(tx, dbi, "get", Some(key))?.map(Cow::Borrowed)
+}
+
+#[throws]
+fn iter_from<'tx, 'env: 'tx>(tx: &'tx impl Transaction<'env>, db: &[u8], from: &[u8]) -> Entries<'tx> {
+	let dbi = dbi(tx, db)?;
+	let mut cursor = lmdb::Cursor::open(tx, dbi).context(tx, dbi, "iter_from", None)?;
+	let first = if from.is_empty() { cursor.get(lmdb::CursorOp::First) } else { cursor.get_with_key(&mut from.to_vec(), lmdb::CursorOp::SetRange) };
+	// a cursor that found nothing isn't positioned, Next would start over from the first entry
+	let Some(first) = first.context(tx, dbi, "iter_from", Some(from))? else { return Box::new(std::iter::empty()) };
+	let entries = std::iter::once(Ok(first)).chain(lmdb::steps(move || cursor.get(lmdb::CursorOp::Next)));
+	Box::new(entries.map(|entry| entry.map(|(key, value)| (Cow::Borrowed(key), Cow::Borrowed(value)))))
+}
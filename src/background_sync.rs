@@ -0,0 +1,104 @@
+//! Bounded durability lag for envs opened with [`Durability::NoSync`](crate::env::Durability::NoSync) or `Async`:
+//! a thread syncs the env every `interval`, or sooner once `max_bytes` were committed since the last sync.
+
+use culpa::throws;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use crate::{Env, Error};
+
+// how often the thread checks Env::unsynced_bytes between syncs
+const POLL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+	pub interval: Duration,
+	pub max_bytes: usize,
+}
+
+impl Default for Policy {
+	fn default() -> Self { Self { interval: Duration::from_secs(1), max_bytes: 64 << 20 } }
+}
+
+/// sync latencies so far, from the thread and [`BackgroundSync::flush_now`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyncStats {
+	pub syncs: u64,
+	pub failures: u64,
+	pub total: Duration,
+	pub max: Duration,
+	pub last: Option<Duration>,
+	pub last_at: Option<Instant>,
+}
+
+/// The syncing thread, stopped with a final sync when this is dropped.
+pub struct BackgroundSync {
+	shared: Arc<Shared>,
+	thread: Option<std::thread::JoinHandle<()>>,
+}
+
+struct Shared {
+	env: &'static Env,
+	stop: Mutex<bool>,
+	wake: Condvar,
+	stats: Mutex<SyncStats>,
+}
+
+impl Shared {
+	#[throws]
+	fn sync(&self) {
+		let start = Instant::now();
+		let res = self.env.sync();
+		let took = start.elapsed();
+		let mut stats = self.stats.lock().unwrap();
+		if res.is_err() { stats.failures += 1; }
+		stats.syncs += 1;
+		stats.total += took;
+		stats.max = stats.max.max(took);
+		stats.last = Some(took);
+		stats.last_at = Some(Instant::now());
+		drop(stats);
+		res?;
+	}
+}
+
+impl BackgroundSync {
+	pub fn start(env: &'static Env, policy: Policy) -> Self {
+		let shared = Arc::new(Shared { env, stop: Mutex::new(false), wake: Condvar::new(), stats: Mutex::default() });
+		let thread = std::thread::Builder::new()
+			.name("batadase-sync".to_owned())
+			.spawn({ let shared = Arc::clone(&shared); move || syncer(&shared, policy) })
+			.expect("failed to spawn the background sync thread");
+		Self { shared, thread: Some(thread) }
+	}
+
+	/// Sync right away on the calling thread, e.g. before acknowledging something that must survive a crash.
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn flush_now(&self) { self.shared.sync()? }
+
+	pub fn stats(&self) -> SyncStats { *self.shared.stats.lock().unwrap() }
+}
+
+impl Drop for BackgroundSync {
+	fn drop(&mut self) {
+		*self.shared.stop.lock().unwrap() = true;
+		self.shared.wake.notify_one();
+		if let Some(thread) = self.thread.take() { let _ = thread.join(); }
+	}
+}
+
+fn syncer(shared: &Shared, policy: Policy) {
+	let mut last = Instant::now();
+	let mut stop = shared.stop.lock().unwrap();
+	loop {
+		stop = shared.wake.wait_timeout(stop, POLL.min(policy.interval)).unwrap().0;
+		let done = *stop;
+		if done || last.elapsed() >= policy.interval || shared.env.unsynced_bytes() >= policy.max_bytes {
+			drop(stop);
+			if let Err(e) = shared.sync() { log::error!("background sync failed: {e}"); }
+			last = Instant::now();
+			if done { return; }
+			stop = shared.stop.lock().unwrap();
+		}
+	}
+}
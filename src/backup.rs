@@ -0,0 +1,381 @@
+//! Single-file backups: a zstd compressed tar archive with a compacted copy of the env's data file and a manifest
+//! describing it (schema version, tables and their entry counts, checksum of the data file).
+//!
+//! ```ignore
+//! let manifest = batadase::backup::create(&ENV, std::fs::File::create("backup.tar.zst")?)?;
+//! // later, somewhere else
+//! let manifest = batadase::backup::restore(std::fs::File::open("backup.tar.zst")?, Path::new("restored"))?;
+//! ```
+//!
+//! With a [`changelog`](crate::changelog) the manifest records its sequence, and incremental backups export only the
+//! entries changed since one, as [`sync`](crate::sync) messages - applied in order over the restored full backup they
+//! bring it up to date:
+//! ```ignore
+//! let base = backup::create(&ENV, File::create("full.tar.zst")?)?;
+//! let increment = backup::create_incremental(&ENV, base.sequence.unwrap(), File::create("1.tar.zst")?)?;
+//! // restoring
+//! backup::restore(File::open("full.tar.zst")?, Path::new("restored"))?;
+//! backup::restore_incremental(File::open("1.tar.zst")?, Path::new("restored"))?;
+//! ```
+//! An increment carries the changelog entries it covers too, so the restored env's changelog keeps the source's
+//! sequences and the next increment can be checked to follow it. Tables created after the base backup aren't in the
+//! restored env, restoring an increment with entries of theirs fails with `Error::UnknownDb` - take a new full backup.
+
+use culpa::{throw, throws};
+use std::io::{Read, Write};
+use std::path::Path;
+use crate::{Env, Error, Transaction, RoTxn, DbName, Meta, lmdb, migrate, sync, changelog::{self, ChangeLog}};
+
+pub const FORMAT: u32 = 1;
+
+const MANIFEST: &str = "manifest";
+const DATA: &str = "data.mdb";
+const CHANGES: &str = "changes";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Manifest {
+	pub format: u32,
+	pub created: u64,                 // unix seconds
+	pub txn_id: usize,                // last committed transaction in the copy
+	pub schema_version: Option<u64>,  // None if there's no Meta table in the env
+	pub tables: Vec<(String, usize)>, // name and number of entries
+	pub sequence: Option<u64>,        // the changelog's last sequence in the copy, None without one
+	pub base: Option<u64>,            // for incremental backups the sequence they're changes since
+	pub data_size: u64,               // of the data file, or the changes of an incremental backup
+	pub data_crc32: u32,
+}
+
+impl Manifest {
+	fn to_text(&self) -> String {
+		let mut text = format!("format={}\ncreated={}\ntxn_id={}\n", self.format, self.created, self.txn_id);
+		if let Some(version) = self.schema_version { text += &format!("schema_version={version}\n"); }
+		if let Some(sequence) = self.sequence { text += &format!("sequence={sequence}\n"); }
+		if let Some(base) = self.base { text += &format!("base={base}\n"); }
+		text += &format!("data_size={}\ndata_crc32={:08x}\n", self.data_size, self.data_crc32);
+		for (name, entries) in &self.tables { text += &format!("table={entries} {name}\n"); }
+		text
+	}
+
+	#[throws]
+	fn parse(text: &str) -> Self {
+		fn num<T: std::str::FromStr>(value: &str) -> Result<T, Error> { value.parse().map_err(|_| Error::InvalidBackup("malformed manifest")) }
+
+		let mut manifest = Self { format: 0, created: 0, txn_id: 0, schema_version: None, tables: Vec::new(), sequence: None, base: None, data_size: 0, data_crc32: 0 };
+		for line in text.lines() {
+			let Some((key, value)) = line.split_once('=') else { throw!(Error::InvalidBackup("malformed manifest")) };
+			match key {
+				"format" => manifest.format = num(value)?,
+				"created" => manifest.created = num(value)?,
+				"txn_id" => manifest.txn_id = num(value)?,
+				"schema_version" => manifest.schema_version = Some(num(value)?),
+				"sequence" => manifest.sequence = Some(num(value)?),
+				"base" => manifest.base = Some(num(value)?),
+				"data_size" => manifest.data_size = num(value)?,
+				"data_crc32" => manifest.data_crc32 = u32::from_str_radix(value, 16).map_err(|_| Error::InvalidBackup("malformed manifest"))?,
+				"table" => {
+					let Some((entries, name)) = value.split_once(' ') else { throw!(Error::InvalidBackup("malformed manifest")) };
+					manifest.tables.push((name.to_owned(), num(entries)?));
+				},
+				_ => {}, // from a newer version, nothing we can do with it
+			}
+		}
+		if manifest.format == 0 || manifest.format > FORMAT { throw!(Error::InvalidBackup("unsupported format")); }
+		manifest
+	}
+}
+
+#[throws]
+fn crc32_of(path: &Path) -> (u64, u32) {
+	let mut file = std::fs::File::open(path)?;
+	let mut hasher = crc32fast::Hasher::new();
+	let mut buf = vec![0; 1 << 16];
+	let mut size = 0;
+	loop {
+		let n = file.read(&mut buf)?;
+		if n == 0 { break; }
+		hasher.update(&buf[..n]);
+		size += n as u64;
+	}
+	(size, hasher.finalize())
+}
+
+// the env as of `tx`, without the data's size and checksum
+#[throws]
+fn manifest_of(tx: &RoTxn, base: Option<u64>) -> Manifest {
+	let env = tx.env();
+	let tables = env.dbs().into_iter()
+		.map(|(name, dbi)| Ok((crate::db_name_lossy(name), tx.stat(dbi)?.entries)))
+		.collect::<Result<Vec<_>, Error>>()?;
+	let schema_version = if env.db(Meta::NAME).is_some() { Some(migrate::version(tx)?) } else { None };
+	let sequence = if env.changelog.is_some() { Some(ChangeLog::of(tx)?.last_sequence()?) } else { None };
+	let created = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |x| x.as_secs());
+	Manifest { format: FORMAT, created, txn_id: tx.id(), schema_version, tables, sequence, base, data_size: 0, data_crc32: 0 }
+}
+
+// the manifest is taken from the copy itself, so it matches what's in the archive even with writes going on
+#[throws]
+fn describe(dir: &Path) -> Manifest {
+	let copy = Env::builder()?.read_only().build_existing(&crate::path_to_cstring(dir)?)?;
+	let mut manifest = manifest_of(&copy.read_tx()?, None)?;
+	drop(copy);
+	(manifest.data_size, manifest.data_crc32) = crc32_of(&dir.join(DATA))?;
+	manifest
+}
+
+#[throws]
+fn append_manifest(archive: &mut tar::Builder<impl Write>, manifest: &Manifest) {
+	let text = manifest.to_text();
+	let mut header = tar::Header::new_gnu();
+	header.set_size(text.len() as u64);
+	header.set_mode(0o644);
+	header.set_mtime(manifest.created);
+	header.set_cksum();
+	archive.append_data(&mut header, MANIFEST, text.as_bytes())?;
+}
+
+/// Write a backup of `env` to `writer`.
+/// The compacted copy is first made in a temporary directory, so there has to be enough space for it there.
+#[throws]
+pub fn create(env: &Env, writer: impl Write) -> Manifest {
+	let dir = crate::TempDir::new("batadase-backup")?;
+	env.copy(&crate::path_to_cstring(&dir.0)?, true)?;
+	let manifest = describe(&dir.0)?;
+
+	let mut archive = tar::Builder::new(zstd::Encoder::new(writer, 0)?);
+	append_manifest(&mut archive, &manifest)?;
+	archive.append_path_with_name(dir.0.join(DATA), DATA)?;
+	archive.into_inner()?.finish()?.flush()?;
+	manifest
+}
+
+/// Write a backup of the entries of `env` changed since the changelog's sequence `since`, usually the last backup's
+/// [`Manifest::sequence`]. Error::UnknownDb if the env was built without
+/// [`EnvBuilder::changelog`](crate::env::EnvBuilder::changelog). If the changelog no longer goes back to `since`
+/// the increment has every entry, after clearing the tables.
+#[throws]
+pub fn create_incremental(env: &Env, since: u64, writer: impl Write) -> Manifest {
+	let tx = env.read_tx()?;
+	let subscriptions = env.dbs().into_iter()
+		.filter(|&(name, _)| name != changelog::NAME)
+		.map(|(name, _)| sync::Subscription { table: name.strip_suffix(b"\0").unwrap_or(name).to_vec(), prefix: Vec::new() })
+		.collect();
+	let mut changes = Vec::new();
+	sync::respond(&tx, &sync::Request { since, subscriptions }, &mut changes)?;
+	// the changelog's own entries, so the restored one keeps the same sequences
+	if let Some(log) = env.changelog {
+		let table = changelog::NAME.strip_suffix(b"\0").unwrap_or(changelog::NAME);
+		let mut cursor = lmdb::Cursor::open(&tx, log)?;
+		let mut entry = cursor.get_with_key(&mut (since + 1).to_be_bytes(), lmdb::CursorOp::SetRange)?;
+		while let Some((key, value)) = entry {
+			sync::write(&mut changes, &sync::Message::Put { table: table.to_vec(), key: key.to_vec(), value: value.to_vec() })?;
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+	}
+	let mut manifest = manifest_of(&tx, Some(since))?;
+	drop(tx);
+	(manifest.data_size, manifest.data_crc32) = (changes.len() as u64, crc32fast::hash(&changes));
+
+	let mut archive = tar::Builder::new(zstd::Encoder::new(writer, 0)?);
+	append_manifest(&mut archive, &manifest)?;
+	let mut header = tar::Header::new_gnu();
+	header.set_size(changes.len() as u64);
+	header.set_mode(0o644);
+	header.set_mtime(manifest.created);
+	header.set_cksum();
+	archive.append_data(&mut header, CHANGES, changes.as_slice())?;
+	archive.into_inner()?.finish()?.flush()?;
+	manifest
+}
+
+/// Restore a backup into `path`, which has to be empty or not exist yet.
+/// The data file is checked against the manifest's checksum and size, returns the manifest.
+#[throws]
+pub fn restore(reader: impl Read, path: &Path) -> Manifest {
+	std::fs::create_dir_all(path)?;
+	if std::fs::read_dir(path)?.next().is_some() {
+		throw!(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("{} isn't empty", path.display())));
+	}
+
+	let mut archive = tar::Archive::new(zstd::Decoder::new(reader)?);
+	let mut manifest = None;
+	let mut data = None;
+	for entry in archive.entries()? {
+		let mut entry = entry?;
+		let entry_path = entry.path()?.into_owned();
+		if entry_path == Path::new(MANIFEST) {
+			let mut text = String::new();
+			entry.read_to_string(&mut text)?;
+			manifest = Some(Manifest::parse(&text)?);
+		} else if entry_path == Path::new(DATA) {
+			let mut file = std::fs::File::create_new(path.join(DATA))?;
+			let mut hasher = crc32fast::Hasher::new();
+			let mut buf = vec![0; 1 << 16];
+			let mut size = 0;
+			loop {
+				let n = entry.read(&mut buf)?;
+				if n == 0 { break; }
+				hasher.update(&buf[..n]);
+				file.write_all(&buf[..n])?;
+				size += n as u64;
+			}
+			file.sync_all()?;
+			data = Some((size, hasher.finalize()));
+		} else if entry_path == Path::new(CHANGES) {
+			throw!(Error::InvalidBackup("an incremental backup, restore it with restore_incremental"));
+		} else {
+			throw!(Error::InvalidBackup("unexpected file in archive"));
+		}
+	}
+
+	let Some(manifest) = manifest else { throw!(Error::InvalidBackup("missing manifest")) };
+	let Some((size, crc)) = data else { throw!(Error::InvalidBackup("missing data file")) };
+	if size != manifest.data_size || crc != manifest.data_crc32 {
+		std::fs::remove_file(path.join(DATA))?;
+		throw!(Error::ChecksumMismatch);
+	}
+	manifest
+}
+
+/// Apply an incremental backup to the env restored in `path`, which mustn't be open. The increment has to follow what
+/// was restored there last - the base backup or the increment before - and its entry counts are checked once it's
+/// applied, all in one write transaction. Returns the manifest.
+#[throws]
+pub fn restore_incremental(reader: impl Read, path: &Path) -> Manifest {
+	let mut archive = tar::Archive::new(zstd::Decoder::new(reader)?);
+	let mut manifest = None;
+	let mut changes = None;
+	for entry in archive.entries()? {
+		let mut entry = entry?;
+		let entry_path = entry.path()?.into_owned();
+		if entry_path == Path::new(MANIFEST) {
+			let mut text = String::new();
+			entry.read_to_string(&mut text)?;
+			manifest = Some(Manifest::parse(&text)?);
+		} else if entry_path == Path::new(CHANGES) {
+			let mut bytes = Vec::new();
+			entry.read_to_end(&mut bytes)?;
+			changes = Some(bytes);
+		} else if entry_path == Path::new(DATA) {
+			throw!(Error::InvalidBackup("a full backup, restore it with restore"));
+		} else {
+			throw!(Error::InvalidBackup("unexpected file in archive"));
+		}
+	}
+
+	let Some(manifest) = manifest else { throw!(Error::InvalidBackup("missing manifest")) };
+	let Some(changes) = changes else { throw!(Error::InvalidBackup("missing changes")) };
+	let Some(base) = manifest.base else { throw!(Error::InvalidBackup("incremental backup without a base")) };
+	if changes.len() as u64 != manifest.data_size || crc32fast::hash(&changes) != manifest.data_crc32 { throw!(Error::ChecksumMismatch); }
+
+	let mut env = Env::builder()?.build_existing(&crate::path_to_cstring(path)?)?;
+	if ChangeLog::of(&env.read_tx()?)?.last_sequence()? != base { throw!(Error::InvalidBackup("the increment doesn't follow the restored backup")); }
+	// the increment has the changelog entries, logging the applied changes again would give them other sequences
+	env.changelog = None;
+	let tx = env.write_tx()?;
+	let mut changes = changes.as_slice();
+	while let Some(message) = sync::read::<sync::Message>(&mut changes)? { sync::apply(&tx, &message)?; }
+	// the changelog may have been trimmed in the source since
+	for (name, entries) in manifest.tables.iter().filter(|(name, _)| name.as_bytes() != changelog::NAME.strip_suffix(b"\0").unwrap_or(changelog::NAME)) {
+		let dbi = env.db(&[name.as_bytes(), b"\0"].concat()).ok_or_else(|| Error::UnknownDb(name.clone()))?;
+		if tx.stat(dbi)?.entries != *entries { throw!(Error::InvalidBackup("entry counts don't match the manifest")); }
+	}
+	tx.commit()?;
+	manifest
+}
+
+#[cfg(test)]
+mod tests {
+	use std::path::Path;
+	use crate::{DbName, Env, Error, RawTable, Transaction, TempDir, changelog::ChangeLog};
+
+	struct Notes;
+
+	impl DbName for Notes {
+		type Table<'tx, 'env: 'tx, TX: Transaction<'env> + 'tx> = RawTable<'tx, TX>;
+		const NAME: &'static [u8] = b"notes\0";
+	}
+
+	fn open(path: &Path) -> Env { Env::builder().unwrap().with::<Notes>().changelog().build(&crate::path_to_cstring(path).unwrap()).unwrap() }
+
+	fn entries(env: &Env) -> Vec<(Vec<u8>, Vec<u8>)> {
+		let tx = env.read_tx().unwrap();
+		tx.get::<Notes>().iter().unwrap().map(|entry| { let (key, value) = entry.unwrap(); (key.to_vec(), value.to_vec()) }).collect()
+	}
+
+	fn write(env: &Env, puts: &[&[u8]], deletes: &[&[u8]]) {
+		let tx = env.write_tx().unwrap();
+		for key in puts { tx.get::<Notes>().put(key, key).unwrap(); }
+		for key in deletes { tx.get::<Notes>().delete(key).unwrap(); }
+		tx.commit().unwrap();
+	}
+
+	fn incremental(env: &Env, since: u64) -> (super::Manifest, Vec<u8>) {
+		let mut archive = Vec::new();
+		let manifest = super::create_incremental(env, since, &mut archive).unwrap();
+		(manifest, archive)
+	}
+
+	#[test]
+	fn full_then_increments() {
+		let source = Env::builder().unwrap().with::<Notes>().changelog().build_temp().unwrap();
+		write(&source, &[b"a", b"b"], &[]);
+		let mut archive = Vec::new();
+		let base = super::create(&source, &mut archive).unwrap();
+		assert_eq!(base.sequence, Some(2));
+		assert!(base.tables.contains(&("notes".to_owned(), 2)));
+
+		let dir = TempDir::new("batadase-backup-test").unwrap();
+		let restored = dir.0.join("restored");
+		assert_eq!(super::restore(archive.as_slice(), &restored).unwrap(), base);
+		assert_eq!(entries(&open(&restored)), entries(&source));
+		assert!(super::restore(archive.as_slice(), &restored).is_err(), "restoring into a non-empty directory");
+
+		write(&source, &[b"c"], &[b"a"]);
+		let (first, first_archive) = incremental(&source, 2);
+		assert_eq!((first.base, first.sequence), (Some(2), Some(4)));
+		write(&source, &[b"d"], &[]);
+		let (second, second_archive) = incremental(&source, 4);
+		assert!(matches!(super::restore_incremental(second_archive.as_slice(), &restored), Err(Error::InvalidBackup(_))), "skipping an increment");
+
+		assert_eq!(super::restore_incremental(first_archive.as_slice(), &restored).unwrap(), first);
+		assert_eq!(super::restore_incremental(second_archive.as_slice(), &restored).unwrap(), second);
+		let env = open(&restored);
+		assert_eq!(entries(&env), entries(&source));
+		assert_eq!(env.last_sequence().unwrap(), source.last_sequence().unwrap());
+		drop(env);
+		assert!(matches!(super::restore_incremental(first_archive.as_slice(), &restored), Err(Error::InvalidBackup(_))), "restoring an increment twice");
+	}
+
+	#[test]
+	fn increment_past_the_trimmed_changelog() {
+		let source = Env::builder().unwrap().with::<Notes>().changelog().build_temp().unwrap();
+		write(&source, &[b"a"], &[]);
+		let mut archive = Vec::new();
+		let base = super::create(&source, &mut archive).unwrap();
+		let dir = TempDir::new("batadase-backup-test").unwrap();
+		super::restore(archive.as_slice(), &dir.0).unwrap();
+
+		write(&source, &[b"b"], &[b"a"]);
+		write(&source, &[b"c"], &[]);
+		let tx = source.write_tx().unwrap();
+		ChangeLog::of(&tx).unwrap().trim_through(base.sequence.unwrap() + 1).unwrap();
+		tx.commit().unwrap();
+
+		let (_, increment) = incremental(&source, base.sequence.unwrap());
+		super::restore_incremental(increment.as_slice(), &dir.0).unwrap();
+		assert_eq!(entries(&open(&dir.0)), entries(&source));
+	}
+
+	#[test]
+	fn wrong_kind_of_backup() {
+		let source = Env::builder().unwrap().with::<Notes>().changelog().build_temp().unwrap();
+		write(&source, &[b"a"], &[]);
+		let mut full = Vec::new();
+		super::create(&source, &mut full).unwrap();
+		let (_, increment) = incremental(&source, 0);
+
+		let dir = TempDir::new("batadase-backup-test").unwrap();
+		assert!(matches!(super::restore(increment.as_slice(), &dir.0), Err(Error::InvalidBackup(_))));
+		assert!(matches!(super::restore_incremental(full.as_slice(), &dir.0), Err(Error::InvalidBackup(_))));
+	}
+}
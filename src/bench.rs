@@ -0,0 +1,73 @@
+//! The criterion harness behind `benches/`, usable for benchmarking your own tables with their real types:
+//! ```ignore
+//! fn users(c: &mut Criterion) {
+//!     batadase::bench::run(c, &batadase::bench::Case {
+//!         name: "users",
+//!         env: &|| Env::builder().unwrap().mapsize(1 << 30).unwrap().with::<Users>(),
+//!         entries: 10_000,
+//!         put: &|tx, i| tx.get::<Users>().put(&UserId(i), &User::fake(i)),
+//!         get: &|tx, i| tx.get::<Users>().get(&UserId(i)).map(drop),
+//!         scan: &|tx| Ok(tx.get::<Users>().iter()?.count()),
+//!     });
+//! }
+//! criterion::criterion_group!(benches, users);
+//! criterion::criterion_main!(benches);
+//! ```
+//! Every case runs in fresh temporary envs, as
+//! * `bulk_load` - putting all `entries` in one transaction and committing it
+//! * `put` - overwriting single entries in an open write transaction
+//! * `get` - reading single entries, spread over the table
+//! * `scan` - one `scan` over the loaded table
+
+use criterion::{BatchSize, Criterion};
+use crate::{Error, RoTxn, RwTxn, Transaction, env::EnvBuilder};
+
+pub struct Case<'a> {
+	pub name: &'a str,
+	/// a builder with the benchmarked table registered
+	pub env: &'a dyn Fn() -> EnvBuilder,
+	pub entries: u64,
+	/// put entry `i`, with `i` in `0..entries`
+	pub put: &'a dyn Fn(&RwTxn, u64) -> Result<(), Error>,
+	/// get entry `i`
+	pub get: &'a dyn Fn(&RoTxn, u64) -> Result<(), Error>,
+	/// read every entry, returns how many were read
+	pub scan: &'a dyn Fn(&RoTxn) -> Result<usize, Error>,
+}
+
+// walks 0..n in a scattered order, n has to be > 0
+fn scattered(n: u64) -> impl FnMut() -> u64 {
+	let mut i = 0u64;
+	move || { i = i.wrapping_add(0x9e37_79b9_7f4a_7c15); i % n }
+}
+
+pub fn run(c: &mut Criterion, case: &Case) {
+	assert!(case.entries > 0, "a bench case needs entries");
+	let load = |env: &crate::env::TempEnv| {
+		let tx = env.write_tx().unwrap();
+		for i in 0..case.entries { (case.put)(&tx, i).unwrap(); }
+		tx.commit().unwrap();
+	};
+	let mut group = c.benchmark_group(case.name);
+
+	group.bench_function("bulk_load", |b| b.iter_batched(
+		|| (case.env)().build_temp().unwrap(),
+		|env| load(&env),
+		BatchSize::PerIteration,
+	));
+
+	let env = (case.env)().build_temp().unwrap();
+	load(&env);
+
+	{
+		let tx = env.write_tx().unwrap();
+		let mut next = scattered(case.entries);
+		group.bench_function("put", |b| b.iter(|| (case.put)(&tx, next()).unwrap()));
+	}
+
+	let tx = env.read_tx().unwrap();
+	let mut next = scattered(case.entries);
+	group.bench_function("get", |b| b.iter(|| (case.get)(&tx, next()).unwrap()));
+	group.bench_function("scan", |b| b.iter(|| std::hint::black_box((case.scan)(&tx).unwrap())));
+	group.finish();
+}
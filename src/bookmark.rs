@@ -0,0 +1,121 @@
+//! The position of a scan, kept between transactions - or between runs, persisted in a progress table - to carry on
+//! after the last entry handled:
+//! ```ignore
+//! let tx = ENV.read_tx()?;
+//! let start = tx.get::<Progress>().get_unrkyv(&Job::Reindex)?;
+//! let mut last = None;
+//! for entry in tx.get::<Blobs>().iter_after(start.as_ref())?.take(1000) {
+//!     let (key, value) = entry?;
+//!     reindex(key, value)?;
+//!     last = Some(Bookmark::key(key)?);
+//! }
+//! if let Some(last) = last { ENV.write(move |tx| tx.get::<Progress>().put(&Job::Reindex, &last)).await??; }
+//! ```
+//! Resuming goes to the first entry after the bookmarked one, which is fine when that one was deleted meanwhile -
+//! the scan carries on from where it would have been. Entries put before it meanwhile aren't seen.
+
+use culpa::{throw, throws};
+use crate::{Transaction, Error, lmdb};
+
+/// The last entry a scan handled. For `DbFlags::DupSort` tables it has the value too, so a scan can stop between
+/// values of one key - without it, resuming skips all of the key's values. Keys and values are the stored bytes, framing
+/// included.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "rkyv", derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize))]
+pub struct Bookmark {
+	pub key: Vec<u8>,
+	pub value: Option<Vec<u8>>,
+}
+
+impl Bookmark {
+	/// fails with `lmdb::Error::BadValSize` for a key LMDB can't store, empty or over `MDB_MAXKEYSIZE`
+	#[throws]
+	pub fn key(key: &[u8]) -> Self {
+		if !storable(key) { throw!(lmdb::Error::BadValSize) }
+		Self { key: key.to_vec(), value: None }
+	}
+
+	/// for dupsort tables, whose values are at most `MDB_MAXKEYSIZE` bytes too
+	#[throws]
+	pub fn entry(key: &[u8], value: &[u8]) -> Self {
+		if !storable(key) || value.len() > lmdb::MAX_KEY_SIZE { throw!(lmdb::Error::BadValSize) }
+		Self { key: key.to_vec(), value: Some(value.to_vec()) }
+	}
+
+	/// For storing in a [`RawTable`](crate::RawTable): a byte saying whether there's a value, the key's length as a
+	/// big-endian u16, the key and the value. Fails like [`Bookmark::key`] for a key set through the field.
+	#[throws]
+	pub fn to_bytes(&self) -> Vec<u8> {
+		if !storable(&self.key) { throw!(lmdb::Error::BadValSize) }
+		let len = self.key.len() as u16; // at most MDB_MAXKEYSIZE
+		[&[u8::from(self.value.is_some())][..], &len.to_be_bytes(), &self.key, self.value.as_deref().unwrap_or_default()].concat()
+	}
+
+	#[throws]
+	pub fn from_bytes(bytes: &[u8]) -> Self {
+		let [has_value @ (0 | 1), len_hi, len_lo, rest @ ..] = bytes else { throw!(Error::InvalidEntry("not a bookmark")) };
+		let len = usize::from(u16::from_be_bytes([*len_hi, *len_lo]));
+		if rest.len() < len || (*has_value == 0 && rest.len() > len) { throw!(Error::InvalidEntry("not a bookmark")); }
+		let (key, value) = rest.split_at(len);
+		if !storable(key) || value.len() > lmdb::MAX_KEY_SIZE { throw!(Error::InvalidEntry("not a bookmark")); }
+		Self { key: key.to_vec(), value: (*has_value == 1).then(|| value.to_vec()) }
+	}
+
+	// The entry after this one with `cursor` on it, whether this one is still there or not. LMDB's own compare
+	// functions are used, so it works with key flags and dup orders too.
+	#[throws(lmdb::Error)]
+	pub(crate) fn resume<'tx, 'env: 'tx, TX: Transaction<'env>>(&self, tx: &'tx TX, dbi: lmdb_sys::MDB_dbi, cursor: &mut lmdb::Cursor<'tx, TX>) -> Option<(&'tx [u8], &'tx [u8])> {
+		if let Some(value) = &self.value && let Some(dup) = cursor.as_dup() && let Some(next) = dup.get_with_key_value(&mut self.key.clone(), &mut value.clone(), lmdb::DupCursorOp::GetBothRange)? {
+			let op = if lmdb::dcmp(tx, dbi, next, value).is_gt() { lmdb::CursorOp::GetCurrent } else { lmdb::CursorOp::Next };
+			return cursor.get(op)?;
+		}
+		// the key's gone, or so are its values from the bookmarked one on
+		let mut entry = cursor.get_with_key(&mut self.key.clone(), lmdb::CursorOp::SetRange)?;
+		while let Some((next, _)) = entry && lmdb::cmp(tx, dbi, next, &self.key).is_eq() { entry = cursor.get(lmdb::CursorOp::Next)?; }
+		entry
+	}
+}
+
+// whether LMDB can store `key`, or seek to it
+fn storable(key: &[u8]) -> bool { (1..=lmdb::MAX_KEY_SIZE).contains(&key.len()) }
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, Env, RawTable, Transaction};
+	use super::Bookmark;
+
+	struct Blobs;
+
+	impl DbName for Blobs {
+		type Table<'tx, 'env: 'tx, TX: Transaction<'env> + 'tx> = RawTable<'tx, TX>;
+		const NAME: &'static [u8] = b"blobs\0";
+	}
+
+	#[test]
+	fn bytes_round_trip() {
+		for bookmark in [Bookmark::key(b"a").unwrap(), Bookmark::entry(b"a", b"").unwrap(), Bookmark::entry(&[7; 511], b"bc").unwrap()] {
+			assert_eq!(Bookmark::from_bytes(&bookmark.to_bytes().unwrap()).unwrap(), bookmark);
+		}
+	}
+
+	#[test]
+	fn unstorable_keys() {
+		assert!(Bookmark::key(b"").is_err());
+		assert!(Bookmark::key(&[0; 512]).is_err());
+		assert!(Bookmark::entry(b"a", &[0; 512]).is_err());
+		assert!(Bookmark { key: Vec::new(), value: None }.to_bytes().is_err());
+		assert!(Bookmark::from_bytes(&[0, 0, 0]).is_err());
+		assert!(Bookmark::from_bytes(&[[0, 2, 0].as_slice(), &[0; 512]].concat()).is_err());
+	}
+
+	#[test]
+	fn resumes_after_a_deleted_key() {
+		let env = Env::builder().unwrap().with::<Blobs>().build_temp().unwrap();
+		let tx = env.write_tx().unwrap();
+		for key in [b"a", b"b", b"c", b"d"] { tx.get::<Blobs>().put(key, b"").unwrap(); }
+		let bookmark = Bookmark::key(b"b").unwrap();
+		tx.get::<Blobs>().delete(b"b").unwrap();
+		let rest: Vec<_> = tx.get::<Blobs>().iter_after(Some(&bookmark)).unwrap().map(|entry| entry.unwrap().0).collect();
+		assert_eq!(rest, [b"c", b"d"]);
+	}
+}
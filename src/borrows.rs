@@ -0,0 +1,104 @@
+//! With the `debug-borrows` feature, transactions count the tables and cursors built on them and panic if any are
+//! still around when they end - which safe code can't do, so it catches handles kept past `commit` through unsafe
+//! code or `mem::forget`. Cursors, which the tables' iterators hold, also panic when they're used after their
+//! transaction ended, and any handle once it's dropped then. Meant for test builds:
+//! ```toml
+//! [dev-dependencies]
+//! batadase = { version = "*", features = ["debug-borrows"] }
+//! ```
+//! References to archived values aren't counted, they're plain `&'tx` references with nothing to check them on use.
+//! Without the feature all of this compiles to nothing.
+
+use crate::Transaction;
+
+/// how many tables and cursors are borrowing the transaction right now, always 0 without `debug-borrows`
+pub fn outstanding<'env>(tx: &impl Transaction<'env>) -> usize {
+	imp::outstanding(tx.raw())
+}
+
+// transactions as they begin and end, by their raw handle since that's unique while they're alive
+pub(crate) fn begin(raw: *mut lmdb_sys::MDB_txn) { imp::begin(raw); }
+pub(crate) fn end(raw: *mut lmdb_sys::MDB_txn) { imp::end(raw); }
+
+// held by whatever borrows a transaction, counted while it's alive
+pub(crate) struct Borrow(imp::Borrow);
+
+impl Borrow {
+	pub(crate) fn of<'env>(tx: &impl Transaction<'env>) -> Self { Self(imp::Borrow::of(tx.raw())) }
+
+	// panics if the transaction ended
+	pub(crate) fn check(&self) { self.0.check(); }
+}
+
+#[cfg(feature = "debug-borrows")]
+mod imp {
+	use std::sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}};
+	use std::collections::HashMap;
+
+	struct Ledger {
+		generation: u64,
+		ended: AtomicBool,
+		outstanding: AtomicUsize,
+	}
+
+	static GENERATION: AtomicU64 = AtomicU64::new(0);
+	static LIVE: Mutex<Option<HashMap<usize, Arc<Ledger>>>> = Mutex::new(None);
+
+	pub(super) fn begin(raw: *mut lmdb_sys::MDB_txn) {
+		let ledger = Ledger { generation: GENERATION.fetch_add(1, Ordering::Relaxed), ended: AtomicBool::new(false), outstanding: AtomicUsize::new(0) };
+		LIVE.lock().unwrap().get_or_insert_default().insert(raw as usize, Arc::new(ledger));
+	}
+
+	pub(super) fn end(raw: *mut lmdb_sys::MDB_txn) {
+		let Some(ledger) = LIVE.lock().unwrap().get_or_insert_default().remove(&(raw as usize)) else { return };
+		ledger.ended.store(true, Ordering::Release);
+		let outstanding = ledger.outstanding.load(Ordering::Acquire);
+		if outstanding > 0 && !std::thread::panicking() {
+			panic!("{outstanding} table or cursor handles borrowing transaction {} are still around as it ends, kept past it through unsafe code or mem::forget", ledger.generation);
+		}
+	}
+
+	pub(super) fn outstanding(raw: *mut lmdb_sys::MDB_txn) -> usize {
+		LIVE.lock().unwrap().get_or_insert_default().get(&(raw as usize)).map_or(0, |ledger| ledger.outstanding.load(Ordering::Acquire))
+	}
+
+	// None for transactions that didn't begin through the env, e.g. ones lmdb_sys was called for directly
+	pub(super) struct Borrow(Option<Arc<Ledger>>);
+
+	impl Borrow {
+		pub(super) fn of(raw: *mut lmdb_sys::MDB_txn) -> Self {
+			let ledger = LIVE.lock().unwrap().get_or_insert_default().get(&(raw as usize)).cloned();
+			if let Some(ledger) = &ledger { ledger.outstanding.fetch_add(1, Ordering::AcqRel); }
+			Self(ledger)
+		}
+
+		pub(super) fn check(&self) {
+			let Some(ledger) = &self.0 else { return };
+			if ledger.ended.load(Ordering::Acquire) { panic!("cursor used after its transaction {} ended", ledger.generation); }
+		}
+	}
+
+	impl Drop for Borrow {
+		fn drop(&mut self) {
+			let Some(ledger) = &self.0 else { return };
+			ledger.outstanding.fetch_sub(1, Ordering::AcqRel);
+			if ledger.ended.load(Ordering::Acquire) && !std::thread::panicking() {
+				panic!("table or cursor dropped after its transaction {} ended", ledger.generation);
+			}
+		}
+	}
+}
+
+#[cfg(not(feature = "debug-borrows"))]
+mod imp {
+	pub(super) fn begin(_raw: *mut lmdb_sys::MDB_txn) {}
+	pub(super) fn end(_raw: *mut lmdb_sys::MDB_txn) {}
+	pub(super) fn outstanding(_raw: *mut lmdb_sys::MDB_txn) -> usize { 0 }
+
+	pub(super) struct Borrow;
+
+	impl Borrow {
+		pub(super) fn of(_raw: *mut lmdb_sys::MDB_txn) -> Self { Self }
+		pub(super) fn check(&self) {}
+	}
+}
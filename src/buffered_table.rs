@@ -0,0 +1,187 @@
+//! Write-behind for hot keys: puts and deletes land in memory, replacing earlier ones to the same key, and a thread
+//! writes them out in one transaction every so often, so a counter bumped a thousand times a second costs a commit
+//! per flush instead of one per bump.
+//! ```ignore
+//! let counters = BufferedTable::<Counter, u64>::new(&ENV, Counters::NAME, LossBound { max_delay: Duration::from_millis(100), max_pending: 10_000 });
+//! counters.put(&name, &count)?;
+//! ```
+//! Everything not flushed yet is lost if the process dies, which is what the [`LossBound`] the table is built with
+//! opts into - a write of a new key that would go over its `max_pending` fails with [`Error::BufferFull`] and wakes
+//! the flusher, since flushing right there would deadlock on the caller's own write transaction or group job.
+//! Dropping the table flushes whatever is left. With [`BufferedTable::with_group_commit`] flushes are queued on a
+//! [`GroupCommit`] instead of taking write transactions of their own, sharing commits with the rest of its writes.
+//! Readers of the table don't see buffered writes until they're flushed, [`BufferedTable::pending_value`] does.
+//! For coalescing within one write transaction, see [`write_batch`](crate::write_batch).
+
+use culpa::{throw, throws};
+use enumflags2::BitFlag;
+use std::{collections::HashMap, marker::PhantomData, sync::{Arc, Condvar, Mutex, atomic::{AtomicU64, Ordering}}, time::Duration};
+use rkyv::util::AlignedVec;
+use crate::{Env, Error, RkyvSer, RkyvVal, RkyvDe, RwTxn, Transaction, lmdb, error::Context, framing::{Framing, Plain}, group_commit::GroupCommit};
+
+/// How much a crash may lose: up to `max_pending` keys, written to the buffer at most `max_delay`
+/// (plus however long a flush takes) before. Past `max_pending` writes of new keys fail until a flush makes room,
+/// `put` and `delete` never block on one.
+#[derive(Debug, Clone, Copy)]
+pub struct LossBound {
+	pub max_delay: Duration,
+	pub max_pending: usize,
+}
+
+pub struct BufferedTable<K, V, F = Plain> {
+	shared: Arc<Shared>,
+	thread: Option<std::thread::JoinHandle<()>>,
+	_pd: PhantomData<fn(&K, &V, F)>,
+}
+
+struct Shared {
+	env: &'static Env,
+	group: Option<&'static GroupCommit>,
+	dbi: lmdb_sys::MDB_dbi,
+	bound: LossBound,
+	// serialized key to archived value, None deletes
+	pending: Mutex<HashMap<Vec<u8>, Option<AlignedVec>>>,
+	coalesced: AtomicU64,
+	// held by whoever is flushing, so flushes land in the order they took their writes
+	flushing: Mutex<()>,
+	stop: Mutex<bool>,
+	wake: Condvar,
+	frame: fn(AlignedVec) -> Vec<u8>,
+}
+
+impl<K, V, F> BufferedTable<K, V, F> where
+	F: Framing + 'static,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+{
+	/// Buffer writes to the table registered as `name`.
+	pub fn new(env: &'static Env, name: &'static [u8], bound: LossBound) -> Self { Self::build(env, None, name, bound) }
+
+	/// Buffer writes to the table registered as `name` and flush them through `group`, which has to write to `env`.
+	pub fn with_group_commit(env: &'static Env, group: &'static GroupCommit, name: &'static [u8], bound: LossBound) -> Self {
+		Self::build(env, Some(group), name, bound)
+	}
+
+	fn build(env: &'static Env, group: Option<&'static GroupCommit>, name: &'static [u8], bound: LossBound) -> Self {
+		let dbi = env.db(name).expect("table isn't registered in the env, add it with EnvBuilder::with");
+		assert!(bound.max_pending > 0, "a buffered table needs room for at least one pending key");
+		let shared = Arc::new(Shared {
+			env,
+			group,
+			dbi,
+			bound,
+			pending: Mutex::default(),
+			coalesced: AtomicU64::default(),
+			flushing: Mutex::new(()),
+			stop: Mutex::new(false),
+			wake: Condvar::new(),
+			frame: |archive| F::frame(archive).as_mut().to_vec(),
+		});
+		let thread = std::thread::Builder::new()
+			.name("batadase-buffered-table".to_owned())
+			.spawn({ let shared = Arc::clone(&shared); move || flusher(&shared) })
+			.expect("failed to spawn the buffered table thread");
+		Self { shared, thread: Some(thread), _pd: PhantomData }
+	}
+
+	/// Buffer the write, or fail with [`Error::BufferFull`] - safe inside a write transaction, it never takes one.
+	#[throws]
+	pub fn put(&self, key: &K, value: &V) { self.buffer(rkyv::to_bytes(key)?.to_vec(), Some(rkyv::to_bytes(value)?))?; }
+
+	/// like [`put`](Self::put)
+	#[throws]
+	pub fn delete(&self, key: &K) { self.buffer(rkyv::to_bytes(key)?.to_vec(), None)?; }
+
+	/// The buffered write to `key` if there is one - `Some(None)` for a delete.
+	#[throws]
+	pub fn pending_value(&self, key: &K) -> Option<Option<V>> where
+		rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe>,
+	{
+		let key_bytes = rkyv::to_bytes(key)?;
+		let pending = self.shared.pending.lock().unwrap();
+		let Some(value) = pending.get(key_bytes.as_slice()) else { return None };
+		Some(value.as_ref().map(|value| rkyv::from_bytes::<V, rkyv::rancor::Error>(value)).transpose()?)
+	}
+
+	/// keys waiting for a flush
+	pub fn pending(&self) -> usize { self.shared.pending.lock().unwrap().len() }
+
+	/// writes replaced by a later one to the same key before they were flushed, since the table was built
+	pub fn coalesced(&self) -> u64 { self.shared.coalesced.load(Ordering::Relaxed) }
+
+	/// Write everything buffered so far in one transaction, returning how many keys were written.
+	/// Blocks on the env's write lock like any write transaction, or until the group's batch is committed. If it
+	/// fails the writes stay buffered. It must not be called inside a write transaction or a group job, which it would
+	/// wait on forever.
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn flush(&self) -> usize { self.shared.flush()? }
+
+	#[throws]
+	fn buffer(&self, key_bytes: Vec<u8>, value: Option<AlignedVec>) {
+		let mut pending = self.shared.pending.lock().unwrap();
+		if !pending.contains_key(&key_bytes) && pending.len() >= self.shared.bound.max_pending {
+			self.shared.wake.notify_one();
+			throw!(Error::BufferFull(pending.len()));
+		}
+		if pending.insert(key_bytes, value).is_some() { self.shared.coalesced.fetch_add(1, Ordering::Relaxed); }
+	}
+}
+
+impl<K, V, F> Drop for BufferedTable<K, V, F> {
+	fn drop(&mut self) {
+		*self.shared.stop.lock().unwrap() = true;
+		self.shared.wake.notify_one();
+		if let Some(thread) = self.thread.take() { let _ = thread.join(); }
+	}
+}
+
+impl Shared {
+	#[throws]
+	fn flush(&self) -> usize {
+		let _flushing = self.flushing.lock().unwrap();
+		let writes = std::mem::take(&mut *self.pending.lock().unwrap());
+		if writes.is_empty() { return 0; }
+		let res = match self.group {
+			// the job outlives this call if the group's commit fails, so it gets a copy and the failure puts back these
+			Some(group) => {
+				let (dbi, frame, copy) = (self.dbi, self.frame, writes.clone());
+				group.try_write_blocking(move |tx| write_all(tx, dbi, frame, &copy)).and_then(|res| res)
+			},
+			None => (|| {
+				let tx = self.env.write_tx()?;
+				write_all(&tx, self.dbi, self.frame, &writes)?;
+				tx.commit()
+			})(),
+		};
+		if let Err(e) = res {
+			// writes buffered since are newer, so they win
+			let mut pending = self.pending.lock().unwrap();
+			for (key, value) in writes { pending.entry(key).or_insert(value); }
+			throw!(e);
+		}
+		writes.len()
+	}
+}
+
+#[throws]
+fn write_all(tx: &RwTxn, dbi: lmdb_sys::MDB_dbi, frame: fn(AlignedVec) -> Vec<u8>, writes: &HashMap<Vec<u8>, Option<AlignedVec>>) {
+	for (key, value) in writes {
+		let mut key = key.clone();
+		match value {
+			Some(value) => lmdb::put(tx, dbi, &mut key, &mut frame(value.clone()), lmdb::PutFlags::empty()).context(tx, dbi, "flush", Some(&key))?,
+			None => { lmdb::del(tx, dbi, &mut key).context(tx, dbi, "flush", Some(&key))?; },
+		}
+	}
+}
+
+fn flusher(shared: &Shared) {
+	let mut stop = shared.stop.lock().unwrap();
+	loop {
+		let stopping = *stop;
+		drop(stop);
+		if let Err(e) = shared.flush() { log::error!("flushing a buffered table failed: {e}"); }
+		if stopping { return; }
+		stop = shared.wake.wait_timeout(shared.stop.lock().unwrap(), shared.bound.max_delay).unwrap().0;
+	}
+}
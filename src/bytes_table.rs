@@ -0,0 +1,129 @@
+//! A table of binary payloads - images, compiled artifacts - under rkyv keys. Values are stored as they're given, with
+//! no archive around them, so reads are the bytes in the map with nothing to validate:
+//! ```ignore
+//! #[derive(DbName)]
+//! #[table(BytesTable<'tx, TX, u64>)]
+//! struct Thumbnails;
+//!
+//! tx.get::<Thumbnails>().put(&id, &png)?;
+//! let png: Option<&[u8]> = tx.get::<Thumbnails>().get(&id)?;
+//! ```
+//! Unlike `AssocTable<K, Vec<u8>>` the stored bytes aren't an rkyv archive, so the two can't share a table. Puts
+//! reserve the value's space in the map and copy into it, so the table can't be `DbFlags::DupSort`.
+
+use culpa::throws;
+use std::marker::PhantomData;
+use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, Error, lmdb, error::Context};
+
+pub struct BytesTable<'tx, TX, K> {
+	tx: &'tx TX,
+	dbi: lmdb_sys::MDB_dbi,
+	_pd: PhantomData<K>,
+	_borrow: crate::borrows::Borrow,
+}
+
+impl<'tx, 'env: 'tx, TX, K> Table<'tx, 'env, TX> for BytesTable<'tx, TX, K> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.dbi }
+	fn txn(&self) -> &TX { self.tx }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
+		Self::build(tx, tx.env().db(name).expect("table isn't registered in the env, add it with EnvBuilder::with"))
+	}
+
+	// any bytes are a value
+	#[throws]
+	fn check_entry(key: &[u8], _value: &[u8]) { rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key)?; }
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, K> BytesTable<'tx, RwTxn<'tx>, K> where
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+{
+	#[throws]
+	pub fn put(&self, key: &K, value: impl AsRef<[u8]>) {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let value = value.as_ref();
+		lmdb::put_reserve(self.tx, self.dbi, &mut key_bytes, value.len()).context(self.tx, self.dbi, "put", Some(&key_bytes))?.copy_from_slice(value);
+	}
+
+	/// Put a `len` byte value that's streamed into its place in the map instead of being built in memory first.
+	///
+	/// # Safety
+	/// The writer points into lmdb's dirty page, which the next write in the transaction can move, reuse or free. It has
+	/// to be dropped before anything else is written in the transaction.
+	#[throws]
+	pub unsafe fn put_writer(&self, key: &K, len: usize) -> lmdb::ValueWriter<'tx> {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		lmdb::ValueWriter::new(lmdb::put_reserve(self.tx, self.dbi, &mut key_bytes, len).context(self.tx, self.dbi, "put_writer", Some(&key_bytes))?)
+	}
+
+	#[throws]
+	pub fn delete(&self, key: &K) -> bool {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		lmdb::del(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "delete", Some(&key_bytes))?
+	}
+
+	#[throws]
+	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi).context(self.tx, self.dbi, "clear", None)?; }
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, K> BytesTable<'tx, TX, K> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { tx, dbi, _pd: PhantomData, _borrow: crate::borrows::Borrow::of(tx) } }
+
+	#[throws]
+	pub fn get(&self, key: &K) -> Option<&'tx [u8]> {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "get", Some(&key_bytes))?
+	}
+
+	/// The value copied into a [`bytes::Bytes`], to hand to code that wants one - a `Bytes` can't borrow the
+	/// transaction, so this is the one read that copies.
+	#[cfg(feature = "bytes")]
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn get_bytes(&self, key: &K) -> Option<bytes::Bytes> { self.get(key)?.map(bytes::Bytes::copy_from_slice) }
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn contains_key(&self, key: &K) -> bool { self.get(key)?.is_some() }
+
+	/// every entry in key order
+	#[throws]
+	pub fn iter(&self) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx [u8]), Error>> + use<'tx, 'env, TX, K> where
+		rkyv::Archived<K>: 'tx,
+	{
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "iter", None)?;
+		lmdb::steps(move || cursor.get(lmdb::CursorOp::Next)).map_while(|next| next.map(entry::<K>).transpose())
+	}
+
+	/// entries from the first key at or after `key`, in key order
+	#[throws]
+	pub fn iter_from(&self, key: &K) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx [u8]), Error>> + use<'tx, 'env, TX, K> where
+		rkyv::Archived<K>: 'tx,
+	{
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "iter_from", Some(&key_bytes))?;
+		let first = cursor.get_with_key(&mut key_bytes, lmdb::CursorOp::SetRange).context(self.tx, self.dbi, "iter_from", Some(&key_bytes))?;
+		// a cursor that found nothing isn't positioned, Next would start over from the first entry
+		let rest = first.is_some().then(|| lmdb::steps(move || cursor.get(lmdb::CursorOp::Next))).into_iter().flatten();
+		first.map(Ok).into_iter().chain(rest).map_while(|next| next.map(entry::<K>).transpose())
+	}
+}
+
+fn entry<'tx, K>((key, value): lmdb::Entry<'tx>) -> Option<(&'tx rkyv::Archived<K>, &'tx [u8])> where
+	K: rkyv::Archive,
+	rkyv::Archived<K>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	match rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key) {
+		Ok(key) => Some((key, value)),
+		Err(e) => { log::error!("Error deserializing key in cursor: {e:?}"); None },
+	}
+}
@@ -0,0 +1,140 @@
+//! A log of every change committed to the env's tables, numbered by a sequence counting up per env, for sync
+//! protocols to ask for what changed since the last sequence they saw:
+//! ```ignore
+//! static ENV: LazyLock<Env> = LazyLock::new(|| Env::builder().unwrap().with::<Docs>().changelog().build(c"data").unwrap());
+//!
+//! let tx = ENV.read_tx()?;
+//! for change in ChangeLog::of(&tx)?.changes_since(client.last_seq)? {
+//!     let change = change?;
+//!     client.send(change.seq, change.table, change.key, change.op); // reading the current values as of tx
+//! }
+//! ```
+//! Changes are recorded as the lmdb writes happen, in the same transaction, so they commit or roll back with them.
+//! They name the table and key, not the value - a client reads the values as of the same transaction. Only
+//! registered dbs are logged, not e.g. [`RwTxn::temp_dbi`]s. The log is kept until [`ChangeLog::trim_through`].
+//!
+//! Entries are `seq:u64 BE -> op:u8 name_len:u16 BE name key` in the db `batadase.changelog`.
+
+use culpa::{throw, throws};
+use crate::{Transaction, RwTxn, Error, lmdb, error::Context};
+
+pub const NAME: &[u8] = b"batadase.changelog\0";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeOp {
+	/// a value was put under the key
+	Put,
+	/// the key was deleted, or with dupsort tables maybe only one of its values
+	Delete,
+	/// every entry of the table was deleted, there's no key
+	Clear,
+}
+
+impl ChangeOp {
+	fn byte(self) -> u8 { self as u8 }
+
+	fn from_byte(byte: u8) -> Option<Self> {
+		[Self::Put, Self::Delete, Self::Clear].into_iter().find(|op| op.byte() == byte)
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Change<'tx> {
+	pub seq: u64,
+	/// the table's db name, without the trailing nul
+	pub table: &'tx [u8],
+	pub op: ChangeOp,
+	/// empty for [`ChangeOp::Clear`]
+	pub key: &'tx [u8],
+}
+
+#[throws]
+pub(crate) fn check_entry(key: &[u8], value: &[u8]) { parse(key, value)?; }
+
+#[throws]
+fn parse<'a>(key: &'a [u8], value: &'a [u8]) -> Change<'a> {
+	let Ok(seq) = <[u8; 8]>::try_from(key) else { throw!(Error::InvalidEntry("changelog key isn't a sequence")) };
+	let [op, len_hi, len_lo, rest @ ..] = value else { throw!(Error::InvalidEntry("changelog entry is too short")) };
+	let Some(op) = ChangeOp::from_byte(*op) else { throw!(Error::InvalidEntry("unknown changelog op")) };
+	let len = usize::from(u16::from_be_bytes([*len_hi, *len_lo]));
+	if rest.len() < len { throw!(Error::InvalidEntry("changelog entry is too short")); }
+	let (table, key) = rest.split_at(len);
+	Change { seq: u64::from_be_bytes(seq), table, op, key }
+}
+
+// Logs a change to `dbi` if the env has a changelog and `dbi` is registered, returns whether it did. Called by the
+// lmdb writes right after they succeed, so it's lmdb's error.
+pub(crate) fn record(tx: &RwTxn, dbi: lmdb_sys::MDB_dbi, op: ChangeOp, key: &[u8]) -> Result<bool, lmdb::Error> {
+	let Some(log) = tx.env().changelog else { return Ok(false) };
+	if dbi == log { return Ok(false); }
+	let Some(name) = tx.env().db_name(dbi) else { return Ok(false) };
+	let name = name.strip_suffix(b"\0").unwrap_or(name);
+	let mut cursor = lmdb::Cursor::open(tx, log)?;
+	let seq = cursor.get(lmdb::CursorOp::Last)?.map_or(0, |(key, _)| key.try_into().map_or(0, u64::from_be_bytes)) + 1;
+	drop(cursor);
+	let len = u16::try_from(name.len()).map_err(|_| lmdb::Error::BadValSize)?;
+	let mut value = Vec::with_capacity(3 + name.len() + key.len());
+	value.push(op.byte());
+	value.extend_from_slice(&len.to_be_bytes());
+	value.extend_from_slice(name);
+	value.extend_from_slice(key);
+	lmdb::put_unchecked(tx, log, &mut seq.to_be_bytes(), &mut value, lmdb::PutFlags::Append.into())?;
+	Ok(true)
+}
+
+/// The env's changelog as of a transaction, see the [module docs](self)
+pub struct ChangeLog<'tx, TX> {
+	tx: &'tx TX,
+	dbi: lmdb_sys::MDB_dbi,
+}
+
+// RwTxn only, so all methods mutate
+impl ChangeLog<'_, RwTxn<'_>> {
+	/// Delete the changes up to and including `seq` once every client has seen them, returns how many were.
+	/// The newest change is always kept, it's what the next sequence counts on from.
+	#[throws]
+	pub fn trim_through(&self, seq: u64) -> usize {
+		let newest = self.last_sequence()?;
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "trim_through", None)?;
+		let mut trimmed = 0;
+		while let Some((key, _)) = cursor.get(lmdb::CursorOp::First)? {
+			let entry_seq = key.try_into().map_or(u64::MAX, u64::from_be_bytes);
+			if entry_seq > seq || entry_seq >= newest { break; }
+			let key = key.to_vec(); // the slice goes stale with the delete
+			cursor.del_current().context(self.tx, self.dbi, "trim_through", Some(&key))?;
+			trimmed += 1;
+		}
+		trimmed
+	}
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX: Transaction<'env>> ChangeLog<'tx, TX> {
+	/// Error::UnknownDb if the env was built without [`EnvBuilder::changelog`](crate::env::EnvBuilder::changelog)
+	#[throws]
+	pub fn of(tx: &'tx TX) -> Self {
+		let Some(dbi) = tx.env().changelog else { throw!(crate::dump::unknown_db(NAME)) };
+		Self { tx, dbi }
+	}
+
+	/// the sequence of the last change committed as of the transaction, 0 before the first one
+	#[throws]
+	pub fn last_sequence(&self) -> u64 {
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "last_sequence", None)?;
+		let Some((key, value)) = cursor.get(lmdb::CursorOp::Last)? else { return 0 };
+		parse(key, value).context(self.tx, self.dbi, "last_sequence", Some(key))?.seq
+	}
+
+	/// The changes after `seq` in order, stopping at a malformed entry after logging it, and after a cursor's error.
+	#[throws]
+	pub fn changes_since(&self, seq: u64) -> impl Iterator<Item = Result<Change<'tx>, Error>> + use<'tx, 'env, TX> {
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "changes_since", None)?;
+		let first = cursor.get_with_key(&mut seq.saturating_add(1).to_be_bytes(), lmdb::CursorOp::SetRange)?;
+		// a cursor that found nothing isn't positioned, Next would start over from the first change
+		let rest = first.is_some().then(|| lmdb::steps(move || cursor.get(lmdb::CursorOp::Next))).into_iter().flatten();
+		first.map(Ok).into_iter().chain(rest).map_while(|entry| match entry {
+			Ok((key, value)) => parse(key, value).inspect_err(|e| log::error!("Error reading the changelog: {e}")).ok().map(Ok),
+			Err(e) => Some(Err(e)),
+		})
+	}
+}
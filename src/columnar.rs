@@ -0,0 +1,198 @@
+//! Table scans into column batches for analytics tools, mapped by a [`Columns`] implementation:
+//! ```ignore
+//! struct UserColumns;
+//! impl Columns<UserId, User> for UserColumns {
+//!     fn schema() -> Vec<Field> { vec![Field::new("id", ColumnType::U64), Field::new("name", ColumnType::Utf8)] }
+//!     fn append(id: &ArchivedUserId, user: &ArchivedUser, batch: &mut Batch) {
+//!         batch.u64(0).push(id.0.to_native());
+//!         batch.utf8(1).push(user.name.to_string());
+//!     }
+//! }
+//! for batch in tx.get::<Users>().batches::<UserColumns, _>(.., 64 * 1024)? { write(batch?)?; }
+//! ```
+//! Each column is a plain `Vec` of one type, which with the `arrow` feature moves into an Arrow array without copying
+//! the numbers - [`Batch::into_record_batch`], or [`AssocTable::record_batches`] for the whole scan. The `parquet`
+//! feature writes a scan to a Parquet file:
+//! ```ignore
+//! let rows = tx.get::<Users>().write_parquet::<UserColumns, _>(.., 64 * 1024, File::create("users.parquet")?)?;
+//! ```
+
+use culpa::throws;
+use crate::{AssocTable, Transaction, RkyvSer, RkyvVal, RkyvDe, Error, lmdb, error::Context, framing::Framing, assoc_table::KeyRange};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType { Bool, I64, U64, F64, Utf8, Binary }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+	pub name: &'static str,
+	pub ty: ColumnType,
+}
+
+impl Field {
+	pub fn new(name: &'static str, ty: ColumnType) -> Self { Self { name, ty } }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+	Bool(Vec<bool>),
+	I64(Vec<i64>),
+	U64(Vec<u64>),
+	F64(Vec<f64>),
+	Utf8(Vec<String>),
+	Binary(Vec<Vec<u8>>),
+}
+
+// what a column reserves upfront at most, a batch size is a limit and may be far more than the range holds
+const MAX_RESERVED: usize = 4096;
+
+impl Column {
+	fn new(ty: ColumnType, rows: usize) -> Self {
+		let capacity = rows.min(MAX_RESERVED);
+		match ty {
+			ColumnType::Bool => Self::Bool(Vec::with_capacity(capacity)),
+			ColumnType::I64 => Self::I64(Vec::with_capacity(capacity)),
+			ColumnType::U64 => Self::U64(Vec::with_capacity(capacity)),
+			ColumnType::F64 => Self::F64(Vec::with_capacity(capacity)),
+			ColumnType::Utf8 => Self::Utf8(Vec::with_capacity(capacity)),
+			ColumnType::Binary => Self::Binary(Vec::with_capacity(capacity)),
+		}
+	}
+}
+
+/// Rows of a scan as columns in [`Columns::schema`] order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Batch {
+	pub schema: Vec<Field>,
+	pub columns: Vec<Column>,
+	pub rows: usize,
+}
+
+macro_rules! column_accessors {
+	($($name:ident: $variant:ident($ty:ty)),* $(,)?) => {$(
+		/// Column `i` to push to, panics if it has another type.
+		pub fn $name(&mut self, i: usize) -> &mut Vec<$ty> {
+			match &mut self.columns[i] {
+				Column::$variant(values) => values,
+				column => panic!("column {i} is {column:?}, not {}", stringify!($variant)),
+			}
+		}
+	)*};
+}
+
+impl Batch {
+	column_accessors!(bool: Bool(bool), i64: I64(i64), u64: U64(u64), f64: F64(f64), utf8: Utf8(String), binary: Binary(Vec<u8>));
+}
+
+#[cfg(feature = "arrow")]
+impl ColumnType {
+	pub fn to_arrow(self) -> arrow_schema::DataType {
+		match self {
+			Self::Bool => arrow_schema::DataType::Boolean,
+			Self::I64 => arrow_schema::DataType::Int64,
+			Self::U64 => arrow_schema::DataType::UInt64,
+			Self::F64 => arrow_schema::DataType::Float64,
+			Self::Utf8 => arrow_schema::DataType::Utf8,
+			Self::Binary => arrow_schema::DataType::Binary,
+		}
+	}
+}
+
+/// the Arrow schema of batches with `fields`, none of them nullable
+#[cfg(feature = "arrow")]
+pub fn arrow_schema(fields: &[Field]) -> arrow_schema::Schema {
+	arrow_schema::Schema::new(fields.iter().map(|field| arrow_schema::Field::new(field.name, field.ty.to_arrow(), false)).collect::<Vec<_>>())
+}
+
+#[cfg(feature = "arrow")]
+impl Batch {
+	#[throws]
+	pub fn into_record_batch(self) -> arrow_array::RecordBatch {
+		use arrow_array::{ArrayRef, BooleanArray, Int64Array, UInt64Array, Float64Array, StringArray, BinaryArray};
+		use std::sync::Arc;
+
+		let columns = self.columns.into_iter().map(|column| -> ArrayRef {
+			match column {
+				Column::Bool(values) => Arc::new(BooleanArray::from(values)),
+				Column::I64(values) => Arc::new(Int64Array::from(values)),
+				Column::U64(values) => Arc::new(UInt64Array::from(values)),
+				Column::F64(values) => Arc::new(Float64Array::from(values)),
+				Column::Utf8(values) => Arc::new(StringArray::from(values)),
+				Column::Binary(values) => Arc::new(BinaryArray::from_iter_values(values)),
+			}
+		}).collect();
+		// the row count is given for mappings without columns
+		let options = arrow_array::RecordBatchOptions::new().with_row_count(Some(self.rows));
+		arrow_array::RecordBatch::try_new_with_options(Arc::new(arrow_schema(&self.schema)), columns, &options)?
+	}
+}
+
+/// How entries of a table map to columns. [`Columns::append`] pushes exactly one value to every column.
+pub trait Columns<K: rkyv::Archive, V: rkyv::Archive> {
+	fn schema() -> Vec<Field>;
+	fn append(key: &rkyv::Archived<K>, value: &rkyv::Archived<V>, batch: &mut Batch);
+}
+
+impl<'tx, 'env: 'tx, TX, K, V, F> AssocTable<'tx, TX, K, V, F> where
+	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe> + 'tx,
+{
+	/// The entries with keys in `range` as batches of up to `rows` rows mapped by `M`. An entry that doesn't validate
+	/// ends the scan with its error.
+	#[throws]
+	pub fn batches<M: Columns<K, V>, R: std::ops::RangeBounds<K>>(&self, range: R, rows: usize) -> impl Iterator<Item = Result<Batch, Error>> + use<'tx, 'env, TX, K, V, F, M, R> {
+		let (tx, dbi) = (self.tx, self.dbi);
+		let range = KeyRange::new(&range)?;
+		let mut cursor = lmdb::Cursor::open(tx, dbi).context(tx, dbi, "batches", None)?;
+		let mut entry = range.seek(&mut cursor)?;
+		let schema = M::schema();
+		std::iter::from_fn(move || {
+			let mut batch = Batch { columns: schema.iter().map(|field| Column::new(field.ty, rows)).collect(), schema: schema.clone(), rows: 0 };
+			while batch.rows < rows.max(1) && let Some((key_bytes, value_bytes)) = entry && range.before_end(tx, dbi, key_bytes) {
+				let res = rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key_bytes).map_err(Error::from)
+					.and_then(|key| Ok((key, rkyv::access::<rkyv::Archived<V>, rkyv::rancor::Error>(F::unframe(value_bytes)?)?)))
+					.context(tx, dbi, "batches", Some(key_bytes));
+				let (key, value) = match res {
+					Ok(x) => x,
+					Err(e) => { entry = None; return Some(Err(e)); },
+				};
+				M::append(key, value, &mut batch);
+				batch.rows += 1;
+				entry = match cursor.get(lmdb::CursorOp::Next) {
+					Ok(next) => next,
+					Err(e) => { entry = None; return Some(Err(e.into())); },
+				};
+			}
+			(batch.rows > 0).then_some(Ok(batch))
+		})
+	}
+
+	/// [`AssocTable::batches`] as Arrow record batches
+	#[cfg(feature = "arrow")]
+	#[throws]
+	pub fn record_batches<M: Columns<K, V>, R: std::ops::RangeBounds<K>>(&self, range: R, rows: usize) -> impl Iterator<Item = Result<arrow_array::RecordBatch, Error>> + use<'tx, 'env, TX, K, V, F, M, R> {
+		self.batches::<M, R>(range, rows)?.map(|batch| batch?.into_record_batch())
+	}
+
+	/// Write the entries with keys in `range` to a Parquet file as row groups of up to `rows` rows, returns how many
+	/// rows were written. An entry that doesn't validate fails it, with the file unfinished.
+	#[cfg(feature = "parquet")]
+	#[throws]
+	pub fn write_parquet<M: Columns<K, V>, R: std::ops::RangeBounds<K>>(&self, range: R, rows: usize, writer: impl std::io::Write + Send) -> usize {
+		let mut out = parquet::arrow::ArrowWriter::try_new(writer, std::sync::Arc::new(arrow_schema(&M::schema())), None)?;
+		let mut written = 0;
+		for batch in self.record_batches::<M, R>(range, rows)? {
+			let batch = batch?;
+			written += batch.num_rows();
+			out.write(&batch)?;
+			// a row group per batch, so memory stays bounded by `rows`
+			out.flush()?;
+		}
+		out.close()?;
+		written
+	}
+}
@@ -0,0 +1,75 @@
+//! Working with environments created by heed/lmdb-rkv (or handing batadase envs to them).
+//!
+//! Both sides are plain LMDB, so most things already line up:
+//! * db names are the same plain keys in the root db, batadase's are the `DbName` paths like `my_app::Users`
+//! * comparators are LMDB's defaults on both sides - dbs with a custom comparator (`mdb_set_compare`) can't be opened correctly here
+//! * db flags are stored in the env, [`EnvBuilder::build_existing`](crate::env::EnvBuilder::build_existing) opens every named db with the flags it was created with
+//! * heed's unnamed db (`open_database(None)`) is the root db itself, so its entries sit next to the db names -
+//!   batadase never writes there, [`copy_db`] with `None` reads them
+//!
+//! What doesn't line up are keys and values: batadase stores rkyv archives (framed by the table's [`Framing`]),
+//! index tables use native-endian u64 keys with `DbFlags::IntegerKey`, while heed stores whatever its codecs produce.
+//! [`copy_db`] moves entries between dbs of two envs through a conversion closure, [`to_value`]/[`from_value`] produce/read
+//! the bytes batadase tables store, e.g. importing a heed `Database<Str, SerdeBincode<User>>`:
+//! ```ignore
+//! let heed_env = Env::builder()?.read_only().build_existing(c"old")?;
+//! let src = heed_env.read_tx()?;
+//! ENV.write(move |tx| compat::copy_db(&src, Some(b"users"), tx, Users::NAME, |key, value| {
+//!     let name = std::str::from_utf8(key).map_err(|_| Error::InvalidEntry("not a str"))?.to_owned();
+//!     let user: User = bincode::deserialize(value).map_err(|_| Error::InvalidEntry("not a user"))?;
+//!     Ok(Some((compat::to_value::<_, Plain>(&name)?, compat::to_value::<_, Plain>(&user)?)))
+//! })).await?;
+//! ```
+//! Going the other way, heed can open batadase envs with `Bytes` codecs (and `DatabaseFlags::INTEGER_KEY` for index tables),
+//! [`from_value`] turns the bytes back into values.
+
+use culpa::{throw, throws};
+use crate::{Transaction, RwTxn, Error, RkyvSer, RkyvVal, RkyvDe, lmdb, framing::Framing};
+
+/// Put every entry of `src_db` (the root db if `None`) into `dst_db` as `convert` maps it, `None` from `convert` skips the entry.
+/// Db names may be given with or without the trailing nul, returns the number of entries put.
+#[throws]
+pub fn copy_db<'src>(
+	src: &impl Transaction<'src>,
+	src_db: Option<&[u8]>,
+	dst: &RwTxn,
+	dst_db: &[u8],
+	mut convert: impl FnMut(&[u8], &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>, Error>,
+) -> usize {
+	let src_dbi = match src_db {
+		Some(name) => match crate::dump::lookup(src.env(), name) { Some(x) => x, None => throw!(crate::dump::unknown_db(name)) },
+		None => lmdb::dbi_open_existing(src.raw(), None)?.expect("root db always exists"),
+	};
+	let Some(dst_dbi) = crate::dump::lookup(dst.env(), dst_db) else { throw!(crate::dump::unknown_db(dst_db)) };
+
+	let mut cursor = lmdb::Cursor::open(src, src_dbi)?;
+	let mut entries = 0;
+	while let Some((key, value)) = cursor.get(lmdb::CursorOp::Next)? {
+		// root db entries naming the other dbs aren't data
+		if src_db.is_none() && crate::dump::lookup(src.env(), key).is_some() { continue; }
+		let Some((mut key, mut value)) = convert(key, value)? else { continue };
+		lmdb::put(dst, dst_dbi, &mut key, &mut value, enumflags2::BitFlags::empty())?;
+		entries += 1;
+	}
+	entries
+}
+
+/// Bytes a batadase table with framing `F` stores for `value` (keys of assoc tables use `Plain`).
+#[throws]
+pub fn to_value<T, F: Framing>(value: &T) -> Vec<u8> where
+	T: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+{
+	F::frame(rkyv::to_bytes(value)?).as_mut().to_vec()
+}
+
+/// Value out of bytes stored by a batadase table with framing `F`.
+#[throws]
+pub fn from_value<T, F: Framing>(stored: &[u8]) -> T where
+	T: rkyv::Archive,
+	rkyv::Archived<T>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<T, RkyvDe>,
+{
+	// stored bytes aren't necessarily aligned
+	let mut aligned = rkyv::util::AlignedVec::<16>::new();
+	aligned.extend_from_slice(F::unframe(stored)?);
+	rkyv::from_bytes::<T, rkyv::rancor::Error>(&aligned)?
+}
@@ -0,0 +1,25 @@
+//! Copying entries between envs and tables as raw bytes, without decoding them.
+
+use culpa::{throw, throws};
+use crate::{Transaction, RwTxn, Error, lmdb};
+
+/// Copy every entry of the db `name` in `src`'s env into the db of the same name in `dst`'s env, returns the number of entries copied.
+/// The db is created in `dst` with the flags it has in `src` if missing (which needs `maxdbs` room there), opening it with other flags fails.
+/// Entries are appended if it's empty, which skips the b-tree searches. The name may be given with or without the trailing nul.
+#[throws]
+pub fn copy_table<'src>(src: &impl Transaction<'src>, dst: &RwTxn, name: &[u8]) -> usize {
+	let name = [name.strip_suffix(b"\0").unwrap_or(name), b"\0"].concat();
+	let Some(src_dbi) = lmdb::dbi_open_existing(src.raw(), Some(&name))? else { throw!(crate::dump::unknown_db(&name)) };
+	let flags = lmdb::dbi_flags(src.raw(), src_dbi)?;
+	let dst_dbi = lmdb::dbi_open(dst.raw(), &name, flags | lmdb::DbFlags::Create)?;
+	let put_flags = if dst.stat(dst_dbi)?.entries == 0 { lmdb::PutFlags::Append | lmdb::PutFlags::AppendDup } else { enumflags2::BitFlags::empty() };
+
+	let mut cursor = lmdb::Cursor::open(src, src_dbi)?;
+	let mut entries = 0;
+	while let Some((key, value)) = cursor.get(lmdb::CursorOp::Next)? {
+		lmdb::put(dst, dst_dbi, &mut key.to_vec(), &mut value.to_vec(), put_flags)?;
+		entries += 1;
+	}
+	log::info!("copied {entries} entries of {}", crate::db_name_lossy(&name));
+	entries
+}
@@ -0,0 +1,85 @@
+//! Crash-safety harness: a child process writes to an env until it's killed at a random point, then the env is reopened
+//! and checked with [`Env::verify`] and the test's own invariants - over and over on the same env.
+//! ```ignore
+//! #[test]
+//! fn counters_survive_crashes() {
+//!     let test = CrashTest { name: "counters", ..CrashTest::default() };
+//!     crash_test::run(&test, || Ok(Env::builder()?.with::<Counters>()), |env, i| {
+//!         // both in one transaction, so they must always agree
+//!         testutil::write(env, |tx| { Counters::get(tx).put(&0, &i)?; Counters::get(tx).put(&1, &i) }).unwrap().unwrap();
+//!     }, |env| {
+//!         let tx = env.read_tx().unwrap();
+//!         assert_eq!(Counters::get(&tx).get(&0).unwrap(), Counters::get(&tx).get(&1).unwrap());
+//!     });
+//! }
+//! ```
+//! The child is the same executable run with the same arguments, so the test has to get to `run` again in it -
+//! which tests do, as long as test filters are passed through. Killing the process tests everything short of
+//! power loss - the OS still writes back what was synced to the page cache, so [`Durability`](crate::env::Durability)
+//! beyond `NoSync` makes no difference here.
+
+use std::time::Duration;
+use crate::{Env, Error, env::EnvBuilder};
+
+// "<name>\n<env directory>" in the child
+const CHILD_VAR: &str = "BATADASE_CRASH_TEST";
+
+#[derive(Debug, Clone)]
+pub struct CrashTest {
+	/// tells tests apart in the child, which runs every test its arguments select
+	pub name: &'static str,
+	pub rounds: u32,
+	/// each child gets killed at a random point up to this long after it's started
+	pub max_run: Duration,
+	pub seed: u64,
+}
+
+impl Default for CrashTest {
+	fn default() -> Self { Self { name: "crash", rounds: 20, max_run: Duration::from_millis(200), seed: 0x2545_f491_4f6c_dd1d } }
+}
+
+/// Run `test`: in the parent this kills children and checks the env after each, in the child it calls `write` with
+/// an increasing counter until killed. Panics if a child can't be run, the env fails verification or `check` panics.
+pub fn run(test: &CrashTest, builder: impl Fn() -> Result<EnvBuilder, Error>, write: impl Fn(&Env, u64), check: impl Fn(&Env)) {
+	if let Ok(child) = std::env::var(CHILD_VAR) {
+		let Some((name, dir)) = child.split_once('\n') else { panic!("malformed {CHILD_VAR}") };
+		// other crash tests selected by the same arguments
+		if name != test.name { return; }
+		let env = open(&builder, std::path::Path::new(dir));
+		for i in 0.. { write(&env, i); }
+		unreachable!();
+	}
+
+	let dir = crate::TempDir::new("batadase-crash").expect("failed to create the env directory");
+	let exe = std::env::current_exe().expect("no path to the current executable");
+	let mut rng = test.seed.max(1);
+	for round in 0..test.rounds {
+		// xorshift, the same kill points for the same seed
+		rng ^= rng << 13;
+		rng ^= rng >> 7;
+		rng ^= rng << 17;
+		let delay = test.max_run.mul_f64((rng % 1024) as f64 / 1024.);
+
+		let mut child = std::process::Command::new(&exe)
+			.args(std::env::args_os().skip(1))
+			.env(CHILD_VAR, format!("{}\n{}", test.name, dir.0.display()))
+			.stdout(std::process::Stdio::null())
+			.stderr(std::process::Stdio::null())
+			.spawn()
+			.expect("failed to spawn the crash test child");
+		std::thread::sleep(delay);
+		child.kill().expect("failed to kill the crash test child");
+		child.wait().expect("failed to wait for the crash test child");
+
+		let env = open(&builder, &dir.0);
+		let report = env.verify().expect("verify failed");
+		assert!(report.is_ok(), "{}: env fails verification after crash {round} at {delay:?}: {report:?}", test.name);
+		check(&env);
+		log::debug!("{}: crash {round} at {delay:?} survived", test.name);
+	}
+}
+
+fn open(builder: &impl Fn() -> Result<EnvBuilder, Error>, dir: &std::path::Path) -> Env {
+	let path = crate::path_to_cstring(dir).expect("env directory isn't a valid path");
+	builder().and_then(|builder| builder.build(&path)).expect("failed to open the env")
+}
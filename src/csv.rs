@@ -0,0 +1,195 @@
+//! CSV in RFC 4180's dialect - fields may be quoted, with `""` for a quote inside, and quoted fields may span lines.
+//! Importing seeds tables from spreadsheets:
+//! ```ignore
+//! // key from the first column, values built from whole records
+//! tx.get::<Countries>().import_csv(file, 0, csv::Options::default())?;
+//! // or both from a closure
+//! tx.get::<Prices>().import_csv_with(file, csv::Options::default(), |record| Ok((record.parse(0)?, record.parse(2)?)))?;
+//! ```
+//! Exporting hands tables to analysts, with a closure picking the columns:
+//! ```ignore
+//! tx.get::<Users>().export_csv(&mut file, .., &["id", "name"], |id, user| vec![id.to_string(), user.name.to_string()])?;
+//! ```
+
+use culpa::throws;
+use enumflags2::BitFlag;
+use std::io::{BufRead, Write};
+use crate::{AssocTable, RwTxn, Transaction, RkyvSer, RkyvVal, RkyvDe, Error, lmdb, error::Context, framing::Framing, assoc_table::KeyRange};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+	pub delimiter: u8,
+	/// the first record names the columns, for [`Record::get`]
+	pub header: bool,
+}
+
+impl Default for Options {
+	fn default() -> Self { Self { delimiter: b',', header: true } }
+}
+
+/// one line (or more, with quoted line breaks) of CSV
+pub struct Record<'a> {
+	fields: &'a [String],
+	header: Option<&'a [String]>,
+}
+
+impl Record<'_> {
+	pub fn field(&self, i: usize) -> Option<&str> { self.fields.get(i).map(String::as_str) }
+
+	/// the field in the column named `name` by the header
+	pub fn get(&self, name: &str) -> Option<&str> { self.field(self.header?.iter().position(|x| x == name)?) }
+
+	/// field `i` parsed, failing if it's missing or doesn't parse
+	pub fn parse<T: std::str::FromStr>(&self, i: usize) -> Result<T, &'static str> {
+		self.field(i).ok_or("missing field")?.parse().map_err(|_| "field doesn't parse")
+	}
+
+	pub fn len(&self) -> usize { self.fields.len() }
+	pub fn is_empty(&self) -> bool { self.fields.is_empty() }
+}
+
+/// Values that can be built from a CSV record, for [`AssocTable::import_csv`].
+pub trait FromRecord: Sized {
+	fn from_record(record: &Record<'_>) -> Result<Self, &'static str>;
+}
+
+impl<'tx, K, V, F> AssocTable<'tx, RwTxn<'tx>, K, V, F> where
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+{
+	/// Put an entry for every record of `csv`, the key parsed from column `key_column` and the value built from the whole record.
+	/// Returns the number of records read, later records of the same key win.
+	#[throws]
+	pub fn import_csv(&self, csv: impl BufRead, key_column: usize, options: Options) -> usize where
+		K: std::str::FromStr,
+		V: FromRecord,
+	{
+		self.import_csv_with(csv, options, |record| Ok((record.parse(key_column)?, V::from_record(record)?)))?
+	}
+
+	/// Put the entry `record` builds for every record of `csv`, returns the number of records read.
+	/// A record failing to build fails the import with [`Error::MalformedCsv`] and nothing is put.
+	/// Entries are sorted first, into an empty table they're appended which skips the b-tree searches.
+	#[throws]
+	pub fn import_csv_with(&self, csv: impl BufRead, options: Options, mut record: impl FnMut(&Record<'_>) -> Result<(K, V), &'static str>) -> usize {
+		let (tx, dbi) = (self.tx, self.dbi);
+		let mut reader = Reader::new(csv, options.delimiter);
+		let header = if options.header { reader.next()? } else { None };
+		let mut entries = Vec::new();
+		while let Some(fields) = reader.next()? {
+			let (key, value) = record(&Record { fields: &fields, header: header.as_deref() }).map_err(|reason| Error::MalformedCsv { line: reader.line, reason })?;
+			entries.push((rkyv::to_bytes(&key)?.to_vec(), F::frame(rkyv::to_bytes(&value)?)));
+		}
+		let records = entries.len();
+		// stable, so the last of equal keys is the one kept
+		entries.sort_by(|(a, _), (b, _)| lmdb::cmp(tx, dbi, a, b));
+		entries.reverse();
+		entries.dedup_by(|(a, _), (b, _)| a == b);
+		entries.reverse();
+		let flags = lmdb::dbi_flags(tx.raw(), dbi)?;
+		let put_flags = if lmdb::stat(tx.raw(), dbi)?.entries == 0 && !flags.contains(lmdb::DbFlags::DupSort) { lmdb::PutFlags::Append.into() } else { lmdb::PutFlags::empty() };
+		for (mut key, mut value) in entries {
+			lmdb::put(tx, dbi, &mut key, &mut value, put_flags).context(tx, dbi, "import_csv", Some(&key))?;
+		}
+		records
+	}
+}
+
+impl<'tx, 'env: 'tx, TX, K, V, F> AssocTable<'tx, TX, K, V, F> where
+	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe> + 'tx,
+{
+	/// Write a record with the fields `project` picks for every entry with a key in `range`, after a `header` record
+	/// unless it's empty. Returns the number of entries written.
+	#[throws]
+	pub fn export_csv(&self, out: &mut impl Write, range: impl std::ops::RangeBounds<K>, header: &[&str], mut project: impl FnMut(&rkyv::Archived<K>, &rkyv::Archived<V>) -> Vec<String>) -> usize {
+		if !header.is_empty() { write_record(out, header)?; }
+		let range = KeyRange::new(&range)?;
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "export_csv", None)?;
+		let mut entry = range.seek(&mut cursor)?;
+		let mut written = 0;
+		while let Some((key_bytes, value_bytes)) = entry && range.before_end(self.tx, self.dbi, key_bytes) {
+			let key = rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key_bytes).context(self.tx, self.dbi, "export_csv", Some(key_bytes))?;
+			let value = F::unframe(value_bytes).and_then(|x| Ok(rkyv::access::<rkyv::Archived<V>, rkyv::rancor::Error>(x)?)).context(self.tx, self.dbi, "export_csv", Some(key_bytes))?;
+			write_record(out, &project(key, value))?;
+			written += 1;
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+		written
+	}
+}
+
+// quoting only fields that need it
+fn write_record(out: &mut impl Write, fields: &[impl AsRef<str>]) -> std::io::Result<()> {
+	let mut line = String::new();
+	for (i, field) in fields.iter().enumerate() {
+		let field = field.as_ref();
+		if i > 0 { line.push(','); }
+		if field.contains([',', '"', '\r', '\n']) {
+			line.push('"');
+			line.push_str(&field.replace('"', "\"\""));
+			line.push('"');
+		} else {
+			line.push_str(field);
+		}
+	}
+	line.push_str("\r\n");
+	out.write_all(line.as_bytes())
+}
+
+struct Reader<R> {
+	csv: R,
+	delimiter: u8,
+	line: usize, // where the last record ended
+}
+
+impl<R: BufRead> Reader<R> {
+	fn new(csv: R, delimiter: u8) -> Self { Self { csv, delimiter, line: 0 } }
+
+	#[throws]
+	fn next(&mut self) -> Option<Vec<String>> {
+		let mut text = String::new();
+		loop {
+			if self.csv.read_line(&mut text)? == 0 {
+				if text.is_empty() { return None; }
+				break;
+			}
+			self.line += 1;
+			// quotes come in pairs, escaped ones included, so an odd count means a quoted line break
+			if text.bytes().filter(|&b| b == b'"').count() % 2 == 0 { break; }
+		}
+		let text = text.strip_suffix('\n').map_or(text.as_str(), |x| x.strip_suffix('\r').unwrap_or(x));
+		Some(parse(text, char::from(self.delimiter)).map_err(|reason| Error::MalformedCsv { line: self.line, reason })?)
+	}
+}
+
+fn parse(text: &str, delimiter: char) -> Result<Vec<String>, &'static str> {
+	let mut fields = Vec::new();
+	let mut chars = text.chars().peekable();
+	loop {
+		let mut field = String::new();
+		if chars.next_if_eq(&'"').is_some() {
+			loop {
+				match chars.next() {
+					Some('"') if chars.next_if_eq(&'"').is_some() => field.push('"'),
+					Some('"') => break,
+					Some(c) => field.push(c),
+					None => return Err("unterminated quoted field"),
+				}
+			}
+			if chars.peek().is_some_and(|&c| c != delimiter) { return Err("text after a quoted field"); }
+		} else {
+			while let Some(c) = chars.next_if(|&c| c != delimiter) {
+				if c == '"' { return Err("quote in an unquoted field"); }
+				field.push(c);
+			}
+		}
+		fields.push(field);
+		if chars.next().is_none() { return Ok(fields); }
+	}
+}
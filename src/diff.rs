@@ -0,0 +1,108 @@
+//! Differences between two tables of the same types, walked with a cursor over each - they can be in different envs,
+//! e.g. production and a snapshot to detect drift, or an expected and an actual env in tests:
+//! ```ignore
+//! let changes = diff::diff(&prod.get::<Users>(), &snapshot.get::<Users>())?.collect::<Result<Vec<_>, _>>()?;
+//! ```
+//! Values are compared as archived bytes, so the framings may differ. Meant for tables without duplicate keys,
+//! with dupsort each value is its own entry. Keys are ordered by the first table's comparator, so both need the same key flags.
+
+use culpa::throws;
+use crate::{AssocTable, Transaction, RkyvSer, RkyvVal, RkyvDe, Error, lmdb, error::Context, framing::Framing};
+
+/// `a` is the first table's side and `b` the second's
+pub enum Diff<'a, 'b, K: rkyv::Archive, V: rkyv::Archive> {
+	/// only in the second table
+	Added(&'b rkyv::Archived<K>, &'b rkyv::Archived<V>),
+	/// only in the first table
+	Removed(&'a rkyv::Archived<K>, &'a rkyv::Archived<V>),
+	Changed { key: &'b rkyv::Archived<K>, old: &'a rkyv::Archived<V>, new: &'b rkyv::Archived<V> },
+}
+
+impl<K: rkyv::Archive, V: rkyv::Archive> std::fmt::Debug for Diff<'_, '_, K, V> where
+	rkyv::Archived<K>: std::fmt::Debug,
+	rkyv::Archived<V>: std::fmt::Debug,
+{
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Self::Added(key, value) => f.debug_tuple("Added").field(key).field(value).finish(),
+			Self::Removed(key, value) => f.debug_tuple("Removed").field(key).field(value).finish(),
+			Self::Changed { key, old, new } => f.debug_struct("Changed").field("key", key).field("old", old).field("new", new).finish(),
+		}
+	}
+}
+
+/// What changed from `a` to `b`, in key order. It stops early if an entry doesn't validate, logging an error, and after
+/// yielding a cursor's error.
+#[throws]
+pub fn diff<'a, 'b, 'env_a: 'a, 'env_b: 'b, TXA, TXB, K, V, FA, FB>(a: &AssocTable<'a, TXA, K, V, FA>, b: &AssocTable<'b, TXB, K, V, FB>) -> impl Iterator<Item = Result<Diff<'a, 'b, K, V>, Error>> + use<'a, 'b, 'env_a, 'env_b, TXA, TXB, K, V, FA, FB> where
+	TXA: Transaction<'env_a>,
+	TXB: Transaction<'env_b>,
+	FA: Framing,
+	FB: Framing,
+	K: rkyv::Archive + for <'x> rkyv::Serialize<RkyvSer<'x>>,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: for <'x> rkyv::bytecheck::CheckBytes<RkyvVal<'x>> + 'a + 'b,
+	rkyv::Archived<V>: for <'x> rkyv::bytecheck::CheckBytes<RkyvVal<'x>> + rkyv::Deserialize<V, RkyvDe> + 'a + 'b,
+{
+	let (tx, dbi) = (a.tx, a.dbi);
+	let mut cursor_a = lmdb::Cursor::open(tx, dbi).context(tx, dbi, "diff", None)?;
+	let mut cursor_b = lmdb::Cursor::open(b.tx, b.dbi).context(b.tx, b.dbi, "diff", None)?;
+	let (mut entry_a, mut entry_b) = (cursor_a.get(lmdb::CursorOp::First)?, cursor_b.get(lmdb::CursorOp::First)?);
+	// what stepping a cursor failed with, yielded after the diff at the entry it stepped from
+	let mut failed = None::<lmdb::Error>;
+	std::iter::from_fn(move || loop {
+		if let Some(e) = failed.take() {
+			(entry_a, entry_b) = (None, None);
+			return Some(Err(e.into()));
+		}
+		let order = match (entry_a, entry_b) {
+			(None, None) => return None,
+			(Some(_), None) => std::cmp::Ordering::Less,
+			(None, Some(_)) => std::cmp::Ordering::Greater,
+			(Some((key_a, _)), Some((key_b, _))) => lmdb::cmp(tx, dbi, key_a, key_b),
+		};
+		let res = match order {
+			std::cmp::Ordering::Less => {
+				let (key, value) = entry_a?;
+				entry_a = cursor_a.get(lmdb::CursorOp::Next).unwrap_or_else(|e| { failed = Some(e); None });
+				access::<K, V, FA>(key, value).map(|(key, value)| Some(Diff::Removed(key, value)))
+			},
+			std::cmp::Ordering::Greater => {
+				let (key, value) = entry_b?;
+				entry_b = cursor_b.get(lmdb::CursorOp::Next).unwrap_or_else(|e| { failed = Some(e); None });
+				access::<K, V, FB>(key, value).map(|(key, value)| Some(Diff::Added(key, value)))
+			},
+			std::cmp::Ordering::Equal => {
+				let ((key_a, value_a), (key_b, value_b)) = (entry_a?, entry_b?);
+				entry_a = cursor_a.get(lmdb::CursorOp::Next).unwrap_or_else(|e| { failed = Some(e); None });
+				entry_b = cursor_b.get(lmdb::CursorOp::Next).unwrap_or_else(|e| { failed = Some(e); None });
+				match (FA::unframe(value_a), FB::unframe(value_b)) {
+					(Ok(old), Ok(new)) if old == new => Ok(None),
+					_ => access::<K, V, FA>(key_a, value_a).and_then(|(_, old)| {
+						let (key, new) = access::<K, V, FB>(key_b, value_b)?;
+						Ok(Some(Diff::Changed { key, old, new }))
+					}),
+				}
+			},
+		};
+		match res {
+			Ok(Some(diff)) => return Some(Ok(diff)),
+			Ok(None) => {},
+			Err(e) => { log::error!("Error reading an entry to diff: {e}"); return None; },
+		}
+	})
+}
+
+#[throws]
+fn access<'tx, K, V, F>(key: &'tx [u8], value: &'tx [u8]) -> (&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>) where
+	F: Framing,
+	K: rkyv::Archive,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: for <'x> rkyv::bytecheck::CheckBytes<RkyvVal<'x>>,
+	rkyv::Archived<V>: for <'x> rkyv::bytecheck::CheckBytes<RkyvVal<'x>>,
+{
+	(
+		rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key)?,
+		rkyv::access::<rkyv::Archived<V>, rkyv::rancor::Error>(F::unframe(value)?)?,
+	)
+}
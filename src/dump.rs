@@ -0,0 +1,441 @@
+//! Text dumps compatible with upstream `mdb_dump`/`mdb_load`.
+//!
+//! Output of [`dump`]/[`dump_all`] can be fed straight to `mdb_load -s <name>`,
+//! and output of `mdb_dump -s <name>` (or `mdb_dump -a`) can be loaded back with [`load`].
+//! Both `bytevalue` (the default) and `print` (`mdb_dump -p`) formats are supported.
+//! [`export_consistent`] dumps a live env without holding one read transaction for the whole time if need be.
+
+use culpa::{throw, throws};
+use enumflags2::BitFlag;
+use std::io::{BufRead, Write};
+use crate::{Transaction, RwTxn, Error, bookmark::Bookmark, lmdb::{self, DbFlags}};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+	Bytevalue, // every byte is hex-encoded
+	Print,     // printable ascii is written as-is, everything else is hex-escaped
+}
+
+const VERSION: u32 = 3;
+
+// in the same order mdb_dump prints them
+const FLAG_NAMES: [(DbFlags, &str); 6] = [
+	(DbFlags::ReverseKey, "reversekey"),
+	(DbFlags::DupSort, "dupsort"),
+	(DbFlags::IntegerKey, "integerkey"),
+	(DbFlags::DupFixed, "dupfixed"),
+	(DbFlags::IntegerDup, "integerdup"),
+	(DbFlags::ReverseDup, "reversedup"),
+];
+
+const HEX: &[u8; 16] = b"0123456789abcdef";
+
+fn strip_nul(name: &[u8]) -> &[u8] { name.strip_suffix(b"\0").unwrap_or(name) }
+
+// names are registered with the trailing nul, but dumps don't have it
+pub(crate) fn lookup(env: &crate::Env, name: &[u8]) -> Option<lmdb_sys::MDB_dbi> {
+	if name.ends_with(b"\0") { return env.db(name); }
+	env.db(&[name, b"\0"].concat())
+}
+
+pub(crate) fn unknown_db(name: &[u8]) -> Error { Error::UnknownDb(crate::db_name_lossy(name)) }
+
+fn write_val(out: &mut impl Write, bytes: &[u8], format: Format) -> std::io::Result<()> {
+	let mut line = Vec::with_capacity(bytes.len() * 2 + 2);
+	line.push(b' ');
+	for &b in bytes {
+		match format {
+			Format::Print if b == b'\\' => line.extend_from_slice(b"\\\\"),
+			Format::Print if b == b' ' || b.is_ascii_graphic() => line.push(b),
+			Format::Print => line.extend_from_slice(&[b'\\', HEX[usize::from(b >> 4)], HEX[usize::from(b & 0xf)]]),
+			Format::Bytevalue => line.extend_from_slice(&[HEX[usize::from(b >> 4)], HEX[usize::from(b & 0xf)]]),
+		}
+	}
+	line.push(b'\n');
+	out.write_all(&line)
+}
+
+// everything before a db's entries
+#[throws]
+fn write_header<'env>(tx: &impl Transaction<'env>, name: &[u8], dbi: lmdb_sys::MDB_dbi, out: &mut impl Write, format: Format) {
+	let info = lmdb::env_info(tx.env().raw())?;
+	let stat = lmdb::stat(tx.raw(), dbi)?;
+	let flags = lmdb::dbi_flags(tx.raw(), dbi)?;
+
+	writeln!(out, "VERSION={VERSION}")?;
+	writeln!(out, "format={}", match format { Format::Bytevalue => "bytevalue", Format::Print => "print" })?;
+	out.write_all(b"database=")?;
+	out.write_all(strip_nul(name))?;
+	writeln!(out)?;
+	writeln!(out, "type=btree")?;
+	writeln!(out, "mapsize={}", info.map_size)?;
+	writeln!(out, "maxreaders={}", info.max_readers)?;
+	if flags.contains(DbFlags::DupSort) { writeln!(out, "duplicates=1")?; }
+	for (flag, flag_name) in FLAG_NAMES {
+		if flags.contains(flag) { writeln!(out, "{flag_name}=1")?; }
+	}
+	writeln!(out, "db_pagesize={}", stat.page_size)?;
+	writeln!(out, "HEADER=END")?;
+}
+
+/// Dumps a single registered db, returns the number of entries written.
+/// `name` may be given with or without the trailing nul.
+#[throws]
+pub fn dump<'env>(tx: &impl Transaction<'env>, name: &[u8], out: &mut impl Write, format: Format) -> usize {
+	let Some(dbi) = lookup(tx.env(), name) else { throw!(unknown_db(name)) };
+	write_header(tx, name, dbi, out, format)?;
+
+	let mut cursor = lmdb::Cursor::open(tx, dbi)?;
+	let mut entries = 0;
+	while let Some((key, value)) = cursor.get(lmdb::CursorOp::Next)? {
+		write_val(out, key, format)?;
+		write_val(out, value, format)?;
+		entries += 1;
+	}
+	writeln!(out, "DATA=END")?;
+	entries
+}
+
+/// Dumps every registered db one after another, like `mdb_dump -a`, returns the number of entries written.
+#[throws]
+pub fn dump_all<'env>(tx: &impl Transaction<'env>, out: &mut impl Write, format: Format) -> usize {
+	let mut entries = 0;
+	for (name, _) in tx.env().dbs() {
+		entries += dump(tx, name, out, format)?;
+	}
+	entries
+}
+
+/// How [`export_consistent`] holds its read transaction. Pages freed by writes while a read transaction is open can't
+/// be reused until it ends, so a long one makes the map grow under a busy writer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Consistency {
+	/// one read transaction for every db, the export is of a single point in time
+	Snapshot,
+	/// a new read transaction for each db, every db is of a single point in time but not necessarily the same one
+	PerTable,
+	/// A new read transaction every `entries` entries, carrying on after the last entry written. Entries changed
+	/// between chunks are exported as of the chunk they're in.
+	Chunked { entries: usize },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExportStats {
+	pub entries: usize,
+	pub transactions: usize,
+	/// whether every transaction read the same snapshot - true for any [`Consistency`] if nothing was written meanwhile
+	pub consistent: bool,
+}
+
+/// Dumps every registered db like [`dump_all`] while writers carry on, reading in transactions of its own as
+/// `consistency` says. The output loads back with [`load`] or `mdb_load` the same whichever is used.
+#[throws]
+pub fn export_consistent<'env>(env: &'env crate::Env, out: &mut impl Write, format: Format, consistency: Consistency) -> ExportStats {
+	let chunk = match consistency { Consistency::Chunked { entries } => entries.max(1), _ => usize::MAX };
+	let mut tx = env.read_tx()?;
+	let snapshot = tx.id();
+	let mut stats = ExportStats { entries: 0, transactions: 1, consistent: true };
+	let renew = |tx: &mut crate::RoTxn<'env>, stats: &mut ExportStats| -> Result<(), Error> {
+		*tx = env.read_tx()?;
+		stats.transactions += 1;
+		stats.consistent &= tx.id() == snapshot;
+		Ok(())
+	};
+
+	for (i, (name, dbi)) in env.dbs().into_iter().enumerate() {
+		if i > 0 && consistency != Consistency::Snapshot { renew(&mut tx, &mut stats)?; }
+		write_header(&tx, name, dbi, out, format)?;
+		let dupsort = lmdb::dbi_flags(tx.raw(), dbi)?.contains(DbFlags::DupSort);
+		let mut last = None::<Bookmark>;
+		loop {
+			let mut cursor = lmdb::Cursor::open(&tx, dbi)?;
+			let mut entry = match &last {
+				None => cursor.get(lmdb::CursorOp::First),
+				Some(bookmark) => bookmark.resume(&tx, dbi, &mut cursor),
+			}?;
+			let mut written = 0;
+			while let Some((key, value)) = entry && written < chunk {
+				write_val(out, key, format)?;
+				write_val(out, value, format)?;
+				written += 1;
+				last = Some(if dupsort { Bookmark::entry(key, value)? } else { Bookmark::key(key)? });
+				entry = cursor.get(lmdb::CursorOp::Next)?;
+			}
+			stats.entries += written;
+			if entry.is_none() { break; }
+			drop(cursor);
+			renew(&mut tx, &mut stats)?;
+		}
+		writeln!(out, "DATA=END")?;
+	}
+	stats
+}
+
+pub(crate) struct Lines<R> {
+	pub(crate) input: R,
+	pub(crate) line: usize,
+}
+
+impl<R: BufRead> Lines<R> {
+	#[throws]
+	fn next(&mut self) -> Option<Vec<u8>> {
+		let mut buf = Vec::new();
+		if self.input.read_until(b'\n', &mut buf)? == 0 { return None; }
+		self.line += 1;
+		if buf.last() == Some(&b'\n') { buf.pop(); }
+		Some(buf)
+	}
+
+	#[throws]
+	pub(crate) fn expect_next(&mut self) -> Vec<u8> {
+		match self.next()? {
+			Some(line) => line,
+			None => throw!(self.malformed("unexpected end of input")),
+		}
+	}
+
+	fn malformed(&self, reason: &'static str) -> Error { Error::MalformedDump { line: self.line, reason } }
+}
+
+pub(crate) struct Header {
+	pub(crate) format: Format,
+	pub(crate) name: Option<Vec<u8>>,
+	pub(crate) flags: enumflags2::BitFlags<DbFlags>,
+}
+
+// None if the input ended before a new header started
+#[throws]
+pub(crate) fn read_header(lines: &mut Lines<impl BufRead>) -> Option<Header> {
+	let Some(first) = lines.next()? else { return None; };
+	let Some(version) = first.strip_prefix(b"VERSION=") else { throw!(lines.malformed("expected VERSION")) };
+	match std::str::from_utf8(version).ok().and_then(|x| x.parse::<u32>().ok()) {
+		Some(1..=VERSION) => {},
+		_ => throw!(lines.malformed("unsupported VERSION")),
+	}
+
+	let mut header = Header { format: Format::Bytevalue, name: None, flags: enumflags2::BitFlags::empty() };
+	loop {
+		let line = lines.expect_next()?;
+		if line == b"HEADER=END" { break; }
+		let Some(eq) = line.iter().position(|&b| b == b'=') else { throw!(lines.malformed("expected key=value")) };
+		let (key, value) = (&line[..eq], &line[eq + 1..]);
+		match key {
+			b"format" => header.format = match value {
+				b"bytevalue" => Format::Bytevalue,
+				b"print" => Format::Print,
+				_ => throw!(lines.malformed("unsupported format")),
+			},
+			b"database" => header.name = Some(value.to_vec()),
+			b"type" if value != b"btree" => throw!(lines.malformed("unsupported type")),
+			b"duplicates" if value == b"1" => header.flags |= DbFlags::DupSort,
+			_ => {
+				// mapsize, maxreaders, db_pagesize etc are properties of the env we're loading into, so they're ignored
+				if let Some((flag, _)) = FLAG_NAMES.iter().find(|(_, flag_name)| flag_name.as_bytes() == key) && value == b"1" {
+					header.flags |= *flag;
+				}
+			},
+		}
+	}
+	Some(header)
+}
+
+fn unhex(hi: u8, lo: u8) -> Option<u8> {
+	let hi = char::from(hi).to_digit(16)?;
+	let lo = char::from(lo).to_digit(16)?;
+	u8::try_from(hi << 4 | lo).ok()
+}
+
+#[throws]
+pub(crate) fn parse_val(lines: &Lines<impl BufRead>, line: &[u8], format: Format) -> Vec<u8> {
+	let Some(line) = line.strip_prefix(b" ") else { throw!(lines.malformed("expected a data line")) };
+	let mut out = Vec::with_capacity(line.len());
+	match format {
+		Format::Bytevalue => {
+			if line.len() % 2 != 0 { throw!(lines.malformed("odd number of hex digits")); }
+			for pair in line.chunks_exact(2) {
+				let Some(b) = unhex(pair[0], pair[1]) else { throw!(lines.malformed("invalid hex digit")) };
+				out.push(b);
+			}
+		},
+		Format::Print => {
+			let mut rest = line;
+			while let Some((&b, tail)) = rest.split_first() {
+				rest = tail;
+				if b != b'\\' { out.push(b); continue; }
+				match rest {
+					[b'\\', tail @ ..] => { out.push(b'\\'); rest = tail; },
+					[hi, lo, tail @ ..] => {
+						let Some(b) = unhex(*hi, *lo) else { throw!(lines.malformed("invalid escape")) };
+						out.push(b);
+						rest = tail;
+					},
+					_ => throw!(lines.malformed("truncated escape")),
+				}
+			}
+		},
+	}
+	out
+}
+
+/// Loads every database section from `input` into the matching registered dbs, like `mdb_load`.
+/// Existing entries with the same keys are overwritten.
+/// Returns the number of entries put.
+#[throws]
+pub fn load(tx: &RwTxn, input: impl BufRead) -> usize {
+	let mut lines = Lines { input, line: 0 };
+	let mut entries = 0;
+	while let Some(header) = read_header(&mut lines)? {
+		let Some(name) = header.name else { throw!(lines.malformed("loading into the unnamed db isn't supported")) };
+		let Some(dbi) = lookup(tx.env(), &name) else { throw!(unknown_db(&name)) };
+		let flags = lmdb::dbi_flags(tx.raw(), dbi)?;
+		if flags != header.flags {
+			log::warn!("loading {} with flags {:?} into a db with flags {flags:?}", crate::db_name_lossy(&name), header.flags);
+		}
+
+		loop {
+			let key_line = lines.expect_next()?;
+			if key_line == b"DATA=END" { break; }
+			let mut key = parse_val(&lines, &key_line, header.format)?;
+			let value_line = lines.expect_next()?;
+			let mut value = parse_val(&lines, &value_line, header.format)?;
+			lmdb::put(tx, dbi, &mut key, &mut value, lmdb::PutFlags::empty())?;
+			entries += 1;
+		}
+	}
+	entries
+}
+
+#[cfg(test)]
+mod tests {
+	use std::process::Command;
+	use crate::{DbName, Env, RawTable, Transaction, TempDir, lmdb::DbFlags};
+	use super::{Consistency, Format};
+
+	struct Notes;
+
+	impl DbName for Notes {
+		type Table<'tx, 'env: 'tx, TX: Transaction<'env> + 'tx> = RawTable<'tx, TX>;
+		const NAME: &'static [u8] = b"notes\0";
+	}
+
+	struct Tags;
+
+	impl DbName for Tags {
+		type Table<'tx, 'env: 'tx, TX: Transaction<'env> + 'tx> = RawTable<'tx, TX>;
+		const NAME: &'static [u8] = b"tags\0";
+
+		fn flags() -> enumflags2::BitFlags<DbFlags> { DbFlags::DupSort.into() }
+	}
+
+	fn builder() -> crate::env::EnvBuilder { Env::builder().unwrap().with::<Notes>().with::<Tags>() }
+
+	fn entries<N: DbName>(env: &Env) -> Vec<(Vec<u8>, Vec<u8>)> {
+		let tx = env.read_tx().unwrap();
+		RawTable::build(&tx, env.db(N::NAME).unwrap()).iter().unwrap().map(|entry| { let (key, value) = entry.unwrap(); (key.to_vec(), value.to_vec()) }).collect()
+	}
+
+	// bytes print has to escape, a backslash, a space and a nul among them
+	fn fill(env: &Env) {
+		let tx = env.write_tx().unwrap();
+		tx.get::<Notes>().put(b"a", b"plain").unwrap();
+		tx.get::<Notes>().put(b"b c", b"\\ \0\xff\n").unwrap();
+		tx.get::<Notes>().put(b"\x01", b"").unwrap();
+		tx.get::<Tags>().put(b"t", b"1").unwrap();
+		tx.get::<Tags>().put(b"t", b"2").unwrap();
+		tx.commit().unwrap();
+	}
+
+	fn dump_all(env: &Env, format: Format) -> Vec<u8> {
+		let mut out = Vec::new();
+		assert_eq!(super::dump_all(&env.read_tx().unwrap(), &mut out, format).unwrap(), 5);
+		out
+	}
+
+	fn load(env: &Env, input: &[u8]) -> usize {
+		let tx = env.write_tx().unwrap();
+		let entries = super::load(&tx, input).unwrap();
+		tx.commit().unwrap();
+		entries
+	}
+
+	#[test]
+	fn dump_then_load() {
+		let source = builder().build_temp().unwrap();
+		fill(&source);
+		for format in [Format::Bytevalue, Format::Print] {
+			let target = builder().build_temp().unwrap();
+			assert_eq!(load(&target, &dump_all(&source, format)), 5);
+			assert_eq!(entries::<Notes>(&target), entries::<Notes>(&source));
+			assert_eq!(entries::<Tags>(&target), entries::<Tags>(&source));
+		}
+	}
+
+	#[test]
+	fn header_like_mdb_dump() {
+		let env = builder().build_temp().unwrap();
+		fill(&env);
+		let mut out = Vec::new();
+		super::dump(&env.read_tx().unwrap(), b"tags", &mut out, Format::Print).unwrap();
+		let out = String::from_utf8(out).unwrap();
+		let keys: Vec<_> = out.lines().take_while(|line| !line.starts_with(' ')).map(|line| line.split('=').next().unwrap()).collect();
+		assert_eq!(keys, ["VERSION", "format", "database", "type", "mapsize", "maxreaders", "duplicates", "dupsort", "db_pagesize", "HEADER"]);
+		assert!(out.ends_with("HEADER=END\n t\n 1\n t\n 2\nDATA=END\n"));
+	}
+
+	// as written by mdb_dump -p -s notes and mdb_dump -s notes of LMDB 0.9.31
+	#[test]
+	fn loads_mdb_dump_output() {
+		let print = b"VERSION=3\nformat=print\ndatabase=notes\ntype=btree\nmapsize=1048576\nmaxreaders=126\ndb_pagesize=4096\nHEADER=END\n a\n \\00x\\\\ y\nDATA=END\n";
+		let bytevalue = b"VERSION=3\nformat=bytevalue\ndatabase=notes\ntype=btree\nmapsize=1048576\nmaxreaders=126\ndb_pagesize=4096\nHEADER=END\n 61\n 00785c2079\nDATA=END\n";
+		for input in [&print[..], &bytevalue[..]] {
+			let env = builder().build_temp().unwrap();
+			assert_eq!(load(&env, input), 1);
+			assert_eq!(entries::<Notes>(&env), [(b"a".to_vec(), b"\0x\\ y".to_vec())]);
+		}
+	}
+
+	#[test]
+	fn malformed() {
+		let env = builder().build_temp().unwrap();
+		let tx = env.write_tx().unwrap();
+		let input = b"VERSION=3\ndatabase=notes\nHEADER=END\n 6\n 00\nDATA=END\n";
+		assert!(matches!(super::load(&tx, &input[..]), Err(crate::Error::MalformedDump { line: 4, .. })));
+		let input = b"VERSION=3\ndatabase=other\nHEADER=END\nDATA=END\n";
+		assert!(matches!(super::load(&tx, &input[..]), Err(crate::Error::UnknownDb(_))));
+	}
+
+	#[test]
+	fn export_consistent_like_dump_all() {
+		let env = builder().build_temp().unwrap();
+		fill(&env);
+		for consistency in [Consistency::Snapshot, Consistency::PerTable, Consistency::Chunked { entries: 1 }] {
+			let mut out = Vec::new();
+			let stats = super::export_consistent(&env, &mut out, Format::Print, consistency).unwrap();
+			assert_eq!((stats.entries, stats.consistent), (5, true));
+			assert_eq!(out, dump_all(&env, Format::Print));
+		}
+	}
+
+	// with the real tools if they're installed, the other way round too
+	#[test]
+	fn with_mdb_load_and_mdb_dump() {
+		let source = builder().build_temp().unwrap();
+		fill(&source);
+		let dir = TempDir::new("batadase-dump").unwrap();
+		let dump = dir.0.join("dump");
+		std::fs::write(&dump, dump_all(&source, Format::Print)).unwrap();
+		let loaded = dir.0.join("loaded");
+		std::fs::create_dir(&loaded).unwrap();
+		match Command::new("mdb_load").arg("-f").arg(&dump).arg(&loaded).output() {
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return eprintln!("mdb_load isn't installed, skipped"),
+			output => assert!(output.unwrap().status.success()),
+		}
+
+		let output = Command::new("mdb_dump").arg("-a").arg("-p").arg(&loaded).output().unwrap();
+		assert!(output.status.success());
+		let target = builder().build_temp().unwrap();
+		assert_eq!(load(&target, &output.stdout), 5);
+		assert_eq!(entries::<Notes>(&target), entries::<Notes>(&source));
+		assert_eq!(entries::<Tags>(&target), entries::<Tags>(&source));
+	}
+}
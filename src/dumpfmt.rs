@@ -0,0 +1,250 @@
+//! A binary export format for tools that aren't written in Rust, simpler to parse than [`dump`](crate::dump)'s text:
+//! every length is a little-endian u32 before the bytes it's the length of, and there's no escaping.
+//! ```text
+//! file   = "BATADUMP" version:u32 table* 0u8
+//! table  = 1u8 name schema flags:u32 entry* 0xFFFF_FFFF
+//! name   = len:u32 bytes                  the db name, without the trailing nul
+//! schema = len:u32 bytes                  UTF-8, empty if there's no description
+//! entry  = len:u32 key len:u32 value      keys are at most 511 bytes, so a key length of 0xFFFF_FFFF ends the table
+//! ```
+//! `flags` are the db's `MDB_*` flags as in lmdb.h - `MDB_REVERSEKEY` 0x02, `MDB_DUPSORT` 0x04, `MDB_INTEGERKEY` 0x08,
+//! `MDB_DUPFIXED` 0x10, `MDB_INTEGERDUP` 0x20 and `MDB_REVERSEDUP` 0x40. Keys and values are the stored bytes
+//! (framing included, integer keys in the exporting machine's byte order), dupsort values one entry each.
+//!
+//! Values of typed tables are rkyv archives, which aren't self-describing. [`Writer::table_of`] puts the table's Rust
+//! type in `schema` - e.g. `batadase::assoc_table::AssocTable<'_, batadase::transaction::RwTxn<'_>, u64, my_app::User>` -
+//! for a reader to pick a decoder of `my_app::User`'s archived layout by, [`Writer::table`] takes any description.
+//! In Python:
+//! ```text
+//! def read(f):
+//!     u32 = lambda: struct.unpack("<I", f.read(4))[0]
+//!     assert f.read(8) == b"BATADUMP" and u32() == 1
+//!     while f.read(1) == b"\x01":
+//!         name, schema, flags, entries = f.read(u32()), f.read(u32()).decode(), u32(), []
+//!         while (n := u32()) != 0xFFFF_FFFF: entries.append((f.read(n), f.read(u32())))
+//!         yield name, schema, flags, entries
+//! ```
+//! The format only changes with [`VERSION`], and readers should refuse versions they don't know.
+
+use culpa::{throw, throws};
+use enumflags2::{BitFlag, BitFlags};
+use std::io::{BufRead, Read, Write};
+use crate::{Transaction, RwTxn, DbName, Error, dump, lmdb::{self, DbFlags}};
+
+pub const MAGIC: [u8; 8] = *b"BATADUMP";
+pub const VERSION: u32 = 1;
+
+const END: u8 = 0;
+const TABLE: u8 = 1;
+const END_OF_TABLE: u32 = u32::MAX;
+
+// flags a db is created with, rather than MDB_CREATE
+fn db_flags(flags: BitFlags<DbFlags>) -> BitFlags<DbFlags> { flags & !DbFlags::Create }
+
+fn write_bytes(out: &mut impl Write, bytes: &[u8]) -> std::io::Result<()> {
+	let len = u32::try_from(bytes.len()).map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "over 4GiB"))?;
+	out.write_all(&len.to_le_bytes())?;
+	out.write_all(bytes)
+}
+
+pub struct Writer<W> {
+	out: W,
+}
+
+impl<W: Write> Writer<W> {
+	/// writes the file header
+	#[throws]
+	pub fn new(mut out: W) -> Self {
+		out.write_all(&MAGIC)?;
+		out.write_all(&VERSION.to_le_bytes())?;
+		Self { out }
+	}
+
+	/// Writes a table's entries as of `tx`, returns how many. `name` may be given with or without the trailing nul.
+	#[throws]
+	pub fn table<'env>(&mut self, tx: &impl Transaction<'env>, name: &[u8], schema: Option<&str>) -> usize {
+		let Some(dbi) = dump::lookup(tx.env(), name) else { throw!(dump::unknown_db(name)) };
+		let flags = lmdb::dbi_flags(tx.raw(), dbi)?;
+		self.header(name, schema, flags)?;
+		let mut cursor = lmdb::Cursor::open(tx, dbi)?;
+		let mut entries = 0;
+		while let Some((key, value)) = cursor.get(lmdb::CursorOp::Next)? {
+			self.entry(key, value)?;
+			entries += 1;
+		}
+		self.out.write_all(&END_OF_TABLE.to_le_bytes())?;
+		entries
+	}
+
+	/// [`Writer::table`] with the table's type as its schema
+	#[throws]
+	pub fn table_of<'env, N: DbName>(&mut self, tx: &impl Transaction<'env>) -> usize {
+		self.table(tx, N::NAME, Some(std::any::type_name::<N::Table<'static, 'static, RwTxn<'static>>>()))?
+	}
+
+	/// every registered table without a schema, returns how many entries
+	#[throws]
+	pub fn all<'env>(&mut self, tx: &impl Transaction<'env>) -> usize {
+		let mut entries = 0;
+		for (name, _) in tx.env().dbs() {
+			entries += self.table(tx, name, None)?;
+		}
+		entries
+	}
+
+	/// writes the end of the file, returns the output
+	#[throws]
+	pub fn finish(mut self) -> W {
+		self.out.write_all(&[END])?;
+		self.out.flush()?;
+		self.out
+	}
+
+	#[throws]
+	fn header(&mut self, name: &[u8], schema: Option<&str>, flags: BitFlags<DbFlags>) {
+		self.out.write_all(&[TABLE])?;
+		write_bytes(&mut self.out, name.strip_suffix(b"\0").unwrap_or(name))?;
+		write_bytes(&mut self.out, schema.unwrap_or_default().as_bytes())?;
+		self.out.write_all(&db_flags(flags).bits().to_le_bytes())?;
+	}
+
+	#[throws]
+	fn entry(&mut self, key: &[u8], value: &[u8]) {
+		write_bytes(&mut self.out, key)?;
+		write_bytes(&mut self.out, value)?;
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableHeader {
+	/// without the trailing nul
+	pub name: Vec<u8>,
+	pub schema: Option<String>,
+	pub flags: BitFlags<DbFlags>,
+}
+
+/// Reads a file table by table: [`Reader::next_table`], then [`Reader::next_entry`] until it's None. Entries not
+/// read are skipped by the next `next_table`.
+pub struct Reader<R> {
+	input: R,
+	offset: u64,
+	in_table: bool,
+	ended: bool,
+}
+
+impl<R: Read> Reader<R> {
+	/// reads the file header
+	#[throws]
+	pub fn new(input: R) -> Self {
+		let mut reader = Self { input, offset: 0, in_table: false, ended: false };
+		if reader.array::<8>()? != MAGIC { throw!(reader.malformed("not a batadase export")); }
+		if reader.u32()? != VERSION { throw!(reader.malformed("unsupported version")); }
+		reader
+	}
+
+	/// None at the end of the file
+	#[throws]
+	pub fn next_table(&mut self) -> Option<TableHeader> {
+		while self.in_table { self.next_entry()?; }
+		if self.ended { return None; }
+		match self.array::<1>()? {
+			[END] => { self.ended = true; return None; },
+			[TABLE] => {},
+			_ => throw!(self.malformed("expected a table")),
+		}
+		let name = self.bytes()?;
+		let schema = self.bytes()?;
+		let Ok(schema) = String::from_utf8(schema) else { throw!(self.malformed("schema isn't UTF-8")) };
+		let Ok(flags) = BitFlags::<DbFlags>::from_bits(self.u32()?) else { throw!(self.malformed("unknown flags")) };
+		self.in_table = true;
+		Some(TableHeader { name, schema: (!schema.is_empty()).then_some(schema), flags })
+	}
+
+	/// key and value, None at the end of the table
+	#[throws]
+	pub fn next_entry(&mut self) -> Option<(Vec<u8>, Vec<u8>)> {
+		if !self.in_table { return None; }
+		let len = self.u32()?;
+		if len == END_OF_TABLE { self.in_table = false; return None; }
+		let key = self.exactly(len)?;
+		let value = self.bytes()?;
+		Some((key, value))
+	}
+
+	fn malformed(&self, reason: &'static str) -> Error { Error::MalformedExport { offset: self.offset, reason } }
+
+	#[throws]
+	fn array<const N: usize>(&mut self) -> [u8; N] {
+		let mut buf = [0; N];
+		match self.input.read_exact(&mut buf) {
+			Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => throw!(self.malformed("truncated")),
+			x => x?,
+		}
+		self.offset += N as u64;
+		buf
+	}
+
+	#[expect(unused_braces)]
+	#[throws]
+	fn u32(&mut self) -> u32 { u32::from_le_bytes(self.array()?) }
+
+	#[throws]
+	fn bytes(&mut self) -> Vec<u8> {
+		let len = self.u32()?;
+		self.exactly(len)?
+	}
+
+	// doesn't allocate `len` up front, so a corrupt length fails as truncated rather than out of memory
+	#[throws]
+	fn exactly(&mut self, len: u32) -> Vec<u8> {
+		let mut buf = Vec::new();
+		(&mut self.input).take(len.into()).read_to_end(&mut buf)?;
+		if buf.len() != len as usize { throw!(self.malformed("truncated")); }
+		self.offset += u64::from(len);
+		buf
+	}
+}
+
+/// Puts every table of `input` into the registered db of the same name, returns how many entries. Existing entries
+/// with the same keys are overwritten.
+#[throws]
+pub fn load(tx: &RwTxn, input: impl Read) -> usize {
+	let mut reader = Reader::new(input)?;
+	let mut entries = 0;
+	while let Some(table) = reader.next_table()? {
+		let Some(dbi) = dump::lookup(tx.env(), &table.name) else { throw!(dump::unknown_db(&table.name)) };
+		let flags = db_flags(lmdb::dbi_flags(tx.raw(), dbi)?);
+		if flags != table.flags {
+			log::warn!("loading {} with flags {:?} into a db with flags {flags:?}", crate::db_name_lossy(&table.name), table.flags);
+		}
+		while let Some((mut key, mut value)) = reader.next_entry()? {
+			lmdb::put(tx, dbi, &mut key, &mut value, lmdb::PutFlags::empty())?;
+			entries += 1;
+		}
+	}
+	entries
+}
+
+/// Converts a text dump - of [`dump`](crate::dump) or `mdb_dump` - to this format without an env, returns how many
+/// entries. Text dumps have no schemas.
+#[throws]
+pub fn from_text(input: impl BufRead, out: impl Write) -> usize {
+	let mut lines = dump::Lines { input, line: 0 };
+	let mut writer = Writer::new(out)?;
+	let mut entries = 0;
+	while let Some(header) = dump::read_header(&mut lines)? {
+		let Some(name) = header.name else { throw!(Error::MalformedDump { line: lines.line, reason: "the unnamed db isn't supported" }) };
+		writer.header(&name, None, header.flags)?;
+		loop {
+			let key_line = lines.expect_next()?;
+			if key_line == b"DATA=END" { break; }
+			let key = dump::parse_val(&lines, &key_line, header.format)?;
+			let value_line = lines.expect_next()?;
+			writer.entry(&key, &dump::parse_val(&lines, &value_line, header.format)?)?;
+			entries += 1;
+		}
+		writer.out.write_all(&END_OF_TABLE.to_le_bytes())?;
+	}
+	writer.finish()?;
+	entries
+}
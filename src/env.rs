@@ -1,20 +1,211 @@
-use culpa::throws;
+use culpa::{throw, throws};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::future::Future;
 
-use super::{lmdb::{self, DbFlags}, DbName, RoTxn, RwTxn, Transaction, error::Error, Table};
+use super::{lmdb::{self, DbFlags}, DbName, RoTxn, RwTxn, ReadView, Transaction, error::Error, Table, verify, snapshot, writer_lock, latency, preflight, health, changelog, borrows};
+#[cfg(feature = "rkyv")] use super::schema;
 
 pub struct Env {
 	raw_env: *mut lmdb_sys::MDB_env,
-	dbs: HashMap<&'static [u8], lmdb_sys::MDB_dbi>,
+	dbs: HashMap<Cow<'static, [u8]>, lmdb_sys::MDB_dbi>,
+	root: Option<(&'static [u8], lmdb_sys::MDB_dbi)>, // see EnvBuilder::with_root, not in dbs
+	pub(crate) changelog: Option<lmdb_sys::MDB_dbi>, // see EnvBuilder::changelog
+	checks: HashMap<&'static [u8], verify::CheckEntry>,
 	write_sema: tokio::sync::Semaphore,
+	unsynced: std::sync::atomic::AtomicUsize, // bytes committed since the last Env::sync
+	backpressure: Option<(f32, Backpressure)>,
+	reader_alert: Option<(f32, fn(ReaderSlots))>,
+	readers_checked: std::sync::Mutex<(Option<std::time::Instant>, bool)>, // when, and whether usage was over the watermark
+	quotas: HashMap<lmdb_sys::MDB_dbi, Quota>,
+	size_limits: HashMap<lmdb_sys::MDB_dbi, SizeLimits>,
+	dup_orders: HashMap<lmdb_sys::MDB_dbi, lmdb::DupOrder>,
+	growth: (Growth, usize),
+	map_full: std::sync::atomic::AtomicBool, // a write ran into MapFull since the map last grew
+	txns: std::sync::Mutex<usize>, // open in this process, only counted with a growth policy
+	idle: std::sync::Condvar,
+	invariants: verify::InvariantsReport,
+	pub(crate) commits: latency::Recorder,
+	syncs: latency::Recorder,
+	amplification: Option<std::sync::Mutex<WriteAmplification>>, // with EnvBuilder::track_write_amplification
 }
 
 pub struct EnvBuilder {
 	raw_env: *mut lmdb_sys::MDB_env,
-	dbs: Vec<(&'static [u8], enumflags2::BitFlags<lmdb::DbFlags>)>,
+	dbs: Vec<(&'static [u8], enumflags2::BitFlags<lmdb::DbFlags>, verify::CheckEntry)>,
+	root: Option<(&'static [u8], enumflags2::BitFlags<lmdb::DbFlags>)>,
+	maxdbs: Option<u32>,
+	read_only: bool,
+	write_map: bool,
+	durability: Durability,
+	read_ahead: bool,
+	track_amplification: bool,
+	backpressure: Option<(f32, Backpressure)>,
+	reader_alert: Option<(f32, fn(ReaderSlots))>,
+	quotas: Vec<(&'static [u8], Quota)>,
+	size_limits: Vec<(&'static [u8], SizeLimits)>,
+	growth: (Growth, usize),
+	#[cfg(feature = "rkyv")] fingerprints: Vec<(&'static [u8], u64)>,
+	dup_orders: HashMap<&'static [u8], lmdb::DupOrder>,
+	#[cfg(feature = "rkyv")] schemas: Option<Vec<&'static [u8]>>, // the tables marked changed, None without a schema registry
+	invariants: Vec<(&'static str, verify::OnViolation, verify::Invariant)>,
 }
 
+/// see [`Env::stat_all`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnvStat {
+	pub txn_id: usize, // of the read transaction everything was read in
+	pub root: lmdb::Stat,
+	pub dbs: Vec<(Vec<u8>, lmdb::Stat)>, // sorted by name, with the trailing nul
+	/// the root db and all the others summed up, with the biggest depth
+	pub total: lmdb::Stat,
+}
+
+/// see [`Env::usage`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapUsage {
+	/// up to the last page ever used, pages on the freelist included
+	pub used_pages: usize,
+	pub total_pages: usize,
+	pub page_size: usize,
+	pub percent: f32,
+}
+
+/// How far an [`Env::chunked_write`] got, pass the same one again to carry on after a failure.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChunkProgress {
+	/// items in committed chunks, they're skipped when the write is resumed
+	pub items: usize,
+	pub chunks: usize,
+	/// the chunk size the last chunk committed with, smaller than asked for after running into TxnFull
+	pub chunk_size: usize,
+}
+
+/// see [`Env::write_amplification`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WriteAmplification {
+	pub commits: u64,
+	/// key and value bytes put, as [`RwTxn::bytes_written`] counts them
+	pub logical_bytes: u64,
+	/// pages the commits wrote, the meta page of each included
+	pub pages: u64,
+	pub page_size: usize,
+	/// the same for the last commit alone
+	pub last_logical_bytes: u64,
+	pub last_pages: u64,
+}
+
+impl WriteAmplification {
+	/// bytes written to the data file per logical byte, over every tracked commit
+	pub fn ratio(&self) -> f64 { (self.pages * self.page_size as u64) as f64 / self.logical_bytes.max(1) as f64 }
+	pub fn last_ratio(&self) -> f64 { (self.last_pages * self.page_size as u64) as f64 / self.last_logical_bytes.max(1) as f64 }
+}
+
+/// see [`Env::readers_in_use`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReaderSlots {
+	pub in_use: u32,
+	pub max: u32,
+}
+
+impl ReaderSlots {
+	pub fn usage(&self) -> f32 { self.in_use as f32 / self.max as f32 }
+}
+
+/// How much a commit waits for the disk, from safest and slowest to fastest.
+/// Set for the env with [`EnvBuilder::durability`] and for single commits with [`RwTxn::set_durability`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+	/// a commit returns once it's on disk
+	Full,
+	/// the data is synced but the meta page isn't, so a crash may lose the last transaction but never corrupts the env
+	#[default]
+	NoMetaSync,
+	/// nothing is synced and the OS writes pages back when it wants - a crash may lose recent transactions,
+	/// or corrupt the env if the filesystem doesn't preserve write order
+	NoSync,
+	/// MDB_MAPASYNC, the map is flushed asynchronously - as with `NoSync` the last transactions can be lost,
+	/// it implies [`EnvBuilder::write_map`] where flushes happen through the map. For a single commit that's
+	/// stronger than the env's it's the same as `Full`.
+	Async,
+}
+
+impl Durability {
+	// every flag any level sets, cleared before setting another level's
+	pub(crate) const MASK: u32 = lmdb_sys::MDB_NOMETASYNC | lmdb_sys::MDB_NOSYNC | lmdb_sys::MDB_MAPASYNC;
+
+	pub(crate) fn flags(self) -> u32 {
+		match self {
+			Self::Full => 0,
+			Self::NoMetaSync => lmdb_sys::MDB_NOMETASYNC,
+			Self::NoSync => lmdb_sys::MDB_NOSYNC,
+			Self::Async => lmdb_sys::MDB_MAPASYNC,
+		}
+	}
+
+	// whether a commit with this syncs more than one with `other`
+	pub(crate) fn stronger_than(self, other: Self) -> bool {
+		let rank = |x| match x { Self::NoSync => 0, Self::Async => 1, Self::NoMetaSync => 2, Self::Full => 3 };
+		rank(self) > rank(other)
+	}
+}
+
+/// What write transactions do once the map is over [`EnvBuilder::backpressure`]'s watermark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+	/// begin anyway, with puts failing
+	Reject,
+	/// wait up to this long before beginning for usage to drop - old readers finishing so their pages can be reused,
+	/// or another process deleting data - then reject
+	Block(std::time::Duration),
+}
+
+/// How the map grows once it's full, see [`EnvBuilder::growth`].
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Growth {
+	/// the mapsize the env was opened with is all there is
+	#[default]
+	Fixed,
+	GrowBy(usize),
+	GrowFactor(f64),
+}
+
+// how often read_tx counts reader slots with a reader alert
+const READER_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+// how long growing waits for the process' other transactions to end
+const GROW_WAIT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// see [`EnvBuilder::quota`], limits that are None don't apply
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quota {
+	pub max_entries: Option<usize>,
+	pub max_bytes: Option<usize>,
+}
+
+/// see [`EnvBuilder::size_limits`], limits that are None don't apply
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SizeLimits {
+	/// keys over it are logged
+	pub soft_key: Option<usize>,
+	/// keys over it fail with [`Error::TooLarge`]
+	pub hard_key: Option<usize>,
+	pub soft_value: Option<usize>,
+	pub hard_value: Option<usize>,
+}
+
+/// How [`Env::open_recovering`] got the env open.
+#[derive(Debug)]
+pub enum Recovery {
+	/// the last commit opened and verified fine
+	Latest,
+	/// the last commit didn't, so the commit before it was opened from the recovery copy
+	Previous { error: Error },
+}
+
+// only used by build_existing, where the number of dbs isn't known before opening
+const EXISTING_MAXDBS: u32 = 128;
+
 unsafe impl Send for Env {}
 unsafe impl Sync for Env {}
 unsafe impl Send for EnvBuilder {}
@@ -44,11 +235,285 @@ fn complain_about_lock_hold(instant: std::time::Instant) {
 impl Env {
 	#[throws]
 	pub fn builder() -> EnvBuilder {
-		EnvBuilder { raw_env: lmdb::env_create()?, dbs: Vec::new() }
+		EnvBuilder {
+			raw_env: lmdb::env_create()?, dbs: Vec::new(), root: None, maxdbs: None, read_only: false, write_map: false, durability: Durability::default(), read_ahead: false, track_amplification: false,
+			backpressure: None, reader_alert: None, quotas: Vec::new(), size_limits: Vec::new(), growth: (Growth::Fixed, usize::MAX), dup_orders: HashMap::new(), invariants: Vec::new(),
+			#[cfg(feature = "rkyv")] fingerprints: Vec::new(),
+			#[cfg(feature = "rkyv")] schemas: None,
+		}
+	}
+
+	/// Check the env directory `path` before opening it with `mapsize`, for messages saying what's wrong instead of
+	/// the errno LMDB's open fails with - see [`preflight`].
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn preflight(path: &std::path::Path, mapsize: usize) -> preflight::Report { preflight::check(path, mapsize)? }
+
+	/// The handle of a registered db. Every registered db is opened once when the env is built, in a single transaction,
+	/// so tables never open dbs themselves - LMDB doesn't allow opening them from concurrent transactions.
+	pub fn db(&self, name: &[u8]) -> Option<lmdb_sys::MDB_dbi> {
+		match self.root {
+			Some((root, dbi)) if root == name => Some(dbi),
+			_ => self.dbs.get(name).copied(),
+		}
+	}
+
+	/// the name `dbi` was registered under, with the trailing nul
+	pub fn db_name(&self, dbi: lmdb_sys::MDB_dbi) -> Option<&[u8]> {
+		if let Some((root, root_dbi)) = self.root && root_dbi == dbi { return Some(root); }
+		self.dbs.iter().find(|&(_, &x)| x == dbi).map(|(name, _)| &**name)
+	}
+
+	/// all registered dbs, sorted by name - not the one registered with [`EnvBuilder::with_root`]
+	pub fn dbs(&self) -> Vec<(&[u8], lmdb_sys::MDB_dbi)> {
+		let mut dbs = self.dbs.iter().map(|(name, &dbi)| (name.as_ref(), dbi)).collect::<Vec<_>>();
+		dbs.sort_unstable();
+		dbs
+	}
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn info(&self) -> lmdb::EnvInfo { lmdb::env_info(self.raw_env)? }
+
+	/// stat of the unnamed root db, which only holds the names of the other dbs
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn stat(&self) -> lmdb::Stat { lmdb::env_stat(self.raw_env)? }
+
+	/// How much of the map is used, e.g. for a health check. Freed pages are reused before the map grows into
+	/// unused ones, so this only goes down after a compacting copy.
+	#[throws]
+	pub fn usage(&self) -> MapUsage {
+		let info = self.info()?;
+		let page_size = self.stat()?.page_size as usize;
+		let (used_pages, total_pages) = (info.last_page + 1, info.map_size / page_size);
+		MapUsage { used_pages, total_pages, page_size, percent: used_pages as f32 / total_pages as f32 * 100. }
+	}
+
+	/// how the [`EnvBuilder::on_open`] invariants fared when the env was built
+	pub fn invariants(&self) -> &verify::InvariantsReport { &self.invariants }
+
+	/// Reader slots taken out of `maxreaders`, by every process using the env. A read transaction holds one while it's open,
+	/// and one of a process that died with it open stays taken until `mdb_reader_check` runs on [`Env::raw`] or the env
+	/// is next opened by a process with nothing else using it.
+	#[throws]
+	pub fn readers_in_use(&self) -> ReaderSlots {
+		ReaderSlots { in_use: lmdb::reader_count(self.raw_env)?, max: self.info()?.max_readers }
+	}
+
+	/// Stats of the root db and every open db as of one read transaction, with their sum, e.g. for a health endpoint.
+	#[throws]
+	pub fn stat_all(&self) -> EnvStat {
+		let tx = self.read_tx()?;
+		let root = tx.stat(lmdb::dbi_open_existing(tx.raw(), None)?.expect("root db always exists"))?;
+		let dbs = self.dbs().into_iter()
+			.map(|(name, dbi)| Ok((name.to_vec(), tx.stat(dbi)?)))
+			.collect::<Result<Vec<_>, Error>>()?;
+		let total = dbs.iter().map(|(_, stat)| stat).fold(root, |total, stat| lmdb::Stat {
+			depth: total.depth.max(stat.depth),
+			branch_pages: total.branch_pages + stat.branch_pages,
+			leaf_pages: total.leaf_pages + stat.leaf_pages,
+			overflow_pages: total.overflow_pages + stat.overflow_pages,
+			entries: total.entries + stat.entries,
+			..total
+		});
+		EnvStat { txn_id: tx.id(), root, dbs, total }
+	}
+
+	/// the sequence of the last committed change, see [`changelog`] - Error::UnknownDb without [`EnvBuilder::changelog`]
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn last_sequence(&self) -> u64 { changelog::ChangeLog::of(&self.read_tx()?)?.last_sequence()? }
+
+	/// Everything a health check or metrics endpoint usually wants in one struct, see [`health`].
+	#[throws]
+	pub fn health(&self) -> health::Health {
+		let usage = self.usage()?;
+		let info = self.info()?;
+		let slots = lmdb::reader_slots(self.raw_env)?;
+		let oldest = slots.iter().filter_map(|&(_, txn_id)| txn_id).min();
+		let tx = self.read_tx()?;
+		let mut tables = self.dbs().into_iter()
+			.map(|(name, dbi)| Ok(health::TableHealth { name: crate::db_name_lossy(name), entries: tx.stat(dbi)?.entries }))
+			.collect::<Result<Vec<_>, Error>>()?;
+		tables.sort_by(|a, b| a.name.cmp(&b.name));
+		health::Health {
+			map_used_bytes: usage.used_pages * usage.page_size,
+			map_size_bytes: info.map_size,
+			map_percent: usage.percent,
+			readers: slots.len() as u32,
+			max_readers: info.max_readers,
+			stale_readers: slots.iter().filter(|&&(pid, _)| health::is_dead(pid)).count() as u32,
+			// slots were listed before tx began, so it isn't counted
+			oldest_reader_lag: oldest.map_or(0, |oldest| info.last_txn_id.saturating_sub(oldest)),
+			last_commit_age_secs: self.commits.last().map(|last| last.elapsed().as_secs_f64()),
+			tables,
+		}
+	}
+
+	pub(crate) fn quota(&self, dbi: lmdb_sys::MDB_dbi) -> Option<Quota> { self.quotas.get(&dbi).copied() }
+	pub(crate) fn size_limits(&self, dbi: lmdb_sys::MDB_dbi) -> Option<SizeLimits> { self.size_limits.get(&dbi).copied() }
+	pub(crate) fn dup_order(&self, dbi: lmdb_sys::MDB_dbi) -> Option<lmdb::DupOrder> { self.dup_orders.get(&dbi).copied() }
+
+	/// entry check of the table registered under this name, if any
+	pub fn check_entry(&self, name: &[u8]) -> Option<verify::CheckEntry> {
+		self.checks.get(name).copied()
+	}
+
+	/// Hint that the map is read in random order, so the OS doesn't read ahead, for datasets much bigger than RAM.
+	/// Does nothing outside of unix.
+	#[throws]
+	pub fn advise_random(&self) {
+		#[cfg(unix)] lmdb::env_madvise(self.raw_env, libc::MADV_RANDOM)?;
+	}
+
+	/// Hint that the map is read in order so the OS reads ahead aggressively, e.g. for full scans or backups.
+	/// Does nothing outside of unix.
+	#[throws]
+	pub fn advise_sequential(&self) {
+		#[cfg(unix)] lmdb::env_madvise(self.raw_env, libc::MADV_SEQUENTIAL)?;
+	}
+
+	/// the durability commits get unless overridden, what the env was opened with
+	#[throws]
+	pub fn durability(&self) -> Durability {
+		let flags = lmdb::env_flags(self.raw_env)? & Durability::MASK;
+		[Durability::Full, Durability::NoMetaSync, Durability::NoSync, Durability::Async].into_iter()
+			.find(|x| x.flags() == flags)
+			.unwrap_or(Durability::NoSync) // NOSYNC wins over anything else set through lmdb_sys
+	}
+
+	/// Flush committed transactions to disk, for envs whose [`Durability`] doesn't do it on every commit.
+	/// See [`BackgroundSync`](crate::background_sync::BackgroundSync) for doing it periodically.
+	#[throws]
+	pub fn sync(&self) {
+		// swapped before syncing to not lose commits counted meanwhile, and added back if the sync fails
+		let unsynced = self.unsynced.swap(0, std::sync::atomic::Ordering::Relaxed);
+		let start = std::time::Instant::now();
+		lmdb::env_sync(self.raw_env, true).inspect_err(|_| { self.unsynced.fetch_add(unsynced, std::sync::atomic::Ordering::Relaxed); })?;
+		self.syncs.record(start.elapsed());
+	}
+
+	/// the pages commits wrote against the bytes they put, since the env was opened - None unless it was built with
+	/// [`EnvBuilder::track_write_amplification`]
+	pub fn write_amplification(&self) -> Option<WriteAmplification> { self.amplification.as_ref().map(|x| *x.lock().unwrap()) }
+
+	// pages in use and not on the freelist as of the commit `tx` reads, for write transactions to compare with once they commit
+	#[expect(unused_braces)]
+	#[throws]
+	fn used_pages<'env>(&self, tx: &impl Transaction<'env>) -> usize { self.info()?.last_page + 1 - lmdb::free_pages(tx)? }
+
+	// A commit writes the pages it allocated, either past the last page or off the freelist, and frees the ones it
+	// replaced - which LMDB keeps as the freelist entry of its txn id. So the growth in pages used plus those freed
+	// is what it wrote.
+	pub(crate) fn record_amplification(&self, txn_id: usize, logical_bytes: usize, used_before: usize) {
+		let Some(amplification) = &self.amplification else { return };
+		let pages = (|| -> Result<u64, Error> {
+			let tx = self.read_tx()?;
+			// mdb_get refuses the FREE_DBI, cursors don't
+			let freed = lmdb::Cursor::open(&tx, 0)?.get_with_key(&mut txn_id.to_ne_bytes(), lmdb::CursorOp::SetKey)?
+				.and_then(|(_, pages)| pages.first_chunk()).map_or(0, |count| usize::from_ne_bytes(*count));
+			Ok((self.used_pages(&tx)? + freed).saturating_sub(used_before) as u64 + 1)
+		})();
+		let pages = match pages {
+			Ok(pages) => pages,
+			Err(e) => { log::warn!("couldn't measure the write amplification of txn {txn_id}: {e}"); return; },
+		};
+		let mut amplification = amplification.lock().unwrap();
+		amplification.page_size = self.stat().map_or(0, |x| x.page_size as usize);
+		amplification.commits += 1;
+		amplification.logical_bytes += logical_bytes as u64;
+		amplification.pages += pages;
+		amplification.last_logical_bytes = logical_bytes as u64;
+		amplification.last_pages = pages;
+	}
+
+	/// how long write transactions' commits and [`Env::sync`]s took since the env was opened, see [`latency`]
+	pub fn commit_latency(&self) -> latency::CommitLatency {
+		latency::CommitLatency { commit: self.commits.snapshot(), sync: self.syncs.snapshot() }
+	}
+
+	/// [`RwTxn::bytes_written`] summed over the transactions committed since the last [`Env::sync`]
+	pub fn unsynced_bytes(&self) -> usize { self.unsynced.load(std::sync::atomic::Ordering::Relaxed) }
+
+	pub(crate) fn add_unsynced(&self, bytes: usize) { self.unsynced.fetch_add(bytes, std::sync::atomic::Ordering::Relaxed); }
+
+	/// Wait up to `timeout` for the advisory writer lock, see [`writer_lock`].
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn lock_writer(&self, timeout: std::time::Duration) -> writer_lock::WriterLock { writer_lock::WriterLock::acquire(self, timeout)? }
+
+	/// whoever holds the advisory writer lock right now
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn writer_lock_holder(&self) -> Option<writer_lock::Holder> { writer_lock::holder(self)? }
+
+	/// whether the env was opened with [`EnvBuilder::write_map`]
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn is_write_map(&self) -> bool { lmdb::env_flags(self.raw_env)? & lmdb_sys::MDB_WRITEMAP != 0 }
+
+	/// Escape hatch for `lmdb_sys` functions that aren't wrapped.
+	/// The handle stays valid for as long as the env lives, it must not be closed or have its maxdbs/mapsize changed
+	/// while transactions are open - and calling into lmdb with it is `unsafe` as with any raw handle.
+	pub fn raw(&self) -> *mut lmdb_sys::MDB_env { self.raw_env }
+
+	/// Copy the env into `path`, which must be an existing empty directory.
+	/// With `compact` free pages are left out and the rest are renumbered sequentially - slower, but the copy is as small as it gets.
+	/// Uses a read transaction for the whole copy, so writers can keep going meanwhile.
+	#[throws]
+	pub fn copy(&self, path: &std::ffi::CStr, compact: bool) {
+		lmdb::env_copy(self.raw_env, path, if compact { lmdb_sys::MDB_CP_COMPACT } else { 0 })?;
+	}
+
+	/// Directory the env was opened from.
+	#[throws]
+	pub fn path(&self) -> std::path::PathBuf {
+		let path = lmdb::env_path(self.raw_env)?;
+		#[cfg(unix)] let path = <std::ffi::OsString as std::os::unix::ffi::OsStringExt>::from_vec(path.into_bytes());
+		#[cfg(not(unix))] let path = path.to_string_lossy().into_owned();
+		path.into()
+	}
+
+	/// Snapshots kept in the `snapshots` directory inside the env's directory.
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn snapshots(&self) -> snapshot::Snapshots { snapshot::Snapshots::new(self.path()?.join("snapshots")) }
+
+	/// Take a named compacted copy into [`Env::snapshots`], e.g. `env.snapshot("before-migration")`.
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn snapshot(&self, name: &str) -> snapshot::Snapshot { self.snapshots()?.take(self, name)? }
+
+	/// Walk every registered db inside a single read transaction, checking ordering and entries of registered tables.
+	/// Nothing is modified, but it does read everything, so expect it to take a while on big envs.
+	#[throws]
+	pub fn verify(&self) -> verify::Report {
+		let tx = self.read_tx()?;
+		verify::verify_all(&tx)?
 	}
 
-	pub fn db(&self, name: &'static [u8]) -> Option<lmdb_sys::MDB_dbi> {
-		self.dbs.get(name).copied()
+	/// Open the env in `path`, and if that fails or [`Env::verify`] finds errors, open the commit before the last one
+	/// from a copy in `recovery` instead, see [`EnvBuilder::build_previous`]. `builder` is called for each attempt.
+	/// After a `Recovery::Previous` the app runs on the copy - nothing of it is written back to `path`.
+	#[throws]
+	pub fn open_recovering(builder: impl Fn() -> Result<EnvBuilder, Error>, path: &std::path::Path, recovery: &std::path::Path) -> (Env, Recovery) {
+		let latest = || -> Result<Env, Error> {
+			let env = builder()?.build(&crate::path_to_cstring(path)?)?;
+			let report = env.verify()?;
+			if !report.is_ok() { throw!(Error::Verify(report.errors())); }
+			Ok(env)
+		};
+		match latest() {
+			Ok(env) => (env, Recovery::Latest),
+			Err(error) => {
+				log::error!("opening {} failed, falling back to the previous commit: {error}", path.display());
+				let env = builder()?.build_previous(path, recovery)?;
+				let report = env.verify()?;
+				if !report.is_ok() { throw!(Error::Verify(report.errors())); }
+				(env, Recovery::Previous { error })
+			},
+		}
 	}
 
 	pub fn reader_list(&self) {
@@ -60,11 +525,163 @@ impl Env {
 		unsafe { lmdb_sys::mdb_reader_list(self.raw_env, Some(msg), std::ptr::null_mut()) };
 	}
 
-	// ????? rustc lint engine?
-	#[expect(unused_braces)]
-	#[throws] pub fn read_tx(&self) -> RoTxn<'_> { RoTxn { raw: lmdb::txn_begin(self.raw_env, lmdb_sys::MDB_RDONLY)?, env: self } }
-	#[expect(unused_braces)]
-	#[throws] pub(super) fn write_tx(&self) -> RwTxn<'_> { RwTxn { raw: lmdb::txn_begin(self.raw_env, 0)?, env: self } }
+	/// Run `f` with a view of one read transaction, so every table it gets from the view reads the same snapshot.
+	/// ```ignore
+	/// let (user, orders) = ENV.read_view(|view| (view.get::<Users>().get_unrkyv(&id), view.get::<Orders>().count_range(id..=id)))?;
+	/// ```
+	#[throws]
+	pub fn read_view<R>(&self, f: impl FnOnce(&ReadView<'_>) -> R) -> R {
+		let view = ReadView { tx: self.read_tx()? };
+		f(&view)
+	}
+
+	/// Run `f` on `items` `chunk_size` at a time, each chunk in its own committed write transaction - for operations
+	/// too big for one, which fail with `TxnFull` once they dirty too many pages. A chunk that runs into `TxnFull`
+	/// is rolled back and tried again in halves.
+	/// ```ignore
+	/// let mut progress = ChunkProgress::default();
+	/// ENV.chunked_write(rows(), 10_000, &mut progress, |tx, chunk| {
+	///     for (id, row) in chunk { tx.get::<Rows>().put(id, row)?; }
+	///     Ok(())
+	/// })?; // if it failed, running it again with the same rows and progress skips the chunks already committed
+	/// ```
+	/// Chunks aren't atomic with each other, readers can see any number of them. Runs its own write transactions, so
+	/// it blocks while another one is open - don't call it from inside one.
+	#[throws]
+	pub fn chunked_write<T>(&self, items: impl IntoIterator<Item = T>, chunk_size: usize, progress: &mut ChunkProgress, mut f: impl FnMut(&RwTxn<'_>, &[T]) -> Result<(), Error>) {
+		let mut items = items.into_iter().skip(progress.items).peekable();
+		let mut size = chunk_size.max(1);
+		let mut chunk = Vec::with_capacity(size);
+		while items.peek().is_some() || !chunk.is_empty() {
+			chunk.extend(items.by_ref().take(size.saturating_sub(chunk.len())));
+			let len = size.min(chunk.len());
+			let tx = self.write_tx()?;
+			match f(&tx, &chunk[..len]).and_then(|()| tx.commit()) {
+				Ok(()) => {
+					chunk.drain(..len);
+					*progress = ChunkProgress { items: progress.items + len, chunks: progress.chunks + 1, chunk_size: len };
+				},
+				Err(e) if matches!(e.cause(), Error::Lmdb(lmdb::Error::TxnFull)) && len > 1 => {
+					size = len / 2;
+					log::warn!("a chunk of {len} items dirtied too many pages, retrying in chunks of {size}");
+				},
+				Err(e) => throw!(e),
+			}
+		}
+	}
+
+	/// Declare reads to compute in parallel, each in its own read transaction, and a write applying what they
+	/// returned in one short write transaction - see [`pipeline`](crate::pipeline).
+	pub fn pipeline(&self) -> crate::pipeline::Pipeline<'_, ()> { crate::pipeline::Pipeline::new(self) }
+
+	#[throws]
+	pub fn read_tx(&self) -> RoTxn<'_> {
+		self.check_readers();
+		let raw = self.begin(lmdb_sys::MDB_RDONLY).inspect_err(|e| if let Error::Lmdb(lmdb::Error::ReadersFull) = e {
+			log::error!("out of reader slots: {:?}", self.readers_in_use());
+		})?;
+		RoTxn { raw, env: self }
+	}
+	#[throws]
+	pub(super) fn write_tx(&self) -> RwTxn<'_> {
+		if self.map_full.swap(false, std::sync::atomic::Ordering::Relaxed) { self.grow()?; }
+		let pressure = self.wait_for_room()?;
+		let mut tx = RwTxn { raw: self.begin(0)?, env: self, durability: Default::default(), written: Default::default(), pressure, used_before: None, temps: Default::default() };
+		// the freelist can only be read in read transactions, this one sees the commit the write began from
+		if self.amplification.is_some() { tx.used_before = Some(self.used_pages(&self.read_tx()?)?); }
+		tx
+	}
+
+	#[throws]
+	fn begin(&self, flags: u32) -> *mut lmdb_sys::MDB_txn {
+		let raw = if self.growth.0 == Growth::Fixed {
+			lmdb::txn_begin(self.raw_env, flags)?
+		} else {
+			let mut txns = self.txns.lock().unwrap();
+			let raw = lmdb::txn_begin(self.raw_env, flags)?;
+			*txns += 1;
+			raw
+		};
+		borrows::begin(raw);
+		raw
+	}
+
+	// counts reader slots at most every READER_CHECK_INTERVAL, alerting once usage goes over the watermark until it's back under.
+	// Another thread checking meanwhile means this one doesn't have to
+	fn check_readers(&self) {
+		let Some((watermark, alert)) = self.reader_alert else { return };
+		let Ok(mut checked) = self.readers_checked.try_lock() else { return };
+		let (last, over) = &mut *checked;
+		if last.is_some_and(|last| last.elapsed() < READER_CHECK_INTERVAL) { return; }
+		*last = Some(std::time::Instant::now());
+		let slots = match self.readers_in_use() {
+			Ok(slots) => slots,
+			Err(e) => { log::warn!("couldn't count reader slots: {e}"); return; },
+		};
+		let was_over = std::mem::replace(over, slots.usage() > watermark);
+		if *over && !was_over {
+			log::warn!("{} of {} reader slots in use", slots.in_use, slots.max);
+			alert(slots);
+		}
+	}
+
+	// every transaction begun with `begin` ends here once it's committed or aborted
+	pub(crate) fn end(&self, raw: *mut lmdb_sys::MDB_txn) {
+		borrows::end(raw);
+		if self.growth.0 == Growth::Fixed { return; }
+		let mut txns = self.txns.lock().unwrap();
+		*txns -= 1;
+		if *txns == 0 { self.idle.notify_all(); }
+	}
+
+	pub(crate) fn note_error(&self, e: &lmdb::Error) {
+		if matches!(e, lmdb::Error::MapFull) { self.map_full.store(true, std::sync::atomic::Ordering::Relaxed); }
+	}
+
+	// LMDB remaps on a resize, so it may only happen while no transaction of this process is open - that many are
+	// waited for, new ones wait on txns meanwhile
+	#[throws]
+	fn grow(&self) {
+		let (growth, max_size) = self.growth;
+		let txns = self.txns.lock().unwrap();
+		let (_txns, waited) = self.idle.wait_timeout_while(txns, GROW_WAIT, |txns| *txns > 0).unwrap();
+		if waited.timed_out() { log::warn!("couldn't grow the map, transactions stayed open for {GROW_WAIT:?}"); return; }
+		let size = self.info()?.map_size;
+		let page_size = self.stat()?.page_size as usize;
+		let grown = match growth {
+			Growth::Fixed => return,
+			Growth::GrowBy(bytes) => size.saturating_add(bytes),
+			Growth::GrowFactor(factor) => (size as f64 * factor) as usize,
+		};
+		let grown = grown.min(max_size) / page_size * page_size;
+		if grown <= size { log::warn!("the map is at its maximum size of {size} bytes"); return; }
+		lmdb::env_set_mapsize(self.raw_env, grown)?;
+		log::info!("grew the map from {size} to {grown} bytes");
+	}
+
+	// the map usage if it's over the backpressure watermark once the policy is done waiting
+	#[throws]
+	fn wait_for_room(&self) -> Option<f32> {
+		let Some((watermark, policy)) = self.backpressure else { return None };
+		let start = std::time::Instant::now();
+		loop {
+			let usage = self.pressure(watermark)?;
+			match (usage, policy) {
+				(Some(_), Backpressure::Block(timeout)) if start.elapsed() < timeout => std::thread::sleep(std::time::Duration::from_millis(10)),
+				_ => return usage,
+			}
+		}
+	}
+
+	// Pages in use over pages in the map, if that's over `watermark`. Pages past the last one used are always free,
+	// so the freelist is only counted when those alone aren't enough.
+	#[throws]
+	fn pressure(&self, watermark: f32) -> Option<f32> {
+		let MapUsage { used_pages, total_pages, .. } = self.usage()?;
+		if used_pages as f32 / total_pages as f32 <= watermark { return None; }
+		let usage = used_pages.saturating_sub(lmdb::free_pages(&self.read_tx()?)?) as f32 / total_pages as f32;
+		(usage > watermark).then_some(usage)
+	}
 
 	#[throws]
 	pub async fn write<Res, Job>(&'static self, job: Job) -> Res where
@@ -183,6 +800,40 @@ impl Env {
 	*/
 }
 
+fn open_error(name: &[u8], e: lmdb::Error) -> Error {
+	Error::Table { table: crate::db_name_lossy(name), op: "open", key: None, source: Box::new(e.into()) }
+}
+
+// the table registered with EnvBuilder::with_root and the root db's handle
+#[throws]
+fn open_root(env: &Env, root: Option<(&'static [u8], enumflags2::BitFlags<DbFlags>)>) -> Option<(&'static [u8], lmdb_sys::MDB_dbi)> {
+	let Some((name, flags)) = root else { return None };
+	if !(flags & !DbFlags::Create).is_empty() { throw!(open_error(name, lmdb::Error::Incompatible)); }
+	let tx = env.read_tx()?;
+	let dbi = lmdb::dbi_open_existing(tx.raw(), None)?.expect("root db always exists");
+	tx.commit()?;
+	Some((name, dbi))
+}
+
+/// An env in a fresh temporary directory which is deleted when this is dropped, see [`EnvBuilder::build_temp`].
+pub struct TempEnv {
+	env: Env, // closed before the directory is removed
+	_dir: crate::TempDir,
+}
+
+impl std::ops::Deref for TempEnv {
+	type Target = Env;
+
+	fn deref(&self) -> &Env { &self.env }
+}
+
+// transactions borrow the env, so none can be alive here
+impl Drop for Env {
+	fn drop(&mut self) {
+		unsafe { lmdb_sys::mdb_env_close(self.raw_env) };
+	}
+}
+
 impl EnvBuilder {
 	#[throws]
 	pub fn mapsize(self, size: usize) -> Self {
@@ -198,32 +849,286 @@ impl EnvBuilder {
 
 	#[must_use]
 	pub fn with<N: DbName>(mut self) -> Self {
-		self.dbs.push((N::NAME, N::flags() | N::Table::<'static, 'static, RwTxn>::flags()));
+		self.dbs.push((N::NAME, N::flags() | N::Table::<'static, 'static, RwTxn>::flags(), N::Table::<'static, 'static, RwTxn>::check_entry));
+		#[cfg(feature = "rkyv")] self.fingerprints.push((N::NAME, schema::fingerprint::<N>()));
+		if let Some(order) = N::dup_order() { self.dup_orders.insert(N::NAME, order); }
+		self
+	}
+
+	/// Register a table on LMDB's unnamed root db rather than a named one, for a bit of metadata that isn't worth one
+	/// of the `maxdbs` slots. It's got by `N` like any other table, but the root db also holds the named dbs' names
+	/// as keys - use it for keys that can't be a db's name, and not with methods going over every entry (`iter`,
+	/// `len`, `clear`...), which see those too. Key flags don't go with the names, so the table can't have any.
+	/// It isn't one of [`Env::dbs`], so dumps, verify and health reports leave it out.
+	#[must_use]
+	pub fn with_root<N: DbName>(mut self) -> Self {
+		self.root = Some((N::NAME, N::flags() | N::Table::<'static, 'static, RwTxn>::flags()));
+		self
+	}
+
+	#[cfg(feature = "rkyv")]
+	/// Record what type each registered table has and fail to build with [`Error::SchemaMismatch`] if one was recorded
+	/// with another, see [`schema`].
+	#[must_use]
+	pub fn schema_registry(mut self) -> Self {
+		self.schemas.get_or_insert_default();
+		self.with::<schema::Schemas>()
+	}
+
+	#[cfg(feature = "rkyv")]
+	/// N's type changed on purpose, record the new one instead of failing - for the release that migrates the table.
+	#[must_use]
+	pub fn schema_changed<N: DbName>(mut self) -> Self {
+		self.schemas.get_or_insert_default().push(N::NAME);
+		self
+	}
+
+	/// open the env with MDB_RDONLY, write transactions will fail with TxnPerm
+	#[must_use]
+	pub fn read_only(mut self) -> Self {
+		self.read_only = true;
+		self
+	}
+
+	/// Open the env with MDB_WRITEMAP - dirty pages live in the map itself instead of being copied in at commit,
+	/// which makes writes and `get_mut` cheaper but lets stray writes through pointers into the map corrupt it.
+	/// Savepoints, which are nested transactions, aren't available in this mode and fail with [`Error::WriteMap`].
+	#[must_use]
+	pub fn write_map(mut self) -> Self {
+		self.write_map = true;
+		self
+	}
+
+	/// defaults to [`Durability::NoMetaSync`]
+	#[must_use]
+	pub fn durability(mut self, durability: Durability) -> Self {
+		self.durability = durability;
+		self
+	}
+
+	/// Let the OS read ahead of accesses to the map, which is off by default - worth it when the data fits in RAM
+	/// or is mostly scanned, it thrashes the page cache with random reads over datasets bigger than RAM.
+	#[must_use]
+	pub fn read_ahead(mut self) -> Self {
+		self.read_ahead = true;
+		self
+	}
+
+	/// Measure how many pages each commit writes against the key and value bytes it put, see
+	/// [`Env::write_amplification`] - for comparing key designs and value layouts. It reads the freelist as write
+	/// transactions begin and again after they commit, in read transactions of its own.
+	#[must_use]
+	pub fn track_write_amplification(mut self) -> Self {
+		self.track_amplification = true;
+		self
+	}
+
+	/// Log every change to the registered tables with a sequence number, see [`changelog`]
+	#[must_use]
+	pub fn changelog(mut self) -> Self {
+		self.dbs.push((changelog::NAME, enumflags2::BitFlags::empty(), changelog::check_entry));
+		self
+	}
+
+	/// Once more than `watermark` (0 to 1) of the map is used, puts fail with [`Error::Backpressure`] instead of running into
+	/// MapFull halfway through a transaction - deletes still go through, so space can be freed. Usage is checked as write
+	/// transactions begin, counting pages on the freelist as free, which costs a read of the freelist once the map's mostly allocated.
+	#[must_use]
+	pub fn backpressure(mut self, watermark: f32, policy: Backpressure) -> Self {
+		self.backpressure = Some((watermark, policy));
+		self
+	}
+
+	/// Call `alert` once more than `watermark` (0 to 1) of the reader slots are taken, see [`Env::readers_in_use`], with a warning
+	/// logged as well - running out makes [`Env::read_tx`] fail with ReadersFull wherever it happens to be. Slots are counted
+	/// as read transactions begin, at most once a second, and the alert only fires again once usage went back under.
+	#[must_use]
+	pub fn reader_alert(mut self, watermark: f32, alert: fn(ReaderSlots)) -> Self {
+		self.reader_alert = Some((watermark, alert));
+		self
+	}
+
+	/// Limit what N's table may hold, puts that would go over fail with [`Error::QuotaExceeded`], e.g. so one tenant's
+	/// table can't take up the whole map. Bytes are the pages the table takes, b-tree overhead included.
+	#[must_use]
+	pub fn quota<N: DbName>(mut self, quota: Quota) -> Self {
+		self.quotas.push((N::NAME, quota));
+		self
+	}
+
+	/// Limit the sizes of N's keys and values as they're put, in bytes as stored - framing included. Going over a soft
+	/// limit logs a warning and puts anyway, going over a hard one fails with [`Error::TooLarge`] before LMDB sees the
+	/// entry - runaway values like an unbounded Vec show up long before they fill the map.
+	#[must_use]
+	pub fn size_limits<N: DbName>(mut self, limits: SizeLimits) -> Self {
+		self.size_limits.push((N::NAME, limits));
+		self
+	}
+
+	/// Grow the map by `growth` when it fills up, up to `max_size` bytes, instead of having to pick the final mapsize upfront.
+	/// A write running into MapFull still fails, but it's [retryable](Error::is_retryable) - the next write transaction grows
+	/// the map before it begins. That waits up to a second for the process' other transactions to end since LMDB can only
+	/// resize with none open, and counts every transaction as it begins and ends. Other processes using the env don't see
+	/// the new size until they reopen it.
+	#[must_use]
+	pub fn growth(mut self, growth: Growth, max_size: usize) -> Self {
+		self.growth = (growth, max_size);
+		self
+	}
+
+	/// Check `invariant` in a read transaction once the env is built, along with the others registered, see [`Env::invariants`].
+	/// Every violation is logged, and building fails with [`Error::Invariants`] if one with [`OnViolation::Fail`](verify::OnViolation::Fail)
+	/// doesn't hold. Each one reads what it has to, so a check walking a big table slows down every start.
+	#[must_use]
+	pub fn on_open(mut self, name: &'static str, policy: verify::OnViolation, invariant: verify::Invariant) -> Self {
+		self.invariants.push((name, policy, invariant));
+		self
+	}
+
+	/// defaults to the number of registered dbs
+	#[must_use]
+	pub fn maxdbs(mut self, maxdbs: u32) -> Self {
+		self.maxdbs = Some(maxdbs);
 		self
 	}
 
 	#[throws]
-	pub fn build(self, path: &std::ffi::CStr) -> Env {
-		let flags =
-			lmdb_sys::MDB_NOMETASYNC | // maybe lose last transaction in case of a crash
-			lmdb_sys::MDB_NOTLS |      // don't use thread-local storage - read and write transactions can be on any thread, still at most 1 write tx
-			lmdb_sys::MDB_NORDAHEAD;   // don't readahead - useful when datasets are bigger than ram (does nothing on Windows)
+	fn open(&self, path: &std::ffi::CStr, maxdbs: u32) {
+		let mut flags =
+			self.durability.flags() |
+			lmdb_sys::MDB_NOTLS; // don't use thread-local storage - read and write transactions can be on any thread, still at most 1 write tx
+		// don't readahead - useful when datasets are bigger than ram (does nothing on Windows)
+		if !self.read_ahead { flags |= lmdb_sys::MDB_NORDAHEAD; }
+		if self.read_only { flags |= lmdb_sys::MDB_RDONLY; }
+		if self.write_map || self.durability == Durability::Async { flags |= lmdb_sys::MDB_WRITEMAP; }
+
+		lmdb::env_set_maxdbs(self.raw_env, maxdbs)?;
 
-		lmdb::env_set_maxdbs(self.raw_env, self.dbs.len() as u32)?;
-		
 		// 0664 is permissions for db folder on Unix - read/write/not execute
 		lmdb::env_open(self.raw_env, path, flags, 664)?;
+		// never set here, but an env without locking shared between processes corrupts silently, so be sure of it
+		if lmdb::env_flags(self.raw_env)? & lmdb_sys::MDB_NOLOCK != 0 { throw!(Error::NoLock); }
+	}
 
-		let mut env = Env { raw_env: self.raw_env, dbs: HashMap::new(), write_sema: tokio::sync::Semaphore::new(1) };
-		let db_create_tx = RwTxn { raw: lmdb::txn_begin(self.raw_env, 0)?, env: &env };
+	/// Open the env and every registered db, creating missing ones unless it's read-only.
+	#[throws]
+	pub fn build(self, path: &std::ffi::CStr) -> Env {
+		self.open(path, self.maxdbs.unwrap_or(self.dbs.len() as u32))?;
+
+		let checks = self.dbs.iter().map(|&(name, _, check)| (name, check)).collect();
+		let mut env = Env {
+			raw_env: self.raw_env, dbs: HashMap::new(), root: None, changelog: None, checks, write_sema: tokio::sync::Semaphore::new(1), unsynced: Default::default(), backpressure: self.backpressure, quotas: HashMap::new(), size_limits: HashMap::new(), dup_orders: HashMap::new(),
+			reader_alert: self.reader_alert, readers_checked: Default::default(),
+			growth: self.growth, map_full: Default::default(), txns: Default::default(), idle: Default::default(), invariants: Default::default(),
+			commits: Default::default(), syncs: Default::default(), amplification: self.track_amplification.then(|| std::sync::Mutex::new(WriteAmplification::default())),
+		};
 		let mut dbs = HashMap::with_capacity(self.dbs.len());
-		for (name, flags) in self.dbs {
-			log::trace!("creating {}", unsafe { std::str::from_utf8_unchecked(name) });
-			dbs.insert(name, lmdb::dbi_open(db_create_tx.raw(), name, flags | DbFlags::Create));
+		if self.read_only {
+			let db_open_tx = env.read_tx()?;
+			for (name, flags, _) in self.dbs {
+				log::trace!("opening {}", unsafe { std::str::from_utf8_unchecked(name) });
+				let dbi = lmdb::dbi_open(db_open_tx.raw(), name, flags).map_err(|e| open_error(name, e))?;
+				if let Some(&order) = self.dup_orders.get(name) { lmdb::set_dupsort(db_open_tx.raw(), dbi, order)?; }
+				dbs.insert(Cow::Borrowed(name), dbi);
+			}
+			db_open_tx.commit()?;
+		} else {
+			let db_create_tx = env.write_tx()?;
+			for (name, flags, _) in self.dbs {
+				log::trace!("creating {}", unsafe { std::str::from_utf8_unchecked(name) });
+				let dbi = lmdb::dbi_open(db_create_tx.raw(), name, flags | DbFlags::Create).map_err(|e| open_error(name, e))?;
+				if let Some(&order) = self.dup_orders.get(name) { lmdb::set_dupsort(db_create_tx.raw(), dbi, order)?; }
+				dbs.insert(Cow::Borrowed(name), dbi);
+			}
+			db_create_tx.commit()?;
 		}
-		db_create_tx.commit()?;
 
 		env.dbs = dbs;
+		env.root = open_root(&env, self.root)?;
+		env.changelog = env.db(changelog::NAME);
+		env.quotas = self.quotas.iter().filter_map(|&(name, quota)| Some((env.db(name)?, quota))).collect();
+		env.size_limits = self.size_limits.iter().filter_map(|&(name, limits)| Some((env.db(name)?, limits))).collect();
+		env.dup_orders = self.dup_orders.iter().filter_map(|(&name, &order)| Some((env.db(name)?, order))).collect();
+		#[cfg(feature = "rkyv")]
+		if let Some(changed) = &self.schemas { schema::check(&env, &self.fingerprints, changed, self.read_only)?; }
+		env.invariants = check_invariants(&env, &self.invariants)?;
 		env
 	}
+
+	/// Open the env in `path` as of the commit before its last one, for when the last commit is what's broken -
+	/// a crash with [`Durability::NoSync`] on a filesystem that reorders writes, say. LMDB 0.9 here has no MDB_PREVSNAPSHOT,
+	/// so `path`'s data.mdb is copied into `recovery` (empty or not there yet) with its newer meta page rolled back and
+	/// that copy is opened, the original stays as it is. Nothing may write to the env in `path` meanwhile.
+	#[throws]
+	pub fn build_previous(self, path: &std::path::Path, recovery: &std::path::Path) -> Env {
+		snapshot::copy_previous(path, recovery)?;
+		self.build(&crate::path_to_cstring(recovery)?)?
+	}
+
+	/// Build the env in a fresh temporary directory, for tests of application logic using the tables.
+	/// Logic written against the [`backend`](crate::backend) traits can use a [`MemEnv`](crate::mem_env::MemEnv) instead.
+	#[throws]
+	pub fn build_temp(self) -> TempEnv {
+		let dir = crate::TempDir::new("batadase-env")?;
+		TempEnv { env: self.build(&crate::path_to_cstring(&dir.0)?)?, _dir: dir }
+	}
+
+	/// Open an env without registering its dbs upfront - every named db found in it is opened with the flags it was created with.
+	/// Meant for tooling, dbs registered with `with` only provide entry checks for verify and `maxdbs` defaults to 128.
+	#[throws]
+	pub fn build_existing(self, path: &std::ffi::CStr) -> Env {
+		self.open(path, self.maxdbs.unwrap_or(EXISTING_MAXDBS))?;
+
+		let checks = self.dbs.iter().map(|&(name, _, check)| (name, check)).collect();
+		let mut env = Env {
+			raw_env: self.raw_env, dbs: HashMap::new(), root: None, changelog: None, checks, write_sema: tokio::sync::Semaphore::new(1), unsynced: Default::default(), backpressure: self.backpressure, quotas: HashMap::new(), size_limits: HashMap::new(), dup_orders: HashMap::new(),
+			reader_alert: self.reader_alert, readers_checked: Default::default(),
+			growth: self.growth, map_full: Default::default(), txns: Default::default(), idle: Default::default(), invariants: Default::default(),
+			commits: Default::default(), syncs: Default::default(), amplification: self.track_amplification.then(|| std::sync::Mutex::new(WriteAmplification::default())),
+		};
+		let db_open_tx = env.read_tx()?;
+		let root = lmdb::dbi_open_existing(db_open_tx.raw(), None)?.expect("root db always exists");
+		let mut names = Vec::new();
+		let mut cursor = lmdb::Cursor::open(&db_open_tx, root)?;
+		while let Some((name, _)) = cursor.get(lmdb::CursorOp::Next)? {
+			// same as mdb_dump, a key with a nul in it can't be a db name
+			if !name.contains(&0) { names.push([name, b"\0"].concat()); }
+		}
+		drop(cursor);
+
+		let mut dbs = HashMap::with_capacity(names.len());
+		for name in names {
+			// the root db may also hold plain entries, which fail to open as dbs
+			let Some(dbi) = lmdb::dbi_open_existing(db_open_tx.raw(), Some(&name))? else { continue; };
+			if let Some(&order) = self.dup_orders.get(name.as_slice()) { lmdb::set_dupsort(db_open_tx.raw(), dbi, order)?; }
+			log::trace!("opened {}", String::from_utf8_lossy(&name));
+			dbs.insert(Cow::Owned(name), dbi);
+		}
+		db_open_tx.commit()?;
+
+		env.dbs = dbs;
+		env.root = open_root(&env, self.root)?;
+		env.changelog = env.db(changelog::NAME);
+		env.quotas = self.quotas.iter().filter_map(|&(name, quota)| Some((env.db(name)?, quota))).collect();
+		env.size_limits = self.size_limits.iter().filter_map(|&(name, limits)| Some((env.db(name)?, limits))).collect();
+		env.dup_orders = self.dup_orders.iter().filter_map(|(&name, &order)| Some((env.db(name)?, order))).collect();
+		env.invariants = check_invariants(&env, &self.invariants)?;
+		env
+	}
+}
+
+// runs on_open's invariants, logging the violated ones and failing if any of them are to fail opening
+#[throws]
+fn check_invariants(env: &Env, invariants: &[(&'static str, verify::OnViolation, verify::Invariant)]) -> verify::InvariantsReport {
+	if invariants.is_empty() { return verify::InvariantsReport::default(); }
+	let report = verify::check_invariants(&env.read_tx()?, invariants);
+	for invariant in report.violated() {
+		let level = match invariant.policy { verify::OnViolation::Fail => log::Level::Error, verify::OnViolation::Warn => log::Level::Warn };
+		if let Some(e) = &invariant.error { log::log!(level, "checking invariant {} failed: {e}", invariant.name); }
+		for violation in &invariant.violations { log::log!(level, "invariant {} violated: {violation}", invariant.name); }
+	}
+	match report.failed() {
+		0 => report,
+		failed => throw!(Error::Invariants(failed)),
+	}
 }
@@ -0,0 +1,155 @@
+//! A [`Framing`] putting a small header before each value - a version byte, per-entry flags and when the entry was
+//! created and last updated - for features needing a bit of metadata with every value to share, rather than each
+//! wrapping the value its own way:
+//! ```ignore
+//! #[derive(DbName)]
+//! #[table(AssocTable<'tx, TX, DocId, Doc, Envelope<Crc32, 2>>)]
+//! struct Docs;
+//!
+//! tx.get::<Docs>().put(&id, &doc)?;
+//! tx.get::<Docs>().set_flags(&id, EntryFlag::Tombstone.into())?;
+//! let (header, doc) = tx.get::<Docs>().get_with_header(&id)?.unwrap();
+//! ```
+//! Stored values are `version:u8 flags:u8 created:u64 updated:u64` (little-endian, milliseconds since the unix epoch)
+//! followed by the inner framing of the archive, so a `Crc32` inside only covers the archive.
+//!
+//! `VERSION` is the value type's version: reads of entries put with another one fail with
+//! [`Error::EnvelopeVersion`], and migrations can tell old entries apart with [`Header::of`] on the raw bytes.
+//! Writes through `AssocTable`'s usual methods stamp a fresh header, created and updated being now -
+//! [`AssocTable::replace`] keeps the created time and flags of the entry it replaces.
+
+use culpa::{throw, throws};
+use enumflags2::{BitFlag, BitFlags};
+use std::marker::PhantomData;
+use crate::{AssocTable, Transaction, RwTxn, RkyvSer, RkyvVal, Error, lmdb, error::Context, framing::{Framing, Plain}, tracked_table::now};
+
+pub const HEADER_LEN: usize = 18;
+
+/// Per-entry flags. The crate doesn't act on them, they're for tables and sweepers built on envelopes to agree on.
+#[enumflags2::bitflags]
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryFlag {
+	Tombstone = 1 << 0, // deleted, kept so the delete can be seen e.g. by replicas
+	Pinned = 1 << 1,    // exempt from expiry
+	Migrated = 1 << 2,  // rewritten by a migration rather than put
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+	pub version: u8,
+	pub flags: BitFlags<EntryFlag>,
+	pub created: u64,
+	pub updated: u64,
+}
+
+impl Header {
+	/// the header of stored bytes, whatever their version
+	#[throws]
+	pub fn of(stored: &[u8]) -> Self {
+		let Some((header, _)) = stored.split_first_chunk::<HEADER_LEN>() else { throw!(Error::InvalidEntry("value is shorter than its envelope")) };
+		let Ok(flags) = BitFlags::<EntryFlag>::from_bits(header[1]) else { throw!(Error::InvalidEntry("unknown envelope flags")) };
+		Self {
+			version: header[0],
+			flags,
+			created: u64::from_le_bytes(header[2..10].try_into().unwrap()),
+			updated: u64::from_le_bytes(header[10..18].try_into().unwrap()),
+		}
+	}
+
+	pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+		let mut bytes = [0; HEADER_LEN];
+		bytes[0] = self.version;
+		bytes[1] = self.flags.bits();
+		bytes[2..10].copy_from_slice(&self.created.to_le_bytes());
+		bytes[10..18].copy_from_slice(&self.updated.to_le_bytes());
+		bytes
+	}
+}
+
+/// see the [module docs](self), `F` frames the archive inside the envelope
+pub struct Envelope<F = Plain, const VERSION: u8 = 0>(PhantomData<F>);
+
+impl<F: Framing, const VERSION: u8> Envelope<F, VERSION> {
+	fn wrap(header: Header, archive: rkyv::util::AlignedVec) -> Vec<u8> {
+		let mut inner = F::frame(archive);
+		let inner = inner.as_mut();
+		let mut framed = Vec::with_capacity(HEADER_LEN + inner.len());
+		framed.extend_from_slice(&header.to_bytes());
+		framed.extend_from_slice(inner);
+		framed
+	}
+}
+
+impl<F: Framing, const VERSION: u8> Framing for Envelope<F, VERSION> {
+	type Framed = Vec<u8>;
+
+	fn frame(archive: rkyv::util::AlignedVec) -> Self::Framed {
+		let now = now();
+		Self::wrap(Header { version: VERSION, flags: BitFlags::empty(), created: now, updated: now }, archive)
+	}
+
+	fn unframe(stored: &[u8]) -> Result<&[u8], Error> {
+		let header = Header::of(stored)?;
+		if header.version != VERSION { return Err(Error::EnvelopeVersion { stored: header.version, expected: VERSION }); }
+		F::unframe(&stored[HEADER_LEN..])
+	}
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, K, V, F, const VERSION: u8> AssocTable<'tx, RwTxn<'tx>, K, V, Envelope<F, VERSION>> where
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+{
+	/// [`AssocTable::put`] keeping the created time and flags of the entry it replaces, if there's one
+	#[throws]
+	pub fn replace(&self, key: &K, value: &V) {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let now = now();
+		let header = match lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "replace", Some(&key_bytes))? {
+			Some(stored) => Header { version: VERSION, updated: now, ..Header::of(stored).context(self.tx, self.dbi, "replace", Some(&key_bytes))? },
+			None => Header { version: VERSION, flags: BitFlags::empty(), created: now, updated: now },
+		};
+		let mut value_bytes = Envelope::<F, VERSION>::wrap(header, rkyv::to_bytes(value)?);
+		lmdb::put(self.tx, self.dbi, &mut key_bytes, &mut value_bytes, lmdb::PutFlags::empty()).context(self.tx, self.dbi, "replace", Some(&key_bytes))?;
+	}
+
+	/// Set an entry's flags without changing its value, which counts as updating it. False if there's no entry.
+	#[throws]
+	pub fn set_flags(&self, key: &K, flags: BitFlags<EntryFlag>) -> bool {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let Some(stored) = lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "set_flags", Some(&key_bytes))? else { return false };
+		let header = Header { flags, updated: now(), ..Header::of(stored).context(self.tx, self.dbi, "set_flags", Some(&key_bytes))? };
+		let mut value_bytes = stored.to_vec();
+		value_bytes[..HEADER_LEN].copy_from_slice(&header.to_bytes());
+		lmdb::put(self.tx, self.dbi, &mut key_bytes, &mut value_bytes, lmdb::PutFlags::empty()).context(self.tx, self.dbi, "set_flags", Some(&key_bytes))?;
+		true
+	}
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, K, V, F, const VERSION: u8> AssocTable<'tx, TX, K, V, Envelope<F, VERSION>> where
+	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+{
+	/// the envelope of an entry, without checking its value
+	#[throws]
+	pub fn header(&self, key: &K) -> Option<Header> {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let Some(stored) = lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "header", Some(&key_bytes))? else { return None };
+		Some(Header::of(stored).context(self.tx, self.dbi, "header", Some(&key_bytes))?)
+	}
+
+	/// [`AssocTable::get`] with the entry's envelope
+	#[throws]
+	pub fn get_with_header(&self, key: &K) -> Option<(Header, &'tx rkyv::Archived<V>)> {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let Some(stored) = lmdb::get(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "get_with_header", Some(&key_bytes))? else { return None };
+		let header = Header::of(stored).context(self.tx, self.dbi, "get_with_header", Some(&key_bytes))?;
+		Some((header, crate::assoc_table::access::<V, Envelope<F, VERSION>>(stored).context(self.tx, self.dbi, "get_with_header", Some(&key_bytes))?))
+	}
+}
@@ -1,5 +1,135 @@
 #[derive(thiserror::Error, Debug)]
+#[cfg_attr(feature = "miette", derive(miette::Diagnostic))]
 pub enum Error {
+	#[cfg_attr(feature = "miette", diagnostic(transparent))]
 	#[error(transparent)] Lmdb(#[from] crate::lmdb::Error),
+	#[cfg(feature = "rkyv")]
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::rkyv), help("the stored bytes don't match the type they're read as")))]
 	#[error(transparent)] Rkyv(#[from] rkyv::rancor::Error),
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::io)))]
+	#[error(transparent)] Io(#[from] std::io::Error),
+	#[cfg(feature = "json")]
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::json), help("the stored text isn't valid JSON")))]
+	#[error(transparent)] Json(#[from] serde_json::Error),
+	#[cfg(feature = "arrow")]
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::arrow)))]
+	#[error(transparent)] Arrow(#[from] arrow_schema::ArrowError),
+	#[cfg(feature = "parquet")]
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::parquet)))]
+	#[error(transparent)] Parquet(#[from] parquet::errors::ParquetError),
+	#[cfg(feature = "mdbx")]
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::mdbx)))]
+	#[error(transparent)] Mdbx(#[from] libmdbx::Error),
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::unknown_db), help("register the table with EnvBuilder::with")))]
+	#[error("database {0:?} isn't registered in this env")] UnknownDb(String),
+	/// a db a [`Backend`](crate::backend::Backend) other than [`Env`](crate::Env) wasn't built with
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::unregistered_db), help("register the table with the backend's `with`, e.g. MemEnv::with or MdbxEnvBuilder::with")))]
+	#[error("database {0:?} isn't registered with this backend")] UnregisteredDb(String),
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::registered_db), help("rename it from an env that doesn't register it, e.g. before registering the table under its new name")))]
+	#[error("database {0:?} is registered in this env")] RegisteredDb(String),
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::checksum_mismatch)))]
+	#[error("value checksum mismatch")] ChecksumMismatch,
+	/// see [`TypeTagged`](crate::assoc_poly_table::TypeTagged)
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::type_mismatch)))]
+	#[error("the stored value wasn't put as a {0}")] TypeMismatch(&'static str),
+	/// see [`schema`](crate::schema)
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::schema_mismatch), help("another binary uses the table for a different type, or the type changed - see EnvBuilder::schema_changed")))]
+	#[error("table {0:?} was created with another type")] SchemaMismatch(String),
+	/// see [`envelope`](crate::envelope)
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::envelope_version), help("the entry was put with another version of the value type, migrate the table")))]
+	#[error("the entry's envelope is version {stored}, not {expected}")] EnvelopeVersion { stored: u8, expected: u8 },
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::invalid_entry)))]
+	#[error("invalid entry: {0}")] InvalidEntry(&'static str),
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::malformed_dump)))]
+	#[error("malformed dump on line {line}: {reason}")] MalformedDump { line: usize, reason: &'static str },
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::malformed_csv)))]
+	#[error("malformed csv on line {line}: {reason}")] MalformedCsv { line: usize, reason: &'static str },
+	/// see [`dumpfmt`](crate::dumpfmt)
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::malformed_export)))]
+	#[error("malformed export at byte {offset}: {reason}")] MalformedExport { offset: u64, reason: &'static str },
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::invalid_snapshot_name)))]
+	#[error("invalid snapshot name {0:?}")] InvalidSnapshotName(String),
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::write_map), help("open the env without EnvBuilder::write_map to use it")))]
+	#[error("{0} isn't available with MDB_WRITEMAP")] WriteMap(&'static str),
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::no_lock), help("every process sharing an env has to use LMDB's locking")))]
+	#[error("the env was opened with MDB_NOLOCK")] NoLock,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::writer_locked), help("see Env::writer_lock_holder")))]
+	#[error("the writer lock is taken{}", fmt_holder(.0))] WriterLocked(Option<crate::writer_lock::Holder>),
+	/// see [`EnvBuilder::backpressure`](crate::env::EnvBuilder::backpressure)
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::backpressure), help("delete data, grow the map or wait for long-running readers to finish")))]
+	#[error("the map is {:.1}% full, over the backpressure watermark", .0 * 100.)] Backpressure(f32),
+	/// see [`EnvBuilder::quota`](crate::env::EnvBuilder::quota)
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::quota_exceeded)))]
+	#[error("the table's {0} quota is used up")] QuotaExceeded(&'static str),
+	/// see [`EnvBuilder::size_limits`](crate::env::EnvBuilder::size_limits)
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::too_large)))]
+	#[error("{what} of {size} bytes is over the table's limit of {max}")] TooLarge { what: &'static str, size: usize, max: usize },
+	/// an appending put (bulk loads, [`IndexTable::append_rows`](crate::IndexTable::append_rows), copies into empty dbs)
+	/// of a key that isn't after the db's last one in its order, or of a dupsort value that isn't after the key's last one
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::out_of_order), help("appended entries have to come in the db's order - sorted by its comparator, and without repeating keys")))]
+	#[error("appended {what} b\"{}\" isn't after the last one, b\"{}\"", .current.escape_ascii(), .previous.escape_ascii())] OutOfOrder { what: &'static str, previous: Vec<u8>, current: Vec<u8> },
+	/// see [`Env::open_recovering`](crate::Env::open_recovering)
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::verify_failed), help("see Env::verify for the details")))]
+	#[error("verify found {0} errors")] Verify(usize),
+	/// see [`EnvBuilder::on_open`](crate::env::EnvBuilder::on_open)
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::invariants), help("the violations are logged, see verify::check_invariants for the report")))]
+	#[error("{0} startup invariants don't hold")] Invariants(usize),
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::invalid_backup)))]
+	#[error("invalid backup: {0}")] InvalidBackup(&'static str),
+	/// see [`BufferedTable::put`](crate::buffered_table::BufferedTable::put), retryable once the flusher made room
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::buffer_full), help("flush more often, raise LossBound::max_pending or retry")))]
+	#[error("the write buffer is full with {0} pending keys")] BufferFull(usize),
+	/// a [`GroupCommit`](crate::group_commit::GroupCommit) write panicked, or was aborted along with one in its batch
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::write_panicked)))]
+	#[error("the write panicked")] WritePanicked,
+	/// the commit of a [`GroupCommit`](crate::group_commit::GroupCommit) batch failed, every write in it gets the same
+	/// error - or the write panicked, see [`Error::WritePanicked`]
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::group_commit)))]
+	#[error("the commit shared with other grouped writes failed")] GroupCommit(#[source] std::sync::Arc<Error>),
+	/// a table operation failed, `key` is at most the first `KEY_CAP` bytes of the key involved.
+	/// The underlying error is only the `source`, use `{:#}` with anyhow or a miette report to see it.
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::table)))]
+	#[error("{op} on {table}{} failed", fmt_key(.key))] Table { table: String, op: &'static str, key: Option<Vec<u8>>, source: Box<Error> },
+}
+
+pub const KEY_CAP: usize = 64;
+
+fn fmt_holder(holder: &Option<crate::writer_lock::Holder>) -> String {
+	holder.as_ref().map_or_else(String::new, |holder| format!(
+		" (pid {}, for {:.1} secs)", holder.pid, holder.since.elapsed().unwrap_or_default().as_secs_f32(),
+	))
+}
+
+fn fmt_key(key: &Option<Vec<u8>>) -> String {
+	key.as_ref().map_or_else(String::new, |key| format!(" with key b\"{}\"", key.escape_ascii()))
+}
+
+impl Error {
+	/// see `lmdb::Error::is_retryable`, and a full write buffer is too
+	pub fn is_retryable(&self) -> bool { matches!(self.cause(), Self::Lmdb(e) if e.is_retryable()) || matches!(self.cause(), Self::BufferFull(_)) }
+
+	/// the underlying error without any table context, for matching on
+	pub fn cause(&self) -> &Error {
+		match self {
+			Self::Table { source, .. } => source.cause(),
+			Self::GroupCommit(source) => source.cause(),
+			x => x,
+		}
+	}
+}
+
+// adds the table (looked up by dbi, only on failure), operation and key to errors of table methods
+pub(crate) trait Context<T> {
+	fn context<'env>(self, tx: &impl crate::Transaction<'env>, dbi: lmdb_sys::MDB_dbi, op: &'static str, key: Option<&[u8]>) -> Result<T, Error>;
+}
+
+impl<T, E: Into<Error>> Context<T> for Result<T, E> {
+	fn context<'env>(self, tx: &impl crate::Transaction<'env>, dbi: lmdb_sys::MDB_dbi, op: &'static str, key: Option<&[u8]>) -> Result<T, Error> {
+		self.map_err(|e| Error::Table {
+			table: tx.env().db_name(dbi).map_or_else(|| format!("dbi {dbi}"), crate::db_name_lossy),
+			op,
+			key: key.map(|key| key[..key.len().min(KEY_CAP)].to_vec()),
+			source: Box::new(e.into()),
+		})
+	}
 }
@@ -0,0 +1,194 @@
+//! A table of many fixed-width records per key, stored as LMDB `DupSort | DupFixed` duplicates so LMDB packs
+//! them tightly onto pages and reads them back a page at a time - e.g. per-entity event vectors:
+//! ```ignore
+//! #[derive(Clone, Copy)]
+//! struct Candle { time: u64, open: u32, close: u32 }
+//!
+//! impl FixedSize for Candle {
+//!     const SIZE: usize = 16;
+//!     fn write(&self, out: &mut [u8]) {
+//!         out[..8].copy_from_slice(&self.time.to_be_bytes()); // big-endian first, so records sort by time
+//!         out[8..12].copy_from_slice(&self.open.to_be_bytes());
+//!         out[12..].copy_from_slice(&self.close.to_be_bytes());
+//!     }
+//!     fn read(bytes: &[u8]) -> Self { .. }
+//! }
+//!
+//! #[derive(DbName)]
+//! #[table(FixedVecTable<'tx, TX, Symbol, Candle>)]
+//! struct Candles;
+//!
+//! tx.get::<Candles>().append(&symbol, &day)?;
+//! for page in tx.get::<Candles>().pages(&symbol)? { chart.extend(page?.iter()); }
+//! ```
+//! Records of a key are kept in the bytewise order of their encoding and each one is stored once, putting the same
+//! record again does nothing.
+
+use culpa::{throw, throws};
+use enumflags2::BitFlag;
+use std::marker::PhantomData;
+use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, Error, lmdb, error::Context, DbFlags};
+
+/// A value encoded into exactly `SIZE` bytes, at most the env's max key size.
+pub trait FixedSize: Sized {
+	const SIZE: usize;
+	fn write(&self, out: &mut [u8]);
+	fn read(bytes: &[u8]) -> Self;
+}
+
+// big-endian, so records are in numeric order
+macro_rules! fixed_size_int {
+	($($ty:ty),*) => {$(
+		impl FixedSize for $ty {
+			const SIZE: usize = std::mem::size_of::<$ty>();
+			fn write(&self, out: &mut [u8]) { out.copy_from_slice(&self.to_be_bytes()); }
+			fn read(bytes: &[u8]) -> Self { Self::from_be_bytes(bytes.try_into().expect("SIZE bytes")) }
+		}
+	)*};
+}
+fixed_size_int!(u8, u16, u32, u64, u128);
+
+impl<const N: usize> FixedSize for [u8; N] {
+	const SIZE: usize = N;
+	fn write(&self, out: &mut [u8]) { out.copy_from_slice(self); }
+	fn read(bytes: &[u8]) -> Self { bytes.try_into().expect("SIZE bytes") }
+}
+
+/// Up to a page of consecutive records of a key, straight from LMDB's page.
+pub struct Page<'tx, V> {
+	bytes: &'tx [u8],
+	_pd: PhantomData<V>,
+}
+
+impl<'tx, V: FixedSize> Page<'tx, V> {
+	pub fn len(&self) -> usize { self.bytes.len() / V::SIZE }
+	pub fn is_empty(&self) -> bool { self.bytes.is_empty() }
+	pub fn get(&self, i: usize) -> Option<V> { self.bytes.chunks_exact(V::SIZE).nth(i).map(V::read) }
+	pub fn iter(&self) -> impl Iterator<Item = V> + use<'tx, V> { self.bytes.chunks_exact(V::SIZE).map(V::read) }
+	/// the records' encodings back to back
+	pub fn as_bytes(&self) -> &'tx [u8] { self.bytes }
+}
+
+pub struct FixedVecTable<'tx, TX, K, V> {
+	tx: &'tx TX,
+	dbi: lmdb_sys::MDB_dbi,
+	_pd: PhantomData<(K, V)>,
+	_borrow: crate::borrows::Borrow,
+}
+
+impl<'tx, 'env: 'tx, TX, K, V> Table<'tx, 'env, TX> for FixedVecTable<'tx, TX, K, V> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	V: FixedSize,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.dbi }
+	fn txn(&self) -> &TX { self.tx }
+	fn flags() -> enumflags2::BitFlags<DbFlags> { DbFlags::DupSort | DbFlags::DupFixed }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
+		Self::build(tx, tx.env().db(name).expect("table isn't registered in the env, add it with EnvBuilder::with"))
+	}
+
+	#[throws]
+	fn check_entry(key: &[u8], value: &[u8]) {
+		rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key)?;
+		if value.len() != V::SIZE { throw!(Error::InvalidEntry("record isn't FixedSize::SIZE bytes")); }
+	}
+}
+
+fn encode<V: FixedSize>(value: &V) -> Vec<u8> {
+	let mut bytes = vec![0; V::SIZE];
+	value.write(&mut bytes);
+	bytes
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, K, V> FixedVecTable<'tx, RwTxn<'tx>, K, V> where
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: FixedSize,
+{
+	#[throws]
+	pub fn put(&self, key: &K, value: &V) {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		lmdb::put(self.tx, self.dbi, &mut key_bytes, &mut encode(value), lmdb::PutFlags::empty()).context(self.tx, self.dbi, "put", Some(&key_bytes))?;
+	}
+
+	/// Put every record of `values` under `key` in one go, returns how many were written.
+	#[throws]
+	pub fn append(&self, key: &K, values: &[V]) -> usize {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let mut bytes = vec![0; values.len() * V::SIZE];
+		for (value, out) in values.iter().zip(bytes.chunks_exact_mut(V::SIZE)) { value.write(out); }
+		lmdb::put_multiple(self.tx, self.dbi, &mut key_bytes, &mut bytes, V::SIZE).context(self.tx, self.dbi, "append", Some(&key_bytes))?
+	}
+
+	/// delete one record of `key`
+	#[throws]
+	pub fn delete(&self, key: &K, value: &V) -> bool {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		lmdb::del_value(self.tx, self.dbi, &mut key_bytes, &mut encode(value)).context(self.tx, self.dbi, "delete", Some(&key_bytes))?
+	}
+
+	/// delete every record of `key`
+	#[throws]
+	pub fn delete_all(&self, key: &K) -> bool {
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		lmdb::del(self.tx, self.dbi, &mut key_bytes).context(self.tx, self.dbi, "delete_all", Some(&key_bytes))?
+	}
+
+	#[throws]
+	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi).context(self.tx, self.dbi, "clear", None)?; }
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, K, V> FixedVecTable<'tx, TX, K, V> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: FixedSize,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { tx, dbi, _pd: PhantomData, _borrow: crate::borrows::Borrow::of(tx) } }
+
+	/// how many records `key` has
+	#[throws]
+	pub fn count(&self, key: &K) -> usize {
+		let key_bytes = rkyv::to_bytes(key)?;
+		let mut cursor = lmdb::DupCursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "count", Some(&key_bytes))?;
+		if cursor.get_with_key(&mut key_bytes.to_vec(), lmdb::CursorOp::SetKey)?.is_none() { return 0; }
+		cursor.count().context(self.tx, self.dbi, "count", Some(&key_bytes))?
+	}
+
+	/// the last record of `key` in order, e.g. the latest one of time-prefixed records
+	#[throws]
+	pub fn last(&self, key: &K) -> Option<V> {
+		let key_bytes = rkyv::to_bytes(key)?;
+		let mut cursor = lmdb::DupCursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "last", Some(&key_bytes))?;
+		if cursor.get_with_key(&mut key_bytes.to_vec(), lmdb::CursorOp::SetKey)?.is_none() { return None; }
+		cursor.get_value(lmdb::DupCursorOp::LastDup)?.map(V::read)
+	}
+
+	/// the records of `key` in order, a page at a time
+	#[throws]
+	pub fn pages(&self, key: &K) -> impl Iterator<Item = Result<Page<'tx, V>, Error>> + use<'tx, 'env, TX, K, V> {
+		let key_bytes = rkyv::to_bytes(key)?;
+		let mut cursor = lmdb::DupFixedCursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "pages", Some(&key_bytes))?;
+		let page = match cursor.get_with_key(&mut key_bytes.to_vec(), lmdb::CursorOp::SetKey).context(self.tx, self.dbi, "pages", Some(&key_bytes))? {
+			// a lone record isn't on a page of its own
+			Some((_, first)) => Some(cursor.get_multiple(lmdb::DupFixedCursorOp::GetMultiple).context(self.tx, self.dbi, "pages", Some(&key_bytes))?.unwrap_or(first)),
+			None => None,
+		};
+		let rest = page.is_some().then(|| lmdb::steps(move || cursor.get_multiple(lmdb::DupFixedCursorOp::NextMultiple))).into_iter().flatten();
+		page.map(Ok).into_iter().chain(rest).map(|bytes| bytes.map(|bytes| Page { bytes, _pd: PhantomData }))
+	}
+
+	/// the records of `key` in order
+	#[throws]
+	pub fn values(&self, key: &K) -> impl Iterator<Item = Result<V, Error>> + use<'tx, 'env, TX, K, V> {
+		self.pages(key)?.flat_map(|page| {
+			let (page, error) = match page {
+				Ok(page) => (Some(page), None),
+				Err(e) => (None, Some(Err(e))),
+			};
+			page.into_iter().flat_map(|page| page.iter()).map(Ok).chain(error)
+		})
+	}
+}
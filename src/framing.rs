@@ -0,0 +1,50 @@
+//! How serialized values are laid out in the db, picked per table with the table's last type parameter, e.g.
+//! ```ignore
+//! #[derive(batadase::DbName)]
+//! #[table(AssocTable<'tx, TX, UserId, User, batadase::framing::Crc32>)]
+//! struct Users;
+//! ```
+//! [`Envelope`](crate::envelope::Envelope) frames values with per-entry metadata.
+//! Changing a table's framing changes its on-disk format, so existing data has to be migrated.
+
+use crate::Error;
+
+pub trait Framing {
+	type Framed: AsMut<[u8]>;
+
+	fn frame(archive: rkyv::util::AlignedVec) -> Self::Framed;
+	/// the archive out of the stored bytes
+	fn unframe(stored: &[u8]) -> Result<&[u8], Error>;
+}
+
+/// the archive as-is, the default
+pub struct Plain;
+
+impl Framing for Plain {
+	type Framed = rkyv::util::AlignedVec;
+
+	fn frame(archive: rkyv::util::AlignedVec) -> Self::Framed { archive }
+	fn unframe(stored: &[u8]) -> Result<&[u8], Error> { Ok(stored) }
+}
+
+/// Little-endian crc32 of the archive followed by the archive, checked on every read.
+/// Bytecheck only rejects bytes that don't make a valid archive,
+/// this also catches bit flips (e.g. from a bad disk) that happen to leave the archive valid.
+pub struct Crc32;
+
+impl Framing for Crc32 {
+	type Framed = Vec<u8>;
+
+	fn frame(archive: rkyv::util::AlignedVec) -> Self::Framed {
+		let mut framed = Vec::with_capacity(4 + archive.len());
+		framed.extend_from_slice(&crc32fast::hash(&archive).to_le_bytes());
+		framed.extend_from_slice(&archive);
+		framed
+	}
+
+	fn unframe(stored: &[u8]) -> Result<&[u8], Error> {
+		let Some((crc, archive)) = stored.split_first_chunk::<4>() else { return Err(Error::ChecksumMismatch) };
+		if u32::from_le_bytes(*crc) != crc32fast::hash(archive) { return Err(Error::ChecksumMismatch); }
+		Ok(archive)
+	}
+}
@@ -0,0 +1,37 @@
+//! Entry points for the cargo-fuzz targets in `fuzz/`, not meant for anything else.
+
+use crate::{AssocTable, IndexTable, RoTxn, Table, Tree, compat, framing::{Crc32, Framing, Plain}};
+
+type Check = fn(&[u8], &[u8]) -> Result<(), crate::Error>;
+
+// a spread of archived layouts - strings, integers, vecs, options, tuples - with and without framing
+const CHECKS: [Check; 6] = [
+	<AssocTable<'static, RoTxn<'static>, String, String> as Table<'static, 'static, RoTxn<'static>>>::check_entry,
+	<AssocTable<'static, RoTxn<'static>, u64, Vec<u8>, Crc32> as Table<'static, 'static, RoTxn<'static>>>::check_entry,
+	<AssocTable<'static, RoTxn<'static>, (u32, String), Option<Vec<String>>> as Table<'static, 'static, RoTxn<'static>>>::check_entry,
+	<IndexTable<'static, RoTxn<'static>, String> as Table<'static, 'static, RoTxn<'static>>>::check_entry,
+	<IndexTable<'static, RoTxn<'static>, Vec<(u16, i64)>, Crc32> as Table<'static, 'static, RoTxn<'static>>>::check_entry,
+	|_, value| compat::from_value::<Vec<Option<String>>, Plain>(value).map(drop),
+];
+
+/// Feed arbitrary bytes to table entry checks and decoding: the first byte picks the layout,
+/// the second how many of the remaining bytes are the key. Errors are fine, panics aren't.
+pub fn decode_any(data: &[u8]) {
+	let [selector, split, rest @ ..] = data else { return };
+	let (key, value) = rest.split_at(usize::from(*split).min(rest.len()));
+	let _ = CHECKS[usize::from(*selector) % CHECKS.len()](key, value);
+	let _ = Crc32::unframe(value);
+}
+
+/// Put `key` and `value` into `tree`, then read them back through get and a cursor, panicking if anything doesn't match.
+pub fn roundtrip(tree: &Tree<'_>, key: &[u8], value: &[u8]) {
+	// lmdb rejects these with BadValSize, which is covered elsewhere
+	if key.is_empty() || key.len() > 511 { return; }
+	tree.insert(key, value).expect("insert failed");
+	assert_eq!(tree.get(key).expect("get failed").as_deref(), Some(value));
+	let tx = tree.db().env().read_tx().expect("read tx failed");
+	let found = tree.iter_in(&tx).expect("cursor failed").map(|entry| entry.expect("cursor failed")).find(|(k, _)| *k == key);
+	assert_eq!(found, Some((key, value)));
+	drop(tx);
+	assert_eq!(tree.remove(key).expect("remove failed").as_deref(), Some(value));
+}
@@ -0,0 +1,237 @@
+//! Group commit: writes queued from any number of tasks run one after another in a shared write transaction,
+//! and each one's future resolves once that transaction is committed. Thousands of tiny writes a second
+//! turn into a few dozen commits, so a few dozen fsyncs.
+//! ```ignore
+//! let group = GroupCommit::new(&ENV, Limits::default());
+//! // from many tasks at once
+//! group.write(move |tx| Counters::get(tx).put(&name, &count)).await??;
+//! ```
+//! Each write runs in a [savepoint](RwTxn::savepoint) of the batch's transaction, so one that panics - or with
+//! [`GroupCommit::try_write`], returns an Err - is rolled back alone and the rest of its batch still commits. Writes
+//! that have to be all or nothing together belong in one job, or in [`Env::try_write`]. Envs with
+//! [`EnvBuilder::write_map`](crate::env::EnvBuilder::write_map) can't have savepoints: there a panicking write aborts
+//! its whole batch, and every write in it fails.
+
+use culpa::throws;
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
+use crate::{Env, Error, RwTxn, Transaction};
+
+/// A batch is committed as soon as any of these is reached, `max_delay` counting from the first write of the batch.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+	pub max_writes: usize,
+	/// compared against [`RwTxn::bytes_written`] after each write
+	pub max_bytes: usize,
+	pub max_delay: Duration,
+}
+
+impl Default for Limits {
+	fn default() -> Self { Self { max_writes: 1000, max_bytes: 16 << 20, max_delay: Duration::from_millis(10) } }
+}
+
+/// Queue of writes to the env, with a writer thread running them that stops once this is dropped.
+pub struct GroupCommit {
+	queue: mpsc::Sender<Box<dyn Queued>>,
+}
+
+type Ack = Box<dyn FnOnce(Result<(), Arc<Error>>) + Send>;
+
+// a write with its result type erased, run gives back what sends the result when the batch is committed
+trait Queued: Send {
+	// None if the job panicked outside a savepoint, leaving `tx` half written - its ack is dropped
+	fn run(self: Box<Self>, tx: &RwTxn<'static>, savepoints: bool) -> Option<Ack>;
+	fn fail(self: Box<Self>, e: Arc<Error>);
+}
+
+// acks through a oneshot for async writes, a channel for blocking ones
+struct Write<Job, Res> {
+	job: Job,
+	// whether to keep the job's writes given what it returned
+	keep: fn(&Res) -> bool,
+	ack: Box<dyn FnOnce(Result<Res, Arc<Error>>) + Send>,
+}
+
+impl<Job, Res> Queued for Write<Job, Res> where
+	Res: Send + 'static,
+	Job: (FnOnce(&RwTxn<'_>) -> Res) + Send,
+{
+	fn run(self: Box<Self>, tx: &RwTxn<'static>, savepoints: bool) -> Option<Ack> {
+		let Self { job, keep, ack } = *self;
+		let res = if savepoints {
+			match tx.savepoint() {
+				Ok(savepoint) => match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| job(&savepoint))) {
+					Ok(res) if keep(&res) => savepoint.release().map(|()| res).map_err(Arc::new),
+					// dropping the savepoint rolls back
+					Ok(res) => Ok(res),
+					Err(_) => { log::error!("a grouped write panicked, rolled back its writes"); Err(Arc::new(Error::WritePanicked)) },
+				},
+				Err(e) => Err(Arc::new(e)),
+			}
+		} else {
+			Ok(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| job(tx))).ok()?)
+		};
+		Some(Box::new(move |committed| ack(committed.and(res))))
+	}
+
+	fn fail(self: Box<Self>, e: Arc<Error>) { (self.ack)(Err(e)); }
+}
+
+impl GroupCommit {
+	pub fn new(env: &'static Env, limits: Limits) -> Self {
+		let (queue, queued) = mpsc::channel();
+		std::thread::Builder::new()
+			.name("batadase-group-commit".to_owned())
+			.spawn(move || writer(env, limits, &queued))
+			.expect("failed to spawn the group commit thread");
+		Self { queue }
+	}
+
+	/// Run `job` in the next batch, returning its result once the batch is committed.
+	/// Fails with [`Error::GroupCommit`] if the commit does or `job` panics, in which case nothing it did was written.
+	#[throws]
+	pub async fn write<Res, Job>(&self, job: Job) -> Res where
+		Res: Send + 'static,
+		Job: (FnOnce(&RwTxn<'_>) -> Res) + Send + 'static,
+	{
+		self.queued(job, |_| true).await?
+	}
+
+	/// [`GroupCommit::write`] with `job`'s writes rolled back if it returns an Err, like [`Env::try_write`]'s.
+	#[throws]
+	pub async fn try_write<Res, Err, Job>(&self, job: Job) -> Result<Res, Err> where
+		Res: Send + 'static,
+		Job: (FnOnce(&RwTxn<'_>) -> Result<Res, Err>) + Send + 'static,
+		Err: Send + 'static,
+	{
+		self.queued(job, Result::is_ok).await?
+	}
+
+	/// [`GroupCommit::write`] for code that isn't async, blocking until the batch is committed.
+	#[throws]
+	pub fn write_blocking<Res, Job>(&self, job: Job) -> Res where
+		Res: Send + 'static,
+		Job: (FnOnce(&RwTxn<'_>) -> Res) + Send + 'static,
+	{
+		self.queued_blocking(job, |_| true)?
+	}
+
+	/// [`GroupCommit::try_write`] for code that isn't async, blocking until the batch is committed.
+	#[throws]
+	pub fn try_write_blocking<Res, Err, Job>(&self, job: Job) -> Result<Res, Err> where
+		Res: Send + 'static,
+		Job: (FnOnce(&RwTxn<'_>) -> Result<Res, Err>) + Send + 'static,
+		Err: Send + 'static,
+	{
+		self.queued_blocking(job, Result::is_ok)?
+	}
+
+	#[throws]
+	async fn queued<Res, Job>(&self, job: Job, keep: fn(&Res) -> bool) -> Res where
+		Res: Send + 'static,
+		Job: (FnOnce(&RwTxn<'_>) -> Res) + Send + 'static,
+	{
+		let (ack, acked) = tokio::sync::oneshot::channel();
+		self.queue.send(Box::new(Write { job, keep, ack: Box::new(move |res| { let _ = ack.send(res); }) })).expect("group commit thread is gone");
+		// dropped unsent by a job that panicked outside a savepoint
+		acked.await.unwrap_or_else(|_| Err(Arc::new(Error::WritePanicked))).map_err(Error::GroupCommit)?
+	}
+
+	#[throws]
+	fn queued_blocking<Res, Job>(&self, job: Job, keep: fn(&Res) -> bool) -> Res where
+		Res: Send + 'static,
+		Job: (FnOnce(&RwTxn<'_>) -> Res) + Send + 'static,
+	{
+		let (ack, acked) = mpsc::sync_channel(1);
+		self.queue.send(Box::new(Write { job, keep, ack: Box::new(move |res| { let _ = ack.send(res); }) })).expect("group commit thread is gone");
+		acked.recv().unwrap_or_else(|_| Err(Arc::new(Error::WritePanicked))).map_err(Error::GroupCommit)?
+	}
+}
+
+fn writer(env: &'static Env, limits: Limits, queued: &mpsc::Receiver<Box<dyn Queued>>) {
+	while let Ok(first) = queued.recv() {
+		let tx = match env.write_tx() {
+			Ok(tx) => tx,
+			Err(e) => { first.fail(Arc::new(e)); continue; },
+		};
+		let savepoints = match env.is_write_map() {
+			Ok(write_map) => !write_map,
+			Err(e) => { first.fail(Arc::new(e)); continue; },
+		};
+		let deadline = Instant::now() + limits.max_delay;
+		let mut acks = Vec::new();
+		let mut next = Some(first);
+		let mut aborted = false;
+		while let Some(write) = next.take() {
+			let Some(ack) = write.run(&tx, savepoints) else { aborted = true; break };
+			acks.push(ack);
+			if acks.len() >= limits.max_writes || tx.bytes_written() >= limits.max_bytes { break; }
+			next = queued.recv_timeout(deadline.saturating_duration_since(Instant::now())).ok();
+		}
+		let committed = if aborted {
+			log::error!("a grouped write panicked, aborted its batch");
+			drop(tx);
+			Err(Arc::new(Error::WritePanicked))
+		} else {
+			tx.commit().map_err(Arc::new)
+		};
+		log::trace!("group committed {} writes", acks.len());
+		for ack in acks { ack(committed.clone()); }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, Env, RawTable, Transaction, env::TempEnv};
+	use super::{GroupCommit, Limits};
+
+	struct Counters;
+
+	impl DbName for Counters {
+		type Table<'tx, 'env: 'tx, TX: Transaction<'env> + 'tx> = RawTable<'tx, TX>;
+		const NAME: &'static [u8] = b"counters\0";
+	}
+
+	// the writer thread outlives the test, so the env is leaked along with it
+	fn group(write_map: bool) -> (&'static TempEnv, GroupCommit) {
+		let builder = Env::builder().unwrap().with::<Counters>();
+		let builder = if write_map { builder.write_map() } else { builder };
+		let env: &'static TempEnv = Box::leak(Box::new(builder.build_temp().unwrap()));
+		// a long delay so the writes below share a batch
+		(env, GroupCommit::new(env, Limits { max_writes: 3, max_delay: std::time::Duration::from_secs(10), ..Limits::default() }))
+	}
+
+	fn stored(env: &Env, key: &[u8]) -> bool { env.read_tx().unwrap().get::<Counters>().get(key).unwrap().is_some() }
+
+	#[test]
+	fn failed_writes_roll_back_alone() {
+		let (env, group) = group(false);
+		std::thread::scope(|s| {
+			let group = &group;
+			let panicked = s.spawn(move || group.write_blocking(|tx| { tx.get::<Counters>().put(b"a", b"1").unwrap(); panic!("job panicked") }));
+			std::thread::sleep(std::time::Duration::from_millis(100));
+			let failed = s.spawn(move || group.try_write_blocking(|tx| { tx.get::<Counters>().put(b"b", b"1")?; Err::<(), _>(crate::Error::InvalidEntry("rejected")) }));
+			std::thread::sleep(std::time::Duration::from_millis(100));
+			assert!(group.write_blocking(|tx| tx.get::<Counters>().put(b"c", b"1")).unwrap().is_ok());
+			assert!(matches!(panicked.join().unwrap(), Err(crate::Error::GroupCommit(e)) if matches!(*e, crate::Error::WritePanicked)));
+			assert!(failed.join().unwrap().unwrap().is_err());
+		});
+		assert!(!stored(env, b"a"));
+		assert!(!stored(env, b"b"));
+		assert!(stored(env, b"c"));
+	}
+
+	#[test]
+	fn panics_abort_the_batch_without_savepoints() {
+		let (env, group) = group(true);
+		std::thread::scope(|s| {
+			let group = &group;
+			let first = s.spawn(move || group.write_blocking(|tx| tx.get::<Counters>().put(b"a", b"1")));
+			std::thread::sleep(std::time::Duration::from_millis(100));
+			let panicked = group.write_blocking(|_| -> () { panic!("job panicked") });
+			assert!(matches!(panicked, Err(crate::Error::GroupCommit(e)) if matches!(*e, crate::Error::WritePanicked)));
+			assert!(matches!(first.join().unwrap(), Err(crate::Error::GroupCommit(e)) if matches!(*e, crate::Error::WritePanicked)));
+		});
+		assert!(!stored(env, b"a"));
+	}
+}
@@ -0,0 +1,66 @@
+//! A health report of the env in one struct, see [`Env::health`](crate::Env::health), for a `/healthz` handler
+//! to serialize (with the `serde` feature) or a metrics endpoint to expose in Prometheus' text format:
+//! ```ignore
+//! async fn healthz() -> Json<Health> { Json(ENV.health().unwrap()) }
+//! async fn metrics() -> String { ENV.health().unwrap().prometheus() }
+//! ```
+//! Getting one takes a short read transaction and walks the reader table, so it's cheap enough to scrape often.
+
+use std::fmt::Write;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Health {
+	/// see [`Env::usage`](crate::Env::usage)
+	pub map_used_bytes: usize,
+	pub map_size_bytes: usize,
+	pub map_percent: f32,
+	pub readers: u32,
+	pub max_readers: u32,
+	/// reader slots held by processes that aren't running anymore, which `mdb_reader_check` would free
+	pub stale_readers: u32,
+	/// transactions committed since the snapshot the oldest open read transaction reads, 0 without any - a reader
+	/// far behind keeps the pages of everything since from being reused
+	pub oldest_reader_lag: usize,
+	/// since the last write transaction of this process committed, None if there's been none since the env was opened
+	pub last_commit_age_secs: Option<f64>,
+	/// entries per table registered in the env, not counting other dbs in the file
+	pub tables: Vec<TableHealth>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TableHealth {
+	/// without the trailing nul
+	pub name: String,
+	pub entries: usize,
+}
+
+impl Health {
+	/// as Prometheus' text exposition format, every metric prefixed with `batadase_` and tables labelled by name
+	pub fn prometheus(&self) -> String {
+		let mut out = String::new();
+		let mut gauge = |name: &str, help: &str, value: &dyn std::fmt::Display| {
+			let _ = writeln!(out, "# HELP batadase_{name} {help}\n# TYPE batadase_{name} gauge\nbatadase_{name} {value}");
+		};
+		gauge("map_used_bytes", "Bytes of the map up to its last used page.", &self.map_used_bytes);
+		gauge("map_size_bytes", "Size of the map.", &self.map_size_bytes);
+		gauge("map_percent", "Percent of the map used.", &self.map_percent);
+		gauge("readers", "Reader slots taken.", &self.readers);
+		gauge("max_readers", "Reader slots there are.", &self.max_readers);
+		gauge("stale_readers", "Reader slots held by dead processes.", &self.stale_readers);
+		gauge("oldest_reader_lag", "Transactions committed since the oldest reader's snapshot.", &self.oldest_reader_lag);
+		if let Some(age) = self.last_commit_age_secs { gauge("last_commit_age_seconds", "Seconds since the last commit.", &age); }
+		let _ = writeln!(out, "# HELP batadase_entries Entries in a table.\n# TYPE batadase_entries gauge");
+		for table in &self.tables {
+			let name = table.name.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+			let _ = writeln!(out, "batadase_entries{{table=\"{name}\"}} {}", table.entries);
+		}
+		out
+	}
+}
+
+// a slot of a pid that's gone - kill with no signal only checks, EPERM means it's alive under another user
+pub(crate) fn is_dead(pid: libc::pid_t) -> bool {
+	(unsafe { libc::kill(pid, 0) } != 0) && std::io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH)
+}
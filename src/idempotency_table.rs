@@ -0,0 +1,122 @@
+//! Results of operations by an idempotency key, so a redelivered webhook or retried job is answered from the first
+//! run instead of being processed again:
+//! ```ignore
+//! #[derive(DbName)]
+//! #[table(IdempotencyTable<'tx, TX, String, Receipt>)]
+//! struct Deliveries;
+//!
+//! // the receipt borrows from the transaction, so it's deserialized to outlive it
+//! let receipt = ENV.try_write(move |tx| {
+//!     let receipt = tx.get::<Deliveries>().run_once(&event.id, Duration::from_secs(24 * 3600), || {
+//!         tx.get::<Orders>().put(&event.order_id, &event.order)?;
+//!         Ok(Receipt { order_id: event.order_id })
+//!     })?;
+//!     Ok::<_, batadase::Error>(rkyv::deserialize::<Receipt, rkyv::rancor::Error>(receipt)?)
+//! }).await??;
+//! respond(receipt);
+//! ```
+//! The operation runs in the caller's write transaction and its result is recorded in it, so either both commit or
+//! neither does - an operation that fails or is rolled back runs again next time. Recorded results are kept until they
+//! expire, then the key runs again; `purge_expired` removes them for good. Times are milliseconds since the unix epoch,
+//! like [`TrackedTable`](crate::TrackedTable)'s.
+
+use culpa::throws;
+use std::time::Duration;
+use crate::{AssocTable, Transaction, RwTxn, Table, RkyvSer, RkyvVal, RkyvDe, Error, lmdb, error::Context, tracked_table};
+
+/// the stored value
+#[derive(Debug, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct Outcome<R> {
+	pub expires: u64,
+	pub result: R,
+}
+
+pub struct IdempotencyTable<'tx, TX, K, R> {
+	inner: AssocTable<'tx, TX, K, Outcome<R>>,
+}
+
+impl<'tx, 'env: 'tx, TX, K, R> Table<'tx, 'env, TX> for IdempotencyTable<'tx, TX, K, R> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	R: rkyv::Archive + 'tx,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<Outcome<R>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<Outcome<R>, RkyvDe> + 'tx,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.inner.dbi() }
+	fn txn(&self) -> &TX { self.inner.txn() }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self { Self { inner: Table::build(tx, name) } }
+
+	#[throws]
+	fn check_entry(key: &[u8], value: &[u8]) { AssocTable::<'tx, TX, K, Outcome<R>>::check_entry(key, value)?; }
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, K, R> IdempotencyTable<'tx, RwTxn<'tx>, K, R> where
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	R: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>> + 'tx,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<Outcome<R>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<Outcome<R>, RkyvDe> + 'tx,
+{
+	/// `run_once_at` the current time
+	#[throws]
+	pub fn run_once(&self, key: &K, ttl: Duration, operation: impl FnOnce() -> Result<R, Error>) -> &'tx rkyv::Archived<R> {
+		self.run_once_at(key, tracked_table::now(), ttl, operation)?
+	}
+
+	/// The result recorded for `key` if it hasn't expired by `now`, otherwise run `operation` and record its result to
+	/// expire `ttl` after `now`. Nothing is recorded if the operation fails.
+	#[throws]
+	pub fn run_once_at(&self, key: &K, now: u64, ttl: Duration, operation: impl FnOnce() -> Result<R, Error>) -> &'tx rkyv::Archived<R> {
+		if let Some(outcome) = self.inner.get(key)? && outcome.expires.to_native() > now { return &outcome.result; }
+		let expires = now.saturating_add(ttl.as_millis().try_into().unwrap_or(u64::MAX));
+		self.inner.put(key, &Outcome { expires, result: operation()? })?;
+		&self.inner.get(key)?.expect("just put").result
+	}
+
+	/// forget `key`'s result, so it runs again
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn forget(&self, key: &K) -> bool { self.inner.delete(key)? }
+
+	/// Remove the results that expired by `now`, returns how many were removed. Walks the whole table.
+	#[throws]
+	pub fn purge_expired(&self, now: u64) -> usize {
+		let (tx, dbi) = (self.inner.txn(), self.inner.dbi());
+		let mut cursor = lmdb::Cursor::open(tx, dbi).context(tx, dbi, "purge_expired", None)?;
+		let mut entry = cursor.get(lmdb::CursorOp::First)?;
+		let mut purged = 0;
+		while let Some((key, value)) = entry {
+			let outcome = rkyv::access::<rkyv::Archived<Outcome<R>>, rkyv::rancor::Error>(value).context(tx, dbi, "purge_expired", Some(key))?;
+			if outcome.expires.to_native() <= now {
+				let key = key.to_vec(); // the slice goes stale with the delete
+				cursor.del_current().context(tx, dbi, "purge_expired", Some(&key))?;
+				purged += 1;
+			}
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+		purged
+	}
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn clear(&self) { self.inner.clear()? }
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, K, R> IdempotencyTable<'tx, TX, K, R> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	R: rkyv::Archive + 'tx,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<Outcome<R>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<Outcome<R>, RkyvDe> + 'tx,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { inner: AssocTable::build(tx, dbi) } }
+
+	/// the assoc table underneath, with the expiry times
+	pub fn inner(&self) -> &AssocTable<'tx, TX, K, Outcome<R>> { &self.inner }
+
+	/// the result recorded for `key`, expired or not
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn get(&self, key: &K) -> Option<&'tx ArchivedOutcome<R>> { self.inner.get(key)? }
+}
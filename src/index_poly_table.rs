@@ -1,11 +1,12 @@
-use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, Error, lmdb, DbFlags};
-use culpa::throws;
+use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, Error, lmdb, error::Context, DbFlags};
+use culpa::{throw, throws};
 use enumflags2::BitFlag;
 use batadase_index::Index;
 
 pub struct IndexPolyTable<'tx, TX> {
 	tx: &'tx TX,
 	dbi: lmdb_sys::MDB_dbi,
+	_borrow: crate::borrows::Borrow,
 }
 
 impl<'tx, 'env: 'tx, TX> Table<'tx, 'env, TX> for IndexPolyTable<'tx, TX> where
@@ -15,7 +16,13 @@ impl<'tx, 'env: 'tx, TX> Table<'tx, 'env, TX> for IndexPolyTable<'tx, TX> where
 	fn txn(&self) -> &TX { self.tx }
 	fn flags() -> enumflags2::BitFlags<DbFlags> { DbFlags::IntegerKey.into() }
 	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
-		Self::build(tx, tx.env().db(name).unwrap())
+		Self::build(tx, tx.env().db(name).expect("table isn't registered in the env, add it with EnvBuilder::with"))
+	}
+
+	// values can be anything
+	#[throws]
+	fn check_entry(key: &[u8], _value: &[u8]) {
+		if key.len() != std::mem::size_of::<u64>() { throw!(Error::InvalidEntry("index key isn't a u64")); }
 	}
 }
 
@@ -26,7 +33,7 @@ impl<'tx> IndexPolyTable<'tx, RwTxn<'tx>> {
 	{
 		let mut index_bytes = u64::from(index).to_ne_bytes();
 		let mut value_bytes = rkyv::to_bytes(t)?;
-		lmdb::put(self.tx, self.dbi, &mut index_bytes, &mut value_bytes, lmdb::PutFlags::empty())?;
+		lmdb::put(self.tx, self.dbi, &mut index_bytes, &mut value_bytes, lmdb::PutFlags::empty()).context(self.tx, self.dbi, "put", Some(&index_bytes))?;
 	}
 
 	#[throws]
@@ -35,7 +42,7 @@ impl<'tx> IndexPolyTable<'tx, RwTxn<'tx>> {
 	{
 		let mut index_bytes = u64::from(index).to_ne_bytes();
 		let mut value_bytes = rkyv::to_bytes(t)?;
-		lmdb::put(self.tx, self.dbi, &mut index_bytes, &mut value_bytes, lmdb::PutFlags::NoOverwrite.into())?;
+		lmdb::put(self.tx, self.dbi, &mut index_bytes, &mut value_bytes, lmdb::PutFlags::NoOverwrite.into()).context(self.tx, self.dbi, "put_no_overwrite", Some(&index_bytes))?;
 	}
 
 	#[throws]
@@ -50,17 +57,17 @@ impl<'tx> IndexPolyTable<'tx, RwTxn<'tx>> {
 	#[throws]
 	pub fn delete_index<T>(&self, index: Index<T>) -> bool {
 		let mut index_bytes = u64::from(index).to_ne_bytes();
-		lmdb::del(self.tx, self.dbi, &mut index_bytes)?
+		lmdb::del(self.tx, self.dbi, &mut index_bytes).context(self.tx, self.dbi, "delete_index", Some(&index_bytes))?
 	}
 
 	#[throws]
-	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi)?; }
+	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi).context(self.tx, self.dbi, "clear", None)?; }
 }
 
 impl<'tx, 'env: 'tx, TX> IndexPolyTable<'tx, TX> where
 	TX: Transaction<'env>,
 {
-	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { tx, dbi } }
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { tx, dbi, _borrow: crate::borrows::Borrow::of(tx) } }
 
 	#[throws]
 	pub fn get<T>(&self, index: Index<T>) -> Option<&'tx rkyv::Archived<T>> where
@@ -68,14 +75,34 @@ impl<'tx, 'env: 'tx, TX> IndexPolyTable<'tx, TX> where
 		rkyv::Archived<T>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 	{
 		let mut index_bytes = u64::from(index).to_ne_bytes();
-		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut index_bytes)? else { return None; };
-		Some(rkyv::access::<rkyv::Archived<T>, _>(value_bytes)?)
+		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut index_bytes).context(self.tx, self.dbi, "get", Some(&index_bytes))? else { return None; };
+		Some(rkyv::access::<rkyv::Archived<T>, rkyv::rancor::Error>(value_bytes).context(self.tx, self.dbi, "get", Some(&index_bytes))?)
+	}
+
+	/// whether `index` has a value, which isn't read or validated
+	#[throws]
+	pub fn contains_index<T>(&self, index: Index<T>) -> bool {
+		let mut index_bytes = u64::from(index).to_ne_bytes();
+		lmdb::get(self.tx, self.dbi, &mut index_bytes).context(self.tx, self.dbi, "contains_index", Some(&index_bytes))?.is_some()
+	}
+
+	/// Like [`IndexPolyTable::get`] but without bytecheck validation, for hot paths over trusted data.
+	///
+	/// # Safety
+	/// The stored value has to be a valid archive of `T`, so it has to have been put as a `T` and not corrupted since.
+	#[throws]
+	pub unsafe fn get_unchecked<T>(&self, index: Index<T>) -> Option<&'tx rkyv::Archived<T>> where
+		T: rkyv::Archive,
+	{
+		let mut index_bytes = u64::from(index).to_ne_bytes();
+		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut index_bytes).context(self.tx, self.dbi, "get", Some(&index_bytes))? else { return None; };
+		Some(unsafe { rkyv::access_unchecked::<rkyv::Archived<T>>(value_bytes) })
 	}
 
 	#[throws]
 	fn last_numeric_index(&self) -> Option<u64> {
 		lmdb::Cursor::open(self.tx, self.dbi)?
-			.get_with_u64_key(lmdb::CursorOpFlags::Last)
+			.get_with_u64_key(lmdb::CursorOp::Last)?
 			.map(|(key, _)| key)
 	}
 
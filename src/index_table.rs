@@ -1,17 +1,78 @@
-use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, Error, lmdb, DbFlags};
-use culpa::throws;
+use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, RkyvDe, Error, lmdb, error::Context, DbFlags, framing::{Framing, Plain}};
+use culpa::{throw, throws};
 use batadase_index::Index;
 use enumflags2::BitFlag;
 use std::marker::PhantomData;
 
-pub struct IndexTable<'tx, TX, T> {
+pub struct IndexTable<'tx, TX, T, F = Plain> {
 	tx: &'tx TX,
 	dbi: lmdb_sys::MDB_dbi,
+	_pd: PhantomData<(T, F)>,
+	_borrow: crate::borrows::Borrow,
+}
+
+/// Consecutive indexes after a table's last one, from [`IndexTable::reserve`]. Split it to serialize the rows of
+/// a bulk load on many threads, then have one writer append them all with [`IndexTable::append_rows`]:
+/// ```ignore
+/// let block = ENV.read_tx()?.get::<Users>().reserve(users.len() as u64)?;
+/// let rows = std::thread::scope(|s| {
+///     let workers = block.split(8).into_iter().zip(users.chunks(users.len().div_ceil(8)))
+///         .map(|(block, users)| s.spawn(move || block.iter().zip(users).map(|(index, user)| Row::new(index, user)).collect::<Result<Vec<_>, _>>()))
+///         .collect::<Vec<_>>();
+///     workers.into_iter().map(|x| x.join().unwrap()).collect::<Result<Vec<_>, _>>()
+/// })?;
+/// ENV.write(move |tx| tx.get::<Users>().append_rows(rows.into_iter().flatten())).await??;
+/// ```
+/// Nothing is written when reserving, so a put past the table's last index between reserving and appending makes the
+/// append fail with `KeyExist` - one loader at a time.
+pub struct IdBlock<T> {
+	start: u64,
+	len: u64,
 	_pd: PhantomData<T>,
 }
 
-impl<'tx, 'env: 'tx, TX, T> Table<'tx, 'env, TX> for IndexTable<'tx, TX, T> where
+impl<T> Clone for IdBlock<T> {
+	fn clone(&self) -> Self { *self }
+}
+
+impl<T> Copy for IdBlock<T> {}
+
+impl<T> IdBlock<T> {
+	pub fn len(&self) -> u64 { self.len }
+	pub fn is_empty(&self) -> bool { self.len == 0 }
+	pub fn first(&self) -> Option<Index<T>> { (self.len > 0).then(|| Index::from(self.start)) }
+	pub fn iter(&self) -> impl Iterator<Item = Index<T>> + use<T> { (self.start..self.start + self.len).map(Index::from) }
+
+	/// into at most `parts` blocks one after the other, as evenly sized as can be
+	pub fn split(&self, parts: u64) -> Vec<Self> {
+		let parts = parts.clamp(1, self.len.max(1));
+		(0..parts).map(|i| {
+			let (start, end) = (self.len * i / parts, self.len * (i + 1) / parts);
+			Self { start: self.start + start, len: end - start, _pd: PhantomData }
+		}).collect()
+	}
+}
+
+/// A value serialized and framed for its index ahead of [`IndexTable::append_rows`].
+pub struct Row<T, F: Framing = Plain> {
+	index: Index<T>,
+	bytes: F::Framed,
+}
+
+impl<T, F: Framing> Row<T, F> {
+	#[throws]
+	pub fn new(index: Index<T>, value: &T) -> Self where
+		T: for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	{
+		Self { index, bytes: F::frame(rkyv::to_bytes(value)?) }
+	}
+
+	pub fn index(&self) -> Index<T> { self.index }
+}
+
+impl<'tx, 'env: 'tx, TX, T, F> Table<'tx, 'env, TX> for IndexTable<'tx, TX, T, F> where
 	TX: Transaction<'env>,
+	F: Framing,
 	T: rkyv::Archive,
 	rkyv::Archived<T>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 {
@@ -19,26 +80,41 @@ impl<'tx, 'env: 'tx, TX, T> Table<'tx, 'env, TX> for IndexTable<'tx, TX, T> wher
 	fn txn(&self) -> &TX { self.tx }
 	fn flags() -> enumflags2::BitFlags<DbFlags> { DbFlags::IntegerKey.into() }
 	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
-		Self::build(tx, tx.env().db(name).unwrap())
+		Self::build(tx, tx.env().db(name).expect("table isn't registered in the env, add it with EnvBuilder::with"))
 	}
+
+	#[throws]
+	fn check_entry(key: &[u8], value: &[u8]) {
+		if key.len() != std::mem::size_of::<u64>() { throw!(Error::InvalidEntry("index key isn't a u64")); }
+		rkyv::access::<rkyv::Archived<T>, rkyv::rancor::Error>(F::unframe(value)?)?;
+	}
+}
+
+#[throws]
+fn access<T, F: Framing>(stored: &[u8]) -> &rkyv::Archived<T> where
+	T: rkyv::Archive,
+	rkyv::Archived<T>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	rkyv::access::<rkyv::Archived<T>, rkyv::rancor::Error>(F::unframe(stored)?)?
 }
 
-impl<'tx, T> IndexTable<'tx, RwTxn<'tx>, T> where
+impl<'tx, T, F> IndexTable<'tx, RwTxn<'tx>, T, F> where
+	F: Framing,
 	T: for <'a> rkyv::Serialize<RkyvSer<'a>>,
 	rkyv::Archived<T>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 {
 	#[throws]
 	pub fn put(&self, index: Index<T>, t: &T) {
 		let mut index_bytes = u64::from(index).to_ne_bytes();
-		let mut value_bytes = rkyv::to_bytes(t)?;
-		lmdb::put(self.tx, self.dbi, &mut index_bytes, &mut value_bytes, lmdb::PutFlags::empty())?;
+		let mut value_bytes = F::frame(rkyv::to_bytes(t)?);
+		lmdb::put(self.tx, self.dbi, &mut index_bytes, &mut value_bytes, lmdb::PutFlags::empty()).context(self.tx, self.dbi, "put", Some(&index_bytes))?;
 	}
 
 	#[throws]
 	pub fn put_no_overwrite(&self, index: Index<T>, t: &T) {
 		let mut index_bytes = u64::from(index).to_ne_bytes();
-		let mut value_bytes = rkyv::to_bytes(t)?;
-		lmdb::put(self.tx, self.dbi, &mut index_bytes, &mut value_bytes, lmdb::PutFlags::NoOverwrite.into())?;
+		let mut value_bytes = F::frame(rkyv::to_bytes(t)?);
+		lmdb::put(self.tx, self.dbi, &mut index_bytes, &mut value_bytes, lmdb::PutFlags::NoOverwrite.into()).context(self.tx, self.dbi, "put_no_overwrite", Some(&index_bytes))?;
 	}
 
 	#[throws]
@@ -48,63 +124,191 @@ impl<'tx, T> IndexTable<'tx, RwTxn<'tx>, T> where
 		index
 	}
 
+	/// Put rows serialized ahead, see [`IdBlock`]. They're sorted by index first, so they can come in any order,
+	/// and appended since their indexes are all past the table's last one. Returns how many were put.
+	#[throws]
+	pub fn append_rows(&self, rows: impl IntoIterator<Item = Row<T, F>>) -> usize {
+		let mut rows = rows.into_iter().collect::<Vec<_>>();
+		rows.sort_unstable_by_key(|row| u64::from(row.index));
+		for row in &mut rows {
+			let mut index_bytes = u64::from(row.index).to_ne_bytes();
+			lmdb::put(self.tx, self.dbi, &mut index_bytes, row.bytes.as_mut(), lmdb::PutFlags::Append.into()).context(self.tx, self.dbi, "append_rows", Some(&index_bytes))?;
+		}
+		rows.len()
+	}
+
 	#[throws]
 	pub fn delete_index(&self, index: Index<T>) -> bool {
 		let mut index_bytes = u64::from(index).to_ne_bytes();
-		lmdb::del(self.tx, self.dbi, &mut index_bytes)?
+		lmdb::del(self.tx, self.dbi, &mut index_bytes).context(self.tx, self.dbi, "delete_index", Some(&index_bytes))?
+	}
+
+	#[throws]
+	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi).context(self.tx, self.dbi, "clear", None)?; }
+
+	/// remove and return the entry with the smallest index
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn pop_first(&self) -> Option<(Index<T>, T)> where
+		rkyv::Archived<T>: rkyv::Deserialize<T, RkyvDe>,
+	{ self.pop(lmdb::CursorOp::First, "pop_first")? }
+
+	/// remove and return the entry with the largest index
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn pop_last(&self) -> Option<(Index<T>, T)> where
+		rkyv::Archived<T>: rkyv::Deserialize<T, RkyvDe>,
+	{ self.pop(lmdb::CursorOp::Last, "pop_last")? }
+
+	#[throws]
+	fn pop(&self, op: lmdb::CursorOp, name: &'static str) -> Option<(Index<T>, T)> where
+		rkyv::Archived<T>: rkyv::Deserialize<T, RkyvDe>,
+	{
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, name, None)?;
+		let Some((index, value_bytes)) = cursor.get_with_u64_key(op)? else { return None; };
+		let index_bytes = index.to_ne_bytes();
+		let value = rkyv::deserialize::<T, rkyv::rancor::Error>(access::<T, F>(value_bytes).context(self.tx, self.dbi, name, Some(&index_bytes))?)
+			.context(self.tx, self.dbi, name, Some(&index_bytes))?;
+		cursor.del_current().context(self.tx, self.dbi, name, Some(&index_bytes))?;
+		Some((Index::from(index), value))
 	}
+}
 
+// RwTxn and Plain framing only
+impl<'tx, T> IndexTable<'tx, RwTxn<'tx>, T, Plain> where
+	T: rkyv::Archive,
+	rkyv::Archived<T>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+{
+	/// The stored value, for updating small fields (counters, flags) in place without a deserialize-reserialize-put cycle.
+	/// It's moved onto a dirty page with MDB_RESERVE first, so readers never see the write and
+	/// [`EnvBuilder::write_map`](crate::env::EnvBuilder::write_map) only makes it cheaper.
+	///
+	/// # Safety
+	/// The seal points into lmdb's dirty page, which the next write in the transaction can move, reuse or free. It has to
+	/// be dropped before anything else is written in the transaction, including another `get_mut`, and no reference from
+	/// an earlier read of the same entry can be used while it's alive.
 	#[throws]
-	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi)?; }
+	pub unsafe fn get_mut(&self, index: Index<T>) -> Option<rkyv::seal::Seal<'tx, rkyv::Archived<T>>> {
+		let mut index_bytes = u64::from(index).to_ne_bytes();
+		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut index_bytes).context(self.tx, self.dbi, "get_mut", Some(&index_bytes))? else { return None; };
+		rkyv::access::<rkyv::Archived<T>, rkyv::rancor::Error>(value_bytes).context(self.tx, self.dbi, "get_mut", Some(&index_bytes))?;
+		let value_bytes = value_bytes.to_vec();
+		let reserved = lmdb::put_reserve(self.tx, self.dbi, &mut index_bytes, value_bytes.len()).context(self.tx, self.dbi, "get_mut", Some(&index_bytes))?;
+		reserved.copy_from_slice(&value_bytes);
+		// checked above
+		Some(unsafe { rkyv::access_unchecked_mut::<rkyv::Archived<T>>(reserved) })
+	}
 }
 
-impl<'tx, 'env: 'tx, TX, T> IndexTable<'tx, TX, T> where
+impl<'tx, 'env: 'tx, TX, T, F> IndexTable<'tx, TX, T, F> where
 	TX: Transaction<'env>,
+	F: Framing,
 	T: rkyv::Archive,
 	rkyv::Archived<T>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 {
 	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self {
-		Self { tx, dbi, _pd: PhantomData }
+		Self { tx, dbi, _pd: PhantomData, _borrow: crate::borrows::Borrow::of(tx) }
 	}
 
 	#[throws]
 	pub fn get(&self, index: Index<T>) -> Option<&'tx rkyv::Archived<T>> {
 		let mut index_bytes = u64::from(index).to_ne_bytes();
-		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut index_bytes)? else { return None; };
-		Some(rkyv::access::<rkyv::Archived<T>, _>(value_bytes)?)
+		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut index_bytes).context(self.tx, self.dbi, "get", Some(&index_bytes))? else { return None; };
+		Some(access::<T, F>(value_bytes).context(self.tx, self.dbi, "get", Some(&index_bytes))?)
+	}
+
+	/// whether `index` has a value, which isn't read or validated
+	#[throws]
+	pub fn contains_index(&self, index: Index<T>) -> bool {
+		let mut index_bytes = u64::from(index).to_ne_bytes();
+		lmdb::get(self.tx, self.dbi, &mut index_bytes).context(self.tx, self.dbi, "contains_index", Some(&index_bytes))?.is_some()
+	}
+
+	/// Like [`IndexTable::get`] but without bytecheck validation, for hot paths over trusted data.
+	/// Framing is still undone, so a `Crc32` checksum is still checked.
+	///
+	/// # Safety
+	/// The stored value has to be a valid archive of `T` - written by this table with the same `T` and framing, and not corrupted since.
+	#[throws]
+	pub unsafe fn get_unchecked(&self, index: Index<T>) -> Option<&'tx rkyv::Archived<T>> {
+		let mut index_bytes = u64::from(index).to_ne_bytes();
+		let Some(value_bytes) = lmdb::get(self.tx, self.dbi, &mut index_bytes).context(self.tx, self.dbi, "get", Some(&index_bytes))? else { return None; };
+		let value_bytes = F::unframe(value_bytes).context(self.tx, self.dbi, "get", Some(&index_bytes))?;
+		Some(unsafe { rkyv::access_unchecked::<rkyv::Archived<T>>(value_bytes) })
 	}
 
 	#[throws]
 	pub fn last(&self) -> Option<(Index<T>, &'tx rkyv::Archived<T>)> {
-		let Some((key_u64, value_bytes)) = lmdb::Cursor::open(self.tx, self.dbi)?.get_with_u64_key(lmdb::CursorOpFlags::Last) else { return None; };
-		Some((Index::from(key_u64), rkyv::access::<rkyv::Archived<T>, _>(value_bytes)?))
+		let Some((key_u64, value_bytes)) = lmdb::Cursor::open(self.tx, self.dbi)?.get_with_u64_key(lmdb::CursorOp::Last)? else { return None; };
+		Some((Index::from(key_u64), access::<T, F>(value_bytes).context(self.tx, self.dbi, "last", Some(&key_u64.to_ne_bytes()))?))
+	}
+
+	/// the `n` indexes after the last one, for a bulk load with [`IndexTable::append_rows`]
+	#[throws]
+	pub fn reserve(&self, n: u64) -> IdBlock<T> {
+		let start = self.max_index()?.map_or(0, |x| u64::from(x) + 1);
+		IdBlock { start, len: n, _pd: PhantomData }
+	}
+
+	/// the smallest index, its value isn't read
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn min_index(&self) -> Option<Index<T>> { self.edge_index(lmdb::CursorOp::First, "min_index")? }
+
+	/// the largest index, its value isn't read
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn max_index(&self) -> Option<Index<T>> { self.edge_index(lmdb::CursorOp::Last, "max_index")? }
+
+	#[throws]
+	fn edge_index(&self, op: lmdb::CursorOp, name: &'static str) -> Option<Index<T>> {
+		lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, name, None)?.get_with_u64_key(op)?.map(|(index, _)| Index::from(index))
 	}
 
 	#[expect(clippy::iter_not_returning_iterator)]
 	#[throws]
-	pub fn iter(&self) -> impl Iterator<Item = (Index<T>, &'tx rkyv::Archived<T>)> + use<'tx, 'env, TX, T> where
+	pub fn iter(&self) -> impl Iterator<Item = Result<(Index<T>, &'tx rkyv::Archived<T>), Error>> + use<'tx, 'env, TX, T, F> where
 		rkyv::Archived<T>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
 	{
-		struct Cursor<'tx, TX, T>(lmdb::Cursor<'tx, TX>, PhantomData<T>);
-
-		impl<'tx, 'env: 'tx, TX, T> Iterator for Cursor<'tx, TX, T> where
-			TX: Transaction<'env>,
-			T: rkyv::Archive,
-			rkyv::Archived<T>: 'tx + for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
-		{
-			type Item = (Index<T>, &'tx rkyv::Archived<T>);
-
-			fn next(&mut self) -> Option<(Index<T>, &'tx rkyv::Archived<T>)> {
-				let (key_u64, value_bytes) = self.0.get_with_u64_key(lmdb::CursorOpFlags::Next)?;
-				let key = Index::from(key_u64);
-				let value = match rkyv::access::<rkyv::Archived<T>, _>(value_bytes) {
-					Ok(x) => x,
-					Err(e) => { log::error!("Error deserializing value in rev cursor: {e:?}"); return None; }
-				};
-				Some((key, value))
-			}
-		}
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi)?;
+		let mut entries = lmdb::steps(move || cursor.get_with_u64_key(lmdb::CursorOp::Next));
+		std::iter::from_fn(move || {
+			let (key_u64, value_bytes) = match entries.next()? {
+				Ok(entry) => entry,
+				Err(e) => return Some(Err(e)),
+			};
+			let value_bytes = match F::unframe(value_bytes) {
+				Ok(x) => x,
+				Err(e) => { log::error!("Error unframing value in cursor: {e:?}"); return None; }
+			};
+			let value = match rkyv::access::<rkyv::Archived<T>, _>(value_bytes) {
+				Ok(x) => x,
+				Err(e) => { log::error!("Error deserializing value in rev cursor: {e:?}"); return None; }
+			};
+			Some(Ok((Index::from(key_u64), value)))
+		})
+	}
 
-		Cursor::<TX, T>(lmdb::Cursor::open(self.tx, self.dbi)?, PhantomData)
+	/// Like [`IndexTable::iter`] but without bytecheck validation of values.
+	///
+	/// # Safety
+	/// Same as [`IndexTable::get_unchecked`], for every entry in the table.
+	#[throws]
+	pub unsafe fn iter_unchecked(&self) -> impl Iterator<Item = Result<(Index<T>, &'tx rkyv::Archived<T>), Error>> + use<'tx, 'env, TX, T, F> where
+		rkyv::Archived<T>: 'tx,
+	{
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi)?;
+		let mut entries = lmdb::steps(move || cursor.get_with_u64_key(lmdb::CursorOp::Next));
+		std::iter::from_fn(move || {
+			let (key_u64, value_bytes) = match entries.next()? {
+				Ok(entry) => entry,
+				Err(e) => return Some(Err(e)),
+			};
+			let value_bytes = match F::unframe(value_bytes) {
+				Ok(x) => x,
+				Err(e) => { log::error!("Error unframing value in cursor: {e:?}"); return None; }
+			};
+			Some(Ok((Index::from(key_u64), unsafe { rkyv::access_unchecked::<rkyv::Archived<T>>(value_bytes) })))
+		})
 	}
 }
@@ -0,0 +1,136 @@
+//! Compact ids for big or oft-repeated keys like paths and long strings, so other tables can store the id instead:
+//! ```ignore
+//! #[derive(DbName)]
+//! #[table(InternTable<'tx, TX, String>)]
+//! struct Paths;
+//!
+//! let id = tx.get::<Paths>().intern(&path)?; // the same id every time for the same path
+//! tx.get::<Sizes>().put(&id, &size)?;
+//! let path = tx.get::<Paths>().resolve(id)?;
+//! ```
+//! Both directions are in the one db, so they change together in the transaction interning. Keys are looked up by a
+//! hash of their bytes, any length works. Ids count up from 0 and are never handed out twice, there's no removing.
+
+use culpa::{throw, throws};
+use batadase_index::Index;
+use std::marker::PhantomData;
+use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, Error, lmdb, error::Context};
+
+// entry kinds, first byte of the db's keys
+const BY_KEY: u8 = 0; // BY_KEY, hash and id (both big-endian) -> nothing
+const BY_ID: u8 = 1; // BY_ID and id -> the key's bytes, last so the last entry has the highest id
+
+pub struct InternTable<'tx, TX, K> {
+	tx: &'tx TX,
+	dbi: lmdb_sys::MDB_dbi,
+	_pd: PhantomData<K>,
+	_borrow: crate::borrows::Borrow,
+}
+
+impl<'tx, 'env: 'tx, TX, K> Table<'tx, 'env, TX> for InternTable<'tx, TX, K> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.dbi }
+	fn txn(&self) -> &TX { self.tx }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
+		Self::build(tx, tx.env().db(name).expect("table isn't registered in the env, add it with EnvBuilder::with"))
+	}
+
+	#[throws]
+	fn check_entry(key: &[u8], value: &[u8]) {
+		match key {
+			[BY_KEY, rest @ ..] if rest.len() == 16 && value.is_empty() => {},
+			[BY_ID, rest @ ..] if rest.len() == 8 => { rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(value)?; },
+			_ => throw!(Error::InvalidEntry("not an intern table entry")),
+		}
+	}
+}
+
+fn by_key(hash: u64, id: u64) -> Vec<u8> { [&[BY_KEY][..], &hash.to_be_bytes(), &id.to_be_bytes()].concat() }
+fn by_id(id: u64) -> Vec<u8> { [&[BY_ID][..], &id.to_be_bytes()].concat() }
+
+// RwTxn only, so all methods mutate
+impl<'tx, K> InternTable<'tx, RwTxn<'tx>, K> where
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+{
+	/// the id of `key`, handing out the next one if it's new
+	#[throws]
+	pub fn intern(&self, key: &K) -> Index<K> {
+		let key_bytes = rkyv::to_bytes(key)?;
+		let hash = crate::fnv1a(key_bytes.iter().copied());
+		if let Some(id) = self.find(&key_bytes, hash)? { return id; }
+
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "intern", Some(&key_bytes))?;
+		let id = match cursor.get(lmdb::CursorOp::Last)? {
+			Some(([BY_ID, last @ ..], _)) => u64::from_be_bytes(last.try_into().map_err(|_| Error::InvalidEntry("not an intern table entry"))?) + 1,
+			_ => 0,
+		};
+		drop(cursor);
+		lmdb::put(self.tx, self.dbi, &mut by_id(id), &mut key_bytes.to_vec(), lmdb::PutFlags::NoOverwrite.into()).context(self.tx, self.dbi, "intern", Some(&key_bytes))?;
+		lmdb::put(self.tx, self.dbi, &mut by_key(hash, id), &mut [], lmdb::PutFlags::NoOverwrite.into()).context(self.tx, self.dbi, "intern", Some(&key_bytes))?;
+		Index::from(id)
+	}
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, K> InternTable<'tx, TX, K> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { tx, dbi, _pd: PhantomData, _borrow: crate::borrows::Borrow::of(tx) } }
+
+	/// the id of `key` if it was interned
+	#[throws]
+	pub fn id(&self, key: &K) -> Option<Index<K>> {
+		let key_bytes = rkyv::to_bytes(key)?;
+		self.find(&key_bytes, crate::fnv1a(key_bytes.iter().copied()))?
+	}
+
+	// keys with the same hash are next to each other, told apart by their stored bytes
+	#[throws]
+	fn find(&self, key_bytes: &[u8], hash: u64) -> Option<Index<K>> {
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "id", Some(key_bytes))?;
+		let prefix = &by_key(hash, 0)[..9];
+		let mut entry = cursor.get_with_key(&mut prefix.to_vec(), lmdb::CursorOp::SetRange)?;
+		while let Some((entry_key, _)) = entry && let Some(id) = entry_key.strip_prefix(prefix) {
+			let id = u64::from_be_bytes(id.try_into().map_err(|_| Error::InvalidEntry("not an intern table entry")).context(self.tx, self.dbi, "id", Some(key_bytes))?);
+			if lmdb::get(self.tx, self.dbi, &mut by_id(id)).context(self.tx, self.dbi, "id", Some(key_bytes))? == Some(key_bytes) { return Some(Index::from(id)); }
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+		None
+	}
+
+	/// the key interned as `id`
+	#[throws]
+	pub fn resolve(&self, id: Index<K>) -> Option<&'tx rkyv::Archived<K>> {
+		let mut id_bytes = by_id(u64::from(id));
+		let Some(key_bytes) = lmdb::get(self.tx, self.dbi, &mut id_bytes).context(self.tx, self.dbi, "resolve", Some(&id_bytes))? else { return None };
+		Some(rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key_bytes).context(self.tx, self.dbi, "resolve", Some(&id_bytes))?)
+	}
+
+	/// how many keys were interned, half the table's entries
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn len(&self) -> usize { self.entries()? / 2 }
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn is_empty(&self) -> bool { self.entries()? == 0 }
+
+	/// every interned key by id
+	#[throws]
+	pub fn iter(&self) -> impl Iterator<Item = Result<(Index<K>, &'tx rkyv::Archived<K>), Error>> + use<'tx, 'env, TX, K> {
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "iter", None)?;
+		let mut entry = cursor.get_with_key(&mut [BY_ID], lmdb::CursorOp::SetRange)?;
+		std::iter::from_fn(move || {
+			let (key, value) = entry.take()?;
+			entry = match cursor.get(lmdb::CursorOp::Next) { Ok(next) => next, Err(e) => return Some(Err(e.into())) };
+			let id = u64::from_be_bytes(key[1..].try_into().ok()?);
+			Some(rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(value).map(|key| (Index::from(id), key)).map_err(Error::from))
+		})
+	}
+}
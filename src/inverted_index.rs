@@ -0,0 +1,179 @@
+//! Documents by the tokens in them, for simple search - tokenizing (splitting, case folding, stemming) is up to the caller:
+//! ```ignore
+//! #[derive(DbName)]
+//! #[table(InvertedIndex<'tx, TX>)]
+//! struct Search;
+//!
+//! tx.get::<Search>().index_document(post.id, post.text.split_whitespace())?;
+//! let hits = tx.get::<Search>().query_and(["rust", "lmdb"])?.collect::<Result<Vec<_>, _>>()?;
+//! ```
+//! A dupsort db with the ids of each token's documents as values, next to each document's tokens so it can be removed
+//! again. Document ids are u64s stored big-endian, queries return them ascending. Tokens can be up to the env's
+//! max key size less a byte long.
+
+use culpa::{throw, throws};
+use enumflags2::BitFlag;
+use crate::{Transaction, RwTxn, Table, DbFlags, Error, lmdb, error::Context};
+
+// entry kinds, first byte of the db's keys
+const BY_TOKEN: u8 = 0; // BY_TOKEN and token -> ids of the documents with it
+const BY_DOC: u8 = 1; // BY_DOC and id -> the document's tokens
+
+fn by_token(token: &[u8]) -> Vec<u8> { [&[BY_TOKEN][..], token].concat() }
+fn by_doc(id: u64) -> Vec<u8> { [&[BY_DOC][..], &id.to_be_bytes()].concat() }
+fn doc_id(value: &[u8]) -> u64 { u64::from_be_bytes(value.try_into().expect("checked by check_entry")) }
+
+pub struct InvertedIndex<'tx, TX> {
+	tx: &'tx TX,
+	dbi: lmdb_sys::MDB_dbi,
+	_borrow: crate::borrows::Borrow,
+}
+
+impl<'tx, 'env: 'tx, TX: Transaction<'env>> Table<'tx, 'env, TX> for InvertedIndex<'tx, TX> {
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.dbi }
+	fn txn(&self) -> &TX { self.tx }
+	fn flags() -> enumflags2::BitFlags<DbFlags> { DbFlags::DupSort.into() }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
+		Self::build(tx, tx.env().db(name).expect("table isn't registered in the env, add it with EnvBuilder::with"))
+	}
+
+	#[throws]
+	fn check_entry(key: &[u8], value: &[u8]) {
+		match key {
+			[BY_TOKEN, ..] if value.len() == 8 => {},
+			[BY_DOC, rest @ ..] if rest.len() == 8 => {},
+			_ => throw!(Error::InvalidEntry("not an inverted index entry")),
+		}
+	}
+}
+
+// RwTxn only, so all methods mutate
+impl InvertedIndex<'_, RwTxn<'_>> {
+	/// Index document `id` under `tokens`, replacing the tokens it was indexed under before. Repeated tokens count once.
+	#[throws]
+	pub fn index_document(&self, id: u64, tokens: impl IntoIterator<Item = impl AsRef<[u8]>>) {
+		self.remove_document(id)?;
+		let mut doc_key = by_doc(id);
+		for token in tokens {
+			let token = token.as_ref();
+			lmdb::put(self.tx, self.dbi, &mut by_token(token), &mut id.to_be_bytes(), lmdb::PutFlags::empty()).context(self.tx, self.dbi, "index_document", Some(token))?;
+			lmdb::put(self.tx, self.dbi, &mut doc_key, &mut token.to_vec(), lmdb::PutFlags::empty()).context(self.tx, self.dbi, "index_document", Some(&doc_key))?;
+		}
+	}
+
+	/// take document `id` out of the index, false if it wasn't in it
+	#[throws]
+	pub fn remove_document(&self, id: u64) -> bool {
+		let mut doc_key = by_doc(id);
+		let tokens = self.tokens(id)?.map(|token| token.map(<[u8]>::to_vec)).collect::<Result<Vec<_>, _>>()?;
+		for token in &tokens {
+			lmdb::del_value(self.tx, self.dbi, &mut by_token(token), &mut id.to_be_bytes()).context(self.tx, self.dbi, "remove_document", Some(token))?;
+		}
+		lmdb::del(self.tx, self.dbi, &mut doc_key).context(self.tx, self.dbi, "remove_document", Some(&doc_key))?
+	}
+
+	#[throws]
+	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi).context(self.tx, self.dbi, "clear", None)?; }
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX: Transaction<'env>> InvertedIndex<'tx, TX> {
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { tx, dbi, _borrow: crate::borrows::Borrow::of(tx) } }
+
+	/// the tokens document `id` is indexed under, in bytewise order
+	#[throws]
+	pub fn tokens(&self, id: u64) -> impl Iterator<Item = Result<&'tx [u8], Error>> + use<'tx, 'env, TX> {
+		let mut doc_key = by_doc(id);
+		let mut cursor = lmdb::DupCursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "tokens", Some(&doc_key))?;
+		let first = cursor.get_with_key(&mut doc_key, lmdb::CursorOp::SetKey).context(self.tx, self.dbi, "tokens", Some(&doc_key))?;
+		// NextDup only steps through the document's values if SetKey found it
+		let rest = first.is_some().then(|| lmdb::steps(move || cursor.get_dup(lmdb::DupCursorOp::NextDup))).into_iter().flatten();
+		first.map(Ok).into_iter().chain(rest).map(|entry| entry.map(|(_, token)| token))
+	}
+
+	/// how many documents have `token`
+	#[throws]
+	pub fn document_count(&self, token: impl AsRef<[u8]>) -> usize {
+		let token_key = by_token(token.as_ref());
+		let mut cursor = lmdb::DupCursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "document_count", Some(&token_key))?;
+		if cursor.get_with_key(&mut token_key.clone(), lmdb::CursorOp::SetKey).context(self.tx, self.dbi, "document_count", Some(&token_key))?.is_none() { return 0; }
+		cursor.count().context(self.tx, self.dbi, "document_count", Some(&token_key))?
+	}
+
+	// a cursor per token, each on the token's first document
+	#[throws]
+	fn cursors(&self, tokens: impl IntoIterator<Item = impl AsRef<[u8]>>, op: &'static str) -> Vec<Posting<'tx, TX>> {
+		let mut postings = Vec::new();
+		for token in tokens {
+			let key = by_token(token.as_ref());
+			let mut cursor = lmdb::DupCursor::open(self.tx, self.dbi).context(self.tx, self.dbi, op, Some(&key))?;
+			let current = cursor.get_with_key(&mut key.clone(), lmdb::CursorOp::SetKey).context(self.tx, self.dbi, op, Some(&key))?.map(|(_, id)| doc_id(id));
+			postings.push(Posting { cursor, key, current });
+		}
+		postings
+	}
+
+	/// the documents with every one of `tokens`, none for no tokens, ending after a cursor's error
+	#[throws]
+	pub fn query_and<I: IntoIterator<Item: AsRef<[u8]>>>(&self, tokens: I) -> impl Iterator<Item = Result<u64, Error>> + use<'tx, 'env, TX, I> {
+		let mut postings = self.cursors(tokens, "query_and")?;
+		let mut done = postings.is_empty();
+		// leapfrogs: every cursor seeks to the highest id any of them is on until they're all on the same one
+		std::iter::from_fn(move || {
+			if done { return None; }
+			let mut target = 0;
+			loop {
+				let mut agreed = true;
+				for posting in &mut postings {
+					let current = match posting.seek(target) {
+						Ok(Some(current)) => current,
+						Ok(None) => { done = true; return None },
+						Err(e) => { done = true; return Some(Err(e.into())) },
+					};
+					if current > target { target = current; agreed = false; }
+				}
+				if agreed { break; }
+			}
+			for posting in &mut postings {
+				if let Err(e) = posting.advance() { done = true; return Some(Err(e.into())); }
+			}
+			Some(Ok(target))
+		})
+	}
+
+	/// the documents with any of `tokens`, ending after a cursor's error
+	#[throws]
+	pub fn query_or<I: IntoIterator<Item: AsRef<[u8]>>>(&self, tokens: I) -> impl Iterator<Item = Result<u64, Error>> + use<'tx, 'env, TX, I> {
+		let mut postings = self.cursors(tokens, "query_or")?;
+		std::iter::from_fn(move || {
+			let lowest = postings.iter().filter_map(|posting| posting.current).min()?;
+			for posting in postings.iter_mut().filter(|posting| posting.current == Some(lowest)) {
+				if let Err(e) = posting.advance() { postings.clear(); return Some(Err(e.into())); }
+			}
+			Some(Ok(lowest))
+		})
+	}
+}
+
+// one token's documents, current is the id the cursor's on
+struct Posting<'tx, TX> {
+	cursor: lmdb::DupCursor<'tx, TX>,
+	key: Vec<u8>,
+	current: Option<u64>,
+}
+
+impl<'tx, 'env: 'tx, TX: Transaction<'env>> Posting<'tx, TX> {
+	// the first id at or after target
+	#[throws(lmdb::Error)]
+	fn seek(&mut self, target: u64) -> Option<u64> {
+		if self.current.is_some_and(|current| current < target) {
+			self.current = self.cursor.get_with_key_value(&mut self.key.clone(), &mut target.to_be_bytes(), lmdb::DupCursorOp::GetBothRange)?.map(doc_id);
+		}
+		self.current
+	}
+
+	#[throws(lmdb::Error)]
+	fn advance(&mut self) {
+		if self.current.is_some() { self.current = self.cursor.get_dup(lmdb::DupCursorOp::NextDup)?.map(|(_, id)| doc_id(id)); }
+	}
+}
@@ -0,0 +1,90 @@
+//! An assoc table of [`serde_json::Value`]s, for config and other schemaless data that isn't worth an rkyv struct:
+//! ```ignore
+//! #[derive(DbName)]
+//! #[table(JsonTable<'tx, TX, String>)]
+//! struct Settings;
+//!
+//! tx.get::<Settings>().put(&"smtp".into(), &serde_json::json!({ "relay": { "host": "mx", "port": 25 } }))?;
+//! let port = tx.get::<Settings>().get_path(&"smtp".into(), "relay.port")?.and_then(|port| port.as_u64());
+//! ```
+//! Values are stored as JSON text in an archived `String`, so reads parse the whole value.
+
+use culpa::throws;
+use serde_json::Value;
+use crate::{AssocTable, Transaction, RwTxn, Table, RkyvSer, RkyvVal, Error, error::Context, framing::{Framing, Plain}};
+
+pub struct JsonTable<'tx, TX, K, F = Plain> {
+	inner: AssocTable<'tx, TX, K, String, F>,
+}
+
+impl<'tx, 'env: 'tx, TX, K, F> Table<'tx, 'env, TX> for JsonTable<'tx, TX, K, F> where
+	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.inner.dbi() }
+	fn txn(&self) -> &TX { self.inner.txn() }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self { Self { inner: Table::build(tx, name) } }
+
+	#[throws]
+	fn check_entry(key: &[u8], value: &[u8]) {
+		AssocTable::<'tx, TX, K, String, F>::check_entry(key, value)?;
+		let text = rkyv::access::<rkyv::Archived<String>, rkyv::rancor::Error>(F::unframe(value)?)?;
+		serde_json::from_str::<Value>(text)?;
+	}
+}
+
+impl<'tx, K, F> JsonTable<'tx, RwTxn<'tx>, K, F> where
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn put(&self, key: &K, value: &Value) { self.inner.put(key, &value.to_string())? }
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn delete(&self, key: &K) -> bool { self.inner.delete(key)? }
+}
+
+impl<'tx, 'env: 'tx, TX, K, F> JsonTable<'tx, TX, K, F> where
+	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { inner: AssocTable::build(tx, dbi) } }
+
+	/// the assoc table of JSON text underneath
+	pub fn inner(&self) -> &AssocTable<'tx, TX, K, String, F> { &self.inner }
+
+	/// the stored JSON text, without parsing it
+	#[throws]
+	pub fn get_str(&self, key: &K) -> Option<&'tx str> {
+		self.inner.get(key)?.map(|text| text.as_str())
+	}
+
+	#[throws]
+	pub fn get(&self, key: &K) -> Option<Value> {
+		let Some(text) = self.get_str(key)? else { return None };
+		Some(serde_json::from_str(text).context(self.inner.tx, self.inner.dbi, "get", None)?)
+	}
+
+	/// The part of the value at a dot-separated path of object keys and array indices, e.g. `"servers.0.host"`.
+	/// None if the entry is missing or the path doesn't lead anywhere, the empty path is the whole value.
+	#[throws]
+	pub fn get_path(&self, key: &K, path: &str) -> Option<Value> {
+		let Some(mut value) = self.get(key)? else { return None };
+		if path.is_empty() { return Some(value) }
+		let pointer = path.split('.').fold(String::new(), |pointer, segment| pointer + "/" + &segment.replace('~', "~0").replace('/', "~1"));
+		value.pointer_mut(&pointer).map(Value::take)
+	}
+
+	/// Entries in key order with their parsed values.
+	#[throws]
+	pub fn iter(&self) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, Result<Value, serde_json::Error>), Error>> + use<'tx, 'env, TX, K, F> {
+		self.inner.iter()?.map(|entry| entry.map(|(key, text)| (key, serde_json::from_str(text))))
+	}
+}
@@ -0,0 +1,100 @@
+//! Keys whose archived bytes sort the way their values do, so iteration and ranges go in order.
+//! That's not a given - rkyv archives integers little-endian, which LMDB's default comparator doesn't sort numerically.
+//!
+//! [`TimeKey`] is a point in time for time-range scans, e.g. `TimeKey::from(SystemTime::now())`. With the `chrono` and
+//! `time` features it converts from and to `chrono::DateTime<Utc>` and `time::OffsetDateTime` too, the latter's offset
+//! isn't kept.
+//!
+//! With the `uuid` feature `uuid::Uuid`s are keys as they are, archived as their 16 bytes in RFC order.
+//! UUIDv7s start with their creation time in milliseconds, so they sort chronologically and [`uuid_v7_range`]
+//! turns a time range into a key range:
+//! ```ignore
+//! let range = keys::uuid_v7_range(since, SystemTime::now());
+//! let recent = tx.get::<Events>().iter_from(range.start())?.take_while(|entry| entry.as_ref().is_ok_and(|(id, _)| *id <= range.end()));
+//! ```
+
+use std::time::{Duration, SystemTime};
+
+/// Nanosecond precision time, archived as big-endian seconds since the unix epoch with the sign bit flipped and then
+/// big-endian nanoseconds, so times before the epoch sort first too. Covers the full range of i64 seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+#[rkyv(derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash), compare(PartialEq, PartialOrd))]
+pub struct TimeKey([u8; 12]);
+
+impl TimeKey {
+	pub fn now() -> Self { SystemTime::now().into() }
+
+	/// nanoseconds outside of i64 seconds are clamped
+	pub fn from_unix_nanos(nanos: i128) -> Self {
+		let secs = nanos.div_euclid(1_000_000_000).clamp(i64::MIN.into(), i64::MAX.into()) as i64;
+		let mut bytes = [0; 12];
+		bytes[..8].copy_from_slice(&(secs.cast_unsigned() ^ 1 << 63).to_be_bytes());
+		bytes[8..].copy_from_slice(&(nanos.rem_euclid(1_000_000_000) as u32).to_be_bytes());
+		Self(bytes)
+	}
+
+	/// None if it's out of SystemTime's range
+	pub fn to_system_time(self) -> Option<SystemTime> {
+		let nanos = self.to_unix_nanos();
+		let duration = Duration::new((nanos.unsigned_abs() / 1_000_000_000) as u64, (nanos.unsigned_abs() % 1_000_000_000) as u32);
+		if nanos < 0 { SystemTime::UNIX_EPOCH.checked_sub(duration) } else { SystemTime::UNIX_EPOCH.checked_add(duration) }
+	}
+
+	pub fn to_unix_nanos(self) -> i128 {
+		let (secs, nanos) = self.0.split_first_chunk::<8>().expect("12 bytes");
+		let secs = (u64::from_be_bytes(*secs) ^ 1 << 63).cast_signed();
+		i128::from(secs) * 1_000_000_000 + i128::from(u32::from_be_bytes(nanos.try_into().expect("4 bytes")))
+	}
+}
+
+impl From<SystemTime> for TimeKey {
+	fn from(time: SystemTime) -> Self {
+		Self::from_unix_nanos(match time.duration_since(SystemTime::UNIX_EPOCH) {
+			Ok(after) => after.as_nanos() as i128,
+			Err(before) => -(before.duration().as_nanos() as i128),
+		})
+	}
+}
+
+#[cfg(feature = "chrono")]
+impl From<chrono::DateTime<chrono::Utc>> for TimeKey {
+	fn from(time: chrono::DateTime<chrono::Utc>) -> Self {
+		Self::from_unix_nanos(i128::from(time.timestamp()) * 1_000_000_000 + i128::from(time.timestamp_subsec_nanos()))
+	}
+}
+
+#[cfg(feature = "chrono")]
+impl TimeKey {
+	/// None if it's out of chrono's range
+	pub fn to_chrono(self) -> Option<chrono::DateTime<chrono::Utc>> {
+		let nanos = self.to_unix_nanos();
+		chrono::DateTime::from_timestamp(nanos.div_euclid(1_000_000_000) as i64, nanos.rem_euclid(1_000_000_000) as u32)
+	}
+}
+
+#[cfg(feature = "time")]
+impl From<time::OffsetDateTime> for TimeKey {
+	fn from(time: time::OffsetDateTime) -> Self { Self::from_unix_nanos(time.unix_timestamp_nanos()) }
+}
+
+#[cfg(feature = "time")]
+impl TimeKey {
+	/// in UTC, None if it's out of time's range
+	pub fn to_offset_date_time(self) -> Option<time::OffsetDateTime> { time::OffsetDateTime::from_unix_timestamp_nanos(self.to_unix_nanos()).ok() }
+}
+
+impl ArchivedTimeKey {
+	pub fn to_native(&self) -> TimeKey { TimeKey(self.0) }
+}
+
+/// The smallest and biggest UUIDv7 generated between `start` and `end`, both included at millisecond precision.
+#[cfg(feature = "uuid")]
+pub fn uuid_v7_range(start: SystemTime, end: SystemTime) -> std::ops::RangeInclusive<uuid::Uuid> {
+	let bound = |time: SystemTime, rest: u8| {
+		let millis = time.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |x| x.as_millis() as u64);
+		let mut bytes = [rest; 16];
+		bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+		uuid::Uuid::from_bytes(bytes)
+	};
+	bound(start, 0)..=bound(end, 0xff)
+}
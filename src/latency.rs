@@ -0,0 +1,84 @@
+//! Latency histograms of the env's commits and syncs, see [`Env::commit_latency`](crate::Env::commit_latency):
+//! ```ignore
+//! let latency = ENV.commit_latency();
+//! log::info!("commits p99 {:?}, syncs p99 {:?}", latency.commit.quantile(0.99), latency.sync.quantile(0.99));
+//! ```
+//! A commit's time includes its fsync unless the env or the transaction's [`Durability`](crate::env::Durability) skips
+//! it, explicit syncs - [`Env::sync`](crate::Env::sync), and so [`background_sync`](crate::background_sync) - are
+//! recorded apart. Buckets are powers of two of microseconds, so quantiles are upper bounds within a factor of two.
+
+use std::{sync::atomic::{AtomicU64, Ordering}, time::{Duration, Instant}};
+
+const BUCKETS: usize = 32; // the last one takes everything over 2^30 microseconds, about 18 minutes
+
+// written by whoever commits, read by commit_latency - relaxed, a snapshot may be off by the commits racing it
+#[derive(Default)]
+pub(crate) struct Recorder {
+	buckets: [AtomicU64; BUCKETS],
+	total_micros: AtomicU64,
+	max_micros: AtomicU64,
+	last: std::sync::Mutex<Option<Instant>>, // when the last recorded one ended
+}
+
+impl Recorder {
+	pub(crate) fn record(&self, took: Duration) {
+		let micros = took.as_micros().try_into().unwrap_or(u64::MAX);
+		self.buckets[bucket(micros)].fetch_add(1, Ordering::Relaxed);
+		self.total_micros.fetch_add(micros, Ordering::Relaxed);
+		self.max_micros.fetch_max(micros, Ordering::Relaxed);
+		*self.last.lock().unwrap() = Some(Instant::now());
+	}
+
+	pub(crate) fn last(&self) -> Option<Instant> { *self.last.lock().unwrap() }
+
+	pub(crate) fn snapshot(&self) -> Histogram {
+		Histogram {
+			buckets: std::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed)),
+			total: Duration::from_micros(self.total_micros.load(Ordering::Relaxed)),
+			max: Duration::from_micros(self.max_micros.load(Ordering::Relaxed)),
+		}
+	}
+}
+
+// bucket i holds durations under 2^i microseconds that aren't in a lower one
+fn bucket(micros: u64) -> usize { ((u64::BITS - micros.leading_zeros()) as usize).min(BUCKETS - 1) }
+
+/// counts of durations by power of two of microseconds
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+	/// `buckets[i]` counts durations under 2^i microseconds and at least 2^(i - 1)
+	pub buckets: [u64; BUCKETS],
+	pub total: Duration,
+	pub max: Duration,
+}
+
+impl Histogram {
+	pub fn count(&self) -> u64 { self.buckets.iter().sum() }
+
+	pub fn mean(&self) -> Duration {
+		match self.count() {
+			0 => Duration::ZERO,
+			count => self.total / count.try_into().unwrap_or(u32::MAX),
+		}
+	}
+
+	/// the upper bound of the bucket the `q`th quantile falls in, at most `max`
+	pub fn quantile(&self, q: f64) -> Duration {
+		let rank = (q.clamp(0., 1.) * self.count() as f64).ceil() as u64;
+		let mut seen = 0;
+		for (i, &n) in self.buckets.iter().enumerate() {
+			seen += n;
+			if seen >= rank.max(1) { return Duration::from_micros(1 << i).min(self.max); }
+		}
+		Duration::ZERO
+	}
+}
+
+/// see [`Env::commit_latency`](crate::Env::commit_latency)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitLatency {
+	/// `mdb_txn_commit` of write transactions, with the fsync if the durability has one
+	pub commit: Histogram,
+	/// [`Env::sync`](crate::Env::sync)
+	pub sync: Histogram,
+}
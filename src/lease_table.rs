@@ -0,0 +1,134 @@
+//! Leases on keys, for worker processes sharing an env to agree on which of them owns a job or partition:
+//! ```no_run
+//! # use std::{sync::LazyLock, time::Duration};
+//! # use batadase::{Env, lease_table::LeaseTable};
+//! # type PartitionId = u32;
+//! # type WorkerId = u64;
+//! batadase::def_env! { Tables { owners: Owners => LeaseTable<'tx, TX, PartitionId, WorkerId> } }
+//! static ENV: LazyLock<Env> = LazyLock::new(|| Tables::builder().unwrap().build(c"db").unwrap());
+//!
+//! # async fn work(partition: PartitionId, me: WorkerId) -> Result<(), batadase::Error> {
+//! if ENV.write(move |tx| Tables::of(tx).owners().acquire(&partition, &me, Duration::from_secs(30))).await?? {
+//!     // renew well before the 30s are up, stop working on the partition once a renew returns false
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//! A lease is held until it's released or expires, whichever comes first. Its holder is checked and changed in the
+//! caller's write transaction, and write transactions are serialized, so two workers never both get one. Times are
+//! milliseconds since the unix epoch, like [`TrackedTable`](crate::TrackedTable)'s - workers need roughly agreeing
+//! clocks, which they have when sharing an env on one machine.
+
+use culpa::throws;
+use std::time::Duration;
+use crate::{AssocTable, Transaction, RwTxn, Table, RkyvSer, RkyvVal, RkyvDe, Error, tracked_table};
+
+/// the stored value
+#[derive(Debug, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct Lease<H> {
+	pub holder: H,
+	pub expires: u64,
+}
+
+pub struct LeaseTable<'tx, TX, K, H> {
+	inner: AssocTable<'tx, TX, K, Lease<H>>,
+}
+
+impl<'tx, 'env: 'tx, TX, K, H> Table<'tx, 'env, TX> for LeaseTable<'tx, TX, K, H> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	H: rkyv::Archive + 'tx,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<Lease<H>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<Lease<H>, RkyvDe> + 'tx,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.inner.dbi() }
+	fn txn(&self) -> &TX { self.inner.txn() }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self { Self { inner: Table::build(tx, name) } }
+
+	#[throws]
+	fn check_entry(key: &[u8], value: &[u8]) { AssocTable::<'tx, TX, K, Lease<H>>::check_entry(key, value)?; }
+}
+
+fn expiry(now: u64, ttl: Duration) -> u64 { now.saturating_add(ttl.as_millis().try_into().unwrap_or(u64::MAX)) }
+
+// RwTxn only, so all methods mutate
+impl<'tx, K, H> LeaseTable<'tx, RwTxn<'tx>, K, H> where
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	H: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>> + Clone + 'tx,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<H>: PartialEq<H>,
+	rkyv::Archived<Lease<H>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<Lease<H>, RkyvDe> + 'tx,
+{
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn acquire(&self, key: &K, holder: &H, ttl: Duration) -> bool { self.acquire_at(key, holder, ttl, tracked_table::now())? }
+
+	/// Take the lease on `key` for `ttl` from `now` if nobody else holds it, false if somebody does.
+	/// Acquiring a lease that's already `holder`'s renews it.
+	#[throws]
+	pub fn acquire_at(&self, key: &K, holder: &H, ttl: Duration, now: u64) -> bool {
+		if let Some(lease) = self.holding(key, now)? && lease.holder != *holder { return false; }
+		self.inner.put(key, &Lease { holder: holder.clone(), expires: expiry(now, ttl) })?;
+		true
+	}
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn renew(&self, key: &K, holder: &H, ttl: Duration) -> bool { self.renew_at(key, holder, ttl, tracked_table::now())? }
+
+	/// Extend `holder`'s lease on `key` to `ttl` from `now`, false if it isn't holding it anymore - it expired or
+	/// somebody else took it.
+	#[throws]
+	pub fn renew_at(&self, key: &K, holder: &H, ttl: Duration, now: u64) -> bool {
+		if !self.holding(key, now)?.is_some_and(|lease| lease.holder == *holder) { return false; }
+		self.inner.put(key, &Lease { holder: holder.clone(), expires: expiry(now, ttl) })?;
+		true
+	}
+
+	/// Give up `holder`'s lease on `key`, false if it wasn't `holder`'s. Releasing an expired lease still removes it.
+	#[throws]
+	pub fn release(&self, key: &K, holder: &H) -> bool {
+		self.inner.delete_if(key, |lease| lease.holder == *holder)?
+	}
+
+	/// take the lease away from whoever holds it
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn revoke(&self, key: &K) -> bool { self.inner.delete(key)? }
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn clear(&self) { self.inner.clear()? }
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, K, H> LeaseTable<'tx, TX, K, H> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	H: rkyv::Archive + 'tx,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<Lease<H>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<Lease<H>, RkyvDe> + 'tx,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { inner: AssocTable::build(tx, dbi) } }
+
+	/// the lease on `key` if it's held at `now`
+	#[throws]
+	pub fn holding(&self, key: &K, now: u64) -> Option<&'tx ArchivedLease<H>> {
+		self.inner.get(key)?.filter(|lease| lease.expires.to_native() > now)
+	}
+
+	/// who holds the lease on `key` now
+	#[throws]
+	pub fn holder(&self, key: &K) -> Option<&'tx rkyv::Archived<H>> {
+		self.holding(key, tracked_table::now())?.map(|lease| &lease.holder)
+	}
+
+	/// Every lease held at `now`, in key order. Walks the whole table.
+	#[throws]
+	pub fn iter_held(&self, now: u64) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx ArchivedLease<H>), Error>> + use<'tx, 'env, TX, K, H> where
+		rkyv::Archived<K>: 'tx,
+	{
+		// errors are kept for the caller
+		self.inner.iter()?.filter(move |entry| entry.as_ref().map_or(true, |(_, lease)| lease.expires.to_native() > now))
+	}
+}
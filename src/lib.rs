@@ -2,6 +2,12 @@
 //!
 //!
 //! Note that this crate does not compile on wasm, but batadase-index does.
+//! Without the default `rkyv` feature there's only [`RawTable`] of byte strings, for targets that don't need typed tables.
+//!
+//! Byte-level get, put, delete, clear and iteration also have a [`Backend`](backend::Backend) trait, for code that should
+//! run on other stores too, like the in-memory [`MemEnv`](mem_env::MemEnv) for unit tests, or libmdbx with the `mdbx`
+//! feature. The tables themselves only run on LMDB.
+//! LMDB only needs a directory (locking lives in `lock.mdb` there, not in /dev/shm), so tests can open envs in a temp dir.
 //!
 //!
 //! You can use the batadase-macros crate to do make a table easier, e.g.
@@ -13,41 +19,182 @@
 //! ```
 //! then use def_tx_ops below to init the db.
 
-pub use batadase_index::Index;
+#[cfg(feature = "rkyv")] pub use batadase_index::Index;
 pub use batadase_macros::DbName;
 pub use env::Env;
-pub use lmdb::{DbFlags, CursorOpFlags};
-pub use transaction::{Transaction, RoTxn, RwTxn};
+pub use lmdb::{DbFlags, CursorOpFlags, CursorOp, DupCursorOp, DupFixedCursorOp, DupOrder};
+pub use transaction::{Transaction, RoTxn, RwTxn, ReadView, ReadOnly, AnyTxn};
+pub use tree::{Db, Tree};
 pub use enumflags2;
 pub use error::Error;
-pub use rkyv;
+#[cfg(feature = "rkyv")] pub use rkyv;
+pub use lmdb_sys; // for the raw handle escape hatches, `Env::raw`, `Transaction::raw` and `Table::dbi`
 
 pub mod env;
 pub mod lmdb;
 pub mod transaction;
 pub mod error;
+#[cfg(feature = "rkyv")] pub mod framing;
+#[cfg(feature = "rkyv")] pub mod envelope;
+pub mod dump;
+pub mod dumpfmt;
+pub mod verify;
+#[cfg(feature = "rkyv")] pub mod migrate;
+pub mod snapshot;
+#[cfg(feature = "rkyv")] pub mod compat;
+pub mod copy;
+pub mod tree;
+pub mod group_commit;
+pub mod background_sync;
+pub mod writer_lock;
+pub mod latency;
+pub mod health;
+pub mod borrows;
+pub mod changelog;
+pub mod pipeline;
+pub mod backend;
+pub mod mem_env;
+#[cfg(feature = "mdbx")] pub mod mdbx;
+pub mod bookmark;
+pub mod preflight;
+#[cfg(feature = "rkyv")] pub mod ttl_sweeper;
+#[cfg(feature = "rkyv")] pub mod read_cache;
+#[cfg(feature = "rkyv")] pub mod buffered_table;
+#[cfg(feature = "rkyv")] pub mod write_batch;
+#[cfg(feature = "rkyv")] pub mod sync;
+#[cfg(feature = "rkyv")] pub mod keys;
+#[cfg(feature = "rkyv")] pub mod diff;
+#[cfg(feature = "rkyv")] pub mod csv;
+#[cfg(feature = "rkyv")] pub mod columnar;
+#[cfg(feature = "rkyv")] pub mod schema;
+#[cfg(feature = "backup")] pub mod backup;
+#[cfg(feature = "json")] pub mod json_table;
+#[cfg(feature = "testutil")] pub mod testutil;
+#[cfg(feature = "testutil")] pub mod crash_test;
+#[cfg(feature = "fuzzing")] #[doc(hidden)] pub mod fuzz;
+#[cfg(feature = "bench")] pub mod bench;
 
-pub mod index_table;
-pub mod assoc_table;
-pub mod index_poly_table;
-pub mod assoc_poly_table;
-pub use assoc_table::AssocTable;
-pub use index_poly_table::IndexPolyTable;
-pub use index_table::IndexTable;
-pub use assoc_poly_table::AssocPolyTable;
+pub mod raw_table;
+pub mod reverse;
+pub mod inverted_index;
+#[cfg(feature = "rkyv")] pub mod index_table;
+#[cfg(feature = "rkyv")] pub mod assoc_table;
+#[cfg(feature = "rkyv")] pub mod index_poly_table;
+#[cfg(feature = "rkyv")] pub mod assoc_poly_table;
+#[cfg(feature = "rkyv")] pub mod rotating_table;
+#[cfg(feature = "rkyv")] pub mod tracked_table;
+#[cfg(feature = "rkyv")] pub mod tombstone_table;
+#[cfg(feature = "rkyv")] pub mod fixed_vec_table;
+#[cfg(feature = "rkyv")] pub mod intern_table;
+#[cfg(feature = "rkyv")] pub mod idempotency_table;
+#[cfg(feature = "rkyv")] pub mod rate_limit_table;
+#[cfg(feature = "rkyv")] pub mod lease_table;
+#[cfg(feature = "rkyv")] pub mod materialized;
+#[cfg(feature = "rkyv")] pub mod outbox_table;
+#[cfg(feature = "rkyv")] pub mod scoped_table;
+#[cfg(feature = "rkyv")] pub mod tiered_table;
+#[cfg(feature = "rkyv")] pub mod bytes_table;
+pub use raw_table::RawTable;
+pub use reverse::{ReverseKey, ReverseDup};
+pub use inverted_index::InvertedIndex;
+#[cfg(feature = "rkyv")] pub use assoc_table::AssocTable;
+#[cfg(feature = "rkyv")] pub use index_poly_table::IndexPolyTable;
+#[cfg(feature = "rkyv")] pub use index_table::IndexTable;
+#[cfg(feature = "rkyv")] pub use assoc_poly_table::AssocPolyTable;
+#[cfg(feature = "rkyv")] pub use rotating_table::RotatingTable;
+#[cfg(feature = "rkyv")] pub use tracked_table::TrackedTable;
+#[cfg(feature = "rkyv")] pub use tombstone_table::TombstoneTable;
+#[cfg(feature = "rkyv")] pub use fixed_vec_table::FixedVecTable;
+#[cfg(feature = "rkyv")] pub use intern_table::InternTable;
+#[cfg(feature = "rkyv")] pub use idempotency_table::IdempotencyTable;
+#[cfg(feature = "rkyv")] pub use rate_limit_table::RateLimitTable;
+#[cfg(feature = "rkyv")] pub use lease_table::LeaseTable;
+#[cfg(feature = "rkyv")] pub use materialized::MaterializedTable;
+#[cfg(feature = "rkyv")] pub use outbox_table::OutboxTable;
+#[cfg(feature = "rkyv")] pub use scoped_table::ScopedTable;
+#[cfg(feature = "rkyv")] pub use tiered_table::TieredTable;
+#[cfg(feature = "rkyv")] pub use bytes_table::BytesTable;
+#[cfg(feature = "json")] pub use json_table::JsonTable;
 
 pub trait Table<'tx, 'env: 'tx, TX: Transaction<'env>> {
+	/// use with [`Transaction::raw`] of [`Table::txn`] for `lmdb_sys` functions that aren't wrapped,
+	/// don't `mdb_dbi_close` it - dbis are opened once for the env's whole life
 	fn dbi(&self) -> lmdb_sys::MDB_dbi;
 	fn txn(&self) -> &TX;
 	fn flags() -> enumflags2::BitFlags<DbFlags> { enumflags2::BitFlags::empty() }
 
 	#[culpa::throws]
 	fn entries(&self) -> usize {
-		let stat = lmdb::stat(self.txn().raw(), self.dbi())?;
-		stat.ms_entries
+		let stat = self.stat()?;
+		stat.entries
+	}
+
+	/// estimated on-disk size, see [`lmdb::Stat::size_bytes`]
+	#[culpa::throws]
+	fn size_bytes(&self) -> usize {
+		self.stat()?.size_bytes()
+	}
+
+	/// entries, b-tree depth and page counts, as of this transaction
+	#[culpa::throws]
+	fn stat(&self) -> lmdb::Stat {
+		lmdb::stat(self.txn().raw(), self.dbi())?
+	}
+
+	/// Read every page of the table so it's in the page cache, e.g. right after a restart before taking traffic.
+	/// Returns the key and value bytes walked.
+	#[culpa::throws]
+	fn warmup(&self) -> usize {
+		let mut cursor = lmdb::Cursor::open(self.txn(), self.dbi())?;
+		let mut bytes = 0;
+		while let Some((key, value)) = cursor.get(lmdb::CursorOp::Next)? { bytes += lmdb::touch(key) + lmdb::touch(value); }
+		bytes
+	}
+
+	/// Entries and their sizes by the first `depth` bytes of their keys, shorter keys by all of theirs - e.g. to see which
+	/// tenant takes up the space in a table keyed by tenant first. Walks the whole table.
+	#[culpa::throws]
+	fn prefix_histogram(&self, depth: usize) -> std::collections::BTreeMap<Vec<u8>, PrefixStats> {
+		self.histogram_by(|key| key[..depth.min(key.len())].to_vec())?
+	}
+
+	/// [`Table::prefix_histogram`] grouping keys by `classify` of their raw bytes instead
+	#[culpa::throws]
+	fn histogram_by<C: Ord>(&self, mut classify: impl FnMut(&[u8]) -> C) -> std::collections::BTreeMap<C, PrefixStats> {
+		let mut cursor = lmdb::Cursor::open(self.txn(), self.dbi())?;
+		let mut histogram = std::collections::BTreeMap::<C, PrefixStats>::new();
+		while let Some((key, value)) = cursor.get(lmdb::CursorOp::Next)? {
+			let stats = histogram.entry(classify(key)).or_default();
+			stats.entries += 1;
+			stats.key_bytes += key.len();
+			stats.value_bytes += value.len();
+		}
+		histogram
+	}
+
+	/// Rewrite the table in key order to defragment it, see [`RwTxn::rewrite_database`]. Returns the entries rewritten.
+	#[culpa::throws]
+	fn rewrite(&self) -> usize where TX: std::borrow::Borrow<RwTxn<'env>> {
+		self.txn().borrow().rewrite(self.dbi())?
 	}
 
 	fn build(tx: &'tx TX, name: &'static [u8]) -> Self;
+
+	/// check that raw entry bytes could've been written by this table, used by verify
+	#[culpa::throws]
+	fn check_entry(_key: &[u8], _value: &[u8]) {}
+}
+
+/// see [`Table::prefix_histogram`], sizes are of the bytes stored without LMDB's page overhead
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PrefixStats {
+	pub entries: usize,
+	pub key_bytes: usize,
+	pub value_bytes: usize,
+}
+
+impl PrefixStats {
+	pub fn bytes(&self) -> usize { self.key_bytes + self.value_bytes }
 }
 
 // potentially useful relation table flavours:
@@ -55,9 +202,9 @@ pub trait Table<'tx, 'env: 'tx, TX: Transaction<'env>> {
 // * two-way one-to-one via Indices
 // * two-way many-to-many via Indices
 
-type RkyvSer<'a> = rkyv::api::high::HighSerializer<rkyv::util::AlignedVec, rkyv::ser::allocator::ArenaHandle<'a>, rkyv::rancor::Error>;
-type RkyvDe = rkyv::api::high::HighDeserializer<rkyv::rancor::Error>;
-type RkyvVal<'a> = rkyv::api::high::HighValidator<'a, rkyv::rancor::Error>;
+#[cfg(feature = "rkyv")] type RkyvSer<'a> = rkyv::api::high::HighSerializer<rkyv::util::AlignedVec, rkyv::ser::allocator::ArenaHandle<'a>, rkyv::rancor::Error>;
+#[cfg(feature = "rkyv")] type RkyvDe = rkyv::api::high::HighDeserializer<rkyv::rancor::Error>;
+#[cfg(feature = "rkyv")] type RkyvVal<'a> = rkyv::api::high::HighValidator<'a, rkyv::rancor::Error>;
 
 pub trait DbName {
 	type Table<'tx, 'env: 'tx, TX: Transaction<'env> + 'tx>: Table<'tx, 'env, TX>;
@@ -65,22 +212,57 @@ pub trait DbName {
 
 	fn get<'tx, 'env: 'tx, TX: Transaction<'env>>(tx: &'tx TX) -> Self::Table<'tx, 'env, TX> { Self::Table::build(tx, Self::NAME) }
 	fn flags() -> enumflags2::BitFlags<lmdb::DbFlags> { enumflags2::BitFlags::empty() }
+	/// the order of a dupsort table's values, LMDB's bytewise one if None
+	fn dup_order() -> Option<lmdb::DupOrder> { None }
 }
 
+#[cfg(feature = "rkyv")]
 #[derive(Debug, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
 pub enum MetaField {
 	Version,
 }
 
+#[cfg(feature = "rkyv")]
 #[derive(DbName)]
 #[table(AssocPolyTable<'tx, TX, MetaField>)]
 pub struct Meta;
 
+/// db name without the trailing nul, for display
+pub fn db_name_lossy(name: &[u8]) -> String { String::from_utf8_lossy(name.strip_suffix(b"\0").unwrap_or(name)).into_owned() }
+
+#[cfg(feature = "rkyv")]
+pub(crate) fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+	bytes.into_iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+pub(crate) fn path_to_cstring(path: &std::path::Path) -> std::io::Result<std::ffi::CString> {
+	std::ffi::CString::new(path.as_os_str().as_encoded_bytes()).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+}
+
+// removed with everything in it on drop
+pub(crate) struct TempDir(pub(crate) std::path::PathBuf);
+
+impl TempDir {
+	pub(crate) fn new(prefix: &str) -> std::io::Result<Self> {
+		static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+		let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+		let path = std::env::temp_dir().join(format!("{prefix}-{}-{n}", std::process::id()));
+		std::fs::create_dir(&path)?;
+		Ok(Self(path))
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) { let _ = std::fs::remove_dir_all(&self.0); }
+}
+
+#[cfg(feature = "rkyv")]
 pub fn unrkyv<T>(archive: &rkyv::Archived<T>) -> Result<T, rkyv::rancor::Error> where
 	T: rkyv::Archive,
 	rkyv::Archived<T>: rkyv::Deserialize<T, RkyvDe>,
 { rkyv::deserialize::<T, rkyv::rancor::Error>(archive) }
 
+#[cfg(feature = "rkyv")]
 pub fn unrkyv_from_bytes<T>(bytes: &[u8]) -> Result<T, rkyv::rancor::Error> where
 	T: rkyv::Archive,
 	rkyv::Archived<T>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<T, RkyvDe>,
@@ -168,3 +350,85 @@ macro_rules! def_tx_ops {
 		{ $env_name.try_write_async(job).await.unwrap() }
 	};
 }
+
+/// Declares an env's tables in one place, e.g.
+/// ```ignore
+/// def_env! {
+///     pub Tables {
+///         users: Users => AssocTable<'tx, TX, UserId, User>,
+///         #[name("sessions")] sessions: Sessions => TrackedTable<'tx, TX, SessionId, Session>,
+///     }
+/// }
+///
+/// static ENV: Lazy<Env> = Lazy::new(|| Tables::builder().unwrap().build(c"db").unwrap());
+/// let user = Tables::of(&tx).users().get(&id)?;
+/// ```
+/// Each table gets a [`DbName`] type like the derive makes, and `Tables` gets
+/// - `MAX_DBS`, the number of tables
+/// - `builder()`, an [`EnvBuilder`](env::EnvBuilder) with all of them registered, to chain other options onto
+/// - `of(&tx)` with a method per table building it in `tx`
+///
+/// Two tables with the same name fail to compile.
+#[macro_export]
+macro_rules! def_env {
+	($vis:vis $env:ident { $($(#[name($db_name:literal)])? $field:ident: $name:ident => $table:ty),* $(,)? }) => {
+		$(
+			$vis struct $name;
+
+			impl $crate::DbName for $name {
+				type Table<'tx, 'env: 'tx, TX: $crate::Transaction<'env> + 'tx> = $table;
+				const NAME: &'static [u8] = $crate::def_env!(@name $name $($db_name)?);
+			}
+		)*
+
+		const _: () = ::std::assert!($crate::names_unique(&[$(<$name as $crate::DbName>::NAME),*]), "two tables have the same name");
+
+		$vis struct $env<'tx, TX>(&'tx TX);
+
+		impl $env<'_, ()> {
+			$vis const MAX_DBS: u32 = [$(::std::stringify!($field)),*].len() as u32;
+
+			$vis fn builder() -> ::std::result::Result<$crate::env::EnvBuilder, $crate::Error> {
+				::std::result::Result::Ok($crate::Env::builder()?$(.with::<$name>())*)
+			}
+		}
+
+		impl<'tx, TX> $env<'tx, TX> {
+			$vis fn of(tx: &'tx TX) -> Self { Self(tx) }
+
+			$(
+				$vis fn $field<'env: 'tx>(&self) -> <$name as $crate::DbName>::Table<'tx, 'env, TX> where TX: $crate::Transaction<'env> {
+					<$name as $crate::DbName>::get(self.0)
+				}
+			)*
+		}
+	};
+
+	(@name $name:ident) => { ::std::concat!(::std::module_path!(), "::", ::std::stringify!($name), "\0").as_bytes() };
+	(@name $name:ident $db_name:literal) => { ::std::concat!($db_name, "\0").as_bytes() };
+}
+
+// for def_env's check, const so it happens at compile time
+#[doc(hidden)]
+pub const fn names_unique(names: &[&[u8]]) -> bool {
+	let mut i = 0;
+	while i < names.len() {
+		let mut j = i + 1;
+		while j < names.len() {
+			if bytes_eq(names[i], names[j]) { return false; }
+			j += 1;
+		}
+		i += 1;
+	}
+	true
+}
+
+const fn bytes_eq(a: &[u8], b: &[u8]) -> bool {
+	if a.len() != b.len() { return false; }
+	let mut i = 0;
+	while i < a.len() {
+		if a[i] != b[i] { return false; }
+		i += 1;
+	}
+	true
+}
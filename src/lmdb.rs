@@ -1,6 +1,9 @@
-use super::{Transaction, RwTxn};
+// RawTable only needs some of these without the typed tables
+#![cfg_attr(not(feature = "rkyv"), allow(dead_code))]
+
+use super::{Transaction, RoTxn, RwTxn, changelog::ChangeOp};
 use std::convert::AsMut;
-use culpa::throws;
+use culpa::{throw, throws};
 pub use error::Error;
 pub use lmdb_sys as sys;
 
@@ -20,6 +23,8 @@ pub enum DbFlags {
 		ReverseDup = sys::MDB_REVERSEDUP, // duplicate data items should be compared in reverse order
 }
 
+/// Every cursor op. Cursors take the ops valid for their db instead - [`CursorOp`] for any db, [`DupCursorOp`] for
+/// `DbFlags::DupSort` and [`DupFixedCursorOp`] for `DbFlags::DupFixed` ones - since lmdb fails the others with EINVAL.
 #[repr(i32)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CursorOpFlags {
@@ -52,6 +57,81 @@ pub enum CursorOpFlags {
 		GetBothRange = sys::MDB_GET_BOTH_RANGE, // position at key, nearest data
 }
 
+/// the [`CursorOpFlags`] of any db
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorOp {
+	GetCurrent = sys::MDB_GET_CURRENT,
+	First = sys::MDB_FIRST,
+	Last = sys::MDB_LAST,
+	Next = sys::MDB_NEXT,
+	Prev = sys::MDB_PREV,
+	Set = sys::MDB_SET,
+	SetKey = sys::MDB_SET_KEY,
+	SetRange = sys::MDB_SET_RANGE,
+}
+
+/// the [`CursorOpFlags`] of `DbFlags::DupSort` dbs only
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DupCursorOp {
+	FirstDup = sys::MDB_FIRST_DUP,
+	LastDup = sys::MDB_LAST_DUP,
+	NextDup = sys::MDB_NEXT_DUP,
+	NextNodup = sys::MDB_NEXT_NODUP,
+	PrevDup = sys::MDB_PREV_DUP,
+	PrevNodup = sys::MDB_PREV_NODUP,
+	GetBoth = sys::MDB_GET_BOTH,
+	GetBothRange = sys::MDB_GET_BOTH_RANGE,
+}
+
+/// the [`CursorOpFlags`] of `DbFlags::DupFixed` dbs only
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DupFixedCursorOp {
+	GetMultiple = sys::MDB_GET_MULTIPLE,
+	NextMultiple = sys::MDB_NEXT_MULTIPLE,
+}
+
+impl From<CursorOp> for CursorOpFlags {
+	fn from(op: CursorOp) -> Self {
+		match op {
+			CursorOp::GetCurrent => Self::GetCurrent,
+			CursorOp::First => Self::First,
+			CursorOp::Last => Self::Last,
+			CursorOp::Next => Self::Next,
+			CursorOp::Prev => Self::Prev,
+			CursorOp::Set => Self::Set,
+			CursorOp::SetKey => Self::SetKey,
+			CursorOp::SetRange => Self::SetRange,
+		}
+	}
+}
+
+impl From<DupCursorOp> for CursorOpFlags {
+	fn from(op: DupCursorOp) -> Self {
+		match op {
+			DupCursorOp::FirstDup => Self::FirstDup,
+			DupCursorOp::LastDup => Self::LastDup,
+			DupCursorOp::NextDup => Self::NextDup,
+			DupCursorOp::NextNodup => Self::NextNodup,
+			DupCursorOp::PrevDup => Self::PrevDup,
+			DupCursorOp::PrevNodup => Self::PrevNodup,
+			DupCursorOp::GetBoth => Self::GetBoth,
+			DupCursorOp::GetBothRange => Self::GetBothRange,
+		}
+	}
+}
+
+impl From<DupFixedCursorOp> for CursorOpFlags {
+	fn from(op: DupFixedCursorOp) -> Self {
+		match op {
+			DupFixedCursorOp::GetMultiple => Self::GetMultiple,
+			DupFixedCursorOp::NextMultiple => Self::NextMultiple,
+		}
+	}
+}
+
 #[enumflags2::bitflags]
 #[repr(u32)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -59,7 +139,7 @@ pub enum PutFlags {
 	NoDupData = sys::MDB_NODUPDATA, // ONLY for DbFlags::DupSort, do not enter duplicate data
 	NoOverwrite = sys::MDB_NOOVERWRITE, // do not enter duplicate data or overwrite existing data, in case of Error::KeyExists - the data parameter will point to existing item
 	Reserve = sys::MDB_RESERVE, // reserve space but do not write the data, caller expected to fill in the data before transaction ends
-	Append = sys::MDB_APPEND, // append key/data to end of the database, allows fast bulk loading of keys in known sorted order, loading unsorted will cause Error::KeyExists
+	Append = sys::MDB_APPEND, // append key/data to end of the database, allows fast bulk loading of keys in known sorted order, loading unsorted fails with crate::Error::OutOfOrder
 	AppendDup = sys::MDB_APPENDDUP, // as above, but for sorted dup data
 }
 
@@ -91,7 +171,10 @@ impl std::ops::DerefMut for Val<'_> {
 	fn deref_mut(&mut self) -> &mut Self::Target { &mut self.0 }
 }
 
-pub(super) struct Cursor<'tx, TX>(*mut sys::MDB_cursor, &'tx TX);
+// a key and its value as a cursor gets them, pointing into the map
+pub(super) type Entry<'tx> = (&'tx [u8], &'tx [u8]);
+
+pub(super) struct Cursor<'tx, TX>(*mut sys::MDB_cursor, &'tx TX, crate::borrows::Borrow);
 unsafe impl<TX> Send for Cursor<'_, TX> {}
 unsafe impl<TX> Sync for Cursor<'_, TX> {}
 
@@ -102,42 +185,217 @@ impl<'tx, 'env: 'tx, TX> Cursor<'tx, TX> where
 	pub(super) fn open(tx: &'tx TX, dbi: sys::MDB_dbi) -> Self {
 		let mut cursor = std::ptr::null_mut();
 		error::handle_cursor_open_code(unsafe { sys::mdb_cursor_open(tx.raw(), dbi, &mut cursor) })?;
-		Self(cursor, tx)
+		Self(cursor, tx, crate::borrows::Borrow::of(tx))
 	}
 
-	pub(super) fn get(&mut self, flags: CursorOpFlags) -> Option<(&'tx [u8], &'tx [u8])> {
+	pub(super) fn get(&mut self, op: CursorOp) -> Result<Option<Entry<'tx>>, Error> { self.op(op.into()) }
+
+	// flags must not include CursorOp::Set because that doesn't change key
+	#[throws]
+	pub(super) fn get_with_key(&mut self, key_in: &mut [u8], op: CursorOp) -> Option<(&'tx [u8], &'tx [u8])> {
+		self.2.check();
 		let mut key = Val::new_outparam(self.1);
+		key.mv_size = key_in.len();
+		key.mv_data = key_in.as_mut_ptr().cast();
 		let mut value = Val::new_outparam(self.1);
-		if !error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0, &mut *key, &mut *value, flags as _) }) { return None }
+		if !error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0, &mut *key, &mut *value, op as _) })? { return None }
 		Some((
 			key.as_slice(),
 			value.as_slice(),
 		))
 	}
 
-	// flags must not include CursorOpFlags::Set because that doesn't change key
-	pub(super) fn get_with_key(&mut self, key_in: &mut [u8], flags: CursorOpFlags) -> Option<(&'tx [u8], &'tx [u8])> {
+	#[throws]
+	pub(super) fn get_with_u64_key(&mut self, op: CursorOp) -> Option<(u64, &'tx [u8])> {
+		self.2.check();
 		let mut key = Val::new_outparam(self.1);
-		key.mv_size = key_in.len();
-		key.mv_data = key_in.as_mut_ptr().cast();
 		let mut value = Val::new_outparam(self.1);
-		if !error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0, &mut *key, &mut *value, flags as _) }) { return None }
+		if !error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0, &mut *key, &mut *value, op as _) })? { return None }
+		debug_assert!(key.mv_size == std::mem::size_of::<u64>());
 		Some((
-			key.as_slice(),
+			u64::from_ne_bytes(unsafe { *key.mv_data.cast::<[u8; std::mem::size_of::<u64>()]>() }),
 			value.as_slice(),
 		))
 	}
 
-	pub(super) fn get_with_u64_key(&mut self, flags: CursorOpFlags) -> Option<(u64, &'tx [u8])> {
+	// the cursor as a DupCursor if its db is DbFlags::DupSort
+	pub(super) fn as_dup(&mut self) -> Option<&mut DupCursor<'tx, TX>> {
+		if !self.has_flag(DbFlags::DupSort) { return None; }
+		Some(unsafe { &mut *std::ptr::from_mut(self).cast::<DupCursor<'tx, TX>>() })
+	}
+
+	pub(super) fn into_dup(self) -> Result<DupCursor<'tx, TX>, Self> {
+		if self.has_flag(DbFlags::DupSort) { Ok(DupCursor(self)) } else { Err(self) }
+	}
+
+	fn has_flag(&self, flag: DbFlags) -> bool {
+		dbi_flags(self.1.raw(), unsafe { sys::mdb_cursor_dbi(self.0) }).is_ok_and(|flags| flags.contains(flag))
+	}
+
+	#[throws]
+	fn op(&mut self, flags: CursorOpFlags) -> Option<(&'tx [u8], &'tx [u8])> {
+		self.2.check();
 		let mut key = Val::new_outparam(self.1);
 		let mut value = Val::new_outparam(self.1);
-		if !error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0, &mut *key, &mut *value, flags as _) }) { return None }
-		debug_assert!(key.mv_size == std::mem::size_of::<u64>());
+		if !error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0, &mut *key, &mut *value, flags as _) })? { return None }
 		Some((
-			u64::from_ne_bytes(unsafe { *key.mv_data.cast::<[u8; std::mem::size_of::<u64>()]>() }),
+			key.as_slice(),
 			value.as_slice(),
 		))
 	}
+
+	// for ops that leave the key unset. None also where GetMultiple finds a key with a single value, which lmdb
+	// doesn't keep on a page of dups so it leaves the value unset
+	#[throws]
+	fn value_op(&mut self, flags: CursorOpFlags) -> Option<&'tx [u8]> {
+		self.2.check();
+		let mut key = Val::new_outparam(self.1);
+		let mut value = Val::new_outparam(self.1);
+		if !error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0, &mut *key, &mut *value, flags as _) })? { return None }
+		if value.mv_data.is_null() { return None; }
+		Some(value.as_slice())
+	}
+}
+
+// a cursor of a DbFlags::DupSort db, so it takes DupCursorOps as well as the CursorOps every cursor does
+#[repr(transparent)]
+pub(super) struct DupCursor<'tx, TX>(Cursor<'tx, TX>);
+
+impl<'tx, TX> std::ops::Deref for DupCursor<'tx, TX> {
+	type Target = Cursor<'tx, TX>;
+
+	fn deref(&self) -> &Self::Target { &self.0 }
+}
+
+impl<TX> std::ops::DerefMut for DupCursor<'_, TX> {
+	fn deref_mut(&mut self) -> &mut Self::Target { &mut self.0 }
+}
+
+impl<'tx, 'env: 'tx, TX> DupCursor<'tx, TX> where
+	TX: Transaction<'env>,
+{
+	// Error::Incompatible if the db isn't DbFlags::DupSort
+	#[throws]
+	pub(super) fn open(tx: &'tx TX, dbi: sys::MDB_dbi) -> Self {
+		let Ok(cursor) = Cursor::open(tx, dbi)?.into_dup() else { throw!(Error::Incompatible) };
+		cursor
+	}
+
+	pub(super) fn get_dup(&mut self, op: DupCursorOp) -> Result<Option<Entry<'tx>>, Error> { self.0.op(op.into()) }
+
+	// for DupCursorOp::GetBoth and GetBothRange, which also take a value - only the value is returned
+	// because they don't change key
+	#[throws]
+	pub(super) fn get_with_key_value(&mut self, key_in: &mut [u8], value_in: &mut [u8], op: DupCursorOp) -> Option<&'tx [u8]> {
+		self.0.2.check();
+		let mut key = Val::from_buf(key_in);
+		let mut value = Val::new_outparam(self.1);
+		value.mv_size = value_in.len();
+		value.mv_data = value_in.as_mut_ptr().cast();
+		if !error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(self.0.0, &mut *key, &mut *value, op as _) })? { return None }
+		Some(value.as_slice())
+	}
+
+	// for DupCursorOp::FirstDup and LastDup, which leave the key unset
+	pub(super) fn get_value(&mut self, op: DupCursorOp) -> Result<Option<&'tx [u8]>, Error> { self.0.value_op(op.into()) }
+
+	// values of the current key
+	#[throws]
+	pub(super) fn count(&self) -> usize {
+		self.0.2.check();
+		let mut count = 0;
+		error::handle_cursor_count_code(unsafe { sys::mdb_cursor_count(self.0.0, &mut count) })?;
+		count
+	}
+
+	// the cursor as a DupFixedCursor if its db is DbFlags::DupFixed
+	pub(super) fn as_dup_fixed(&mut self) -> Option<&mut DupFixedCursor<'tx, TX>> {
+		if !self.has_flag(DbFlags::DupFixed) { return None; }
+		Some(unsafe { &mut *std::ptr::from_mut(self).cast::<DupFixedCursor<'tx, TX>>() })
+	}
+}
+
+// a cursor of a DbFlags::DupFixed db, taking DupFixedCursorOps too
+#[repr(transparent)]
+pub(super) struct DupFixedCursor<'tx, TX>(DupCursor<'tx, TX>);
+
+impl<'tx, TX> std::ops::Deref for DupFixedCursor<'tx, TX> {
+	type Target = DupCursor<'tx, TX>;
+
+	fn deref(&self) -> &Self::Target { &self.0 }
+}
+
+impl<TX> std::ops::DerefMut for DupFixedCursor<'_, TX> {
+	fn deref_mut(&mut self) -> &mut Self::Target { &mut self.0 }
+}
+
+impl<'tx, 'env: 'tx, TX> DupFixedCursor<'tx, TX> where
+	TX: Transaction<'env>,
+{
+	// Error::Incompatible if the db isn't DbFlags::DupFixed
+	#[throws]
+	pub(super) fn open(tx: &'tx TX, dbi: sys::MDB_dbi) -> Self {
+		let mut cursor = DupCursor::open(tx, dbi)?;
+		if cursor.as_dup_fixed().is_none() { throw!(Error::Incompatible); }
+		Self(cursor)
+	}
+
+	// a page of values, see value_op for when it's None
+	pub(super) fn get_multiple(&mut self, op: DupFixedCursorOp) -> Result<Option<&'tx [u8]>, Error> { self.0.0.value_op(op.into()) }
+}
+
+// what the cursor steps through with `step`, as an iterator that ends after the first error since cursor ops
+// keep failing once the transaction has
+pub(super) fn steps<T, E: Into<super::Error>>(mut step: impl FnMut() -> Result<Option<T>, E>) -> impl Iterator<Item = Result<T, super::Error>> {
+	let mut failed = false;
+	std::iter::from_fn(move || {
+		if failed { return None; }
+		let next = step().map_err(Into::into).transpose();
+		failed = matches!(next, Some(Err(_)));
+		next
+	})
+}
+
+impl Cursor<'_, RwTxn<'_>> {
+	// slices from earlier gets shouldn't be used after this
+	#[throws]
+	pub(super) fn del_current(&mut self) {
+		let key = self.op(CursorOpFlags::GetCurrent)?.map(|(key, _)| key.to_vec());
+		error::handle_cursor_del_code(unsafe { sys::mdb_cursor_del(self.0, 0) })?;
+		if let Some(key) = key { crate::changelog::record(self.1, unsafe { sys::mdb_cursor_dbi(self.0) }, ChangeOp::Delete, &key)?; }
+	}
+}
+
+impl<'tx, 'env: 'tx> Cursor<'tx, RoTxn<'env>> {
+	// renews the idle cursor if it's one of dbi, taking it out of there, otherwise opens one
+	#[throws]
+	pub(super) fn renew_or_open(tx: &'tx RoTxn<'env>, dbi: sys::MDB_dbi, idle: &mut Option<IdleCursor<'env>>) -> Self {
+		match idle.take() {
+			Some(cursor) if unsafe { sys::mdb_cursor_dbi(cursor.0) } == dbi => {
+				// dropping it closes it if this fails
+				error::handle_cursor_open_code(unsafe { sys::mdb_cursor_renew(tx.raw(), cursor.0) })?;
+				Self(std::mem::ManuallyDrop::new(cursor).0, tx, crate::borrows::Borrow::of(tx))
+			},
+			_ => Self::open(tx, dbi)?,
+		}
+	}
+
+	pub(super) fn into_idle(self) -> IdleCursor<'env> {
+		let mut this = std::mem::ManuallyDrop::new(self);
+		// an idle cursor doesn't borrow the transaction any more
+		unsafe { std::ptr::drop_in_place(&raw mut this.2) };
+		IdleCursor(this.0, std::marker::PhantomData)
+	}
+}
+
+/// A read cursor kept between read transactions to be renewed onto the next one instead of opening another,
+/// see [`AssocTable::iter_reusing`](crate::AssocTable::iter_reusing). Closed on drop.
+pub struct IdleCursor<'env>(*mut sys::MDB_cursor, std::marker::PhantomData<&'env crate::Env>);
+unsafe impl Send for IdleCursor<'_> {}
+
+impl Drop for IdleCursor<'_> {
+	// fine after its transaction ended since it's a read cursor
+	fn drop(&mut self) { unsafe { sys::mdb_cursor_close(self.0) }; }
 }
 
 impl<TX> Drop for Cursor<'_, TX> {
@@ -146,19 +404,179 @@ impl<TX> Drop for Cursor<'_, TX> {
 	}
 }
 
+#[throws(super::Error)]
+pub(super) fn put(tx: &RwTxn, dbi: sys::MDB_dbi, mut key: impl AsMut<[u8]>, mut val: impl AsMut<[u8]>, flags: enumflags2::BitFlags<PutFlags>) {
+	if let Some(usage) = tx.pressure { throw!(super::Error::Backpressure(usage)); }
+	// dupsort values are stored like keys, lmdb only says BadValSize for those
+	let size = val.as_mut().len();
+	let dup_sort = dbi_flags(tx.raw(), dbi)?.contains(DbFlags::DupSort);
+	if dup_sort {
+		let max = env_max_key_size(tx.env().raw());
+		if size > max { throw!(Error::DupValueTooLarge { size, max }); }
+	}
+	if let Some(limits) = tx.env().size_limits(dbi) { check_sizes(tx, dbi, limits, key.as_mut(), size)?; }
+	if let Some(quota) = tx.env().quota(dbi) { check_quota(tx, dbi, quota, key.as_mut(), size, dup_sort)?; }
+	tx.written.fetch_add(key.as_mut().len() + size, std::sync::atomic::Ordering::Relaxed);
+	let res = error::handle_put_code(unsafe { sys::mdb_put(tx.raw(), dbi, &mut *Val::from_buf(key.as_mut()), &mut *Val::from_buf(val.as_mut()), flags.bits()) });
+	if matches!(res, Err(Error::KeyExists)) && flags.intersects(PutFlags::Append | PutFlags::AppendDup) {
+		throw!(out_of_order(tx, dbi, key.as_mut(), val.as_mut(), dup_sort && flags.contains(PutFlags::AppendDup)));
+	}
+	res.inspect_err(|e| tx.env().note_error(e))?;
+	crate::changelog::record(tx, dbi, ChangeOp::Put, key.as_mut())?;
+}
+
+// a put without put's checks, accounting or changelog entry, for batadase's own bookkeeping
 #[throws]
-pub(super) fn put(tx: &RwTxn, dbi: sys::MDB_dbi, key: impl AsMut<[u8]>, val: impl AsMut<[u8]>, flags: enumflags2::BitFlags<PutFlags>) {
-	error::handle_put_code(unsafe { sys::mdb_put(tx.raw(), dbi, &mut *Val::from_buf(key), &mut *Val::from_buf(val), flags.bits()) })?;
+pub(super) fn put_unchecked(tx: &RwTxn, dbi: sys::MDB_dbi, key: &mut [u8], val: &mut [u8], flags: enumflags2::BitFlags<PutFlags>) {
+	error::handle_put_code(unsafe { sys::mdb_put(tx.raw(), dbi, &mut *Val::from_buf(key), &mut *Val::from_buf(val), flags.bits()) })
+		.inspect_err(|e| tx.env().note_error(e))?;
+}
+
+// An append of `key` hit KeyExists, which is all LMDB says when the key isn't past the db's last one - or with
+// AppendDup, the value isn't past the key's last one. Found after the fact, so appends in order cost nothing extra.
+fn out_of_order(tx: &RwTxn, dbi: sys::MDB_dbi, key: &[u8], value: &[u8], append_dup: bool) -> super::Error {
+	let Ok(mut cursor) = Cursor::open(tx, dbi) else { return Error::KeyExists.into() };
+	let Ok(Some((last_key, last_value))) = cursor.get(CursorOp::Last) else { return Error::KeyExists.into() };
+	if append_dup && cmp(tx, dbi, key, last_key).is_eq() {
+		return super::Error::OutOfOrder { what: "value", previous: last_value.to_vec(), current: value.to_vec() };
+	}
+	super::Error::OutOfOrder { what: "key", previous: last_key.to_vec(), current: key.to_vec() }
+}
+
+// puts every `size` byte value in `values` under `key` with MDB_MULTIPLE, for DbFlags::DupFixed dbs - returns how many were
+#[throws(super::Error)]
+pub(super) fn put_multiple(tx: &RwTxn, dbi: sys::MDB_dbi, mut key: impl AsMut<[u8]>, values: &mut [u8], size: usize) -> usize {
+	if let Some(usage) = tx.pressure { throw!(super::Error::Backpressure(usage)); }
+	if values.is_empty() { return 0; }
+	let max = env_max_key_size(tx.env().raw());
+	if size > max { throw!(Error::DupValueTooLarge { size, max }); }
+	if let Some(limits) = tx.env().size_limits(dbi) { check_sizes(tx, dbi, limits, key.as_mut(), size)?; }
+	if let Some(quota) = tx.env().quota(dbi) { check_quota(tx, dbi, quota, key.as_mut(), values.len(), true)?; }
+	tx.written.fetch_add(key.as_mut().len() + values.len(), std::sync::atomic::Ordering::Relaxed);
+	let cursor = Cursor::open(tx, dbi)?;
+	let mut data = [
+		sys::MDB_val { mv_size: size, mv_data: values.as_mut_ptr().cast() },
+		sys::MDB_val { mv_size: values.len() / size, mv_data: std::ptr::null_mut() },
+	];
+	error::handle_put_code(unsafe { sys::mdb_cursor_put(cursor.0, &mut *Val::from_buf(key.as_mut()), data.as_mut_ptr(), sys::MDB_MULTIPLE) })
+		.inspect_err(|e| tx.env().note_error(e))?;
+	crate::changelog::record(tx, dbi, ChangeOp::Put, key.as_mut())?;
+	data[1].mv_size
+}
+
+// hard limits fail, soft ones only warn
+#[throws(super::Error)]
+fn check_sizes(tx: &RwTxn, dbi: sys::MDB_dbi, limits: crate::env::SizeLimits, key: &[u8], size: usize) {
+	for (what, size, soft, hard) in [("key", key.len(), limits.soft_key, limits.hard_key), ("value", size, limits.soft_value, limits.hard_value)] {
+		if let Some(max) = hard && size > max { throw!(super::Error::TooLarge { what, size, max }); }
+		if let Some(max) = soft && size > max {
+			let table = tx.env().db_name(dbi).map_or_else(|| format!("dbi {dbi}"), crate::db_name_lossy);
+			log::warn!("{what} of {size} bytes put in {table} is over its soft limit of {max}, key b\"{}\"", key[..key.len().min(crate::error::KEY_CAP)].escape_ascii());
+		}
+	}
+}
+
+// Whether putting `size` value bytes under `key` stays within the quota. The db's own stat is the accounting, it already
+// counts this transaction's writes. Only once a put would cross a limit is the key looked up, since replacing a value
+// doesn't add an entry and frees the old value's bytes - for dupsort dbs every put counts as an addition.
+#[throws(super::Error)]
+fn check_quota(tx: &RwTxn, dbi: sys::MDB_dbi, quota: crate::env::Quota, key: &mut [u8], size: usize, dup_sort: bool) {
+	let stat = stat(tx.raw(), dbi)?;
+	let bytes = (stat.branch_pages + stat.leaf_pages + stat.overflow_pages) * stat.page_size as usize;
+	let full_entries = quota.max_entries.is_some_and(|max| stat.entries >= max);
+	let full_bytes = quota.max_bytes.is_some_and(|max| bytes + key.len() + size > max);
+	if !full_entries && !full_bytes { return; }
+	let replaced = if dup_sort { None } else { get(tx, dbi, &mut *key)?.map(<[u8]>::len) };
+	if full_entries && replaced.is_none() { throw!(super::Error::QuotaExceeded("entry")); }
+	if full_bytes && quota.max_bytes.is_some_and(|max| (bytes + key.len() + size).saturating_sub(replaced.map_or(0, |old| key.len() + old)) > max) {
+		throw!(super::Error::QuotaExceeded("byte"));
+	}
+}
+
+// space for a `len` byte value of `key`, which has to be filled in before the next write in `tx`
+#[expect(clippy::mut_from_ref)] // the space is lmdb's dirty page, not part of tx
+#[throws(super::Error)]
+pub(super) fn put_reserve<'tx>(tx: &'tx RwTxn, dbi: sys::MDB_dbi, mut key: impl AsMut<[u8]>, len: usize) -> &'tx mut [u8] {
+	if let Some(usage) = tx.pressure { throw!(super::Error::Backpressure(usage)); }
+	// lmdb doesn't support MDB_RESERVE for dupsort dbs
+	if dbi_flags(tx.raw(), dbi)?.contains(DbFlags::DupSort) { throw!(Error::InvalidParameter); }
+	if let Some(limits) = tx.env().size_limits(dbi) { check_sizes(tx, dbi, limits, key.as_mut(), len)?; }
+	if let Some(quota) = tx.env().quota(dbi) { check_quota(tx, dbi, quota, key.as_mut(), len, false)?; }
+	tx.written.fetch_add(key.as_mut().len() + len, std::sync::atomic::Ordering::Relaxed);
+	let mut value = sys::MDB_val { mv_size: len, mv_data: std::ptr::null_mut() };
+	error::handle_put_code(unsafe { sys::mdb_put(tx.raw(), dbi, &mut *Val::from_buf(key.as_mut()), &mut value, sys::MDB_RESERVE) })
+		.inspect_err(|e| tx.env().note_error(e))?;
+	// logging it is a write that can move the reserved space, reserving the same size again reuses the entry in place
+	if crate::changelog::record(tx, dbi, ChangeOp::Put, key.as_mut())? {
+		value = sys::MDB_val { mv_size: len, mv_data: std::ptr::null_mut() };
+		error::handle_put_code(unsafe { sys::mdb_put(tx.raw(), dbi, &mut *Val::from_buf(key.as_mut()), &mut value, sys::MDB_RESERVE) })
+			.inspect_err(|e| tx.env().note_error(e))?;
+	}
+	unsafe { std::slice::from_raw_parts_mut(value.mv_data.cast::<u8>(), value.mv_size) }
+}
+
+/// [`std::io::Write`] into space reserved for a value of a known length, which has to be filled in before the next
+/// write in its transaction. Whatever isn't written by the time it's dropped is zeroed, writing past the end fails with `WriteZero`.
+/// The `put_writer`s that make one are unsafe, since nothing stops another write moving the space while it's alive.
+pub struct ValueWriter<'tx> {
+	space: &'tx mut [u8],
+	written: usize,
+}
+
+impl<'tx> ValueWriter<'tx> {
+	pub(super) fn new(space: &'tx mut [u8]) -> Self { Self { space, written: 0 } }
+
+	/// bytes left until the value is filled in
+	pub fn remaining(&self) -> usize { self.space.len() - self.written }
+}
+
+impl std::io::Write for ValueWriter<'_> {
+	fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+		let n = buf.len().min(self.remaining());
+		self.space[self.written..self.written + n].copy_from_slice(&buf[..n]);
+		self.written += n;
+		Ok(n)
+	}
+
+	fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+}
+
+// reserved space is whatever was on the page before
+impl Drop for ValueWriter<'_> {
+	fn drop(&mut self) { self.space[self.written..].fill(0); }
 }
 
 #[throws]
-pub(super) fn del(tx: &RwTxn, dbi: sys::MDB_dbi, key: impl AsMut<[u8]>) -> bool {
-	error::handle_del_code(unsafe { sys::mdb_del(tx.raw(), dbi, &mut *Val::from_buf(key), std::ptr::null_mut()) })?
+pub(super) fn del(tx: &RwTxn, dbi: sys::MDB_dbi, mut key: impl AsMut<[u8]>) -> bool {
+	let deleted = error::handle_del_code(unsafe { sys::mdb_del(tx.raw(), dbi, &mut *Val::from_buf(key.as_mut()), std::ptr::null_mut()) })?;
+	if deleted { crate::changelog::record(tx, dbi, ChangeOp::Delete, key.as_mut())?; }
+	deleted
 }
 
+// deletes just `value` of `key`, for DbFlags::DupSort dbs
+#[throws]
+pub(super) fn del_value(tx: &RwTxn, dbi: sys::MDB_dbi, mut key: impl AsMut<[u8]>, value: impl AsMut<[u8]>) -> bool {
+	let deleted = error::handle_del_code(unsafe { sys::mdb_del(tx.raw(), dbi, &mut *Val::from_buf(key.as_mut()), &mut *Val::from_buf(value)) })?;
+	if deleted { crate::changelog::record(tx, dbi, ChangeOp::Delete, key.as_mut())?; }
+	deleted
+}
+
+// the root db's handle, as mdb_dbi_open gives it for no name
+pub(super) const MAIN_DBI: sys::MDB_dbi = 1;
+
 #[throws]
 pub(super) fn drop(tx: &RwTxn, dbi: sys::MDB_dbi) {
+	// the root db's entries include the named dbs, emptying it would lose them all
+	if dbi == MAIN_DBI { throw!(Error::Incompatible); }
 	error::handle_drop_code(unsafe { sys::mdb_drop(tx.raw(), dbi, 0) })?;
+	crate::changelog::record(tx, dbi, ChangeOp::Clear, &[])?;
+}
+
+// deletes the db from the env and closes dbi
+#[throws]
+pub(super) fn drop_delete(tx: &RwTxn, dbi: sys::MDB_dbi) {
+	error::handle_drop_code(unsafe { sys::mdb_drop(tx.raw(), dbi, 1) })?;
+	crate::changelog::record(tx, dbi, ChangeOp::Clear, &[])?;
 }
 
 #[throws]
@@ -168,6 +586,25 @@ pub(super) fn get<'tx, 'env: 'tx>(tx: &'tx impl Transaction<'env>, dbi: sys::MDB
 	Some(value.as_slice())
 }
 
+// order of two keys by dbi's comparator
+pub(super) fn cmp<'env>(tx: &impl Transaction<'env>, dbi: sys::MDB_dbi, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+	let val = |x: &[u8]| sys::MDB_val { mv_size: x.len(), mv_data: x.as_ptr().cast_mut().cast() };
+	unsafe { sys::mdb_cmp(tx.raw(), dbi, &val(a), &val(b)) }.cmp(&0)
+}
+
+// order of two dupsort values by dbi's comparator, which may be a DupOrder
+pub(super) fn dcmp<'env>(tx: &impl Transaction<'env>, dbi: sys::MDB_dbi, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+	let val = |x: &[u8]| sys::MDB_val { mv_size: x.len(), mv_data: x.as_ptr().cast_mut().cast() };
+	unsafe { sys::mdb_dcmp(tx.raw(), dbi, &val(a), &val(b)) }.cmp(&0)
+}
+
+// faults in every page `bytes` spans, values bigger than a page live on their own overflow pages
+pub(super) fn touch(bytes: &[u8]) -> usize {
+	const PAGE: usize = 4096; // the smallest page size around, reading twice per bigger page doesn't hurt
+	for i in (0..bytes.len()).step_by(PAGE).chain(bytes.len().checked_sub(1)) { std::hint::black_box(bytes[i]); }
+	bytes.len()
+}
+
 #[throws]
 pub(super) fn txn_begin(env: *mut sys::MDB_env, flags: u32) -> *mut sys::MDB_txn {
 	let mut tx: *mut sys::MDB_txn = std::ptr::null_mut();
@@ -175,6 +612,14 @@ pub(super) fn txn_begin(env: *mut sys::MDB_env, flags: u32) -> *mut sys::MDB_txn
 	tx
 }
 
+// a child transaction of the write transaction `parent`, which can't be used until the child ends
+#[throws]
+pub(super) fn txn_begin_nested(env: *mut sys::MDB_env, parent: *mut sys::MDB_txn) -> *mut sys::MDB_txn {
+	let mut tx: *mut sys::MDB_txn = std::ptr::null_mut();
+	error::handle_txn_begin_code(unsafe { sys::mdb_txn_begin(env, parent, 0, &mut tx) })?;
+	tx
+}
+
 #[throws]
 pub(super) fn txn_commit(tx: *mut sys::MDB_txn) {
 	error::handle_txn_commit_code(unsafe { sys::mdb_txn_commit(tx) })?;
@@ -202,25 +647,234 @@ pub(super) fn env_set_maxreaders(env: *mut sys::MDB_env, maxreaders: u32) {
 	error::handle_env_set_maxreaders_code(unsafe { sys::mdb_env_set_maxreaders(env, maxreaders) })?;
 }
 
+#[throws]
+pub(super) fn env_copy(env: *mut sys::MDB_env, path: &std::ffi::CStr, flags: u32) {
+	error::handle_env_copy_code(unsafe { sys::mdb_env_copy2(env, path.as_ptr(), flags) })?;
+}
+
+// MDB_MAXKEYSIZE, which the bundled LMDB is compiled with - mdb_env_get_maxkeysize returns it for every env
+pub(crate) const MAX_KEY_SIZE: usize = 511;
+
+pub(super) fn env_max_key_size(env: *mut sys::MDB_env) -> usize {
+	usize::try_from(unsafe { sys::mdb_env_get_maxkeysize(env) }).unwrap_or(0)
+}
+
+#[throws]
+pub(super) fn env_path(env: *mut sys::MDB_env) -> std::ffi::CString {
+	let mut path: *const libc::c_char = std::ptr::null();
+	error::handle_env_get_path_code(unsafe { sys::mdb_env_get_path(env, &mut path) })?;
+	unsafe { std::ffi::CStr::from_ptr(path) }.to_owned()
+}
+
+#[throws]
+pub(super) fn env_flags(env: *mut sys::MDB_env) -> u32 {
+	let mut flags = 0;
+	error::handle_env_flags_code(unsafe { sys::mdb_env_get_flags(env, &mut flags) })?;
+	flags
+}
+
+#[throws]
+pub(super) fn env_sync(env: *mut sys::MDB_env, force: bool) {
+	error::handle_env_sync_code(unsafe { sys::mdb_env_sync(env, i32::from(force)) })?;
+}
+
 #[allow(unused_variables)]
 #[throws]
 pub(super) fn env_open(env: *mut sys::MDB_env, path: &std::ffi::CStr, flags: u32, mode: u32) {
-	#[cfg(unix)] let mode = mode;
 	#[cfg(windows)] let mode = 0;
 	error::handle_env_open(unsafe { sys::mdb_env_open(env, path.as_ptr(), flags, mode) })?;
 }
 
+// None if there's no such db or it's a plain entry of the root db, name None opens the root db
+#[throws]
+pub(super) fn dbi_open_existing(tx: *mut sys::MDB_txn, name: Option<&[u8]>) -> Option<sys::MDB_dbi> {
+	let mut dbi: sys::MDB_dbi = 0;
+	let name = name.map_or(std::ptr::null(), |x| x.as_ptr().cast());
+	if !error::handle_dbi_open_existing_code(unsafe { sys::mdb_dbi_open(tx, name, 0, &mut dbi) })? { return None; }
+	Some(dbi)
+}
+
+#[throws]
 pub(super) fn dbi_open(tx: *mut sys::MDB_txn, name: &[u8], flags: enumflags2::BitFlags<DbFlags>) -> sys::MDB_dbi {
 	let mut dbi: sys::MDB_dbi = 0;
-	error::handle_dbi_open_code(unsafe { sys::mdb_dbi_open(tx, name.as_ptr().cast(), flags.bits(), &mut dbi) });
+	error::handle_dbi_open_code(unsafe { sys::mdb_dbi_open(tx, name.as_ptr().cast(), flags.bits(), &mut dbi) })?;
 	dbi
 }
 
 #[throws]
-pub(super) fn stat(txn: *mut sys::MDB_txn, dbi: sys::MDB_dbi) -> sys::MDB_stat {
+pub(super) fn set_dupsort(tx: *mut sys::MDB_txn, dbi: sys::MDB_dbi, order: DupOrder) {
+	error::handle_set_dupsort_code(unsafe { sys::mdb_set_dupsort(tx, dbi, Some(order.0)) })?;
+}
+
+/// How dupsort values are compared, see [`DupOrder`].
+pub trait Compare {
+	/// compares stored values, framing included
+	fn compare(a: &[u8], b: &[u8]) -> std::cmp::Ordering;
+}
+
+/// An order for the values of a dupsort table in place of LMDB's bytewise one, given by [`DbName::dup_order`](crate::DbName::dup_order).
+/// It's set as the env opens the table - every process using the env has to use the same order, or lookups and
+/// inserts go wrong, and changing it needs the values to be rewritten, like a migration.
+#[derive(Clone, Copy)]
+pub struct DupOrder(unsafe extern "C" fn(*const sys::MDB_val, *const sys::MDB_val) -> libc::c_int);
+
+impl DupOrder {
+	pub fn of<C: Compare>() -> Self { Self(compare::<C>) }
+}
+
+// a panic in here aborts, it can't unwind through lmdb
+unsafe extern "C" fn compare<C: Compare>(a: *const sys::MDB_val, b: *const sys::MDB_val) -> libc::c_int {
+	let slice = |val: *const sys::MDB_val| match unsafe { *val } {
+		sys::MDB_val { mv_size: 0, .. } => &[][..],
+		sys::MDB_val { mv_size, mv_data } => unsafe { std::slice::from_raw_parts(mv_data.cast::<u8>(), mv_size) },
+	};
+	C::compare(slice(a), slice(b)) as libc::c_int
+}
+
+// for the whole map, lmdb maps mapsize upfront
+#[cfg(unix)]
+#[throws(super::Error)]
+pub(super) fn env_madvise(env: *mut sys::MDB_env, advice: i32) {
+	let Some(map) = env_map(env)? else { return };
+	let size = env_info(env)?.map_size;
+	if unsafe { libc::madvise(map.cast(), size, advice) } != 0 { throw!(std::io::Error::last_os_error()); }
+}
+
+// Where the map starts, None if the root db is empty. lmdb only reports the address with MDB_FIXEDMAP, but every page
+// starts with its own page number (the file format's page header), so the map starts that many pages before the page holding a key.
+#[cfg(unix)]
+#[throws]
+fn env_map(env: *mut sys::MDB_env) -> Option<*mut u8> {
+	let page_size = env_stat(env)?.page_size as usize;
+	let last_page = env_info(env)?.last_page;
+	let txn = txn_begin(env, sys::MDB_RDONLY)?;
+	let mut cursor = std::ptr::null_mut();
+	let code = unsafe { sys::mdb_cursor_open(txn, 1, &mut cursor) }; // 1 is always the root db
+	if code != sys::MDB_SUCCESS { unsafe { sys::mdb_txn_abort(txn) }; throw!(Error::from_code(code)); }
+	let mut key = sys::MDB_val { mv_size: 0, mv_data: std::ptr::null_mut() };
+	let mut value = sys::MDB_val { mv_size: 0, mv_data: std::ptr::null_mut() };
+	let found = error::handle_cursor_get_code(unsafe { sys::mdb_cursor_get(cursor, &mut key, &mut value, sys::MDB_FIRST) });
+	let found = found.inspect_err(|_| unsafe { sys::mdb_cursor_close(cursor); sys::mdb_txn_abort(txn); })?;
+	let page = key.mv_data.cast::<u8>().wrapping_sub(key.mv_data as usize % page_size);
+	let page_number = if found { unsafe { page.cast::<usize>().read_unaligned() } } else { 0 };
+	unsafe { sys::mdb_cursor_close(cursor); sys::mdb_txn_abort(txn); }
+	if !found || page_number > last_page { return None; }
+	Some(page.wrapping_sub(page_number * page_size))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EnvInfo {
+	pub map_size: usize,
+	pub last_page: usize,   // number of the last used page
+	pub last_txn_id: usize, // id of the last committed transaction
+	pub max_readers: u32,
+	pub num_readers: u32,   // reader slots ever handed out, freed ones included - see reader_count
+}
+
+#[throws]
+pub(super) fn env_info(env: *mut sys::MDB_env) -> EnvInfo {
+	let mut info: sys::MDB_envinfo = unsafe { std::mem::zeroed() };
+	error::handle_env_info_code(unsafe { sys::mdb_env_info(env, &mut info) })?;
+	EnvInfo {
+		map_size: info.me_mapsize,
+		last_page: info.me_last_pgno,
+		last_txn_id: info.me_last_txnid,
+		max_readers: info.me_maxreaders,
+		num_readers: info.me_numreaders,
+	}
+}
+
+// reader slots taken right now, stale ones of dead processes included. mdb_reader_list calls back with a header
+// and then a line per taken slot, which are the ones starting with a pid
+#[throws]
+pub(super) fn reader_count(env: *mut sys::MDB_env) -> u32 {
+	unsafe extern "C" fn count(msg: *const libc::c_char, ctx: *mut libc::c_void) -> libc::c_int {
+		let line = unsafe { std::ffi::CStr::from_ptr(msg) }.to_bytes();
+		if line.trim_ascii_start().first().is_some_and(u8::is_ascii_digit) { unsafe { *ctx.cast::<u32>() += 1 }; }
+		0
+	}
+	let mut readers = 0u32;
+	error::handle_reader_list_code(unsafe { sys::mdb_reader_list(env, Some(count), (&raw mut readers).cast()) })?;
+	readers
+}
+
+// the taken reader slots' pids, and the txn id being read for slots with a transaction open rather than reset -
+// lines are "pid thread txnid", with "-" for no txn id
+#[throws]
+pub(super) fn reader_slots(env: *mut sys::MDB_env) -> Vec<(libc::pid_t, Option<usize>)> {
+	unsafe extern "C" fn collect(msg: *const libc::c_char, ctx: *mut libc::c_void) -> libc::c_int {
+		let line = unsafe { std::ffi::CStr::from_ptr(msg) }.to_string_lossy();
+		let mut fields = line.split_ascii_whitespace();
+		if let Some(Ok(pid)) = fields.next().map(str::parse) {
+			let txn_id = fields.nth(1).and_then(|x| x.parse().ok());
+			unsafe { (*ctx.cast::<Vec<(libc::pid_t, Option<usize>)>>()).push((pid, txn_id)) };
+		}
+		0
+	}
+	let mut slots = Vec::<(libc::pid_t, Option<usize>)>::new();
+	error::handle_reader_list_code(unsafe { sys::mdb_reader_list(env, Some(collect), (&raw mut slots).cast()) })?;
+	slots
+}
+
+#[throws]
+pub(super) fn dbi_flags(txn: *mut sys::MDB_txn, dbi: sys::MDB_dbi) -> enumflags2::BitFlags<DbFlags> {
+	let mut flags = 0;
+	error::handle_dbi_flags_code(unsafe { sys::mdb_dbi_flags(txn, dbi, &mut flags) })?;
+	enumflags2::BitFlags::from_bits_truncate(flags)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stat {
+	pub page_size: u32,
+	pub depth: u32, // of the b-tree
+	pub branch_pages: usize,
+	pub leaf_pages: usize,
+	pub overflow_pages: usize,
+	pub entries: usize,
+}
+
+impl From<sys::MDB_stat> for Stat {
+	fn from(stat: sys::MDB_stat) -> Self {
+		Self {
+			page_size: stat.ms_psize,
+			depth: stat.ms_depth,
+			branch_pages: stat.ms_branch_pages,
+			leaf_pages: stat.ms_leaf_pages,
+			overflow_pages: stat.ms_overflow_pages,
+			entries: stat.ms_entries,
+		}
+	}
+}
+
+impl Stat {
+	/// bytes taken by the db's pages, leaving out free pages and the root db
+	pub fn size_bytes(&self) -> usize { (self.branch_pages + self.leaf_pages + self.overflow_pages) * self.page_size as usize }
+}
+
+#[throws]
+pub(super) fn stat(txn: *mut sys::MDB_txn, dbi: sys::MDB_dbi) -> Stat {
 	let mut stat: sys::MDB_stat = unsafe { std::mem::zeroed() };
 	error::handle_stat_code(unsafe { sys::mdb_stat(txn, dbi, &mut stat) })?;
-	stat
+	stat.into()
+}
+
+// pages on the freelist, reused by writes once no reader can see them
+#[throws]
+pub(super) fn free_pages<'env>(tx: &impl Transaction<'env>) -> usize {
+	let mut cursor = Cursor::open(tx, 0)?; // FREE_DBI
+	let mut free = 0;
+	// every value is a list of page numbers freed by one transaction, prefixed with their count
+	while let Some((_, pages)) = cursor.get(CursorOp::Next)? {
+		free += pages.first_chunk().map_or(0, |count| usize::from_ne_bytes(*count));
+	}
+	free
+}
+
+#[throws]
+pub(super) fn env_stat(env: *mut sys::MDB_env) -> Stat {
+	let mut stat: sys::MDB_stat = unsafe { std::mem::zeroed() };
+	error::handle_stat_code(unsafe { sys::mdb_env_stat(env, &mut stat) })?;
+	stat.into()
 }
 
 pub trait MdbValExt {
@@ -1,26 +1,116 @@
 use culpa::{throw, throws};
 
 #[derive(thiserror::Error, Debug)]
+#[cfg_attr(feature = "miette", derive(miette::Diagnostic))]
 pub enum Error {
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::version_mismatch), help("the env was created by a different LMDB major version")))]
 	#[error("the version of the LMDB library doesn't match the version that created the database environment")] VersionMismatch,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::corrupted)))]
 	#[error("the environment file headers are corrupted")] Corrupted,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::dir_doesnt_exist)))]
 	#[error("the directory specified by the path parameter doesn't exist")] DirDoesntExist,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::no_access)))]
 	#[error("the user didn't have permission to access the environment files")] NoAccess,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::env_locked), help("another process has the env open exclusively")))]
 	#[error("the environment was locked by another process")] EnvLocked,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::create_error)))]
 	#[error("database create error")] CreateError(i32),
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::invalid_parameter)))]
 	#[error("an invalid parameter was specified")] InvalidParameter,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::panic)))]
 	#[error("a fatal error occurred earlier and the environment must be shut down")] Panic,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::map_resized), help("reopen the env, or have it resized with mdb_env_set_mapsize(0) through Env::raw")))]
 	#[error("another process wrote data beyond this MDB_env's mapsize and this environment's map must be resized as well")] MapResized,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::readers_full), help("raise the reader limit with EnvBuilder::maxreaders, or clear stale readers")))]
 	#[error("a read-only transaction was requested and the reader lock table is full")] ReadersFull,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::no_disk_space)))]
 	#[error("no more disk space")] NoDiskSpace,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::map_full), help("raise the map size with EnvBuilder::mapsize")))]
 	#[error("database is full")] MapFull,
-	#[error("too many tx dirty pags")] TxnFull,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::txn_full), help("the transaction dirtied too many pages, split it into smaller ones - Env::chunked_write does it for a batch of items")))]
+	#[error("too many dirty pages in one write transaction")] TxnFull,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::txn_perm), help("the env or this transaction is read-only")))]
 	#[error("trying to write a readonly transaction")] TxnPerm,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::io)))]
 	#[error("a low-level I/O error occured while writing")] Io,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::oom)))]
 	#[error("out of memory")] Oom,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::key_exists)))]
 	#[error("key already exists and overwrite isn't requested")] KeyExists,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::bad_val_size), help("keys are limited to 511 bytes and can't be empty")))]
 	#[error("unsupported size of key/DB name/data, or wrong DUPFIXED size")] BadValSize,
-	#[error("misc error {0}")] Misc(i32),
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::dbs_full), help("raise the limit with EnvBuilder::maxdbs")))]
+	#[error("too many dbs open, maxdbs has to be raised")] DbsFull,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::dup_value_too_large), help("values of DupSort dbs are limited to the max key size")))]
+	#[error("value of {size} bytes is too large for a DupSort db, at most {max} are allowed")] DupValueTooLarge { size: usize, max: usize },
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::db_not_found), help("the env doesn't have this db yet, open it writable once to create it")))]
+	#[error("the db doesn't exist and isn't being created")] DbNotFound,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::incompatible), help("open the db with the flags it was created with")))]
+	#[error("the db was created with incompatible flags, or the name is a plain entry of the root db")] Incompatible,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::not_found)))]
+	#[error("key/data pair not found")] NotFound,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::page_not_found), help("the env is likely corrupted, run verify")))]
+	#[error("requested page not found")] PageNotFound,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::page_corrupted), help("the env is likely corrupted, run verify")))]
+	#[error("located page was the wrong type")] PageCorrupted,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::tls_full)))]
+	#[error("too many TLS keys in use")] TlsFull,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::cursor_full)))]
+	#[error("cursor stack too deep")] CursorFull,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::page_full)))]
+	#[error("page has not enough space")] PageFull,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::bad_rslot)))]
+	#[error("invalid reuse of reader locktable slot")] BadRslot,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::bad_txn), help("the transaction had an error and can only be aborted")))]
+	#[error("transaction must abort, has a child, or is invalid")] BadTxn,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::bad_dbi)))]
+	#[error("the db handle was closed or changed unexpectedly")] BadDbi,
+	#[cfg_attr(feature = "miette", diagnostic(code(batadase::lmdb::misc)))]
+	#[error("misc error {code}: {message}", code = .0, message = strerror(*.0))] Misc(i32),
+}
+
+fn strerror(code: i32) -> String {
+	unsafe { std::ffi::CStr::from_ptr(lmdb_sys::mdb_strerror(code)) }.to_string_lossy().into_owned()
+}
+
+impl Error {
+	/// Any return code that isn't specific to the call it came from, the handlers below map the codes
+	/// whose meaning depends on the call (EACCES, ENOENT, EAGAIN...) themselves and fall back to this.
+	pub(crate) fn from_code(code: i32) -> Self {
+		match code {
+			lmdb_sys::MDB_KEYEXIST => Self::KeyExists,
+			lmdb_sys::MDB_NOTFOUND => Self::NotFound,
+			lmdb_sys::MDB_PAGE_NOTFOUND => Self::PageNotFound,
+			lmdb_sys::MDB_CORRUPTED => Self::PageCorrupted,
+			lmdb_sys::MDB_PANIC => Self::Panic,
+			lmdb_sys::MDB_VERSION_MISMATCH => Self::VersionMismatch,
+			lmdb_sys::MDB_INVALID => Self::Corrupted,
+			lmdb_sys::MDB_MAP_FULL => Self::MapFull,
+			lmdb_sys::MDB_DBS_FULL => Self::DbsFull,
+			lmdb_sys::MDB_READERS_FULL => Self::ReadersFull,
+			lmdb_sys::MDB_TLS_FULL => Self::TlsFull,
+			lmdb_sys::MDB_TXN_FULL => Self::TxnFull,
+			lmdb_sys::MDB_CURSOR_FULL => Self::CursorFull,
+			lmdb_sys::MDB_PAGE_FULL => Self::PageFull,
+			lmdb_sys::MDB_MAP_RESIZED => Self::MapResized,
+			lmdb_sys::MDB_INCOMPATIBLE => Self::Incompatible,
+			lmdb_sys::MDB_BAD_RSLOT => Self::BadRslot,
+			lmdb_sys::MDB_BAD_TXN => Self::BadTxn,
+			lmdb_sys::MDB_BAD_VALSIZE => Self::BadValSize,
+			lmdb_sys::MDB_BAD_DBI => Self::BadDbi,
+			libc::EINVAL => Self::InvalidParameter,
+			libc::ENOMEM => Self::Oom,
+			libc::ENOSPC => Self::NoDiskSpace,
+			libc::EIO => Self::Io,
+			code => Self::Misc(code),
+		}
+	}
+
+	/// Whether the same operation can succeed later without anything being wrong with the env or the code:
+	/// after the map is grown, readers or disk space are freed, another process lets go of the env, or in a smaller transaction.
+	pub fn is_retryable(&self) -> bool {
+		matches!(self, Self::MapFull | Self::MapResized | Self::ReadersFull | Self::TxnFull | Self::EnvLocked | Self::NoDiskSpace | Self::Oom)
+	}
 }
 
 #[throws]
@@ -30,7 +120,7 @@ pub(crate) fn handle_del_code(code: i32) -> bool {
 		lmdb_sys::MDB_NOTFOUND => false,
 		libc::EACCES => throw!(Error::TxnPerm),
 		libc::EINVAL => throw!(Error::InvalidParameter),
-		code => throw!(Error::Misc(code)),
+		code => throw!(Error::from_code(code)),
 	}
 }
 
@@ -44,7 +134,7 @@ pub(crate) fn handle_put_code(code: i32) {
 		lmdb_sys::MDB_BAD_VALSIZE => throw!(Error::BadValSize),
 		libc::EACCES => throw!(Error::TxnPerm),
 		libc::EINVAL => throw!(Error::InvalidParameter),
-		code => throw!(Error::Misc(code)),
+		code => throw!(Error::from_code(code)),
 	}
 }
 
@@ -52,7 +142,7 @@ pub(crate) fn handle_put_code(code: i32) {
 pub(crate) fn handle_drop_code(code: i32) {
 	match code {
 		lmdb_sys::MDB_SUCCESS => {},
-		code => throw!(Error::Misc(code)),
+		code => throw!(Error::from_code(code)),
 	}
 }
 
@@ -62,7 +152,16 @@ pub(crate) fn handle_get_code(code: i32) -> bool {
 		lmdb_sys::MDB_SUCCESS => true,
 		lmdb_sys::MDB_NOTFOUND => false,
 		libc::EINVAL => throw!(Error::InvalidParameter),
-		code => throw!(Error::Misc(code)),
+		code => throw!(Error::from_code(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_set_dupsort_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		libc::EINVAL => throw!(Error::InvalidParameter),
+		code => throw!(Error::from_code(code)),
 	}
 }
 
@@ -71,16 +170,26 @@ pub(crate) fn handle_cursor_open_code(code: i32) {
 	match code {
 		lmdb_sys::MDB_SUCCESS => {},
 		libc::EINVAL => throw!(Error::InvalidParameter),
-		code => throw!(Error::Misc(code)),
+		code => throw!(Error::from_code(code)),
 	}
 }
 
+#[throws]
 pub(crate) fn handle_cursor_get_code(code: i32) -> bool {
 	match code {
 		lmdb_sys::MDB_SUCCESS => true,
 		lmdb_sys::MDB_NOTFOUND => false,
-		libc::EINVAL => panic!("cursor invalid parameter"),
-		e => panic!("cursor misc error {e}"),
+		code => throw!(Error::from_code(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_cursor_del_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		libc::EACCES => throw!(Error::TxnPerm),
+		libc::EINVAL => throw!(Error::InvalidParameter),
+		code => throw!(Error::from_code(code)),
 	}
 }
 
@@ -92,7 +201,8 @@ pub(crate) fn handle_txn_begin_code(code: i32) {
 		lmdb_sys::MDB_MAP_RESIZED => throw!(Error::MapResized),
 		lmdb_sys::MDB_READERS_FULL => throw!(Error::ReadersFull),
 		libc::ENOMEM => throw!(Error::Oom),
-		code => throw!(Error::Misc(code)),
+		libc::EACCES => throw!(Error::TxnPerm),
+		code => throw!(Error::from_code(code)),
 	}
 }
 
@@ -104,7 +214,7 @@ pub(crate) fn handle_txn_commit_code(code: i32) {
 		libc::ENOSPC => throw!(Error::NoDiskSpace),
 		libc::EIO => throw!(Error::Io),
 		libc::ENOMEM => throw!(Error::Oom),
-		code => throw!(Error::Misc(code)),
+		code => throw!(Error::from_code(code)),
 	}
 }
 
@@ -120,7 +230,7 @@ pub(crate) fn handle_env_create_code(code: i32) {
 pub(crate) fn handle_env_set_maxdbs_code(code: i32) {
 	match code {
 		lmdb_sys::MDB_SUCCESS => {},
-		_ => throw!(Error::InvalidParameter),
+		code => throw!(Error::from_code(code)), // EINVAL if the env is already open
 	}
 }
 
@@ -128,7 +238,7 @@ pub(crate) fn handle_env_set_maxdbs_code(code: i32) {
 pub(crate) fn handle_env_set_mapsize_code(code: i32) {
 	match code {
 		lmdb_sys::MDB_SUCCESS => {},
-		_ => throw!(Error::InvalidParameter),
+		code => throw!(Error::from_code(code)), // EINVAL if the env is already open
 	}
 }
 
@@ -136,7 +246,7 @@ pub(crate) fn handle_env_set_mapsize_code(code: i32) {
 pub(crate) fn handle_env_set_maxreaders_code(code: i32) {
 	match code {
 		lmdb_sys::MDB_SUCCESS => {},
-		_ => throw!(Error::InvalidParameter),
+		code => throw!(Error::from_code(code)), // EINVAL if the env is already open
 	}
 }
 
@@ -149,16 +259,72 @@ pub(crate) fn handle_env_open(code: i32) {
 		libc::ENOENT | libc::ESRCH => throw!(Error::DirDoesntExist),
 		libc::EACCES => throw!(Error::NoAccess),
 		libc::EAGAIN => throw!(Error::EnvLocked),
-		code => throw!(Error::Misc(code)),
+		code => throw!(Error::from_code(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_env_get_path_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		libc::EINVAL => throw!(Error::InvalidParameter),
+		code => throw!(Error::from_code(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_env_flags_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		libc::EINVAL => throw!(Error::InvalidParameter),
+		code => throw!(Error::from_code(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_env_sync_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		libc::EACCES => throw!(Error::TxnPerm), // read-only env
+		libc::EINVAL => throw!(Error::InvalidParameter),
+		libc::EIO => throw!(Error::Io),
+		code => throw!(Error::from_code(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_env_copy_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		libc::ENOENT => throw!(Error::DirDoesntExist),
+		libc::EACCES => throw!(Error::NoAccess),
+		libc::ENOSPC => throw!(Error::NoDiskSpace),
+		libc::EIO => throw!(Error::Io),
+		libc::EINVAL => throw!(Error::InvalidParameter),
+		code => throw!(Error::from_code(code)),
 	}
 }
 
+#[throws]
 pub(crate) fn handle_dbi_open_code(code: i32) {
 	match code {
 		lmdb_sys::MDB_SUCCESS => {},
-		lmdb_sys::MDB_NOTFOUND => panic!("not found"), // should be unreachable
-		lmdb_sys::MDB_DBS_FULL => panic!("too many dbs"),
-		e => panic!("misc error {e}"),
+		lmdb_sys::MDB_NOTFOUND => throw!(Error::DbNotFound), // only without DbFlags::Create, e.g. read-only envs
+		lmdb_sys::MDB_INCOMPATIBLE => throw!(Error::Incompatible),
+		lmdb_sys::MDB_DBS_FULL => throw!(Error::DbsFull),
+		libc::EACCES => throw!(Error::TxnPerm),
+		libc::EINVAL => throw!(Error::InvalidParameter),
+		code => throw!(Error::from_code(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_dbi_open_existing_code(code: i32) -> bool {
+	match code {
+		lmdb_sys::MDB_SUCCESS => true,
+		lmdb_sys::MDB_NOTFOUND | lmdb_sys::MDB_INCOMPATIBLE => false,
+		lmdb_sys::MDB_DBS_FULL => throw!(Error::DbsFull),
+		code => throw!(Error::from_code(code)),
 	}
 }
 
@@ -167,6 +333,40 @@ pub(crate) fn handle_stat_code(code: i32) {
 	match code {
 		lmdb_sys::MDB_SUCCESS => {},
 		libc::EINVAL => throw!(Error::InvalidParameter),
-		code => throw!(Error::Misc(code)),
+		code => throw!(Error::from_code(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_env_info_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		code => throw!(Error::from_code(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_cursor_count_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		libc::EINVAL => throw!(Error::InvalidParameter),
+		code => throw!(Error::from_code(code)),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_reader_list_code(code: i32) {
+	match code {
+		0.. => {},
+		_ => throw!(Error::InvalidParameter),
+	}
+}
+
+#[throws]
+pub(crate) fn handle_dbi_flags_code(code: i32) {
+	match code {
+		lmdb_sys::MDB_SUCCESS => {},
+		libc::EINVAL => throw!(Error::InvalidParameter),
+		code => throw!(Error::from_code(code)),
 	}
 }
@@ -0,0 +1,134 @@
+//! Views derived from a table, kept up to date by its puts and deletes in the same transaction - secondary indexes,
+//! per-group projections, anything computable from one entry at a time:
+//! ```ignore
+//! #[derive(DbName)]
+//! #[table(AssocTable<'tx, TX, (AuthorId, PostId), Title>)]
+//! struct PostsByAuthor;
+//!
+//! struct ByAuthor;
+//! impl Derivation for ByAuthor {
+//!     type Key = PostId;
+//!     type Value = Post;
+//!     type ViewKey = (AuthorId, PostId);
+//!     type ViewValue = Title;
+//!     type View = PostsByAuthor;
+//!     fn derive(id: &PostId, post: &Post) -> Vec<((AuthorId, PostId), Title)> { vec![((post.author, *id), post.title.clone())] }
+//! }
+//!
+//! #[derive(DbName)]
+//! #[table(MaterializedTable<'tx, TX, ByAuthor>)]
+//! struct Posts;
+//!
+//! tx.get::<Posts>().put(&id, &post)?; // also updates PostsByAuthor
+//! let titles = tx.get::<PostsByAuthor>().iter_from(&(author, PostId::MIN))?;
+//! ```
+//! Both tables are registered with `EnvBuilder::with` as usual. Only writes through the `MaterializedTable` update the
+//! view, `rebuild_view` recomputes it from scratch after writes that went around it or a change to `derive`.
+//! The view is an assoc table, so entries derived from different source entries mustn't have the same key - including
+//! the source key in the view key, like above, keeps them apart.
+
+use culpa::throws;
+use crate::{AssocTable, DbName, Transaction, RwTxn, Table, RkyvSer, RkyvVal, RkyvDe, Error};
+
+/// How source entries map to view entries.
+pub trait Derivation {
+	type Key: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>;
+	type Value: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>;
+	type ViewKey: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>;
+	type ViewValue: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>;
+	/// the view table, an `AssocTable<ViewKey, ViewValue>`
+	type View: DbName;
+
+	/// the view entries of a source entry, none is fine
+	fn derive(key: &Self::Key, value: &Self::Value) -> Vec<(Self::ViewKey, Self::ViewValue)>;
+}
+
+pub struct MaterializedTable<'tx, TX, D: Derivation> {
+	source: AssocTable<'tx, TX, D::Key, D::Value>,
+	view: AssocTable<'tx, TX, D::ViewKey, D::ViewValue>,
+}
+
+impl<'tx, 'env: 'tx, TX, D> Table<'tx, 'env, TX> for MaterializedTable<'tx, TX, D> where
+	TX: Transaction<'env>,
+	D: Derivation,
+	rkyv::Archived<D::Key>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<D::Value>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<D::Value, RkyvDe> + 'tx,
+	rkyv::Archived<D::ViewKey>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<D::ViewValue>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<D::ViewValue, RkyvDe> + 'tx,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.source.dbi() }
+	fn txn(&self) -> &TX { self.source.txn() }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self { Self { source: Table::build(tx, name), view: Table::build(tx, D::View::NAME) } }
+
+	#[throws]
+	fn check_entry(key: &[u8], value: &[u8]) { AssocTable::<'tx, TX, D::Key, D::Value>::check_entry(key, value)?; }
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, D> MaterializedTable<'tx, RwTxn<'tx>, D> where
+	D: Derivation,
+	rkyv::Archived<D::Key>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<D::Key, RkyvDe> + 'tx,
+	rkyv::Archived<D::Value>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<D::Value, RkyvDe> + 'tx,
+{
+	/// put, replacing the view entries derived from the old value with the new value's
+	#[throws]
+	pub fn put(&self, key: &D::Key, value: &D::Value) {
+		self.underive(key)?;
+		self.source.put(key, value)?;
+		for (view_key, view_value) in D::derive(key, value) { self.view.put(&view_key, &view_value)?; }
+	}
+
+	/// delete, along with the view entries derived from the value
+	#[throws]
+	pub fn delete(&self, key: &D::Key) -> bool {
+		self.underive(key)?;
+		self.source.delete(key)?
+	}
+
+	// take out the view entries of key's current value
+	#[throws]
+	fn underive(&self, key: &D::Key) {
+		let Some(old) = self.source.get_unrkyv(key)? else { return };
+		for (view_key, _) in D::derive(key, &old) { self.view.delete(&view_key)?; }
+	}
+
+	/// Recompute the whole view from the source table, returns how many view entries there are.
+	#[throws]
+	pub fn rebuild_view(&self) -> usize {
+		self.view.clear()?;
+		let mut derived = 0;
+		for entry in self.source.iter()? {
+			let (key, value) = entry?;
+			let key = rkyv::deserialize::<D::Key, rkyv::rancor::Error>(key)?;
+			let value = rkyv::deserialize::<D::Value, rkyv::rancor::Error>(value)?;
+			for (view_key, view_value) in D::derive(&key, &value) {
+				self.view.put(&view_key, &view_value)?;
+				derived += 1;
+			}
+		}
+		derived
+	}
+
+	/// clear both the source table and the view
+	#[throws]
+	pub fn clear(&self) {
+		self.source.clear()?;
+		self.view.clear()?;
+	}
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, D> MaterializedTable<'tx, TX, D> where
+	TX: Transaction<'env>,
+	D: Derivation,
+	rkyv::Archived<D::Key>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<D::Value>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<D::Value, RkyvDe> + 'tx,
+{
+	/// the source table, writes through it don't update the view
+	pub fn source(&self) -> &AssocTable<'tx, TX, D::Key, D::Value> { &self.source }
+	pub fn view(&self) -> &AssocTable<'tx, TX, D::ViewKey, D::ViewValue> { &self.view }
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn get(&self, key: &D::Key) -> Option<&'tx rkyv::Archived<D::Value>> { self.source.get(key)? }
+}
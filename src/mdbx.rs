@@ -0,0 +1,208 @@
+//! A [`Backend`] over libmdbx, with the `mdbx` feature, for code written against the [`backend`](crate::backend) traits:
+//! ```ignore
+//! let env = MdbxEnv::builder().with::<Users>().build(Path::new("/var/lib/app/mdbx"))?;
+//! rename_user(&env, b"42", b"ferris")?;
+//! ```
+//! The feature doesn't swap libmdbx in under [`Env`](crate::Env) and the typed tables, this is a separate env next
+//! to it: libmdbx's file format, flags and error codes differ from LMDB's, and the tables hand out `lmdb_sys` handles
+//! and references into LMDB's map, so they stay on LMDB. Only code written against the backend traits runs on both.
+//! Tables are created with bytewise key order when the env is built, and dbs are named by their
+//! [`DbName::NAME`](crate::DbName::NAME), without the nul in the file. libmdbx grows the map by itself, see
+//! [`DatabaseOptions`](libmdbx::DatabaseOptions) for the geometry.
+
+use std::{borrow::Cow, path::Path};
+use culpa::{throw, throws};
+use libmdbx::{Database, DatabaseOptions, NoWriteMap, RO, RW, TableFlags, WriteFlags};
+use crate::{DbName, Error, backend::{Backend, ReadTxn, WriteTxn, Entries}};
+pub use libmdbx;
+
+pub struct MdbxEnv {
+	db: Database<NoWriteMap>,
+	names: Vec<&'static [u8]>,
+}
+
+pub struct MdbxEnvBuilder {
+	names: Vec<&'static [u8]>,
+	options: DatabaseOptions,
+}
+
+/// a read transaction of an [`MdbxEnv`]
+pub struct MdbxRoTxn<'env> {
+	env: &'env MdbxEnv,
+	tx: libmdbx::Transaction<'env, RO, NoWriteMap>,
+}
+
+/// the write transaction of an [`MdbxEnv`]
+pub struct MdbxRwTxn<'env> {
+	env: &'env MdbxEnv,
+	tx: libmdbx::Transaction<'env, RW, NoWriteMap>,
+}
+
+impl MdbxEnv {
+	pub fn builder() -> MdbxEnvBuilder { MdbxEnvBuilder { names: Vec::new(), options: DatabaseOptions::default() } }
+
+	/// escape hatch for what the backend traits don't cover
+	pub fn database(&self) -> &Database<NoWriteMap> { &self.db }
+
+	// the table's name in the file, if the db is registered
+	#[throws]
+	fn table_name<'n>(&self, db: &'n [u8]) -> &'n str {
+		if !self.names.contains(&db) { throw!(Error::UnregisteredDb(crate::db_name_lossy(db))) }
+		match db.strip_suffix(b"\0").map(std::str::from_utf8) {
+			Some(Ok(name)) if !name.contains('\0') => name,
+			_ => throw!(libmdbx::Error::InvalidArgument("libmdbx table names are UTF-8, without a nul inside")),
+		}
+	}
+}
+
+impl MdbxEnvBuilder {
+	/// register a db, like [`EnvBuilder::with`](crate::env::EnvBuilder::with)
+	#[must_use]
+	pub fn with<N: DbName>(mut self) -> Self {
+		self.names.push(N::NAME);
+		self
+	}
+
+	/// options to open libmdbx with, `max_tables` defaults to the number of registered dbs
+	#[must_use]
+	pub fn options(mut self, options: DatabaseOptions) -> Self {
+		self.options = options;
+		self
+	}
+
+	/// open or create the env in the directory at `path`, creating the registered tables that aren't there yet
+	#[throws]
+	pub fn build(self, path: &Path) -> MdbxEnv {
+		let Self { names, mut options } = self;
+		options.max_tables.get_or_insert(names.len() as u64);
+		let env = MdbxEnv { db: Database::open_with_options(path, options)?, names };
+		let tx = env.db.begin_rw_txn()?;
+		for &db in &env.names {
+			tx.create_table(Some(env.table_name(db)?), TableFlags::empty())?;
+		}
+		tx.commit()?;
+		env
+	}
+}
+
+impl Backend for MdbxEnv {
+	type Read<'b> = MdbxRoTxn<'b>;
+	type Write<'b> = MdbxRwTxn<'b>;
+
+	#[expect(unused_braces)]
+	#[throws]
+	fn begin_read(&self) -> MdbxRoTxn<'_> { MdbxRoTxn { env: self, tx: self.db.begin_ro_txn()? } }
+
+	#[expect(unused_braces)]
+	#[throws]
+	fn begin_write(&self) -> MdbxRwTxn<'_> { MdbxRwTxn { env: self, tx: self.db.begin_rw_txn()? } }
+}
+
+// both transaction kinds read the same way, read transactions borrow values from the map and write ones copy them
+macro_rules! read_txn {
+	($txn:ident) => {
+		impl ReadTxn for $txn<'_> {
+			#[throws]
+			fn fetch(&self, db: &[u8], key: &[u8]) -> Option<Cow<'_, [u8]>> {
+				let table = self.tx.open_table(Some(self.env.table_name(db)?))?;
+				self.tx.get(&table, key)?
+			}
+
+			#[throws]
+			fn iter_from(&self, db: &[u8], from: &[u8]) -> Entries<'_> {
+				let table = self.tx.open_table(Some(self.env.table_name(db)?))?;
+				let cursor = self.tx.cursor(&table)?;
+				let entries = if from.is_empty() { cursor.into_iter_start() } else { cursor.into_iter_from(from) };
+				Box::new(entries.map(|entry| entry.map_err(Error::from)))
+			}
+		}
+	};
+}
+
+read_txn!(MdbxRoTxn);
+read_txn!(MdbxRwTxn);
+
+impl WriteTxn for MdbxRwTxn<'_> {
+	#[throws]
+	fn put(&mut self, db: &[u8], key: &[u8], value: &[u8]) {
+		let table = self.tx.open_table(Some(self.env.table_name(db)?))?;
+		self.tx.put(&table, key, value, WriteFlags::empty())?;
+	}
+
+	#[throws]
+	fn delete(&mut self, db: &[u8], key: &[u8]) -> bool {
+		let table = self.tx.open_table(Some(self.env.table_name(db)?))?;
+		self.tx.del(&table, key, None)?
+	}
+
+	#[throws]
+	fn clear(&mut self, db: &[u8]) {
+		let table = self.tx.open_table(Some(self.env.table_name(db)?))?;
+		self.tx.clear_table(&table)?;
+	}
+
+	#[throws]
+	fn finish(self) { self.tx.commit()?; }
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, RawTable, Transaction, TempDir, backend::{Backend, ReadTxn, WriteTxn}};
+	use super::MdbxEnv;
+
+	struct Users;
+
+	impl DbName for Users {
+		type Table<'tx, 'env: 'tx, TX: Transaction<'env> + 'tx> = RawTable<'tx, TX>;
+		const NAME: &'static [u8] = b"users\0";
+	}
+
+	fn entries(tx: &impl ReadTxn, from: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+		tx.iter_from(Users::NAME, from).unwrap().map(|entry| { let (key, value) = entry.unwrap(); (key.into_owned(), value.into_owned()) }).collect()
+	}
+
+	#[test]
+	fn put_and_read() {
+		let dir = TempDir::new("batadase-mdbx").unwrap();
+		let env = MdbxEnv::builder().with::<Users>().build(&dir.0).unwrap();
+		let mut tx = env.begin_write().unwrap();
+		tx.put(Users::NAME, b"b", b"2").unwrap();
+		tx.put(Users::NAME, b"a", b"1").unwrap();
+		tx.put(Users::NAME, b"c", b"3").unwrap();
+		assert!(tx.delete(Users::NAME, b"c").unwrap());
+		assert!(!tx.delete(Users::NAME, b"c").unwrap());
+		tx.finish().unwrap();
+
+		let before = env.begin_read().unwrap();
+		let mut tx = env.begin_write().unwrap();
+		tx.clear(Users::NAME).unwrap();
+		tx.finish().unwrap();
+
+		assert_eq!(before.fetch(Users::NAME, b"a").unwrap().as_deref(), Some(&b"1"[..]));
+		assert_eq!(entries(&before, b""), [(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), b"2".to_vec())]);
+		assert_eq!(entries(&before, b"aa"), [(b"b".to_vec(), b"2".to_vec())]);
+		assert_eq!(entries(&env.begin_read().unwrap(), b""), []);
+	}
+
+	#[test]
+	fn reopened() {
+		let dir = TempDir::new("batadase-mdbx").unwrap();
+		let env = MdbxEnv::builder().with::<Users>().build(&dir.0).unwrap();
+		let mut tx = env.begin_write().unwrap();
+		tx.put(Users::NAME, b"a", b"1").unwrap();
+		tx.finish().unwrap();
+		drop(env);
+
+		let env = MdbxEnv::builder().with::<Users>().build(&dir.0).unwrap();
+		assert_eq!(env.begin_read().unwrap().fetch(Users::NAME, b"a").unwrap().as_deref(), Some(&b"1"[..]));
+	}
+
+	#[test]
+	fn unregistered_db() {
+		let dir = TempDir::new("batadase-mdbx").unwrap();
+		let env = MdbxEnv::builder().with::<Users>().build(&dir.0).unwrap();
+		let mut tx = env.begin_write().unwrap();
+		assert!(matches!(tx.put(b"other\0", b"a", b"1"), Err(crate::Error::UnregisteredDb(_))));
+		assert!(matches!(tx.fetch(b"other\0", b"a"), Err(crate::Error::UnregisteredDb(_))));
+	}
+}
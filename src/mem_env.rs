@@ -0,0 +1,198 @@
+//! An in-memory [`Backend`] over BTreeMaps, for unit tests of logic written against the [`backend`](crate::backend)
+//! traits, without a filesystem:
+//! ```ignore
+//! let env = MemEnv::new().with::<Users>();
+//! rename_user(&env, b"42", b"ferris")?;
+//! assert_eq!(env.begin_read()?.fetch(Users::NAME, b"42")?.as_deref(), Some(&b"ferris"[..]));
+//! ```
+//! Like LMDB, readers see the commit they began from and there's one writer at a time. A commit is a map of each db's
+//! BTreeMap behind an `Arc`: the write transaction starts from the last one and copies a db the first time it writes
+//! to it, so readers never see a write, dropping the transaction drops the copies and finishing swaps them in.
+//! Keys are in bytewise order, the env's key flags and per-table checks, quotas and changelog don't apply.
+//!
+//! It isn't a [`Transaction`](crate::Transaction) and has no typed tables: those are built on LMDB's handles and hand
+//! out references into its map, so logic using them is tested on an
+//! [`EnvBuilder::build_temp`](crate::env::EnvBuilder::build_temp) env instead, which only needs a temp directory.
+
+use std::{borrow::Cow, collections::{BTreeMap, HashMap}, sync::{Arc, Mutex, MutexGuard, PoisonError}};
+use culpa::{throw, throws};
+use crate::{DbName, Error, backend::{Backend, ReadTxn, WriteTxn, Entries}};
+
+type Db = BTreeMap<Vec<u8>, Vec<u8>>;
+type Dbs = HashMap<Vec<u8>, Arc<Db>>;
+
+#[derive(Default)]
+pub struct MemEnv {
+	committed: Mutex<Arc<Dbs>>,
+	writer: Mutex<()>,
+}
+
+/// a read transaction of a [`MemEnv`], holding on to the commit it began from
+pub struct MemRoTxn {
+	dbs: Arc<Dbs>,
+}
+
+/// the write transaction of a [`MemEnv`]
+pub struct MemRwTxn<'env> {
+	env: &'env MemEnv,
+	dbs: Dbs,
+	_writer: MutexGuard<'env, ()>,
+}
+
+impl MemEnv {
+	pub fn new() -> Self { Self::default() }
+
+	/// register a db, like [`EnvBuilder::with`](crate::env::EnvBuilder::with)
+	#[must_use]
+	pub fn with<N: DbName>(mut self) -> Self {
+		Arc::make_mut(self.committed.get_mut().unwrap_or_else(PoisonError::into_inner)).entry(N::NAME.to_vec()).or_default();
+		self
+	}
+}
+
+impl Backend for MemEnv {
+	type Read<'b> = MemRoTxn;
+	type Write<'b> = MemRwTxn<'b>;
+
+	#[expect(unused_braces)]
+	#[throws]
+	fn begin_read(&self) -> MemRoTxn { MemRoTxn { dbs: self.committed.lock().unwrap_or_else(PoisonError::into_inner).clone() } }
+
+	#[throws]
+	fn begin_write(&self) -> MemRwTxn<'_> {
+		// a writer that panicked left nothing half done, commits are swapped in whole
+		let writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+		// cloning the map only clones the Arcs of its dbs
+		let dbs = Dbs::clone(&self.committed.lock().unwrap_or_else(PoisonError::into_inner));
+		MemRwTxn { env: self, dbs, _writer: writer }
+	}
+}
+
+impl ReadTxn for MemRoTxn {
+	fn fetch(&self, db: &[u8], key: &[u8]) -> Result<Option<Cow<'_, [u8]>>, Error> { fetch(&self.dbs, db, key) }
+	fn iter_from(&self, db: &[u8], from: &[u8]) -> Result<Entries<'_>, Error> { iter_from(&self.dbs, db, from) }
+}
+
+impl ReadTxn for MemRwTxn<'_> {
+	fn fetch(&self, db: &[u8], key: &[u8]) -> Result<Option<Cow<'_, [u8]>>, Error> { fetch(&self.dbs, db, key) }
+	fn iter_from(&self, db: &[u8], from: &[u8]) -> Result<Entries<'_>, Error> { iter_from(&self.dbs, db, from) }
+}
+
+impl WriteTxn for MemRwTxn<'_> {
+	#[throws]
+	fn put(&mut self, db: &[u8], key: &[u8], value: &[u8]) { self.db_mut(db)?.insert(key.to_vec(), value.to_vec()); }
+
+	#[expect(unused_braces)]
+	#[throws]
+	fn delete(&mut self, db: &[u8], key: &[u8]) -> bool { self.db_mut(db)?.remove(key).is_some() }
+
+	#[throws]
+	fn clear(&mut self, db: &[u8]) {
+		// a fresh map instead of copying the committed one just to empty it
+		*self.dbs.get_mut(db).ok_or_else(|| unregistered(db))? = Arc::default();
+	}
+
+	#[throws]
+	fn finish(self) { *self.env.committed.lock().unwrap_or_else(PoisonError::into_inner) = Arc::new(self.dbs); }
+}
+
+impl MemRwTxn<'_> {
+	// the db to write to, copied on the transaction's first write to it
+	#[throws]
+	fn db_mut(&mut self, db: &[u8]) -> &mut Db {
+		let Some(entries) = self.dbs.get_mut(db) else { throw!(unregistered(db)) };
+		Arc::make_mut(entries)
+	}
+}
+
+fn unregistered(db: &[u8]) -> Error { Error::UnregisteredDb(crate::db_name_lossy(db)) }
+
+#[throws]
+fn fetch<'tx>(dbs: &'tx Dbs, db: &[u8], key: &[u8]) -> Option<Cow<'tx, [u8]>> {
+	let Some(entries) = dbs.get(db) else { throw!(unregistered(db)) };
+	entries.get(key).map(|value| Cow::Borrowed(value.as_slice()))
+}
+
+#[throws]
+fn iter_from<'tx>(dbs: &'tx Dbs, db: &[u8], from: &[u8]) -> Entries<'tx> {
+	let Some(entries) = dbs.get(db) else { throw!(unregistered(db)) };
+	Box::new(entries.range(from.to_vec()..).map(|(key, value)| Ok((Cow::Borrowed(key.as_slice()), Cow::Borrowed(value.as_slice())))))
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, Env, RawTable, Transaction, backend::{Backend, ReadTxn, WriteTxn}};
+	use super::MemEnv;
+
+	struct Users;
+
+	impl DbName for Users {
+		type Table<'tx, 'env: 'tx, TX: Transaction<'env> + 'tx> = RawTable<'tx, TX>;
+		const NAME: &'static [u8] = b"users\0";
+	}
+
+	// what a reader sees, in key order
+	fn entries(tx: &impl ReadTxn) -> Vec<(Vec<u8>, Vec<u8>)> {
+		tx.iter_from(Users::NAME, b"").unwrap().map(|entry| { let (key, value) = entry.unwrap(); (key.into_owned(), value.into_owned()) }).collect()
+	}
+
+	fn put_and_read<B: Backend>(backend: &B) {
+		let mut tx = backend.begin_write().unwrap();
+		tx.put(Users::NAME, b"b", b"2").unwrap();
+		tx.put(Users::NAME, b"a", b"1").unwrap();
+		tx.put(Users::NAME, b"c", b"3").unwrap();
+		assert!(tx.delete(Users::NAME, b"c").unwrap());
+		assert!(!tx.delete(Users::NAME, b"c").unwrap());
+		tx.finish().unwrap();
+
+		let tx = backend.begin_read().unwrap();
+		assert_eq!(tx.fetch(Users::NAME, b"a").unwrap().as_deref(), Some(&b"1"[..]));
+		assert_eq!(tx.fetch(Users::NAME, b"c").unwrap(), None);
+		assert_eq!(entries(&tx), [(b"a".to_vec(), b"1".to_vec()), (b"b".to_vec(), b"2".to_vec())]);
+		let from: Vec<_> = tx.iter_from(Users::NAME, b"aa").unwrap().map(|entry| entry.unwrap().0.into_owned()).collect();
+		assert_eq!(from, [b"b".to_vec()]);
+	}
+
+	#[test]
+	fn same_as_lmdb() {
+		put_and_read(&MemEnv::new().with::<Users>());
+		put_and_read(&*Env::builder().unwrap().with::<Users>().build_temp().unwrap());
+	}
+
+	#[test]
+	fn readers_keep_their_snapshot() {
+		let env = MemEnv::new().with::<Users>();
+		let mut tx = env.begin_write().unwrap();
+		tx.put(Users::NAME, b"a", b"1").unwrap();
+		tx.finish().unwrap();
+
+		let before = env.begin_read().unwrap();
+		let mut tx = env.begin_write().unwrap();
+		tx.put(Users::NAME, b"a", b"2").unwrap();
+		tx.clear(Users::NAME).unwrap();
+		tx.put(Users::NAME, b"b", b"3").unwrap();
+		assert_eq!(entries(&tx), [(b"b".to_vec(), b"3".to_vec())]);
+		assert_eq!(entries(&env.begin_read().unwrap()), [(b"a".to_vec(), b"1".to_vec())]);
+		tx.finish().unwrap();
+
+		assert_eq!(entries(&before), [(b"a".to_vec(), b"1".to_vec())]);
+		assert_eq!(entries(&env.begin_read().unwrap()), [(b"b".to_vec(), b"3".to_vec())]);
+	}
+
+	#[test]
+	fn dropped_writes_are_discarded() {
+		let env = MemEnv::new().with::<Users>();
+		let mut tx = env.begin_write().unwrap();
+		tx.put(Users::NAME, b"a", b"1").unwrap();
+		drop(tx);
+		assert_eq!(entries(&env.begin_read().unwrap()), []);
+	}
+
+	#[test]
+	fn unregistered_db() {
+		let env = MemEnv::new().with::<Users>();
+		let mut tx = env.begin_write().unwrap();
+		assert!(matches!(tx.put(b"other\0", b"a", b"1"), Err(crate::Error::UnregisteredDb(_))));
+		assert!(matches!(tx.fetch(b"other\0", b"a"), Err(crate::Error::UnregisteredDb(_))));
+	}
+}
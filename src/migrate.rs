@@ -0,0 +1,90 @@
+//! Schema migrations, the applied version is tracked as `MetaField::Version` in the [`Meta`] table.
+//! Remember to register `Meta` with `EnvBuilder::with::<Meta>()`.
+//!
+//! ```ignore
+//! static MIGRATIONS: &[Migration] = &[
+//!     Migration { version: 1, name: "split names", run: split_names },
+//!     Migration { version: 2, name: "drop legacy sessions", run: |tx| tx.get::<LegacySessions>().clear() },
+//! ];
+//!
+//! batadase::migrate::run_pending(&ENV, MIGRATIONS).await?;
+//! ```
+
+use culpa::{throw, throws};
+use crate::{Transaction, RwTxn, Env, Error, DbName, Meta, MetaField};
+
+pub struct Migration {
+	pub version: u64, // migrations are applied in order of version, which has to be > 0
+	pub name: &'static str,
+	pub run: fn(&RwTxn) -> Result<(), Error>,
+}
+
+fn ensure_meta(env: &Env) -> Result<(), Error> {
+	if env.db(Meta::NAME).is_none() { return Err(Error::UnknownDb(crate::db_name_lossy(Meta::NAME))); }
+	Ok(())
+}
+
+/// currently applied version, 0 if nothing was ever applied
+#[throws]
+pub fn version<'env>(tx: &impl Transaction<'env>) -> u64 {
+	ensure_meta(tx.env())?;
+	tx.get::<Meta>().get::<u64>(&MetaField::Version)?.map_or(0, |x| x.to_native())
+}
+
+#[throws]
+fn set_version(tx: &RwTxn, version: u64) {
+	tx.get::<Meta>().put(&MetaField::Version, &version)?;
+}
+
+/// migrations newer than the applied version, in the order they'd be applied
+#[throws]
+pub fn pending<'env, 'm>(tx: &impl Transaction<'env>, migrations: &'m [Migration]) -> Vec<&'m Migration> {
+	let version = version(tx)?;
+	let mut pending = migrations.iter().filter(|x| x.version > version).collect::<Vec<_>>();
+	pending.sort_by_key(|x| x.version);
+	pending
+}
+
+/// Apply pending migrations, each in its own write transaction along with the version bump.
+/// Stops at the first failing migration, leaving the ones before it applied.
+/// Returns versions that were applied.
+#[throws]
+pub async fn run_pending(env: &'static Env, migrations: &'static [Migration]) -> Vec<u64> {
+	let pending = pending(&env.read_tx()?, migrations)?;
+	let mut applied = Vec::with_capacity(pending.len());
+	for migration in pending {
+		log::info!("applying migration {} {}", migration.version, migration.name);
+		env.try_write(move |tx| {
+			(migration.run)(tx)?;
+			set_version(tx, migration.version)
+		}).await??;
+		applied.push(migration.version);
+	}
+	applied
+}
+
+#[throws]
+fn try_all(tx: &RwTxn, migrations: &[Migration]) -> Vec<u64> {
+	let mut applied = Vec::new();
+	for migration in pending(tx, migrations)? {
+		log::info!("trying migration {} {}", migration.version, migration.name);
+		(migration.run)(tx)?;
+		set_version(tx, migration.version)?;
+		applied.push(migration.version);
+	}
+	applied
+}
+
+/// Run all pending migrations in a single write transaction which is then aborted,
+/// to see whether they succeed without changing anything.
+/// Returns versions that would be applied.
+#[throws]
+pub async fn dry_run(env: &'static Env, migrations: &'static [Migration]) -> Vec<u64> {
+	// the job always fails so try_write aborts, the outcome is smuggled out in the error
+	let outcome = env.try_write(move |tx| Err::<(), _>(try_all(tx, migrations))).await?;
+	match outcome {
+		Ok(()) => unreachable!(),
+		Err(Ok(applied)) => applied,
+		Err(Err(e)) => throw!(e),
+	}
+}
@@ -0,0 +1,151 @@
+//! Outgoing messages recorded in the same transaction as the writes they're about, for a relay to send on afterwards -
+//! so a message is sent if and only if its writes committed, without two-phase commit against the broker:
+//! ```ignore
+//! #[derive(DbName)]
+//! #[table(OutboxTable<'tx, TX, Event>)]
+//! struct Outbox;
+//!
+//! ENV.try_write(move |tx| {
+//!     tx.get::<Orders>().put(&id, &order)?;
+//!     tx.get::<Outbox>().push(&Event::OrderPlaced(id))?;
+//!     Ok::<_, batadase::Error>(())
+//! }).await??;
+//!
+//! // the relay
+//! loop {
+//!     let sent = ENV.read_tx()?.get::<Outbox>().poll_batch(100)?.into_iter().map(|(id, event)| broker.publish(id, event)).collect::<Result<Vec<_>, _>>()?;
+//!     if let Some(&last) = sent.last() { ENV.write(move |tx| tx.get::<Outbox>().mark_sent_through(last)).await??; }
+//! }
+//! ```
+//! Messages get ids counting up from 0 in commit order, never the same one twice, and are kept until marked sent.
+//! A relay that crashes between sending and marking sends those messages again, so consumers should deduplicate by id.
+
+use culpa::{throw, throws};
+use enumflags2::BitFlag;
+use std::marker::PhantomData;
+use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, Error, lmdb, error::Context};
+
+// entry kinds, first byte of the db's keys
+const MESSAGE: u8 = 0; // MESSAGE and id (big-endian) -> the message
+const NEXT: u8 = 1; // NEXT -> the id the next message gets, big-endian
+
+fn message_key(id: u64) -> Vec<u8> { [&[MESSAGE][..], &id.to_be_bytes()].concat() }
+
+pub struct OutboxTable<'tx, TX, M> {
+	tx: &'tx TX,
+	dbi: lmdb_sys::MDB_dbi,
+	_pd: PhantomData<M>,
+	_borrow: crate::borrows::Borrow,
+}
+
+impl<'tx, 'env: 'tx, TX, M> Table<'tx, 'env, TX> for OutboxTable<'tx, TX, M> where
+	TX: Transaction<'env>,
+	M: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<M>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.dbi }
+	fn txn(&self) -> &TX { self.tx }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
+		Self::build(tx, tx.env().db(name).expect("table isn't registered in the env, add it with EnvBuilder::with"))
+	}
+
+	#[throws]
+	fn check_entry(key: &[u8], value: &[u8]) {
+		match key {
+			[MESSAGE, rest @ ..] if rest.len() == 8 => { rkyv::access::<rkyv::Archived<M>, rkyv::rancor::Error>(value)?; },
+			[NEXT] if value.len() == 8 => {},
+			_ => throw!(Error::InvalidEntry("not an outbox entry")),
+		}
+	}
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, M> OutboxTable<'tx, RwTxn<'tx>, M> where
+	M: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<M>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+{
+	/// record `message` to be sent once the transaction commits, returns its id
+	#[throws]
+	pub fn push(&self, message: &M) -> u64 {
+		let id = self.next_id()?;
+		let mut key = message_key(id);
+		lmdb::put(self.tx, self.dbi, &mut key, &mut rkyv::to_bytes(message)?, lmdb::PutFlags::empty()).context(self.tx, self.dbi, "push", Some(&key))?;
+		lmdb::put(self.tx, self.dbi, &mut [NEXT], &mut (id + 1).to_be_bytes(), lmdb::PutFlags::empty()).context(self.tx, self.dbi, "push", Some(&[NEXT]))?;
+		id
+	}
+
+	/// drop message `id`, false if there was none - it was marked sent before
+	#[throws]
+	pub fn mark_sent(&self, id: u64) -> bool {
+		let mut key = message_key(id);
+		lmdb::del(self.tx, self.dbi, &mut key).context(self.tx, self.dbi, "mark_sent", Some(&key))?
+	}
+
+	/// drop every message up to and including `id`, returns how many there were
+	#[throws]
+	pub fn mark_sent_through(&self, id: u64) -> usize {
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "mark_sent_through", None)?;
+		let mut entry = cursor.get_with_key(&mut [MESSAGE], lmdb::CursorOp::SetRange)?;
+		let mut sent = 0;
+		while let Some((key, _)) = entry && key.len() == 9 && key[0] == MESSAGE && key[1..] <= id.to_be_bytes()[..] {
+			let key = key.to_vec(); // the slice goes stale with the delete
+			cursor.del_current().context(self.tx, self.dbi, "mark_sent_through", Some(&key))?;
+			sent += 1;
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+		sent
+	}
+
+	/// Drop every message, sent or not. Ids carry on from where they were.
+	#[throws]
+	pub fn clear(&self) { self.mark_sent_through(u64::MAX)?; }
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, M> OutboxTable<'tx, TX, M> where
+	TX: Transaction<'env>,
+	M: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<M>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + 'tx,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { tx, dbi, _pd: PhantomData, _borrow: crate::borrows::Borrow::of(tx) } }
+
+	/// the id the next message pushed gets
+	#[throws]
+	pub fn next_id(&self) -> u64 {
+		match lmdb::get(self.tx, self.dbi, &mut [NEXT]).context(self.tx, self.dbi, "next_id", Some(&[NEXT]))? {
+			Some(next) => u64::from_be_bytes(next.try_into().map_err(|_| Error::InvalidEntry("not an outbox entry"))?),
+			None => 0,
+		}
+	}
+
+	/// up to `max` of the messages not marked sent yet, oldest first
+	#[throws]
+	pub fn poll_batch(&self, max: usize) -> Vec<(u64, &'tx rkyv::Archived<M>)> {
+		self.pending()?.take(max).collect::<Result<_, _>>()?
+	}
+
+	/// every message not marked sent yet, oldest first
+	#[throws]
+	pub fn pending(&self) -> impl Iterator<Item = Result<(u64, &'tx rkyv::Archived<M>), Error>> + use<'tx, 'env, TX, M> {
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "pending", None)?;
+		let mut entry = cursor.get_with_key(&mut [MESSAGE], lmdb::CursorOp::SetRange)?;
+		std::iter::from_fn(move || {
+			let (key, value) = entry.take()?;
+			let [MESSAGE, id @ ..] = key else { return None };
+			entry = match cursor.get(lmdb::CursorOp::Next) { Ok(next) => next, Err(e) => return Some(Err(e.into())) };
+			let id = u64::from_be_bytes(id.try_into().ok()?);
+			Some(rkyv::access::<rkyv::Archived<M>, rkyv::rancor::Error>(value).map(|message| (id, message)).map_err(Error::from))
+		})
+	}
+
+	/// how many messages aren't marked sent yet
+	#[throws]
+	pub fn len(&self) -> usize {
+		let entries = self.entries()?;
+		if lmdb::get(self.tx, self.dbi, &mut [NEXT]).context(self.tx, self.dbi, "len", Some(&[NEXT]))?.is_some() { entries - 1 } else { entries }
+	}
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn is_empty(&self) -> bool { self.len()? == 0 }
+}
@@ -0,0 +1,75 @@
+//! The read-plan/write-apply pattern for keeping write transactions short: the expensive part of a change is computed
+//! in read transactions, in parallel, and only applying it takes the write transaction.
+//! ```ignore
+//! let merged = ENV.pipeline()
+//!     .read(|tx| duplicates(&tx.get::<Users>()))  // each read on a thread with a read transaction of its own
+//!     .read(|tx| order_counts(&tx.get::<Orders>()))
+//!     .write(|tx, (duplicates, counts)| {          // once they're all done, with what they returned
+//!         for (keep, drop) in duplicates { merge_users(tx, keep, drop, &counts)? }
+//!         Ok(duplicates.len())
+//!     })?; // committed if the write returned Ok
+//! ```
+//! Reads begin at about the same time but each gets its own snapshot, and writes committing before the write
+//! transaction begins aren't in any of them - the write should check what its plan depends on if that matters, which is
+//! cheap next to computing the plan. Reads return owned values since their transactions end before the write begins.
+//! The first read to fail fails the pipeline, once the others are done. Runs its own write transaction, so it blocks
+//! while another one is open - don't run a pipeline from inside one.
+
+use culpa::throws;
+use crate::{Env, RoTxn, RwTxn, Transaction, Error};
+
+/// see [`Env::pipeline`], the reads are a tuple of up to 8 closures
+pub struct Pipeline<'env, R> {
+	env: &'env Env,
+	reads: R,
+}
+
+impl<'env> Pipeline<'env, ()> {
+	pub(crate) fn new(env: &'env Env) -> Self { Self { env, reads: () } }
+}
+
+macro_rules! phases {
+	($($read:ident $out:ident),*) => {
+		impl<'env, $($read, $out),*> Pipeline<'env, ($($read,)*)> where
+			$($read: FnOnce(&RoTxn<'env>) -> Result<$out, Error> + Send, $out: Send,)*
+		{
+			/// add a read to run in parallel with the others, its output goes into the write's tuple in this position
+			#[allow(non_snake_case)]
+			pub fn read<F, T>(self, read: F) -> Pipeline<'env, ($($read,)* F,)> where
+				F: FnOnce(&RoTxn<'env>) -> Result<T, Error> + Send,
+				T: Send,
+			{
+				let ($($read,)*) = self.reads;
+				Pipeline { env: self.env, reads: ($($read,)* read,) }
+			}
+
+			/// Run the reads, then `write` with their outputs in a write transaction that's committed if it returns Ok.
+			#[allow(non_snake_case, unused_variables)] // the scope's unused without reads
+			#[throws]
+			pub fn write<W>(self, write: impl FnOnce(&RwTxn<'env>, ($($out,)*)) -> Result<W, Error>) -> W {
+				let env = self.env;
+				let ($($read,)*) = self.reads;
+				let outputs = std::thread::scope(|scope| {
+					$(let $read = scope.spawn(move || $read(&env.read_tx()?));)*
+					// joined before returning the first error, so a failed read doesn't leave the others running
+					let ($($out,)*) = ($($read.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)),)*);
+					Ok::<_, Error>(($($out?,)*))
+				})?;
+				let tx = env.write_tx()?;
+				let res = write(&tx, outputs)?;
+				tx.commit()?;
+				res
+			}
+		}
+	};
+}
+
+phases!();
+phases!(R1 T1);
+phases!(R1 T1, R2 T2);
+phases!(R1 T1, R2 T2, R3 T3);
+phases!(R1 T1, R2 T2, R3 T3, R4 T4);
+phases!(R1 T1, R2 T2, R3 T3, R4 T4, R5 T5);
+phases!(R1 T1, R2 T2, R3 T3, R4 T4, R5 T5, R6 T6);
+phases!(R1 T1, R2 T2, R3 T3, R4 T4, R5 T5, R6 T6, R7 T7);
+phases!(R1 T1, R2 T2, R3 T3, R4 T4, R5 T5, R6 T6, R7 T7, R8 T8);
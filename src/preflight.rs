@@ -0,0 +1,131 @@
+//! Checks of an env's directory before opening it, see [`Env::preflight`](crate::Env::preflight). LMDB's open fails
+//! with a bare errno - EACCES, EINVAL, ENOENT - for anything from a missing directory to a data file of another
+//! architecture, these say which it is:
+//! ```ignore
+//! let report = Env::preflight(path, MAPSIZE)?;
+//! for problem in &report.problems { log::warn!("{problem}"); }
+//! if !report.is_ok() { anyhow::bail!("can't open the env in {}", path.display()); }
+//! ```
+//! They assume a read-write open by this process' user, and only look - nothing's created or locked.
+
+use culpa::throws;
+use std::path::{Path, PathBuf};
+use crate::Error;
+
+const MAGIC: u32 = 0xBEEF_C0DE;
+const DATA_VERSION: u32 = 1;
+
+const W: usize = std::mem::size_of::<usize>();
+const PAGE_HEADER: usize = W + 8; // mp_pgno, mp_pad, mp_flags, mp_lower, mp_upper
+const VERSION: usize = PAGE_HEADER + 4; // after mm_magic
+const MAPSIZE: usize = PAGE_HEADER + 8 + W; // after mm_address
+const PSIZE: usize = PAGE_HEADER + 8 + 2 * W; // mm_dbs[0].md_pad, the free db's pad holds the page size
+
+#[derive(thiserror::Error, Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+	#[error("{0} doesn't exist, LMDB only creates the files in it - create the directory first")] DirMissing(PathBuf),
+	#[error("{0} isn't a directory")] NotADirectory(PathBuf),
+	#[error("{0} isn't readable and writable by this user, check its owner and permissions")] NoAccess(PathBuf),
+	#[error("{path} can't be read: {reason}")] Unreadable { path: PathBuf, reason: String },
+	#[error("{0} isn't an LMDB data file, or was written on a machine of another endianness or word size")] NotAnEnv(PathBuf),
+	#[error("{path} has data format version {found}, this LMDB reads version {DATA_VERSION}")] VersionMismatch { path: PathBuf, found: u32 },
+	#[error("{path} has pages of {found} bytes and this system's page size is {system}, it opens but may not perform as well")] PageSize { path: PathBuf, found: u32, system: usize },
+	#[error("the map can grow {needed} bytes past the data file, but only {free} bytes are free on its filesystem - writes may fail with no disk space")] DiskSpace { needed: u64, free: u64 },
+}
+
+impl Problem {
+	/// whether opening is going to fail, rather than the env opening with a catch
+	pub fn is_fatal(&self) -> bool { !matches!(self, Self::PageSize { .. } | Self::DiskSpace { .. }) }
+}
+
+/// what data.mdb's first meta page says
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DataFile {
+	pub size: u64,
+	pub page_size: u32,
+	/// the mapsize it was last opened with
+	pub mapsize: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+	/// None if there's no data.mdb yet, or it's unreadable
+	pub data: Option<DataFile>,
+	pub lock_exists: bool,
+	/// bytes free for this user on the directory's filesystem
+	pub free_bytes: Option<u64>,
+	pub problems: Vec<Problem>,
+}
+
+impl Report {
+	/// whether opening should work, maybe with warnings
+	pub fn is_ok(&self) -> bool { !self.problems.iter().any(Problem::is_fatal) }
+}
+
+#[throws]
+pub(crate) fn check(path: &Path, mapsize: usize) -> Report {
+	let mut report = Report::default();
+	match std::fs::metadata(path) {
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => { report.problems.push(Problem::DirMissing(path.to_owned())); return report; },
+		Err(e) => { report.problems.push(Problem::Unreadable { path: path.to_owned(), reason: e.to_string() }); return report; },
+		Ok(meta) if !meta.is_dir() => { report.problems.push(Problem::NotADirectory(path.to_owned())); return report; },
+		Ok(_) => {},
+	}
+	if !accessible(path)? { report.problems.push(Problem::NoAccess(path.to_owned())); }
+
+	let lock = path.join("lock.mdb");
+	report.lock_exists = lock.exists();
+	if report.lock_exists && !accessible(&lock)? { report.problems.push(Problem::NoAccess(lock)); }
+
+	let data = path.join("data.mdb");
+	if data.exists() {
+		if !accessible(&data)? { report.problems.push(Problem::NoAccess(data.clone())); }
+		match read_meta(&data) {
+			Ok(Ok(file)) => report.data = Some(file),
+			Ok(Err(problem)) => report.problems.push(problem),
+			Err(e) => report.problems.push(Problem::Unreadable { path: data.clone(), reason: e.to_string() }),
+		}
+	}
+	if let Some(file) = report.data {
+		let system = page_size();
+		if file.page_size as usize != system { report.problems.push(Problem::PageSize { path: data, found: file.page_size, system }); }
+	}
+
+	report.free_bytes = free_bytes(path)?;
+	// the map is at least as big as the file, LMDB raises a smaller mapsize to the one last used
+	let map = mapsize.max(report.data.map_or(0, |x| x.mapsize)) as u64;
+	let needed = map.saturating_sub(report.data.map_or(0, |x| x.size));
+	if let Some(free) = report.free_bytes && needed > free { report.problems.push(Problem::DiskSpace { needed, free }); }
+	report
+}
+
+#[throws(std::io::Error)]
+fn accessible(path: &Path) -> bool {
+	let path = crate::path_to_cstring(path)?;
+	unsafe { libc::access(path.as_ptr(), libc::R_OK | libc::W_OK) == 0 }
+}
+
+#[throws(std::io::Error)]
+fn free_bytes(path: &Path) -> Option<u64> {
+	let path = crate::path_to_cstring(path)?;
+	let mut stat = unsafe { std::mem::zeroed::<libc::statvfs>() };
+	if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 { return None; }
+	#[allow(clippy::useless_conversion)] // the field types differ between platforms
+	Some(u64::from(stat.f_bavail).saturating_mul(u64::from(stat.f_frsize)))
+}
+
+#[throws(std::io::Error)]
+fn read_meta(data: &Path) -> Result<DataFile, Problem> {
+	use std::io::Read;
+
+	let size = std::fs::metadata(data)?.len();
+	let mut meta = [0; PSIZE + 4];
+	if std::fs::File::open(data)?.read_exact(&mut meta).is_err() { return Err(Problem::NotAnEnv(data.to_owned())); }
+	let u32_at = |at: usize| u32::from_ne_bytes(meta[at..at + 4].try_into().expect("4 bytes"));
+	if u32_at(PAGE_HEADER) != MAGIC { return Err(Problem::NotAnEnv(data.to_owned())); }
+	if u32_at(VERSION) != DATA_VERSION { return Err(Problem::VersionMismatch { path: data.to_owned(), found: u32_at(VERSION) }); }
+	let mapsize = usize::from_ne_bytes(meta[MAPSIZE..MAPSIZE + W].try_into().expect("W bytes"));
+	Ok(DataFile { size, page_size: u32_at(PSIZE), mapsize })
+}
+
+fn page_size() -> usize { unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.try_into().unwrap_or(4096) }
@@ -0,0 +1,184 @@
+//! Rate limiting state per key, for workers sharing an env without a separate rate limiting service:
+//! ```no_run
+//! # use std::{sync::LazyLock, time::Duration};
+//! # use batadase::{Env, rate_limit_table::{Decision, Limit, RateLimitTable}, tracked_table};
+//! # type ApiKey = u64;
+//! # fn handle() {}
+//! # fn too_many_requests(_: Duration) {}
+//! batadase::def_env! { Tables { api_limits: ApiLimits => RateLimitTable<'tx, TX, ApiKey> } }
+//! static ENV: LazyLock<Env> = LazyLock::new(|| Tables::builder().unwrap().build(c"db").unwrap());
+//!
+//! const LIMIT: Limit = Limit { rate: 100, per: Duration::from_secs(60), burst: 20 };
+//! # async fn serve(key: ApiKey) -> Result<(), batadase::Error> {
+//! match ENV.write(move |tx| Tables::of(tx).api_limits().check_and_consume(&key, 1, tracked_table::now(), LIMIT)).await?? {
+//!     Decision::Allowed { .. } => handle(),
+//!     Decision::Limited { retry_after } => too_many_requests(retry_after),
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//! A generic cell rate algorithm: each key stores only the time its bucket will be full again, so a check is one read
+//! and at most one write in the caller's transaction, and write transactions being serialized keeps every worker's
+//! view consistent. Times are milliseconds since the unix epoch, like [`TrackedTable`](crate::TrackedTable)'s.
+
+use culpa::throws;
+use std::time::Duration;
+use crate::{AssocTable, Transaction, RwTxn, Table, RkyvSer, RkyvVal, Error, lmdb, error::Context};
+
+/// `rate` tokens refilled every `per`, holding at most `burst` of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+	pub rate: u32,
+	pub per: Duration,
+	pub burst: u32,
+}
+
+impl Limit {
+	/// `rate` a second, all of which can be used at once
+	pub const fn per_second(rate: u32) -> Self { Self { rate, per: Duration::from_secs(1), burst: rate } }
+
+	// nanoseconds it takes to refill a token
+	fn interval(&self) -> u64 { (self.per.as_nanos() / u128::from(self.rate.max(1))).try_into().unwrap_or(u64::MAX).max(1) }
+	// nanoseconds of tokens a full bucket holds
+	fn tolerance(&self) -> u64 { self.interval().saturating_mul(u64::from(self.burst)) }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+	/// the tokens were taken, leaving `remaining`
+	Allowed { remaining: u32 },
+	/// not enough tokens, nothing was taken - there will be after `retry_after`
+	Limited { retry_after: Duration },
+}
+
+impl Decision {
+	pub fn is_allowed(&self) -> bool { matches!(self, Self::Allowed { .. }) }
+}
+
+fn nanos(millis: u64) -> u64 { millis.saturating_mul(1_000_000) }
+
+pub struct RateLimitTable<'tx, TX, K> {
+	// nanoseconds since the epoch at which each key's bucket is full again
+	inner: AssocTable<'tx, TX, K, u64>,
+}
+
+impl<'tx, 'env: 'tx, TX, K> Table<'tx, 'env, TX> for RateLimitTable<'tx, TX, K> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.inner.dbi() }
+	fn txn(&self) -> &TX { self.inner.txn() }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self { Self { inner: Table::build(tx, name) } }
+
+	#[throws]
+	fn check_entry(key: &[u8], value: &[u8]) { AssocTable::<'tx, TX, K, u64>::check_entry(key, value)?; }
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, K> RateLimitTable<'tx, RwTxn<'tx>, K> where
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	/// Take `cost` tokens from `key`'s bucket at `now` if it has them. A cost over `limit.burst` is never allowed.
+	#[throws]
+	pub fn check_and_consume(&self, key: &K, cost: u32, now: u64, limit: Limit) -> Decision {
+		let now = nanos(now);
+		let full_at = self.full_at(key)?.max(now);
+		let new_full_at = full_at.saturating_add(limit.interval().saturating_mul(u64::from(cost)));
+		let allowed_at = new_full_at.saturating_sub(limit.tolerance());
+		if allowed_at > now { return Decision::Limited { retry_after: Duration::from_nanos(allowed_at - now) }; }
+		self.inner.put(key, &new_full_at)?;
+		Decision::Allowed { remaining: (now.saturating_add(limit.tolerance()).saturating_sub(new_full_at) / limit.interval()).try_into().unwrap_or(u32::MAX) }
+	}
+
+	/// refill `key`'s bucket
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn reset(&self, key: &K) -> bool { self.inner.delete(key)? }
+
+	/// Remove the keys whose buckets are full by `now`, which is the same as them not being there. Returns how many were
+	/// removed. Walks the whole table.
+	#[throws]
+	pub fn purge_full(&self, now: u64) -> usize {
+		let (tx, dbi, now) = (self.inner.txn(), self.inner.dbi(), nanos(now));
+		let mut cursor = lmdb::Cursor::open(tx, dbi).context(tx, dbi, "purge_full", None)?;
+		let mut entry = cursor.get(lmdb::CursorOp::First)?;
+		let mut purged = 0;
+		while let Some((key, value)) = entry {
+			let full_at = rkyv::access::<rkyv::Archived<u64>, rkyv::rancor::Error>(value).context(tx, dbi, "purge_full", Some(key))?;
+			if full_at.to_native() <= now {
+				let key = key.to_vec(); // the slice goes stale with the delete
+				cursor.del_current().context(tx, dbi, "purge_full", Some(&key))?;
+				purged += 1;
+			}
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+		purged
+	}
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn clear(&self) { self.inner.clear()? }
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, K> RateLimitTable<'tx, TX, K> where
+	TX: Transaction<'env>,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { inner: AssocTable::build(tx, dbi) } }
+
+	// 0 for keys that aren't there, their buckets are full
+	#[expect(unused_braces)]
+	#[throws]
+	fn full_at(&self, key: &K) -> u64 { self.inner.get(key)?.map_or(0, |x| x.to_native()) }
+
+	/// the tokens `key` has at `now`, without taking any
+	#[throws]
+	pub fn remaining(&self, key: &K, now: u64, limit: Limit) -> u32 {
+		let now = nanos(now);
+		let full_at = self.full_at(key)?.max(now);
+		(now.saturating_add(limit.tolerance()).saturating_sub(full_at) / limit.interval()).try_into().unwrap_or(u32::MAX)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+	use crate::{DbName, Env, Transaction};
+	use super::{Decision, Limit, RateLimitTable};
+
+	struct Limits;
+
+	impl DbName for Limits {
+		type Table<'tx, 'env: 'tx, TX: Transaction<'env> + 'tx> = RateLimitTable<'tx, TX, u64>;
+		const NAME: &'static [u8] = b"limits\0";
+	}
+
+	#[test]
+	fn burst_and_refill() {
+		let env = Env::builder().unwrap().with::<Limits>().build_temp().unwrap();
+		let tx = env.write_tx().unwrap();
+		let limits = tx.get::<Limits>();
+		let limit = Limit { rate: 1, per: Duration::from_secs(1), burst: 2 };
+		assert_eq!(limits.check_and_consume(&1, 1, 1000, limit).unwrap(), Decision::Allowed { remaining: 1 });
+		assert_eq!(limits.check_and_consume(&1, 1, 1000, limit).unwrap(), Decision::Allowed { remaining: 0 });
+		assert_eq!(limits.check_and_consume(&1, 1, 1000, limit).unwrap(), Decision::Limited { retry_after: Duration::from_secs(1) });
+		assert_eq!(limits.remaining(&1, 2000, limit).unwrap(), 1);
+		assert!(!limits.check_and_consume(&1, 3, 10_000, limit).unwrap().is_allowed(), "the cost is over the burst");
+		assert_eq!(limits.purge_full(2999).unwrap(), 0);
+		assert_eq!(limits.purge_full(3000).unwrap(), 1);
+	}
+
+	#[test]
+	fn huge_tolerance() {
+		let env = Env::builder().unwrap().with::<Limits>().build_temp().unwrap();
+		let tx = env.write_tx().unwrap();
+		let limit = Limit { rate: 1, per: Duration::from_secs(24 * 60 * 60), burst: 1_000_000 };
+		// a full bucket's worth of nanoseconds is past u64::MAX
+		assert!(tx.get::<Limits>().check_and_consume(&1, 1, 1000, limit).unwrap().is_allowed());
+		assert!(tx.get::<Limits>().check_and_consume(&1, 1, 1000, limit).unwrap().is_allowed());
+	}
+}
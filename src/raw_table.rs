@@ -0,0 +1,96 @@
+//! A table of plain byte strings, for data that's serialized some other way, and the only table there is without the
+//! `rkyv` feature:
+//! ```ignore
+//! #[derive(DbName)]
+//! #[table(RawTable<'tx, TX>)]
+//! struct Blobs;
+//!
+//! tx.get::<Blobs>().put(b"config", &encoded)?;
+//! let encoded = tx.get::<Blobs>().get(b"config")?;
+//! ```
+//! Keys are in LMDB's bytewise order, unless the table is registered with key flags.
+
+use culpa::throws;
+use enumflags2::BitFlag;
+use crate::{Transaction, RwTxn, Table, Error, lmdb, bookmark::Bookmark, error::Context};
+
+pub struct RawTable<'tx, TX> {
+	pub(crate) tx: &'tx TX,
+	pub(crate) dbi: lmdb_sys::MDB_dbi,
+	_borrow: crate::borrows::Borrow,
+}
+
+impl<'tx, 'env: 'tx, TX: Transaction<'env>> Table<'tx, 'env, TX> for RawTable<'tx, TX> {
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.dbi }
+	fn txn(&self) -> &TX { self.tx }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
+		Self::build(tx, tx.env().db(name).expect("table isn't registered in the env, add it with EnvBuilder::with"))
+	}
+}
+
+// RwTxn only, so all methods mutate
+impl RawTable<'_, RwTxn<'_>> {
+	#[throws]
+	pub fn put(&self, key: &[u8], value: &[u8]) {
+		lmdb::put(self.tx, self.dbi, &mut key.to_vec(), &mut value.to_vec(), lmdb::PutFlags::empty()).context(self.tx, self.dbi, "put", Some(key))?;
+	}
+
+	#[throws]
+	pub fn put_no_overwrite(&self, key: &[u8], value: &[u8]) {
+		lmdb::put(self.tx, self.dbi, &mut key.to_vec(), &mut value.to_vec(), lmdb::PutFlags::NoOverwrite.into()).context(self.tx, self.dbi, "put_no_overwrite", Some(key))?;
+	}
+
+	#[throws]
+	pub fn delete(&self, key: &[u8]) -> bool {
+		lmdb::del(self.tx, self.dbi, &mut key.to_vec()).context(self.tx, self.dbi, "delete", Some(key))?
+	}
+
+	#[throws]
+	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi).context(self.tx, self.dbi, "clear", None)?; }
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX: Transaction<'env>> RawTable<'tx, TX> {
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { tx, dbi, _borrow: crate::borrows::Borrow::of(tx) } }
+
+	#[throws]
+	pub fn get(&self, key: &[u8]) -> Option<&'tx [u8]> {
+		lmdb::get(self.tx, self.dbi, &mut key.to_vec()).context(self.tx, self.dbi, "get", Some(key))?
+	}
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn contains_key(&self, key: &[u8]) -> bool { self.get(key)?.is_some() }
+
+	/// every entry in key order
+	#[throws]
+	pub fn iter(&self) -> impl Iterator<Item = Result<(&'tx [u8], &'tx [u8]), Error>> + use<'tx, 'env, TX> {
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "iter", None)?;
+		lmdb::steps(move || cursor.get(lmdb::CursorOp::Next))
+	}
+
+	/// entries from the first key at or after `key`, in key order
+	#[throws]
+	pub fn iter_from(&self, key: &[u8]) -> impl Iterator<Item = Result<(&'tx [u8], &'tx [u8]), Error>> + use<'tx, 'env, TX> {
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "iter_from", None)?;
+		let first = cursor.get_with_key(&mut key.to_vec(), lmdb::CursorOp::SetRange).context(self.tx, self.dbi, "iter_from", Some(key))?;
+		rest_after(first, cursor)
+	}
+
+	/// entries after `bookmark` in key order, or all of them without one, see [`bookmark`](crate::bookmark)
+	#[throws]
+	pub fn iter_after(&self, bookmark: Option<&Bookmark>) -> impl Iterator<Item = Result<(&'tx [u8], &'tx [u8]), Error>> + use<'tx, 'env, TX> {
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "iter_after", None)?;
+		let first = match bookmark {
+			Some(bookmark) => bookmark.resume(self.tx, self.dbi, &mut cursor),
+			None => cursor.get(lmdb::CursorOp::First),
+		}.context(self.tx, self.dbi, "iter_after", None)?;
+		rest_after(first, cursor)
+	}
+}
+
+// `first` and the entries after it, a cursor that found nothing isn't positioned and Next would start over
+fn rest_after<'tx, 'env: 'tx, TX: Transaction<'env>>(first: Option<(&'tx [u8], &'tx [u8])>, mut cursor: lmdb::Cursor<'tx, TX>) -> impl Iterator<Item = Result<(&'tx [u8], &'tx [u8]), Error>> + use<'tx, 'env, TX> {
+	let rest = first.is_some().then(|| lmdb::steps(move || cursor.get(lmdb::CursorOp::Next))).into_iter().flatten();
+	first.map(Ok).into_iter().chain(rest)
+}
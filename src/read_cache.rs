@@ -0,0 +1,147 @@
+//! An in-process cache of deserialized values for hot keys of an [`AssocTable`], saving the validation and
+//! deserialization of every read:
+//! ```ignore
+//! static USERS: LazyLock<ReadCache<UserId, User>> = LazyLock::new(|| ReadCache::new(10_000));
+//!
+//! let user = USERS.get(&tx.get::<Users>(), &id)?;
+//! USERS.put(&tx.get::<Users>(), &id, &user)?;
+//! ```
+//! Entries remember the snapshot they were read from and writes remember the transaction that made them,
+//! so a read only gets a cached value if no write to its key happened between the two - old snapshots stay consistent too.
+//! Writes have to go through the cache to invalidate it, ones straight to the table are never noticed.
+//! Only read transactions fill the cache, so nothing uncommitted gets cached.
+
+use culpa::throws;
+use std::{collections::HashMap, marker::PhantomData, sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}}};
+use crate::{AssocTable, RoTxn, RwTxn, Table, Transaction, RkyvSer, RkyvVal, RkyvDe, Error, framing::Framing};
+
+pub struct ReadCache<K, V> {
+	inner: Mutex<Inner<V>>,
+	capacity: usize,
+	hits: AtomicU64,
+	misses: AtomicU64,
+	_pd: PhantomData<fn(&K)>,
+}
+
+struct Inner<V> {
+	slots: HashMap<Vec<u8>, Slot<V>>,
+	// the newest write to any key that isn't in slots
+	floor: usize,
+}
+
+struct Slot<V> {
+	// with the snapshot it was read from, None values are cached too
+	value: Option<(usize, Option<Arc<V>>)>,
+	last_write: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+	pub hits: u64,
+	pub misses: u64,
+	pub entries: usize,
+}
+
+impl<K, V> ReadCache<K, V> where
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	/// `capacity` counts keys, when it's full an arbitrary one is dropped.
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			inner: Mutex::new(Inner { slots: HashMap::new(), floor: 0 }),
+			capacity: capacity.max(1),
+			hits: AtomicU64::new(0),
+			misses: AtomicU64::new(0),
+			_pd: PhantomData,
+		}
+	}
+
+	#[throws]
+	pub fn get<'tx, F>(&self, table: &AssocTable<'tx, RoTxn<'tx>, K, V, F>, key: &K) -> Option<Arc<V>> where
+		F: Framing,
+		rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe> + 'tx,
+	{
+		let key_bytes = rkyv::to_bytes(key)?.to_vec();
+		let snapshot = table.txn().id();
+		{
+			let inner = self.inner.lock().unwrap();
+			if let Some(Slot { value: Some((read_at, value)), last_write }) = inner.slots.get(&key_bytes)
+				&& *last_write <= snapshot.min(*read_at)
+			{
+				self.hits.fetch_add(1, Ordering::Relaxed);
+				return value.clone();
+			}
+		}
+		self.misses.fetch_add(1, Ordering::Relaxed);
+		let value = table.get_unrkyv(key)?.map(Arc::new);
+		let mut inner = self.inner.lock().unwrap();
+		let slot = inner.slot(key_bytes, self.capacity);
+		// a write this snapshot doesn't see, or the cached read is newer already
+		if slot.last_write > snapshot || slot.value.as_ref().is_some_and(|(read_at, _)| *read_at >= snapshot) { return value; }
+		slot.value = Some((snapshot, value.clone()));
+		value
+	}
+
+	/// Drop `key` without writing, e.g. after writing it straight to the table.
+	/// Readers of snapshots from before `tx` can't get it from the cache either until it's read again.
+	#[throws]
+	pub fn invalidate(&self, tx: &RwTxn, key: &K) { self.written(rkyv::to_bytes(key)?.to_vec(), tx.id()); }
+
+	pub fn stats(&self) -> CacheStats {
+		CacheStats {
+			hits: self.hits.load(Ordering::Relaxed),
+			misses: self.misses.load(Ordering::Relaxed),
+			entries: self.inner.lock().unwrap().slots.len(),
+		}
+	}
+
+	fn written(&self, key_bytes: Vec<u8>, txn: usize) {
+		let mut inner = self.inner.lock().unwrap();
+		let slot = inner.slot(key_bytes, self.capacity);
+		slot.value = None;
+		slot.last_write = slot.last_write.max(txn);
+	}
+}
+
+// writes through the table, invalidating as they go
+impl<'tx, K, V> ReadCache<K, V> where
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe> + 'tx,
+{
+	#[throws]
+	pub fn put<F: Framing>(&self, table: &AssocTable<'tx, RwTxn<'tx>, K, V, F>, key: &K, value: &V) {
+		self.invalidate(table.txn(), key)?;
+		table.put(key, value)?;
+	}
+
+	#[throws]
+	pub fn delete<F: Framing>(&self, table: &AssocTable<'tx, RwTxn<'tx>, K, V, F>, key: &K) -> bool {
+		self.invalidate(table.txn(), key)?;
+		table.delete(key)?
+	}
+
+	#[throws]
+	pub fn clear<F: Framing>(&self, table: &AssocTable<'tx, RwTxn<'tx>, K, V, F>) {
+		let mut inner = self.inner.lock().unwrap();
+		inner.slots.clear();
+		inner.floor = inner.floor.max(table.txn().id());
+		drop(inner);
+		table.clear()?;
+	}
+}
+
+impl<V> Inner<V> {
+	fn slot(&mut self, key_bytes: Vec<u8>, capacity: usize) -> &mut Slot<V> {
+		if self.slots.len() >= capacity && !self.slots.contains_key(&key_bytes) {
+			let evicted = self.slots.keys().next().cloned().expect("capacity is at least 1");
+			let slot = self.slots.remove(&evicted).expect("the key was just found");
+			self.floor = self.floor.max(slot.last_write);
+		}
+		let floor = self.floor;
+		self.slots.entry(key_bytes).or_insert(Slot { value: None, last_write: floor })
+	}
+}
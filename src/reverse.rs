@@ -0,0 +1,61 @@
+//! Tables with keys or dupsort values compared from their last byte to their first, `DbFlags::ReverseKey` and
+//! `DbFlags::ReverseDup`, e.g. for suffix lookups of domain names without reversing them by hand:
+//! ```ignore
+//! #[derive(DbName)]
+//! #[table(ReverseKey<RawTable<'tx, TX>>)]
+//! struct Hosts;
+//!
+//! tx.get::<Hosts>().put(b"mail.example.com", b"..")?;
+//! let under_example = tx.get::<Hosts>().iter_suffix(b".example.com")?.collect::<Result<Vec<_>, _>>()?;
+//! ```
+//! Either wraps another table and derefs to it, its methods work in the reversed order. That order only means something
+//! for bytes that read backwards sensibly - serialized rkyv strings end in their length, so use a `RawTable` for text.
+//! Flags are fixed when a db is created, switching an existing table needs a migration.
+
+use culpa::throws;
+use crate::{Transaction, Table, RawTable, DbFlags, Error, lmdb, error::Context};
+
+macro_rules! reverse_table {
+	($name:ident, $flag:ident) => {
+		pub struct $name<T>(T);
+
+		impl<'tx, 'env: 'tx, TX: Transaction<'env>, T: Table<'tx, 'env, TX>> Table<'tx, 'env, TX> for $name<T> {
+			fn dbi(&self) -> lmdb_sys::MDB_dbi { self.0.dbi() }
+			fn txn(&self) -> &TX { self.0.txn() }
+			fn flags() -> enumflags2::BitFlags<DbFlags> { T::flags() | DbFlags::$flag }
+			fn build(tx: &'tx TX, name: &'static [u8]) -> Self { Self(T::build(tx, name)) }
+
+			#[throws]
+			fn check_entry(key: &[u8], value: &[u8]) { T::check_entry(key, value)?; }
+		}
+
+		impl<T> $name<T> {
+			pub fn into_inner(self) -> T { self.0 }
+		}
+
+		impl<T> std::ops::Deref for $name<T> {
+			type Target = T;
+
+			fn deref(&self) -> &T { &self.0 }
+		}
+	};
+}
+
+reverse_table!(ReverseKey, ReverseKey);
+reverse_table!(ReverseDup, ReverseDup);
+
+impl<'tx, 'env: 'tx, TX: Transaction<'env>> ReverseKey<RawTable<'tx, TX>> {
+	/// every entry with a key ending in `suffix`, which are next to each other in reversed order
+	#[throws]
+	pub fn iter_suffix(&self, suffix: &[u8]) -> impl Iterator<Item = Result<(&'tx [u8], &'tx [u8]), Error>> + use<'tx, 'env, TX> {
+		let (tx, dbi) = (self.0.tx, self.0.dbi);
+		let mut cursor = lmdb::Cursor::open(tx, dbi).context(tx, dbi, "iter_suffix", Some(suffix))?;
+		let suffix = suffix.to_vec();
+		let mut entry = cursor.get_with_key(&mut suffix.clone(), lmdb::CursorOp::SetRange)?;
+		std::iter::from_fn(move || {
+			let current = entry.take().filter(|(key, _)| key.ends_with(&suffix))?;
+			entry = match cursor.get(lmdb::CursorOp::Next) { Ok(next) => next, Err(e) => return Some(Err(e.into())) };
+			Some(Ok(current))
+		})
+	}
+}
@@ -0,0 +1,111 @@
+//! An assoc table split into generations by epoch (e.g. the hour or day entries were written in),
+//! so expired entries are dropped a whole db at a time instead of one delete per key.
+//! ```ignore
+//! static EVENTS: LazyLock<RotatingTable<EventId, Event>> = LazyLock::new(|| RotatingTable::open(&ENV, "events", 24).unwrap());
+//!
+//! // every hour, outside of write transactions
+//! EVENTS.rotate(&ENV, unix_hours())?;
+//!
+//! ENV.write(|tx| EVENTS.current(tx)?.put(&id, &event)).await??;
+//! let tx = ENV.read_tx()?;
+//! let event = EVENTS.get(&tx, &id)?;
+//! ```
+//! Generations are the named dbs `<name>@<epoch>`, the env needs `maxdbs` room for them on top of its registered tables.
+
+use culpa::{throw, throws};
+use std::{collections::BTreeMap, marker::PhantomData, sync::Mutex};
+use crate::{AssocTable, Env, Error, RkyvSer, RkyvVal, RkyvDe, Transaction, RwTxn, lmdb::{self, DbFlags}, framing::{Framing, Plain}};
+
+pub struct RotatingTable<K, V, F = Plain> {
+	name: String,
+	keep: u64,
+	generations: Mutex<BTreeMap<u64, lmdb_sys::MDB_dbi>>, // by epoch
+	_pd: PhantomData<(K, V, F)>,
+}
+
+impl<K, V, F> RotatingTable<K, V, F> where
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe>,
+{
+	/// Open the generations of `name` already in `env`, `rotate` keeps the `keep` latest epochs.
+	#[throws]
+	pub fn open(env: &Env, name: &str, keep: u64) -> Self {
+		if name.contains('\0') { throw!(Error::InvalidEntry("table names can't contain a nul")); }
+		let prefix = format!("{name}@");
+		let tx = env.write_tx()?;
+		let root = lmdb::dbi_open_existing(tx.raw(), None)?.expect("root db always exists");
+		let mut epochs = Vec::new();
+		let mut cursor = lmdb::Cursor::open(&tx, root)?;
+		while let Some((key, _)) = cursor.get(lmdb::CursorOp::Next)? {
+			let Some(epoch) = key.strip_prefix(prefix.as_bytes()) else { continue };
+			if let Some(epoch) = std::str::from_utf8(epoch).ok().and_then(|x| x.parse::<u64>().ok()) { epochs.push(epoch); }
+		}
+		drop(cursor);
+		let mut generations = BTreeMap::new();
+		for epoch in epochs {
+			let Some(dbi) = lmdb::dbi_open_existing(tx.raw(), Some(&db_name(name, epoch)))? else { continue };
+			generations.insert(epoch, dbi);
+		}
+		tx.commit()?;
+		Self { name: name.to_owned(), keep, generations: Mutex::new(generations), _pd: PhantomData }
+	}
+
+	/// Create the generation for `epoch` if it's missing and drop the ones at or before `epoch - keep`.
+	/// Runs its own write transaction, so it blocks while another one is open - don't call it from inside one.
+	/// Reads racing a drop can fail with `BadDbi`.
+	#[throws]
+	pub fn rotate(&self, env: &Env, epoch: u64) {
+		// locked after getting the write lock, writers use generations too
+		let tx = env.write_tx()?;
+		let mut generations = self.generations.lock().unwrap();
+		let created = match generations.get(&epoch) {
+			Some(_) => None,
+			None => Some(lmdb::dbi_open(tx.raw(), &db_name(&self.name, epoch), DbFlags::Create.into())?),
+		};
+		let expired = generations.keys().copied().take_while(|&x| x + self.keep <= epoch).collect::<Vec<_>>();
+		for &x in &expired { lmdb::drop_delete(&tx, generations[&x])?; }
+		tx.commit()?;
+
+		// the handles only change once the transaction went through
+		if let Some(dbi) = created { generations.insert(epoch, dbi); }
+		for x in expired {
+			generations.remove(&x);
+			log::info!("dropped generation {x} of {}", self.name);
+		}
+	}
+
+	/// epochs of the current generations, oldest first
+	pub fn epochs(&self) -> Vec<u64> { self.generations.lock().unwrap().keys().copied().collect() }
+
+	/// the latest generation, for writing
+	#[throws]
+	pub fn current<'tx>(&self, tx: &'tx RwTxn<'tx>) -> AssocTable<'tx, RwTxn<'tx>, K, V, F> where
+		rkyv::Archived<V>: 'tx,
+	{
+		let Some((_, &dbi)) = self.generations.lock().unwrap().last_key_value() else { throw!(Error::UnknownDb(format!("{}@", self.name))) };
+		AssocTable::build(tx, dbi)
+	}
+
+	/// every generation, latest first
+	pub fn tables<'tx, 'env: 'tx, TX: Transaction<'env>>(&self, tx: &'tx TX) -> Vec<AssocTable<'tx, TX, K, V, F>> where
+		rkyv::Archived<V>: 'tx,
+	{
+		self.generations.lock().unwrap().values().rev().map(|&dbi| AssocTable::build(tx, dbi)).collect()
+	}
+
+	/// the value from the latest generation that has `key`
+	#[throws]
+	pub fn get<'tx, 'env: 'tx, TX: Transaction<'env>>(&self, tx: &'tx TX, key: &K) -> Option<&'tx rkyv::Archived<V>> where
+		rkyv::Archived<V>: 'tx,
+	{
+		for table in self.tables(tx) {
+			if let Some(value) = table.get(key)? { return Some(value); }
+		}
+		None
+	}
+}
+
+fn db_name(name: &str, epoch: u64) -> Vec<u8> { format!("{name}@{epoch}\0").into_bytes() }
@@ -0,0 +1,58 @@
+//! A registry of what type each table was created with, so two binaries sharing an env can't use the same table name
+//! for different types. Turned on with [`EnvBuilder::schema_registry`](crate::env::EnvBuilder::schema_registry):
+//! building the env then records the [`fingerprint`] of every registered table the first time it's seen and fails with
+//! [`Error::SchemaMismatch`](crate::Error::SchemaMismatch) if a table was recorded with another one.
+//! ```ignore
+//! let env = Env::builder()?.schema_registry().with::<Users>().build(path)?;
+//! // the release that migrates Users to a new type
+//! let env = Env::builder()?.schema_registry().with::<Users>().schema_changed::<Users>().build(path)?;
+//! ```
+//! Checks happen as the env is built rather than in `Table::build`, which runs for every transaction and can't fail.
+
+use culpa::{throw, throws};
+use crate::{Env, Error, Transaction, DbName, RwTxn};
+
+// fingerprints keyed by db name, without the trailing nul
+#[derive(DbName)]
+#[table(crate::AssocTable<'tx, TX, String, u64>)]
+pub struct Schemas;
+
+/// FNV-1a of the table's type name - which covers its key and value types and framing, but also the paths they're
+/// defined at, so moving a type to another module counts as a change too. Type names aren't guaranteed to stay the
+/// same across compiler versions either.
+pub fn fingerprint<N: DbName>() -> u64 { crate::fnv1a(std::any::type_name::<N::Table<'static, 'static, RwTxn<'static>>>().bytes()) }
+
+// records new fingerprints, `changed` tables get theirs overwritten instead of checked
+#[throws]
+pub(crate) fn check(env: &Env, fingerprints: &[(&'static [u8], u64)], changed: &[&'static [u8]], read_only: bool) {
+	if read_only {
+		unrecorded(&env.read_tx()?, fingerprints, changed)?;
+	} else {
+		let tx = env.write_tx()?;
+		for (name, fingerprint) in unrecorded(&tx, fingerprints, changed)? { tx.get::<Schemas>().put(&name, &fingerprint)?; }
+		tx.commit()?;
+	}
+}
+
+#[throws]
+fn unrecorded<'env>(tx: &impl Transaction<'env>, fingerprints: &[(&'static [u8], u64)], changed: &[&'static [u8]]) -> Vec<(String, u64)> {
+	let mut unrecorded = Vec::new();
+	for &(name, fingerprint) in fingerprints {
+		if name == Schemas::NAME { continue; }
+		let key = crate::db_name_lossy(name);
+		match tx.get::<Schemas>().get(&key)?.map(|x| x.to_native()) {
+			Some(stored) if stored == fingerprint => {},
+			Some(_) if !changed.contains(&name) => throw!(Error::SchemaMismatch(key)),
+			_ => unrecorded.push((key, fingerprint)),
+		}
+	}
+	unrecorded
+}
+
+/// recorded fingerprints by table name, e.g. for tooling
+#[throws]
+pub fn registered(env: &Env) -> Vec<(String, u64)> {
+	if env.db(Schemas::NAME).is_none() { throw!(Error::UnknownDb(crate::db_name_lossy(Schemas::NAME))); }
+	let tx = env.read_tx()?;
+	tx.get::<Schemas>().iter()?.map(|entry| entry.map(|(name, fingerprint)| (name.to_string(), fingerprint.to_native()))).collect::<Result<_, _>>()?
+}
@@ -0,0 +1,239 @@
+//! One table shared by tenants (or any other namespaces), each only seeing its own entries through a scope:
+//! ```ignore
+//! #[derive(DbName)]
+//! #[table(ScopedTable<'tx, TX, DocId, Doc>)]
+//! struct Docs;
+//!
+//! let docs = tx.get::<Docs>().scoped(tenant.as_bytes())?;
+//! docs.put(&id, &doc)?;
+//! let doc = docs.get(&id)?; // only ever this tenant's
+//! for entry in docs.iter()? { let (id, doc) = entry?; .. }
+//! ```
+//! Keys are stored as the scope's length, the scope and the archived key, so no scope is a prefix of another's keys -
+//! "acme" never sees "acme-corp"'s entries. Scopes are at most 255 bytes, and there's nothing to create or register
+//! per scope: one exists while it has entries.
+
+use culpa::{throw, throws};
+use enumflags2::BitFlag;
+use std::marker::PhantomData;
+use crate::{Transaction, RwTxn, Table, RkyvSer, RkyvVal, RkyvDe, Error, lmdb, error::Context, framing::{Framing, Plain}};
+
+pub struct ScopedTable<'tx, TX, K, V, F = Plain> {
+	tx: &'tx TX,
+	dbi: lmdb_sys::MDB_dbi,
+	_pd: PhantomData<(K, V, F)>,
+	_borrow: crate::borrows::Borrow,
+}
+
+impl<'tx, 'env: 'tx, TX, K, V, F> Table<'tx, 'env, TX> for ScopedTable<'tx, TX, K, V, F> where
+	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.dbi }
+	fn txn(&self) -> &TX { self.tx }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self {
+		Self::build(tx, tx.env().db(name).expect("table isn't registered in the env, add it with EnvBuilder::with"))
+	}
+
+	#[throws]
+	fn check_entry(key: &[u8], value: &[u8]) {
+		let Some((_, key)) = split(key) else { throw!(Error::InvalidEntry("not a scoped key")) };
+		rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key)?;
+		rkyv::access::<rkyv::Archived<V>, rkyv::rancor::Error>(F::unframe(value)?)?;
+	}
+}
+
+// a stored key's scope and key
+fn split(key: &[u8]) -> Option<(&[u8], &[u8])> {
+	let (&len, rest) = key.split_first()?;
+	rest.split_at_checked(len.into())
+}
+
+// the first key after every key starting with `prefix`, None if there's no such key
+fn after(prefix: &[u8]) -> Option<Vec<u8>> {
+	let mut next = prefix.to_vec();
+	while next.pop_if(|x| *x == u8::MAX).is_some() {}
+	*next.last_mut()? += 1;
+	Some(next)
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, K, V, F> ScopedTable<'tx, RwTxn<'tx>, K, V, F> where
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	/// every scope's entries
+	#[throws]
+	pub fn clear(&self) { lmdb::drop(self.tx, self.dbi).context(self.tx, self.dbi, "clear", None)?; }
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, K, V, F> ScopedTable<'tx, TX, K, V, F> where
+	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { tx, dbi, _pd: PhantomData, _borrow: crate::borrows::Borrow::of(tx) } }
+
+	/// the entries of `scope`, at most 255 bytes
+	#[throws]
+	pub fn scoped(&self, scope: &[u8]) -> Scope<'tx, TX, K, V, F> {
+		let Ok(len) = u8::try_from(scope.len()) else { throw!(Error::InvalidEntry("scopes are at most 255 bytes")) };
+		Scope { tx: self.tx, dbi: self.dbi, prefix: [&[len][..], scope].concat(), _pd: PhantomData }
+	}
+
+	/// Every scope with entries, in order of length and then bytes. Skips from one scope to the next without
+	/// walking their entries.
+	#[throws]
+	pub fn scopes(&self) -> Vec<Vec<u8>> {
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "scopes", None)?;
+		let mut entry = cursor.get(lmdb::CursorOp::First)?;
+		let mut scopes = Vec::new();
+		while let Some((key, _)) = entry {
+			let Some((scope, _)) = split(key) else { throw!(Error::InvalidEntry("not a scoped key")) };
+			scopes.push(scope.to_vec());
+			let Some(mut next) = after(&key[..=scope.len()]) else { break };
+			entry = cursor.get_with_key(&mut next, lmdb::CursorOp::SetRange)?;
+		}
+		scopes
+	}
+}
+
+/// see [`ScopedTable::scoped`], the methods are those of an assoc table holding only the scope's entries
+pub struct Scope<'tx, TX, K, V, F = Plain> {
+	tx: &'tx TX,
+	dbi: lmdb_sys::MDB_dbi,
+	prefix: Vec<u8>, // the scope's length and the scope
+	_pd: PhantomData<(K, V, F)>,
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, K, V, F> Scope<'tx, RwTxn<'tx>, K, V, F> where
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	#[throws]
+	pub fn put(&self, key: &K, value: &V) {
+		let mut key = self.key(key)?;
+		lmdb::put(self.tx, self.dbi, &mut key, &mut F::frame(rkyv::to_bytes(value)?), lmdb::PutFlags::empty()).context(self.tx, self.dbi, "put", Some(&key))?;
+	}
+
+	#[throws]
+	pub fn delete(&self, key: &K) -> bool {
+		let mut key = self.key(key)?;
+		lmdb::del(self.tx, self.dbi, &mut key).context(self.tx, self.dbi, "delete", Some(&key))?
+	}
+
+	/// delete the scope's entries, returns how many there were
+	#[throws]
+	pub fn clear(&self) -> usize {
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "clear", Some(&self.prefix))?;
+		let mut entry = cursor.get_with_key(&mut self.prefix.clone(), lmdb::CursorOp::SetRange)?;
+		let mut cleared = 0;
+		while let Some((key, _)) = entry && key.starts_with(&self.prefix) {
+			let key = key.to_vec(); // the slice goes stale with the delete
+			cursor.del_current().context(self.tx, self.dbi, "clear", Some(&key))?;
+			cleared += 1;
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+		cleared
+	}
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, K, V, F> Scope<'tx, TX, K, V, F> where
+	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	/// the scope, without its length
+	pub fn scope(&self) -> &[u8] { &self.prefix[1..] }
+
+	#[expect(unused_braces)]
+	#[throws]
+	fn key(&self, key: &K) -> Vec<u8> { [&self.prefix[..], &rkyv::to_bytes(key)?].concat() }
+
+	#[throws]
+	pub fn get(&self, key: &K) -> Option<&'tx rkyv::Archived<V>> where
+		rkyv::Archived<V>: 'tx,
+	{
+		let mut key = self.key(key)?;
+		let Some(value) = lmdb::get(self.tx, self.dbi, &mut key).context(self.tx, self.dbi, "get", Some(&key))? else { return None };
+		Some(rkyv::access::<rkyv::Archived<V>, rkyv::rancor::Error>(F::unframe(value)?).context(self.tx, self.dbi, "get", Some(&key))?)
+	}
+
+	#[throws]
+	pub fn get_unrkyv(&self, key: &K) -> Option<V> where
+		rkyv::Archived<V>: rkyv::Deserialize<V, RkyvDe> + 'tx,
+	{
+		let Some(archived) = self.get(key)? else { return None };
+		Some(rkyv::deserialize::<V, rkyv::rancor::Error>(archived)?)
+	}
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn contains_key(&self, key: &K) -> bool where
+		rkyv::Archived<V>: 'tx,
+	{ self.get(key)?.is_some() }
+
+	/// the scope's entries in key order
+	#[expect(clippy::iter_not_returning_iterator)]
+	#[throws]
+	pub fn iter(&self) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V, F> where
+		rkyv::Archived<K>: 'tx,
+		rkyv::Archived<V>: 'tx,
+	{
+		let prefix = self.prefix.clone();
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "iter", Some(&prefix))?;
+		let mut entry = cursor.get_with_key(&mut prefix.clone(), lmdb::CursorOp::SetRange)?;
+		std::iter::from_fn(move || {
+			let (key, value) = entry.take()?;
+			let key = key.strip_prefix(&prefix[..])?;
+			entry = match cursor.get(lmdb::CursorOp::Next) { Ok(next) => next, Err(e) => return Some(Err(e.into())) };
+			let archived = || -> Result<_, Error> { Ok((
+				rkyv::access::<rkyv::Archived<K>, rkyv::rancor::Error>(key)?,
+				rkyv::access::<rkyv::Archived<V>, rkyv::rancor::Error>(F::unframe(value)?)?,
+			)) };
+			Some(archived())
+		})
+	}
+
+	/// how many entries the scope has, walks them
+	#[throws]
+	pub fn len(&self) -> usize where
+		rkyv::Archived<K>: 'tx,
+		rkyv::Archived<V>: 'tx,
+	{
+		let mut cursor = lmdb::Cursor::open(self.tx, self.dbi).context(self.tx, self.dbi, "len", Some(&self.prefix))?;
+		let mut entry = cursor.get_with_key(&mut self.prefix.clone(), lmdb::CursorOp::SetRange)?;
+		let mut len = 0;
+		while let Some((key, _)) = entry && key.starts_with(&self.prefix) {
+			len += 1;
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+		len
+	}
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn is_empty(&self) -> bool where
+		rkyv::Archived<K>: 'tx,
+		rkyv::Archived<V>: 'tx,
+	{ self.len()? == 0 }
+}
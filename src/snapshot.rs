@@ -0,0 +1,138 @@
+//! Named, timestamped compacted copies of an env, kept as `<dir>/<created>-<name>/data.mdb`.
+//!
+//! ```ignore
+//! ENV.snapshot("before-migration")?;
+//! // ...something went wrong, with the app stopped:
+//! ENV.snapshots()?.restore("before-migration", Path::new("restored"))?;
+//! ```
+
+use culpa::{throw, throws};
+use std::path::{Path, PathBuf};
+use crate::{Env, Error};
+
+const DATA: &str = "data.mdb";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+	pub name: String,
+	pub created: u64, // unix millis
+	pub path: PathBuf, // directory with the snapshot's data.mdb, can be opened as an env
+}
+
+pub struct Snapshots {
+	dir: PathBuf,
+}
+
+impl Snapshots {
+	pub fn new(dir: impl Into<PathBuf>) -> Self { Self { dir: dir.into() } }
+
+	pub fn dir(&self) -> &Path { &self.dir }
+
+	/// Take a compacted copy of `env` named `name`, the directory is created if missing.
+	/// Names can't be empty, start with a dot or contain path separators.
+	#[throws]
+	pub fn take(&self, env: &Env, name: &str) -> Snapshot {
+		if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\', '\0']) {
+			throw!(Error::InvalidSnapshotName(name.to_owned()));
+		}
+		let created = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |x| x.as_millis() as u64);
+		let path = self.dir.join(format!("{created}-{name}"));
+		std::fs::create_dir_all(&self.dir)?;
+		std::fs::create_dir(&path)?;
+		if let Err(e) = env.copy(&crate::path_to_cstring(&path)?, true) {
+			let _ = std::fs::remove_dir_all(&path);
+			throw!(e);
+		}
+		log::info!("took snapshot {name} into {}", path.display());
+		Snapshot { name: name.to_owned(), created, path }
+	}
+
+	/// Oldest first, an empty list if the directory doesn't exist.
+	#[throws]
+	pub fn list(&self) -> Vec<Snapshot> {
+		let entries = match std::fs::read_dir(&self.dir) {
+			Ok(x) => x,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+			Err(e) => throw!(e),
+		};
+		let mut snapshots = Vec::new();
+		for entry in entries {
+			let entry = entry?;
+			let file_name = entry.file_name();
+			// anything that isn't ours is left alone
+			let Some((created, name)) = file_name.to_str().and_then(|x| x.split_once('-')) else { continue };
+			let Ok(created) = created.parse() else { continue };
+			if !entry.path().join(DATA).is_file() { continue; }
+			snapshots.push(Snapshot { name: name.to_owned(), created, path: entry.path() });
+		}
+		snapshots.sort_by(|a, b| (a.created, &a.name).cmp(&(b.created, &b.name)));
+		snapshots
+	}
+
+	/// The latest snapshot named `name`.
+	#[throws]
+	pub fn get(&self, name: &str) -> Option<Snapshot> {
+		self.list()?.into_iter().rev().find(|x| x.name == name)
+	}
+
+	/// Copy the latest snapshot named `name` into `path`, which has to be empty or not exist yet.
+	/// An env can't be swapped under a running process, so point the app to `path` (or move its data.mdb over
+	/// the original one) while nothing has the env open.
+	#[throws]
+	pub fn restore(&self, name: &str, path: &Path) -> Snapshot {
+		let Some(snapshot) = self.get(name)? else {
+			throw!(std::io::Error::new(std::io::ErrorKind::NotFound, format!("no snapshot named {name:?} in {}", self.dir.display())));
+		};
+		std::fs::create_dir_all(path)?;
+		if std::fs::read_dir(path)?.next().is_some() {
+			throw!(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("{} isn't empty", path.display())));
+		}
+		std::fs::copy(snapshot.path.join(DATA), path.join(DATA))?;
+		std::fs::File::open(path.join(DATA))?.sync_all()?;
+		snapshot
+	}
+
+	#[throws]
+	pub fn remove(&self, snapshot: &Snapshot) {
+		std::fs::remove_dir_all(&snapshot.path)?;
+	}
+}
+
+// Copy the env in `path` to `recovery` as of the commit before its last one. LMDB keeps two meta pages and opens
+// the one with the higher txn id, while pages freed by the last commit aren't reused until the one after, so
+// overwriting the newer meta with the older one leaves a consistent env a commit behind (what MDB_PREVSNAPSHOT
+// does in LMDB 0.9.70+, which lmdb-sys doesn't have).
+#[throws]
+pub(crate) fn copy_previous(path: &Path, recovery: &Path) {
+	use std::io::{Read, Seek, SeekFrom, Write};
+
+	const W: usize = std::mem::size_of::<usize>();
+	const PAGE_HEADER: usize = W + 8; // mp_pgno, mp_pad, mp_flags, mp_lower, mp_upper
+	const PSIZE: usize = 16 + 3 * W; // mm_dbs[0].md_pad, the free db's pad holds the page size
+	const TXNID: usize = 32 + 14 * W; // last member of MDB_meta
+	const META_END: usize = TXNID + W;
+
+	std::fs::create_dir_all(recovery)?;
+	if std::fs::read_dir(recovery)?.next().is_some() {
+		throw!(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("{} isn't empty", recovery.display())));
+	}
+	std::fs::copy(path.join(DATA), recovery.join(DATA))?;
+	let mut file = std::fs::OpenOptions::new().read(true).write(true).open(recovery.join(DATA))?;
+
+	let mut meta0 = [0; META_END];
+	file.read_exact(&mut meta0)?;
+	let psize = u32::from_ne_bytes(meta0[PSIZE..PSIZE + 4].try_into().expect("4 bytes")) as u64;
+	if !psize.is_power_of_two() || psize < META_END as u64 { throw!(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("first meta page has a page size of {psize}"))); }
+	let mut meta1 = [0; META_END];
+	file.seek(SeekFrom::Start(psize))?;
+	file.read_exact(&mut meta1)?;
+
+	let txnid = |meta: &[u8; META_END]| usize::from_ne_bytes(meta[TXNID..].try_into().expect("W bytes"));
+	let (newer, older, older_body) = if txnid(&meta1) > txnid(&meta0) { (psize, txnid(&meta0), &meta0[PAGE_HEADER..]) } else { (0, txnid(&meta1), &meta1[PAGE_HEADER..]) };
+	log::warn!("opening {} at txn {older}", path.display());
+
+	// the page header stays, it's the page number
+	file.seek(SeekFrom::Start(newer + PAGE_HEADER as u64))?;
+	file.write_all(older_body)?;
+	file.sync_all()?;
+}
@@ -0,0 +1,279 @@
+//! A pull protocol on top of the [`changelog`](crate::changelog), for offline-first clients replicating some
+//! tables or key prefixes of a server's env. The client sends the last sequence it applied and the server answers with
+//! the current state of every entry changed since:
+//! ```ignore
+//! // client
+//! let request = sync::Request { since: last_seq, subscriptions: vec![sync::Subscription::of::<Notes>().with_prefix(user_id)] };
+//! sync::write(&mut conn, &request)?;
+//! CLIENT.try_write(move |tx| {
+//!     while let Some(message) = sync::read::<sync::Message>(&mut conn)? {
+//!         if let Some(seq) = sync::apply(tx, &message)? { store_last_seq(tx, seq)?; }
+//!     }
+//!     Ok::<_, batadase::Error>(())
+//! }).await??;
+//!
+//! // server, built with EnvBuilder::changelog
+//! let request = sync::read::<sync::Request>(&mut conn)?.ok_or(Closed)?;
+//! sync::respond(&SERVER.read_tx()?, &request, &mut conn)?;
+//! ```
+//! Messages are rkyv archives behind a `u32` LE length. Values are sent as stored, so both sides need the same table
+//! types and framing. A key changed several times since is only sent once, as it is now. When the changelog no
+//! longer goes back to the client's sequence - it was trimmed, or the client is new and may predate entries put
+//! before the changelog was enabled - the subscribed entries are sent in full after a [`Message::Clear`] of them.
+
+use culpa::{throw, throws};
+use enumflags2::BitFlag;
+use std::collections::BTreeSet;
+use std::io::{Read, Write};
+use crate::{DbName, Transaction, RwTxn, RkyvSer, RkyvVal, RkyvDe, Error, lmdb, error::Context, changelog::{ChangeLog, ChangeOp}};
+
+// well over anything a single entry can be
+const MAX_FRAME: usize = 1 << 30;
+
+/// the entries of a table whose keys start with `prefix`, every one with an empty prefix
+#[derive(Debug, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct Subscription {
+	/// the table's db name, without the trailing nul
+	pub table: Vec<u8>,
+	pub prefix: Vec<u8>,
+}
+
+impl Subscription {
+	pub fn of<N: DbName>() -> Self {
+		Self { table: N::NAME.strip_suffix(b"\0").unwrap_or(N::NAME).to_vec(), prefix: Vec::new() }
+	}
+
+	/// the stored key bytes to match, for rkyv keys that's their archived form
+	#[must_use]
+	pub fn with_prefix(mut self, prefix: impl Into<Vec<u8>>) -> Self {
+		self.prefix = prefix.into();
+		self
+	}
+
+	fn matches(&self, table: &[u8], key: &[u8]) -> bool { self.table == table && key.starts_with(&self.prefix) }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct Request {
+	/// the sequence of the last [`Message::Done`] applied, 0 for a first sync
+	pub since: u64,
+	pub subscriptions: Vec<Subscription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub enum Message {
+	/// for dupsort tables one of the key's values, sent after a `Delete` of the others
+	Put { table: Vec<u8>, key: Vec<u8>, value: Vec<u8> },
+	Delete { table: Vec<u8>, key: Vec<u8> },
+	/// delete every entry with keys starting with `prefix`
+	Clear { table: Vec<u8>, prefix: Vec<u8> },
+	/// the last message of a response, `seq` is the next request's `since`
+	Done { seq: u64 },
+}
+
+/// Write a request or message framed for [`read`].
+#[throws]
+pub fn write<T: for <'a> rkyv::Serialize<RkyvSer<'a>>>(writer: &mut impl Write, value: &T) {
+	let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(value)?;
+	let len = u32::try_from(bytes.len()).ok().filter(|&len| len as usize <= MAX_FRAME)
+		.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "sync message too large"))?;
+	writer.write_all(&len.to_le_bytes())?;
+	writer.write_all(&bytes)?;
+}
+
+/// Read a request or message written by [`write`], None once the stream ends between messages.
+#[throws]
+pub fn read<T>(reader: &mut impl Read) -> Option<T> where
+	T: rkyv::Archive,
+	rkyv::Archived<T>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<T, RkyvDe>,
+{
+	let mut len = [0; 4];
+	match reader.read_exact(&mut len) {
+		Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return None,
+		res => res?,
+	}
+	let len = u32::from_le_bytes(len) as usize;
+	if len > MAX_FRAME { throw!(std::io::Error::new(std::io::ErrorKind::InvalidData, "sync message too large")); }
+	let mut bytes = rkyv::util::AlignedVec::<16>::with_capacity(len);
+	bytes.resize(len, 0);
+	reader.read_exact(&mut bytes)?;
+	Some(rkyv::from_bytes::<T, rkyv::rancor::Error>(&bytes)?)
+}
+
+/// Write the messages answering `request` as of `tx` to `out` as they're read, ending with [`Message::Done`], whose
+/// sequence is returned. Error::UnknownDb for subscriptions of tables the env doesn't have, or if it was built without
+/// [`EnvBuilder::changelog`](crate::env::EnvBuilder::changelog). `tx` stays open while `out` is written to, so a slow
+/// client holds back the reuse of pages freed meanwhile.
+#[throws]
+pub fn respond<'env>(tx: &impl Transaction<'env>, request: &Request, out: &mut impl Write) -> u64 {
+	let log = ChangeLog::of(tx)?;
+	let last = log.last_sequence()?;
+	let dbis = request.subscriptions.iter().map(|sub| dbi(tx, &sub.table)).collect::<Result<Vec<_>, _>>()?;
+	let first = log.changes_since(0)?.next().transpose()?.map_or(1, |change| change.seq);
+	// a first sync starts from what's stored, entries put before the changelog was enabled aren't in it
+	if request.since == 0 || request.since > last || request.since + 1 < first {
+		for (sub, &dbi) in request.subscriptions.iter().zip(&dbis) {
+			write(out, &Message::Clear { table: sub.table.clone(), prefix: sub.prefix.clone() })?;
+			let mut cursor = lmdb::Cursor::open(tx, dbi).context(tx, dbi, "sync", None)?;
+			// lmdb takes no empty keys
+			let mut entry = if sub.prefix.is_empty() { cursor.get(lmdb::CursorOp::First) } else { cursor.get_with_key(&mut sub.prefix.clone(), lmdb::CursorOp::SetRange) }?;
+			while let Some((key, value)) = entry && key.starts_with(&sub.prefix) {
+				write(out, &Message::Put { table: sub.table.clone(), key: key.to_vec(), value: value.to_vec() })?;
+				entry = cursor.get(lmdb::CursorOp::Next)?;
+			}
+		}
+	} else {
+		// per subscription whether it was cleared since, and the keys changed after that
+		let mut changed = vec![(false, BTreeSet::new()); request.subscriptions.len()];
+		for change in log.changes_since(request.since)? {
+			let change = change?;
+			for (sub, (cleared, keys)) in request.subscriptions.iter().zip(&mut changed) {
+				if change.op == ChangeOp::Clear && sub.table == change.table {
+					*cleared = true;
+					keys.clear();
+				} else if sub.matches(change.table, change.key) {
+					keys.insert(change.key.to_vec());
+				}
+			}
+		}
+		for ((sub, &dbi), (cleared, keys)) in request.subscriptions.iter().zip(&dbis).zip(changed) {
+			if cleared { write(out, &Message::Clear { table: sub.table.clone(), prefix: sub.prefix.clone() })?; }
+			for key in keys { current(tx, dbi, &sub.table, key, !cleared, out)?; }
+		}
+	}
+	write(out, &Message::Done { seq: last })?;
+	last
+}
+
+// the messages bringing `key` up to date, with a Delete first if the client may have other values
+#[throws]
+fn current<'env>(tx: &impl Transaction<'env>, dbi: lmdb_sys::MDB_dbi, table: &[u8], mut key: Vec<u8>, delete: bool, out: &mut impl Write) {
+	let mut cursor = lmdb::Cursor::open(tx, dbi).context(tx, dbi, "sync", Some(&key))?;
+	let (dup_sort, values) = match cursor.as_dup() {
+		Some(cursor) => {
+			let mut values = Vec::new();
+			let mut value = cursor.get_with_key(&mut key, lmdb::CursorOp::SetKey)?.map(|(_, value)| value);
+			while let Some(x) = value {
+				values.push(x.to_vec());
+				value = cursor.get_dup(lmdb::DupCursorOp::NextDup)?.map(|(_, value)| value);
+			}
+			(true, values)
+		},
+		None => (false, lmdb::get(tx, dbi, &mut key).context(tx, dbi, "sync", Some(&key))?.map(<[u8]>::to_vec).into_iter().collect()),
+	};
+	if delete && (dup_sort || values.is_empty()) { write(out, &Message::Delete { table: table.to_vec(), key: key.clone() })?; }
+	for value in values { write(out, &Message::Put { table: table.to_vec(), key: key.clone(), value })?; }
+}
+
+#[throws]
+fn dbi<'env>(tx: &impl Transaction<'env>, table: &[u8]) -> lmdb_sys::MDB_dbi {
+	let name = [table, b"\0"].concat();
+	let Some(dbi) = tx.env().db(&name) else { throw!(crate::dump::unknown_db(&name)) };
+	dbi
+}
+
+/// Apply a message of a response to the client's tables, which have to be registered. The sequence once it's
+/// [`Message::Done`], to store in the same transaction and send as the next request's `since`.
+#[throws]
+pub fn apply(tx: &RwTxn, message: &Message) -> Option<u64> {
+	match message {
+		Message::Put { table, key, value } => {
+			let dbi = dbi(tx, table)?;
+			lmdb::put(tx, dbi, key.clone(), value.clone(), lmdb::PutFlags::empty()).context(tx, dbi, "sync", Some(key))?;
+		},
+		Message::Delete { table, key } => {
+			let dbi = dbi(tx, table)?;
+			lmdb::del(tx, dbi, key.clone()).context(tx, dbi, "sync", Some(key))?;
+		},
+		Message::Clear { table, prefix } if prefix.is_empty() => {
+			let dbi = dbi(tx, table)?;
+			lmdb::drop(tx, dbi).context(tx, dbi, "sync", None)?;
+		},
+		Message::Clear { table, prefix } => {
+			let dbi = dbi(tx, table)?;
+			let mut cursor = lmdb::Cursor::open(tx, dbi).context(tx, dbi, "sync", None)?;
+			while let Some((key, _)) = cursor.get_with_key(&mut prefix.clone(), lmdb::CursorOp::SetRange)? && key.starts_with(prefix) {
+				let key = key.to_vec(); // the slice goes stale with the delete
+				cursor.del_current().context(tx, dbi, "sync", Some(&key))?;
+			}
+		},
+		&Message::Done { seq } => return Some(seq),
+	}
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, Env, RawTable, Transaction, changelog::ChangeLog};
+	use super::{Message, Request, Subscription};
+
+	struct Notes;
+
+	impl DbName for Notes {
+		type Table<'tx, 'env: 'tx, TX: Transaction<'env> + 'tx> = RawTable<'tx, TX>;
+		const NAME: &'static [u8] = b"notes\0";
+	}
+
+	fn entries(env: &Env) -> Vec<(Vec<u8>, Vec<u8>)> {
+		let tx = env.read_tx().unwrap();
+		tx.get::<Notes>().iter().unwrap().map(|entry| { let (key, value) = entry.unwrap(); (key.to_vec(), value.to_vec()) }).collect()
+	}
+
+	fn write(env: &Env, puts: &[&[u8]], deletes: &[&[u8]]) {
+		let tx = env.write_tx().unwrap();
+		for key in puts { tx.get::<Notes>().put(key, b"x").unwrap(); }
+		for key in deletes { tx.get::<Notes>().delete(key).unwrap(); }
+		tx.commit().unwrap();
+	}
+
+	// one round of the protocol, returning the next request's since and the messages applied
+	fn pull(server: &Env, client: &Env, since: u64, prefix: &[u8]) -> (u64, Vec<Message>) {
+		let request = Request { since, subscriptions: vec![Subscription::of::<Notes>().with_prefix(prefix)] };
+		let mut response = Vec::new();
+		let last = super::respond(&server.read_tx().unwrap(), &request, &mut response).unwrap();
+		let (mut response, mut messages) = (response.as_slice(), Vec::new());
+		let tx = client.write_tx().unwrap();
+		let mut seq = None;
+		while let Some(message) = super::read::<Message>(&mut response).unwrap() {
+			seq = super::apply(&tx, &message).unwrap().or(seq);
+			messages.push(message);
+		}
+		tx.commit().unwrap();
+		assert_eq!(seq, Some(last));
+		(last, messages)
+	}
+
+	#[test]
+	fn fresh_client_then_changes() {
+		let server = Env::builder().unwrap().with::<Notes>().changelog().build_temp().unwrap();
+		let client = Env::builder().unwrap().with::<Notes>().build_temp().unwrap();
+		write(&server, &[b"a", b"b"], &[]);
+		let (since, messages) = pull(&server, &client, 0, b"");
+		assert!(matches!(messages[0], Message::Clear { .. }), "a first sync starts over");
+		assert_eq!(entries(&client), entries(&server));
+
+		write(&server, &[b"c"], &[b"a"]);
+		let (since, messages) = pull(&server, &client, since, b"");
+		assert_eq!(messages.len(), 3, "a Delete, a Put and Done");
+		assert_eq!(entries(&client), entries(&server));
+		assert_eq!(pull(&server, &client, since, b"").1, [Message::Done { seq: since }]);
+	}
+
+	#[test]
+	fn resync_after_trim() {
+		let server = Env::builder().unwrap().with::<Notes>().changelog().build_temp().unwrap();
+		let client = Env::builder().unwrap().with::<Notes>().build_temp().unwrap();
+		write(&server, &[b"a1", b"a2", b"b1"], &[]);
+		let (since, _) = pull(&server, &client, 0, b"a");
+		write(&server, &[b"a3"], &[b"a1"]);
+		write(&server, &[b"a4"], &[]);
+		let tx = server.write_tx().unwrap();
+		assert!(ChangeLog::of(&tx).unwrap().trim_through(since + 2).unwrap() > 0);
+		tx.commit().unwrap();
+
+		let (_, messages) = pull(&server, &client, since, b"a");
+		assert_eq!(messages[0], Message::Clear { table: b"notes".to_vec(), prefix: b"a".to_vec() });
+		let subscribed: Vec<_> = entries(&server).into_iter().filter(|(key, _)| key.starts_with(b"a")).collect();
+		assert_eq!(entries(&client), subscribed);
+	}
+}
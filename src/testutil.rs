@@ -0,0 +1,189 @@
+//! Property-testing helpers: proptest strategies for keys and op sequences, and a harness checking an
+//! [`AssocTable`] against a `BTreeMap` model ordered the way LMDB orders keys - by their archived bytes.
+//! ```ignore
+//! use proptest::prelude::*;
+//! use batadase::testutil;
+//!
+//! proptest! {
+//!     #[test]
+//!     fn users(ops in testutil::ops(any::<String>(), any::<User>(), 8, 64)) {
+//!         let env = Env::builder()?.with::<Users>().build_temp()?;
+//!         testutil::write(&env, |tx| testutil::check_assoc_table(&tx.get::<Users>(), &ops))?;
+//!     }
+//! }
+//! ```
+//! A mismatch panics with the op that caused it, which proptest then shrinks.
+
+use culpa::throws;
+pub use proptest; // so strategies can be combined without depending on a matching version
+use proptest::prelude::*;
+use std::collections::BTreeMap;
+use crate::{AssocTable, Env, Error, RwTxn, Table, Transaction, RkyvSer, RkyvVal, RkyvDe, framing::Framing};
+
+#[derive(Clone, Debug)]
+pub enum Op<K, V> {
+	Put(K, V),
+	PutNoOverwrite(K, V),
+	Delete(K),
+	Get(K),
+	Clear,
+}
+
+/// Keys LMDB accepts as-is: 1 to 511 bytes.
+pub fn key_bytes() -> impl Strategy<Value = Vec<u8>> {
+	proptest::collection::vec(any::<u8>(), 1..=511)
+}
+
+/// Up to `len` ops over a pool of at most `pool` keys, so ops keep hitting keys that exist.
+pub fn ops<K, V>(keys: impl Strategy<Value = K>, values: impl Strategy<Value = V>, pool: usize, len: usize) -> impl Strategy<Value = Vec<Op<K, V>>> where
+	K: Clone + std::fmt::Debug,
+	V: std::fmt::Debug,
+{
+	// puts are the most common so tables actually fill up
+	let kind = prop_oneof![4 => Just(0), 1 => Just(1), 2 => Just(2), 2 => Just(3), 1 => Just(4)];
+	let op = (kind, any::<proptest::sample::Index>(), values);
+	(proptest::collection::vec(keys, 1..=pool.max(1)), proptest::collection::vec(op, 0..=len)).prop_map(|(keys, ops)| {
+		ops.into_iter().map(|(kind, index, value)| {
+			let key = index.get(&keys).clone();
+			match kind {
+				0 => Op::Put(key, value),
+				1 => Op::PutNoOverwrite(key, value),
+				2 => Op::Delete(key),
+				3 => Op::Get(key),
+				_ => Op::Clear,
+			}
+		}).collect()
+	})
+}
+
+/// Run `job` in a write transaction on a non-static env (e.g. from [`EnvBuilder::build_temp`](crate::env::EnvBuilder::build_temp)) and commit it.
+#[throws]
+pub fn write<R>(env: &Env, job: impl FnOnce(&RwTxn) -> R) -> R {
+	let tx = env.write_tx()?;
+	let res = job(&tx);
+	tx.commit()?;
+	res
+}
+
+/// A fresh [`TempEnv`](crate::env::TempEnv) with the tables given registered and filled with the entries given, put
+/// through their `put(&key, &value)` in one transaction:
+/// ```ignore
+/// let env = batadase::fixture! {
+///     Users => { 1 => User::new("ann"), 2 => User::new("bob") },
+///     Tags => { "rust".to_owned() => 2 },
+///     Sessions => {},
+/// }?;
+/// assert_eq!(env.read_tx()?.get::<Users>().entries()?, 2);
+/// ```
+/// Tables iterate in key order whatever order the entries are written in, so tests over fixtures are deterministic.
+#[macro_export]
+macro_rules! fixture {
+	($($name:ty => { $($key:expr => $value:expr),* $(,)? }),* $(,)?) => {
+		(|| -> ::std::result::Result<$crate::env::TempEnv, $crate::Error> {
+			let env = $crate::Env::builder()?$(.with::<$name>())*.build_temp()?;
+			$crate::testutil::write(&env, |tx| -> ::std::result::Result<(), $crate::Error> {
+				$($($crate::Transaction::get::<$name>(tx).put(&$key, &$value)?;)*)*
+				Ok(())
+			})??;
+			Ok(env)
+		})()
+	};
+}
+
+/// Apply `ops` to `table` and to a model, panicking as soon as they disagree. Whatever's in the table beforehand is cleared.
+pub fn check_assoc_table<'tx, K, V, F>(table: &AssocTable<'tx, RwTxn<'tx>, K, V, F>, ops: &[Op<K, V>]) where
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>> + Clone + PartialEq + std::fmt::Debug,
+	V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>> + Clone + PartialEq + std::fmt::Debug,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<K, RkyvDe>,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe> + 'tx,
+{
+	fn archived<T: for <'a> rkyv::Serialize<RkyvSer<'a>>>(x: &T) -> Vec<u8> { rkyv::to_bytes::<rkyv::rancor::Error>(x).expect("serializing failed").to_vec() }
+
+	table.clear().expect("clear failed");
+	let mut model = BTreeMap::<Vec<u8>, (K, V)>::new();
+	for (i, op) in ops.iter().enumerate() {
+		match op {
+			Op::Put(key, value) => {
+				table.put(key, value).unwrap_or_else(|e| panic!("op {i} {op:?} failed: {e}"));
+				model.insert(archived(key), (key.clone(), value.clone()));
+			},
+			Op::PutNoOverwrite(key, value) => {
+				let res = table.put_no_overwrite(key, value);
+				match model.entry(archived(key)) {
+					std::collections::btree_map::Entry::Occupied(_) => assert!(
+						matches!(res.as_ref().map_err(Error::cause), Err(Error::Lmdb(crate::lmdb::Error::KeyExists))),
+						"op {i} {op:?}: expected KeyExists, got {res:?}",
+					),
+					std::collections::btree_map::Entry::Vacant(entry) => {
+						res.unwrap_or_else(|e| panic!("op {i} {op:?} failed: {e}"));
+						entry.insert((key.clone(), value.clone()));
+					},
+				}
+			},
+			Op::Delete(key) => {
+				let deleted = table.delete(key).unwrap_or_else(|e| panic!("op {i} {op:?} failed: {e}"));
+				assert_eq!(deleted, model.remove(&archived(key)).is_some(), "op {i} {op:?}: delete disagrees with the model");
+			},
+			Op::Get(key) => {
+				let got = table.get_unrkyv(key).unwrap_or_else(|e| panic!("op {i} {op:?} failed: {e}"));
+				assert_eq!(got.as_ref(), model.get(&archived(key)).map(|(_, value)| value), "op {i} {op:?}: get disagrees with the model");
+			},
+			Op::Clear => {
+				table.clear().unwrap_or_else(|e| panic!("op {i} {op:?} failed: {e}"));
+				model.clear();
+			},
+		}
+	}
+
+	let entries = table.iter().expect("iter failed")
+		.map(|entry| entry.expect("iter failed"))
+		.map(|(key, value)| (crate::unrkyv::<K>(key).expect("deserializing key failed"), crate::unrkyv::<V>(value).expect("deserializing value failed")))
+		.collect::<Vec<_>>();
+	let expected = model.into_values().collect::<Vec<_>>();
+	assert_eq!(entries, expected, "table contents disagree with the model after all ops");
+	assert_eq!(table.entries().expect("stat failed"), expected.len(), "entry count disagrees with the model");
+}
+
+#[cfg(test)]
+mod tests {
+	use proptest::prelude::*;
+	use crate::{AssocTable, DbName, Env, Transaction};
+	use super::{Op, check_assoc_table, ops, write};
+
+	struct Names;
+
+	impl DbName for Names {
+		type Table<'tx, 'env: 'tx, TX: Transaction<'env> + 'tx> = AssocTable<'tx, TX, u32, String>;
+		const NAME: &'static [u8] = b"names\0";
+	}
+
+	proptest! {
+		#![proptest_config(ProptestConfig::with_cases(32))]
+
+		#[test]
+		fn assoc_table_agrees_with_the_model(ops in ops(any::<u32>(), ".{0,8}", 8, 64)) {
+			let env = Env::builder().unwrap().with::<Names>().build_temp().unwrap();
+			write(&env, |tx| check_assoc_table(&tx.get::<Names>(), &ops)).unwrap();
+		}
+	}
+
+	// every kind of op, and that the model orders keys like LMDB - u32s archive little-endian
+	#[test]
+	fn every_op() {
+		let env = Env::builder().unwrap().with::<Names>().build_temp().unwrap();
+		let ops = [
+			Op::Put(256, "b".to_owned()), Op::Put(1, "a".to_owned()), Op::PutNoOverwrite(1, "x".to_owned()), Op::PutNoOverwrite(2, "c".to_owned()),
+			Op::Get(1), Op::Delete(2), Op::Delete(3), Op::Get(2), Op::Clear, Op::Put(7, "d".to_owned()), Op::Put(256, "e".to_owned()),
+		];
+		write(&env, |tx| check_assoc_table(&tx.get::<Names>(), &ops)).unwrap();
+	}
+
+	#[test]
+	fn fixture() {
+		let env = crate::fixture! { Names => { 2 => "b".to_owned(), 1 => "a".to_owned() } }.unwrap();
+		let tx = env.read_tx().unwrap();
+		let entries: Vec<_> = tx.get::<Names>().iter().unwrap().map(|entry| entry.unwrap().0.to_native()).collect();
+		assert_eq!(entries, [1, 2]);
+	}
+}
@@ -0,0 +1,162 @@
+//! A table split over two envs: a hot one with the active entries on fast storage, and a cold one - an archive, e.g. a
+//! data file copied off and mounted read-only - with the rest. Reads try the hot env first:
+//! ```ignore
+//! static ARCHIVE: LazyLock<Env> = LazyLock::new(|| Env::builder().unwrap().with::<Orders>().read_only().build_existing("/mnt/archive").unwrap());
+//!
+//! ENV.try_write(move |tx| {
+//!     let cold = ARCHIVE.read_tx()?;
+//!     let orders = TieredTable::new(tx.get::<Orders>(), cold.get::<Orders>(), Promotion::OnRead);
+//!     let order = orders.get_and_promote(&id)?; // copied to the hot env if it was cold
+//!     Ok::<_, batadase::Error>(())
+//! }).await??;
+//! ```
+//! Both envs need the table with the same key and value types, framing and flags, and it can't be `DbFlags::DupSort`.
+//! The cold env is never written, so its entries can't be deleted - [`TieredTable::evict`] only drops hot copies of
+//! them, and a hot entry shadows the cold one with the same key.
+
+use culpa::throws;
+use enumflags2::BitFlag;
+use crate::{AssocTable, Transaction, RoTxn, RwTxn, RkyvSer, RkyvVal, RkyvDe, Error, lmdb, error::Context, framing::{Framing, Plain}};
+
+/// when [`TieredTable::get_and_promote`] copies a cold entry to the hot env
+pub enum Promotion<V: rkyv::Archive> {
+	Never,
+	OnRead,
+	/// if the archived value passes the check, e.g. it's of a recent date
+	If(fn(&rkyv::Archived<V>) -> bool),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tier {
+	Hot,
+	Cold,
+}
+
+pub struct TieredTable<'tx, TX, K, V, F = Plain> where
+	V: rkyv::Archive,
+{
+	hot: AssocTable<'tx, TX, K, V, F>,
+	cold: AssocTable<'tx, RoTxn<'tx>, K, V, F>,
+	promotion: Promotion<V>,
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, K, V, F> TieredTable<'tx, RwTxn<'tx>, K, V, F> where
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe> + 'tx,
+{
+	/// always to the hot env
+	#[throws]
+	pub fn put(&self, key: &K, value: &V) { self.hot.put(key, value)?; }
+
+	/// [`TieredTable::get`], copying the entry to the hot env if it's cold and the promotion policy says so
+	#[throws]
+	pub fn get_and_promote(&self, key: &K) -> Option<&'tx rkyv::Archived<V>> {
+		if let Some(value) = self.hot.get(key)? { return Some(value); }
+		let Some(value) = self.cold.get(key)? else { return None };
+		let promote = match self.promotion {
+			Promotion::Never => false,
+			Promotion::OnRead => true,
+			Promotion::If(check) => check(value),
+		};
+		if promote { self.promote(key)?; }
+		Some(value)
+	}
+
+	/// Copy the cold entry of `key` to the hot env as it's stored, without deserializing it. False if the cold env
+	/// doesn't have it.
+	#[throws]
+	pub fn promote(&self, key: &K) -> bool {
+		let mut key = rkyv::to_bytes(key)?;
+		let Some(value) = lmdb::get(self.cold.tx, self.cold.dbi, &mut key).context(self.cold.tx, self.cold.dbi, "promote", Some(&key))? else { return false };
+		lmdb::put(self.hot.tx, self.hot.dbi, &mut key, &mut value.to_vec(), lmdb::PutFlags::empty()).context(self.hot.tx, self.hot.dbi, "promote", Some(&key))?;
+		true
+	}
+
+	/// Drop the hot copy of an entry the cold env has too, so reads get the cold one again. Entries only in the hot env
+	/// are left alone, false if nothing was dropped.
+	#[throws]
+	pub fn evict(&self, key: &K) -> bool {
+		if !self.cold.contains_key(key)? { return false; }
+		self.hot.delete(key)?
+	}
+}
+
+// both RoTxn and RwTxn, so all methods are read-only
+impl<'tx, 'env: 'tx, TX, K, V, F> TieredTable<'tx, TX, K, V, F> where
+	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe> + 'tx,
+{
+	pub fn new(hot: AssocTable<'tx, TX, K, V, F>, cold: AssocTable<'tx, RoTxn<'tx>, K, V, F>, promotion: Promotion<V>) -> Self {
+		Self { hot, cold, promotion }
+	}
+
+	pub fn hot(&self) -> &AssocTable<'tx, TX, K, V, F> { &self.hot }
+	pub fn cold(&self) -> &AssocTable<'tx, RoTxn<'tx>, K, V, F> { &self.cold }
+
+	/// the hot entry, or else the cold one
+	#[throws]
+	pub fn get(&self, key: &K) -> Option<&'tx rkyv::Archived<V>> {
+		match self.hot.get(key)? {
+			Some(value) => Some(value),
+			None => self.cold.get(key)?,
+		}
+	}
+
+	#[throws]
+	pub fn get_unrkyv(&self, key: &K) -> Option<V> {
+		let Some(archived) = self.get(key)? else { return None };
+		Some(rkyv::deserialize::<V, rkyv::rancor::Error>(archived)?)
+	}
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn contains_key(&self, key: &K) -> bool { self.tier(key)?.is_some() }
+
+	/// which env `get` reads the entry from
+	#[throws]
+	pub fn tier(&self, key: &K) -> Option<Tier> {
+		if self.hot.contains_key(key)? { return Some(Tier::Hot); }
+		if self.cold.contains_key(key)? { return Some(Tier::Cold); }
+		None
+	}
+
+	/// Every entry of both envs in key order, the hot one where they both have a key.
+	#[expect(clippy::iter_not_returning_iterator)]
+	#[throws]
+	pub fn iter(&self) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V, F> where
+		rkyv::Archived<K>: 'tx,
+	{
+		let (tx, dbi) = (self.hot.tx, self.hot.dbi);
+		let mut hot = lmdb::Cursor::open(tx, dbi).context(tx, dbi, "iter", None)?;
+		let mut cold = lmdb::Cursor::open(self.cold.tx, self.cold.dbi).context(self.cold.tx, self.cold.dbi, "iter", None)?;
+		let (mut hot_entry, mut cold_entry) = (hot.get(lmdb::CursorOp::First)?, cold.get(lmdb::CursorOp::First)?);
+		// what stepping a cursor failed with, yielded after the entry it stepped from
+		let mut failed = None::<lmdb::Error>;
+		std::iter::from_fn(move || {
+			if let Some(e) = failed.take() {
+				(hot_entry, cold_entry) = (None, None);
+				return Some(Err(e.into()));
+			}
+			let entry = match (hot_entry, cold_entry) {
+				(None, None) => return None,
+				(Some(h), Some(c)) if lmdb::cmp(tx, dbi, h.0, c.0).is_gt() => { cold_entry = cold.get(lmdb::CursorOp::Next).unwrap_or_else(|e| { failed = Some(e); None }); c },
+				(Some(h), Some(c)) => {
+					if lmdb::cmp(tx, dbi, h.0, c.0).is_eq() { cold_entry = cold.get(lmdb::CursorOp::Next).unwrap_or_else(|e| { failed = Some(e); None }); }
+					hot_entry = hot.get(lmdb::CursorOp::Next).unwrap_or_else(|e| { failed = Some(e); None });
+					h
+				},
+				(Some(h), None) => { hot_entry = hot.get(lmdb::CursorOp::Next).unwrap_or_else(|e| { failed = Some(e); None }); h },
+				(None, Some(c)) => { cold_entry = cold.get(lmdb::CursorOp::Next).unwrap_or_else(|e| { failed = Some(e); None }); c },
+			};
+			crate::assoc_table::archived_from_cursor_get::<K, V, F>(Ok(Some(entry)))
+		})
+	}
+}
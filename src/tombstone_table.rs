@@ -0,0 +1,137 @@
+//! A [`TrackedTable`] where deletes leave a tombstone instead of removing the entry, so they show up in
+//! `modified_since` and propagate to replicas like any other change. Reads treat tombstones as absent,
+//! and `purge_tombstones` removes the ones every replica has seen.
+//! ```ignore
+//! #[derive(DbName)]
+//! #[table(TombstoneTable<'tx, TX, DocId, Doc>)]
+//! struct Docs;
+//!
+//! tx.get::<Docs>().delete(&id)?;
+//! for entry in tx.get::<Docs>().modified_since(last_sync)? {
+//!     let (id, change) = entry?;
+//!     send(id, change.value.as_ref()); // None for deletes
+//! }
+//! tx.get::<Docs>().purge_tombstones(oldest_replica_sync)?;
+//! ```
+//! Values are stored as `Tracked<Option<V>>`, with `None` being a tombstone.
+
+use culpa::throws;
+use crate::{TrackedTable, Transaction, RwTxn, Table, RkyvSer, RkyvVal, RkyvDe, Error, lmdb, error::Context, framing::{Framing, Plain}, tracked_table::{self, Tracked, ArchivedTracked}};
+
+pub struct TombstoneTable<'tx, TX, K, V, F = Plain> {
+	inner: TrackedTable<'tx, TX, K, Option<V>, F>,
+}
+
+impl<'tx, 'env: 'tx, TX, K, V, F> Table<'tx, 'env, TX> for TombstoneTable<'tx, TX, K, V, F> where
+	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + 'tx,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<Tracked<Option<V>>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<Tracked<Option<V>>, RkyvDe> + 'tx,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.inner.dbi() }
+	fn txn(&self) -> &TX { self.inner.txn() }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self { Self { inner: Table::build(tx, name) } }
+
+	#[throws]
+	fn check_entry(key: &[u8], value: &[u8]) { TrackedTable::<'tx, TX, K, Option<V>, F>::check_entry(key, value)?; }
+}
+
+impl<'tx, K, V, F> TombstoneTable<'tx, RwTxn<'tx>, K, V, F> where
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>> + 'tx,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<Tracked<Option<V>>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<Tracked<Option<V>>, RkyvDe> + 'tx,
+{
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn put(&self, key: &K, value: &V) { self.put_at(key, value, tracked_table::now())? }
+
+	#[throws]
+	pub fn put_at(&self, key: &K, value: &V, modified: u64) {
+		// archives like a Some(value) without having to own it
+		self.inner.put_as(key, rkyv::with::With::<_, rkyv::with::Map<rkyv::with::Inline>>::cast(&Some(value)), modified)?;
+	}
+
+	/// Replace the entry with a tombstone, whether there was one or not. Returns whether there was a live entry.
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn delete(&self, key: &K) -> bool { self.delete_at(key, tracked_table::now())? }
+
+	#[throws]
+	pub fn delete_at(&self, key: &K, modified: u64) -> bool {
+		let live = self.inner.get(key)?.is_some_and(|value| value.is_some());
+		self.inner.put_at(key, &None, modified)?;
+		live
+	}
+
+	/// Remove the tombstones of deletes before `before` for good, returns how many were removed.
+	/// Walks the whole table.
+	#[throws]
+	pub fn purge_tombstones(&self, before: u64) -> usize {
+		let (tx, dbi) = (self.inner.txn(), self.inner.dbi());
+		let mut cursor = lmdb::Cursor::open(tx, dbi).context(tx, dbi, "purge_tombstones", None)?;
+		let mut entry = cursor.get(lmdb::CursorOp::First)?;
+		let mut purged = 0;
+		while let Some((key, value)) = entry {
+			let tracked = rkyv::access::<rkyv::Archived<Tracked<Option<V>>>, rkyv::rancor::Error>(F::unframe(value)?).context(tx, dbi, "purge_tombstones", Some(key))?;
+			if tracked.value.is_none() && tracked.modified.to_native() < before {
+				let key = key.to_vec(); // the slice goes stale with the delete
+				cursor.del_current().context(tx, dbi, "purge_tombstones", Some(&key))?;
+				purged += 1;
+			}
+			entry = cursor.get(lmdb::CursorOp::Next)?;
+		}
+		purged
+	}
+
+	/// Remove everything, leaving no tombstones.
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn clear(&self) { self.inner.clear()? }
+}
+
+impl<'tx, 'env: 'tx, TX, K, V, F> TombstoneTable<'tx, TX, K, V, F> where
+	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + 'tx,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<Tracked<Option<V>>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<Tracked<Option<V>>, RkyvDe> + 'tx,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { inner: TrackedTable::build(tx, dbi) } }
+
+	/// the tracked table underneath, tombstones included
+	pub fn inner(&self) -> &TrackedTable<'tx, TX, K, Option<V>, F> { &self.inner }
+
+	/// None for tombstones as well as missing entries
+	#[throws]
+	pub fn get(&self, key: &K) -> Option<&'tx rkyv::Archived<V>> {
+		self.inner.get(key)?.and_then(|value| value.as_ref())
+	}
+
+	/// the tombstone or live entry with its timestamps
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn get_tracked(&self, key: &K) -> Option<&'tx ArchivedTracked<Option<V>>> { self.inner.get_tracked(key)? }
+
+	/// Live entries in key order, skipping tombstones.
+	#[throws]
+	pub fn iter(&self) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx rkyv::Archived<V>), Error>> + use<'tx, 'env, TX, K, V, F> where
+		rkyv::Archived<K>: 'tx,
+	{
+		self.inner.inner().iter()?.filter_map(|entry| match entry {
+			Ok((key, tracked)) => Some(Ok((key, tracked.value.as_ref()?))),
+			Err(e) => Some(Err(e)),
+		})
+	}
+
+	/// Entries and tombstones modified at or after `since`, see [`TrackedTable::modified_since`].
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn modified_since(&self, since: u64) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx ArchivedTracked<Option<V>>), Error>> + use<'tx, 'env, TX, K, V, F> where
+		rkyv::Archived<K>: 'tx,
+	{ self.inner.modified_since(since)? }
+}
@@ -0,0 +1,146 @@
+//! An assoc table keeping when each entry was created and last modified next to its value, e.g. for sync protocols
+//! and cache revalidation:
+//! ```ignore
+//! #[derive(DbName)]
+//! #[table(TrackedTable<'tx, TX, DocId, Doc>)]
+//! struct Docs;
+//!
+//! tx.get::<Docs>().put(&id, &doc)?;
+//! let changed = tx.get::<Docs>().modified_since(last_sync)?.collect::<Result<Vec<_>, _>>()?;
+//! ```
+//! Timestamps are milliseconds since the unix epoch, from the system clock unless given with `put_at`.
+//! Values are stored as [`Tracked`], so switching a table to or from tracking needs a migration.
+
+use culpa::throws;
+use enumflags2::BitFlag;
+use std::marker::PhantomData;
+use rkyv::munge::munge;
+use crate::{AssocTable, Transaction, RwTxn, Table, RkyvSer, RkyvVal, RkyvDe, Error, lmdb, error::Context, framing::{Framing, Plain}};
+
+/// the stored value
+#[derive(Debug, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub struct Tracked<V> {
+	pub created: u64,
+	pub modified: u64,
+	pub value: V,
+}
+
+// archives as a Tracked<V>, so puts don't need to own or clone the value - which is anything archiving like a V
+struct TrackedRef<'a, V, W> {
+	created: u64,
+	modified: u64,
+	value: &'a W,
+	_pd: PhantomData<V>,
+}
+
+impl<V: rkyv::Archive, W: rkyv::Archive<Archived = rkyv::Archived<V>>> rkyv::Archive for TrackedRef<'_, V, W> {
+	type Archived = ArchivedTracked<V>;
+	type Resolver = W::Resolver;
+
+	fn resolve(&self, resolver: Self::Resolver, out: rkyv::Place<Self::Archived>) {
+		munge!(let ArchivedTracked { created, modified, value } = out);
+		rkyv::Archive::resolve(&self.created, (), created);
+		rkyv::Archive::resolve(&self.modified, (), modified);
+		self.value.resolve(resolver, value);
+	}
+}
+
+impl<S: rkyv::rancor::Fallible + ?Sized, V: rkyv::Archive, W: rkyv::Serialize<S, Archived = rkyv::Archived<V>>> rkyv::Serialize<S> for TrackedRef<'_, V, W> {
+	fn serialize(&self, serializer: &mut S) -> Result<Self::Resolver, S::Error> { self.value.serialize(serializer) }
+}
+
+/// now in milliseconds since the unix epoch, what `put` stamps entries with
+pub fn now() -> u64 {
+	std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |x| x.as_millis() as u64)
+}
+
+pub struct TrackedTable<'tx, TX, K, V, F = Plain> {
+	inner: AssocTable<'tx, TX, K, Tracked<V>, F>,
+}
+
+impl<'tx, 'env: 'tx, TX, K, V, F> Table<'tx, 'env, TX> for TrackedTable<'tx, TX, K, V, F> where
+	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + 'tx, // so the archived envelope outlives the transaction
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<Tracked<V>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<Tracked<V>, RkyvDe> + 'tx,
+{
+	fn dbi(&self) -> lmdb_sys::MDB_dbi { self.inner.dbi() }
+	fn txn(&self) -> &TX { self.inner.txn() }
+	fn build(tx: &'tx TX, name: &'static [u8]) -> Self { Self { inner: Table::build(tx, name) } }
+
+	#[throws]
+	fn check_entry(key: &[u8], value: &[u8]) { AssocTable::<'tx, TX, K, Tracked<V>, F>::check_entry(key, value)?; }
+}
+
+impl<'tx, K, V, F> TrackedTable<'tx, RwTxn<'tx>, K, V, F> where
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>> + 'tx,
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<Tracked<V>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<Tracked<V>, RkyvDe> + 'tx,
+{
+	/// put stamped with [`now`], keeping the created time of an entry that's replaced
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn put(&self, key: &K, value: &V) { self.put_at(key, value, now())? }
+
+	/// put that was modified at `modified`, which is also the created time if there's no entry yet
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn put_at(&self, key: &K, value: &V, modified: u64) { self.put_as(key, value, modified)? }
+
+	// put_at of anything archiving like a V
+	#[throws]
+	pub(crate) fn put_as<W>(&self, key: &K, value: &W, modified: u64) where
+		W: rkyv::Archive<Archived = rkyv::Archived<V>> + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	{
+		let created = self.inner.get(key)?.map_or(modified, |old| old.created.to_native());
+		let mut key_bytes = rkyv::to_bytes(key)?;
+		let mut value_bytes = F::frame(rkyv::to_bytes(&TrackedRef::<V, W> { created, modified, value, _pd: PhantomData })?);
+		let (tx, dbi) = (self.inner.txn(), self.inner.dbi());
+		lmdb::put(tx, dbi, &mut key_bytes, &mut value_bytes, lmdb::PutFlags::empty()).context(tx, dbi, "put", Some(&key_bytes))?;
+	}
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn delete(&self, key: &K) -> bool { self.inner.delete(key)? }
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn clear(&self) { self.inner.clear()? }
+}
+
+impl<'tx, 'env: 'tx, TX, K, V, F> TrackedTable<'tx, TX, K, V, F> where
+	TX: Transaction<'env>,
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + 'tx, // so the archived envelope outlives the transaction
+	rkyv::Archived<K>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	rkyv::Archived<Tracked<V>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<Tracked<V>, RkyvDe> + 'tx,
+{
+	pub fn build(tx: &'tx TX, dbi: lmdb_sys::MDB_dbi) -> Self { Self { inner: AssocTable::build(tx, dbi) } }
+
+	/// the assoc table underneath, with the timestamps
+	pub fn inner(&self) -> &AssocTable<'tx, TX, K, Tracked<V>, F> { &self.inner }
+
+	#[throws]
+	pub fn get(&self, key: &K) -> Option<&'tx rkyv::Archived<V>> {
+		self.inner.get(key)?.map(|tracked| &tracked.value)
+	}
+
+	/// the value with its timestamps
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn get_tracked(&self, key: &K) -> Option<&'tx ArchivedTracked<V>> { self.inner.get(key)? }
+
+	/// Entries modified at or after `since`, in key order. There's no index on the times, so this walks the whole table.
+	#[throws]
+	pub fn modified_since(&self, since: u64) -> impl Iterator<Item = Result<(&'tx rkyv::Archived<K>, &'tx ArchivedTracked<V>), Error>> + use<'tx, 'env, TX, K, V, F> where
+		rkyv::Archived<K>: 'tx,
+	{
+		// errors are kept for the caller
+		self.inner.iter()?.filter(move |entry| entry.as_ref().map_or(true, |(_, tracked)| tracked.modified.to_native() >= since))
+	}
+}
@@ -1,5 +1,6 @@
-use culpa::throws;
-use crate::{DbName, Error, lmdb};
+use culpa::{throw, throws};
+use enumflags2::BitFlag;
+use crate::{DbName, Error, lmdb, env::Durability, error::Context};
 
 // The third Triagon was born of Death. It saw that the world was radiating excess energy.
 // It wanted to put great things into motion. But greatness wasn't possible without value. The first transaction.
@@ -19,6 +20,11 @@ pub struct RoTxn<'env> {
 pub struct RwTxn<'env> {
 	pub(super) raw: *mut lmdb_sys::MDB_txn,
 	pub(super) env: &'env super::Env,
+	pub(super) durability: std::sync::Arc<std::sync::Mutex<Option<Durability>>>, // overrides the env's for this commit, shared with savepoints
+	pub(super) written: std::sync::atomic::AtomicUsize,
+	pub(super) pressure: Option<f32>, // map usage if it was over the backpressure watermark when this began
+	pub(super) used_before: Option<usize>, // Env::used_pages as this began, when tracking write amplification
+	pub(super) temps: std::sync::Mutex<Vec<lmdb_sys::MDB_dbi>>, // see RwTxn::temp_dbi, deleted before committing
 }
 
 /// it is Sync + Send since you can't close a db after you open it
@@ -56,8 +62,13 @@ impl RoTxn {
 */
 
 pub trait Transaction<'env>: Sized {
+	/// Escape hatch for `lmdb_sys` functions that aren't wrapped, valid until the transaction is committed or dropped.
+	/// Don't commit, abort, reset or renew it through lmdb_sys - the wrapper does that and would do it twice.
+	/// Data lmdb returns for it is only valid while the transaction is alive, and for write transactions, until the next write.
 	fn raw(&self) -> *mut lmdb_sys::MDB_txn;
 	fn env(&self) -> &'env super::Env;
+	/// for read transactions, the id of the snapshot being read
+	fn id(&self) -> usize { unsafe { lmdb_sys::mdb_txn_id(self.raw()) } }
 	#[throws]
 	fn commit(self) {
 		lmdb::txn_commit(self.raw())?;
@@ -69,16 +80,428 @@ pub trait Transaction<'env>: Sized {
 		// runs Drop, which does mdb_txn_abort
 	}
 	fn get<'tx, Name: DbName>(&'tx self) -> Name::Table<'tx, 'env, Self> { Name::get(self) }
+	#[expect(unused_braces)]
+	#[throws]
+	fn stat(&self, dbi: lmdb_sys::MDB_dbi) -> lmdb::Stat { lmdb::stat(self.raw(), dbi)? }
 }
 
 impl<'env> Transaction<'env> for RoTxn<'env> {
 	fn raw(&self) -> *mut lmdb_sys::MDB_txn { self.raw }
 	fn env(&self) -> &'env super::Env { self.env }
+
+	#[throws]
+	fn commit(self) {
+		let (env, raw) = (self.env, self.raw);
+		let res = lmdb::txn_commit(raw);
+		std::mem::forget(self);
+		env.end(raw);
+		res?;
+	}
 }
 impl<'env> Transaction<'env> for RwTxn<'env> {
 	fn raw(&self) -> *mut lmdb_sys::MDB_txn { self.raw }
 	fn env(&self) -> &'env super::Env { self.env }
+
+	// lmdb only has sync flags on the env, and switching them for one commit would race the next writer, which may
+	// begin and commit as soon as lmdb's writer mutex is released - so it commits under the env's flags and a
+	// stronger override syncs right after
+	#[throws]
+	fn commit(self) {
+		self.drop_temps()?;
+		let (env, raw, written, id, used_before) = (self.env, self.raw, self.bytes_written(), self.id(), self.used_before);
+		let durability = *self.durability.lock().unwrap();
+		let start = std::time::Instant::now();
+		let res = lmdb::txn_commit(self.raw);
+		env.commits.record(start.elapsed());
+		std::mem::forget(self);
+		env.end(raw);
+		res.inspect_err(|e| env.note_error(e))?;
+		env.add_unsynced(written);
+		if let Some(used_before) = used_before { env.record_amplification(id, written, used_before); }
+		if let Some(durability) = durability && durability.stronger_than(env.durability()?) { env.sync()?; }
+	}
+}
+
+impl RwTxn<'_> {
+	/// Commit this transaction at least as durably as `durability`, e.g. [`Durability::Full`] for the one write
+	/// that must not be lost in an env that's otherwise [`Durability::NoSync`]. The commit goes through under the
+	/// env's durability and is then flushed with [`Env::sync`](crate::Env::sync) if `durability` is stronger - an Err
+	/// from that flush means the transaction committed but may not be on disk. A weaker one than the env's is ignored.
+	/// Set through a [`Savepoint`] it's for the transaction the savepoint is of, whether the savepoint is released or not.
+	pub fn set_durability(&self, durability: Durability) {
+		*self.durability.lock().unwrap() = Some(durability);
+	}
+
+	/// Key and value bytes put so far, not counting what LMDB writes around them - a rough measure of how big the commit gets.
+	pub fn bytes_written(&self) -> usize { self.written.load(std::sync::atomic::Ordering::Relaxed) }
+
+	/// Move every entry of the db `old` into a new db `new` created with the same flags, then delete `old`.
+	/// LMDB can't rename dbs, so this copies everything. Names may be given with or without the trailing nul.
+	/// Deleting `old` closes its handle right away, even if the transaction is then aborted, so neither name can be
+	/// registered in the env - that fails with [`Error::RegisteredDb`]. Rename from an env built without either table,
+	/// then reopen it with the table registered under `new` to use it. The env needs `maxdbs` room for `new`.
+	/// Since neither db is registered the [`changelog`](crate::changelog) doesn't record the rename.
+	#[throws]
+	pub fn rename_database(&self, old: &[u8], new: &[u8]) {
+		let with_nul = |name: &[u8]| [name.strip_suffix(b"\0").unwrap_or(name), b"\0"].concat();
+		let (old, new) = (with_nul(old), with_nul(new));
+		if let Some(name) = [&old, &new].into_iter().find(|name| self.env.db(name).is_some()) { throw!(Error::RegisteredDb(crate::db_name_lossy(name))); }
+		let Some(old_dbi) = lmdb::dbi_open_existing(self.raw, Some(&old))? else { throw!(crate::dump::unknown_db(&old)) };
+		if lmdb::dbi_open_existing(self.raw, Some(&new))?.is_some() { throw!(lmdb::Error::KeyExists); }
+		let flags = lmdb::dbi_flags(self.raw, old_dbi)?;
+		let new_dbi = lmdb::dbi_open(self.raw, &new, flags | lmdb::DbFlags::Create)?;
+		self.append_all(old_dbi, new_dbi)?;
+		lmdb::drop_delete(self, old_dbi)?;
+		log::info!("renamed db {} to {}", crate::db_name_lossy(&old), crate::db_name_lossy(&new));
+	}
+
+	/// Replace the contents of the db `target` with the contents of `replacement` and empty `replacement`,
+	/// for rebuilding a table next to the live one. Readers flip from the old contents to the new ones when this commits,
+	/// but LMDB can't swap dbs under their names, so the entries are copied. Both dbs need the same flags.
+	#[throws]
+	pub fn replace_database(&self, target: &[u8], replacement: &[u8]) {
+		let Some(target_dbi) = crate::dump::lookup(self.env, target) else { throw!(crate::dump::unknown_db(target)) };
+		let Some(replacement_dbi) = crate::dump::lookup(self.env, replacement) else { throw!(crate::dump::unknown_db(replacement)) };
+		if target_dbi == replacement_dbi { return; }
+		if lmdb::dbi_flags(self.raw, target_dbi)? != lmdb::dbi_flags(self.raw, replacement_dbi)? { throw!(lmdb::Error::Incompatible); }
+		lmdb::drop(self, target_dbi)?;
+		self.append_all(replacement_dbi, target_dbi)?;
+		lmdb::drop(self, replacement_dbi)?;
+		log::info!("replaced db {} with {}", crate::db_name_lossy(target), crate::db_name_lossy(replacement));
+	}
+
+	/// Rewrite the db `name` in key order, for defragmenting one table that's seen a lot of churn without compacting the
+	/// whole env: after random puts and deletes its pages are part empty and scattered over the file, appending packs
+	/// them full and in order. LMDB can't swap dbs, so the entries are appended to a scratch db `<name>.rewrite` and back,
+	/// all in this transaction - readers see the same entries before and after, and the env needs `maxdbs` room for the
+	/// scratch db. Every entry is written twice, so a big table may need a transaction of its own to not run into
+	/// TxnFull. Returns the entries rewritten.
+	#[throws]
+	pub fn rewrite_database(&self, name: &[u8]) -> usize {
+		let Some(dbi) = crate::dump::lookup(self.env, name) else { throw!(crate::dump::unknown_db(name)) };
+		self.rewrite(dbi)?
+	}
+
+	#[throws]
+	pub(crate) fn rewrite(&self, dbi: lmdb_sys::MDB_dbi) -> usize {
+		let name = self.env.db_name(dbi).ok_or(lmdb::Error::BadDbi)?;
+		let scratch = [name.strip_suffix(b"\0").unwrap_or(name), b".rewrite\0"].concat();
+		if lmdb::dbi_open_existing(self.raw, Some(&scratch))?.is_some() { throw!(lmdb::Error::KeyExists); }
+		let scratch_dbi = lmdb::dbi_open(self.raw, &scratch, lmdb::dbi_flags(self.raw, dbi)? | lmdb::DbFlags::Create)?;
+		// the values have to be in the same order for AppendDup
+		if let Some(order) = self.env.dup_order(dbi) { lmdb::set_dupsort(self.raw, scratch_dbi, order)?; }
+		self.append_all(dbi, scratch_dbi)?;
+		lmdb::drop(self, dbi)?;
+		self.append_all(scratch_dbi, dbi)?;
+		lmdb::drop_delete(self, scratch_dbi)?;
+		let entries = lmdb::stat(self.raw, dbi)?.entries;
+		log::info!("rewrote db {}, {entries} entries", crate::db_name_lossy(name));
+		entries
+	}
+
+	/// A new scratch db for intermediate results, not registered in the env and deleted when this commits - and never there if it aborts.
+	/// Build a table on it with the table's `build(tx, dbi)`, e.g. `AssocTable::<_, u64, Row>::build(tx, tx.temp_dbi(..)?)`,
+	/// and don't keep the dbi past this transaction. Temporary dbs count against `maxdbs`, so the env needs room for
+	/// them with [`EnvBuilder::maxdbs`](crate::env::EnvBuilder::maxdbs).
+	#[throws]
+	pub fn temp_dbi(&self, flags: enumflags2::BitFlags<lmdb::DbFlags>) -> lmdb_sys::MDB_dbi {
+		let mut temps = self.temps.lock().unwrap();
+		let mut n = temps.len();
+		// a savepoint's are named after its parent's, and a table could be called this too
+		let name = loop {
+			let name = format!("batadase.temp.{n}\0").into_bytes();
+			if lmdb::dbi_open_existing(self.raw, Some(&name))?.is_none() { break name; }
+			n += 1;
+		};
+		let dbi = lmdb::dbi_open(self.raw, &name, flags | lmdb::DbFlags::Create)?;
+		temps.push(dbi);
+		dbi
+	}
+
+	/// [`RwTxn::temp_dbi`] as a table of byte strings
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn temp_table(&self) -> crate::RawTable<'_, Self> { crate::RawTable::build(self, self.temp_dbi(lmdb::DbFlags::empty())?) }
+
+	#[throws]
+	fn drop_temps(&self) {
+		for dbi in std::mem::take(&mut *self.temps.lock().unwrap()) { lmdb::drop_delete(self, dbi)?; }
+	}
+
+	/// Copy every db of `src` into the db of the same name in this env, e.g. to consolidate databases collected from
+	/// devices. Keys that already exist here are handled by `conflict`. Every db of `src` has to be registered here,
+	/// open `src` with [`EnvBuilder::build_existing`](crate::env::EnvBuilder::build_existing) to merge all it has.
+	/// For dupsort dbs missing values are added and there are no conflicts.
+	#[throws]
+	pub fn merge_from(&self, src: &super::Env, mut conflict: Conflict<'_>) -> MergeStats {
+		let src_tx = src.read_tx()?;
+		let mut stats = MergeStats::default();
+		for (name, src_dbi) in src.dbs() {
+			let Some(dbi) = self.env.db(name) else { throw!(crate::dump::unknown_db(name)) };
+			let dup_sort = lmdb::dbi_flags(self.raw, dbi)?.contains(lmdb::DbFlags::DupSort);
+			let mut cursor = lmdb::Cursor::open(&src_tx, src_dbi)?;
+			while let Some((key, value)) = cursor.get(lmdb::CursorOp::Next)? {
+				let mut key = key.to_vec();
+				if dup_sort {
+					match lmdb::put(self, dbi, &mut key, &mut value.to_vec(), lmdb::PutFlags::NoDupData.into()) {
+						Err(e) if matches!(e.cause(), Error::Lmdb(lmdb::Error::KeyExists)) => stats.skipped += 1,
+						res => { res.context(self, dbi, "merge_from", Some(&key))?; stats.inserted += 1; },
+					}
+					continue;
+				}
+				let Some(existing) = lmdb::get(self, dbi, &mut key).context(self, dbi, "merge_from", Some(&key))? else {
+					lmdb::put(self, dbi, &mut key, &mut value.to_vec(), lmdb::PutFlags::empty()).context(self, dbi, "merge_from", Some(&key))?;
+					stats.inserted += 1;
+					continue;
+				};
+				if existing == value { stats.skipped += 1; continue; }
+				let merged = match &mut conflict {
+					Conflict::Skip => None,
+					Conflict::Overwrite => Some(value.to_vec()),
+					Conflict::Error => Err(lmdb::Error::KeyExists).context(self, dbi, "merge_from", Some(&key))?,
+					Conflict::Resolve(resolve) => resolve(name, &key, existing, value),
+				};
+				let Some(mut merged) = merged else { stats.skipped += 1; continue; };
+				lmdb::put(self, dbi, &mut key, &mut merged, lmdb::PutFlags::empty()).context(self, dbi, "merge_from", Some(&key))?;
+				stats.overwritten += 1;
+			}
+		}
+		log::info!("merged {} entries in, {} overwritten, {} skipped", stats.inserted, stats.overwritten, stats.skipped);
+		stats
+	}
+
+	// dst has to be empty
+	#[throws]
+	fn append_all(&self, src_dbi: lmdb_sys::MDB_dbi, dst_dbi: lmdb_sys::MDB_dbi) {
+		// entries come out in order, so appending skips the searches
+		let dup_sort = lmdb::dbi_flags(self.raw, src_dbi)?.contains(lmdb::DbFlags::DupSort);
+		let mut cursor = lmdb::Cursor::open(self, src_dbi)?;
+		let mut last_key = None::<Vec<u8>>;
+		while let Some((key, value)) = cursor.get(lmdb::CursorOp::Next)? {
+			// MDB_APPEND fails with KeyExists for a key that's already there, more values of it need MDB_APPENDDUP alone
+			let flags = if dup_sort && last_key.as_deref() == Some(key) { lmdb::PutFlags::AppendDup } else { lmdb::PutFlags::Append };
+			lmdb::put(self, dst_dbi, &mut key.to_vec(), &mut value.to_vec(), flags.into())?;
+			if dup_sort { last_key = Some(key.to_vec()); }
+		}
+	}
+}
+
+/// What [`RwTxn::merge_from`] does with a key that's in both envs with different values.
+pub enum Conflict<'f> {
+	/// keep this env's value
+	Skip,
+	/// take the other env's value
+	Overwrite,
+	/// fail the merge with KeyExists
+	Error,
+	/// The value to store given the db name, key, this env's value and the other env's, None keeps this env's.
+	/// Values are the stored bytes, framing included.
+	Resolve(Resolver<'f>),
+}
+
+pub type Resolver<'f> = &'f mut dyn FnMut(&[u8], &[u8], &[u8], &[u8]) -> Option<Vec<u8>>;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeStats {
+	pub inserted: usize,
+	pub overwritten: usize,
+	/// already there with the same value, or kept by the conflict policy
+	pub skipped: usize,
+}
+
+impl<'env> RwTxn<'env> {
+	/// Mark a point to roll back to: writes done through the savepoint are undone if it's dropped, and become part of
+	/// this transaction with [`Savepoint::release`]. It's a nested LMDB transaction, so this one fails with `BadTxn` until
+	/// the savepoint is released or dropped, and envs with [`EnvBuilder::write_map`](crate::env::EnvBuilder::write_map)
+	/// can't have them.
+	/// ```ignore
+	/// for stage in stages {
+	///     let savepoint = tx.savepoint()?;
+	///     match stage.run(&savepoint) {
+	///         Ok(()) => savepoint.release()?,
+	///         Err(e) => log::warn!("skipping stage {}: {e}", stage.name), // dropping it rolls back
+	///     }
+	/// }
+	/// ```
+	#[throws]
+	pub fn savepoint(&self) -> Savepoint<'_, 'env> {
+		if lmdb::env_flags(self.env.raw())? & lmdb_sys::MDB_WRITEMAP != 0 { throw!(Error::WriteMap("a savepoint")); }
+		let raw = lmdb::txn_begin_nested(self.env.raw(), self.raw)?;
+		crate::borrows::begin(raw);
+		Savepoint {
+			child: std::mem::ManuallyDrop::new(RwTxn { raw, env: self.env, durability: std::sync::Arc::clone(&self.durability), written: Default::default(), pressure: self.pressure, used_before: None, temps: Default::default() }),
+			parent: self,
+		}
+	}
+}
+
+/// see [`RwTxn::savepoint`], derefs to the write transaction to write through
+pub struct Savepoint<'tx, 'env> {
+	// ended here rather than by RwTxn's drop, it isn't counted by the env like transactions it began are
+	child: std::mem::ManuallyDrop<RwTxn<'env>>,
+	parent: &'tx RwTxn<'env>,
+}
+
+impl<'env> Savepoint<'_, 'env> {
+	/// keep the savepoint's writes in the parent transaction
+	#[throws]
+	pub fn release(self) {
+		let this = std::mem::ManuallyDrop::new(self);
+		// frees the child whether it succeeds or not
+		let res = lmdb::txn_commit(this.child.raw);
+		crate::borrows::end(this.child.raw);
+		res.inspect_err(|e| this.child.env.note_error(e))?;
+		this.parent.written.fetch_add(this.child.bytes_written(), std::sync::atomic::Ordering::Relaxed);
+		this.parent.temps.lock().unwrap().append(&mut this.child.temps.lock().unwrap());
+	}
+
+	/// undo the savepoint's writes, same as dropping it
+	pub fn rollback(self) {}
+}
+
+impl<'env> std::ops::Deref for Savepoint<'_, 'env> {
+	type Target = RwTxn<'env>;
+
+	fn deref(&self) -> &Self::Target { &self.child }
+}
+
+impl Drop for Savepoint<'_, '_> {
+	fn drop(&mut self) {
+		unsafe { lmdb_sys::mdb_txn_abort(self.child.raw); }
+		crate::borrows::end(self.child.raw);
+		std::mem::take(self.child.temps.get_mut().unwrap());
+	}
+}
+impl Drop for RoTxn<'_> { fn drop(&mut self) { unsafe { lmdb_sys::mdb_txn_abort(self.raw); } self.env.end(self.raw); } }
+impl Drop for RwTxn<'_> { fn drop(&mut self) { unsafe { lmdb_sys::mdb_txn_abort(self.raw); } self.env.end(self.raw); } }
+
+/// see [`Env::read_view`](crate::Env::read_view), tables got from it all read the same snapshot. It's only lent to the
+/// closure, so neither it nor its tables can be kept around and mixed up with another transaction's.
+pub struct ReadView<'env> {
+	pub(crate) tx: RoTxn<'env>,
+}
+
+impl<'env> ReadView<'env> {
+	/// the table of N, built when it's asked for - which only looks up its dbi
+	pub fn get<N: DbName>(&self) -> N::Table<'_, 'env, RoTxn<'env>> { self.tx.get::<N>() }
+	/// id of the snapshot being read
+	pub fn id(&self) -> usize { self.tx.id() }
+	/// the transaction underneath, for functions taking one
+	pub fn txn(&self) -> &RoTxn<'env> { &self.tx }
+}
+
+/// A write transaction that can only be read through, see [`RwTxn::read_only`]. Tables got from it are typed over it
+/// rather than over the `RwTxn`, so they don't have any of the mutating methods - for handing tables to code that
+/// mustn't write, checked by the compiler:
+/// ```ignore
+/// fn report(users: &AssocTable<'_, ReadOnly<'_>, UserId, User>) { /* users.put(..) doesn't compile */ }
+///
+/// ENV.write(|tx| {
+///     tx.get::<Users>().put(&id, &user)?;
+///     report(&tx.read_only().get::<Users>()); // sees the put
+///     Ok(())
+/// }).await??;
+/// ```
+/// It's only ever lent out, so it can't be committed or aborted either.
+#[repr(transparent)]
+pub struct ReadOnly<'env>(RwTxn<'env>);
+
+impl<'env> Transaction<'env> for ReadOnly<'env> {
+	fn raw(&self) -> *mut lmdb_sys::MDB_txn { self.0.raw }
+	fn env(&self) -> &'env super::Env { self.0.env }
+}
+
+impl<'env> RwTxn<'env> {
+	/// this transaction without its writes, see [`ReadOnly`]
+	pub fn read_only(&self) -> &ReadOnly<'env> {
+		// repr(transparent), so the layout is the same
+		let ptr = std::ptr::from_ref::<RwTxn>(self).cast::<ReadOnly>();
+		unsafe { &*ptr }
+	}
+}
+
+/// [`Transaction`] as a trait object, for services taking `&dyn AnyTxn` instead of being generic over the transaction
+/// type - `Transaction` itself can't be one, its methods are generic and commit takes it by value:
+/// ```ignore
+/// fn user_name(tx: &dyn AnyTxn<'_>, id: &UserId) -> Result<Option<String>, Error> {
+///     Ok(tx.get::<Users>().get(id)?.map(|user| user.name.to_string()))
+/// }
+///
+/// fn rename(tx: &dyn AnyTxn<'_>, id: &UserId, name: &str) -> Result<(), Error> {
+///     let Some(tx) = tx.as_rw() else { return Err(lmdb::Error::TxnPerm.into()) };
+///     tx.get::<Users>().put(id, &User::new(name))
+/// }
+/// ```
+/// Tables got from a `&dyn AnyTxn` have the read-only methods, writes go through [`AnyTxn::as_rw`]. Tests pass
+/// transactions of a temp env, see [`EnvBuilder::build_temp`](crate::env::EnvBuilder::build_temp) - tables read
+/// LMDB's map directly, so [`MemEnv`](crate::mem_env::MemEnv) only stands in for code using the
+/// [`backend`](crate::backend) traits.
+pub trait AnyTxn<'env> {
+	fn raw(&self) -> *mut lmdb_sys::MDB_txn;
+	fn env(&self) -> &'env super::Env;
+	/// None for read transactions and [`ReadOnly`]
+	fn as_rw(&self) -> Option<&RwTxn<'env>>;
+}
+
+impl<'env> AnyTxn<'env> for RoTxn<'env> {
+	fn raw(&self) -> *mut lmdb_sys::MDB_txn { self.raw }
+	fn env(&self) -> &'env super::Env { self.env }
+	fn as_rw(&self) -> Option<&RwTxn<'env>> { None }
+}
+
+impl<'env> AnyTxn<'env> for RwTxn<'env> {
+	fn raw(&self) -> *mut lmdb_sys::MDB_txn { self.raw }
+	fn env(&self) -> &'env super::Env { self.env }
+	fn as_rw(&self) -> Option<&RwTxn<'env>> { Some(self) }
+}
+
+impl<'env> AnyTxn<'env> for ReadOnly<'env> {
+	fn raw(&self) -> *mut lmdb_sys::MDB_txn { self.0.raw }
+	fn env(&self) -> &'env super::Env { self.0.env }
+	fn as_rw(&self) -> Option<&RwTxn<'env>> { None }
 }
 
-impl Drop for RoTxn<'_> { fn drop(&mut self) { unsafe { lmdb_sys::mdb_txn_abort(self.raw); } } }
-impl Drop for RwTxn<'_> { fn drop(&mut self) { unsafe { lmdb_sys::mdb_txn_abort(self.raw); } } }
+impl<'env> Transaction<'env> for &dyn AnyTxn<'env> {
+	fn raw(&self) -> *mut lmdb_sys::MDB_txn { AnyTxn::raw(*self) }
+	fn env(&self) -> &'env super::Env { AnyTxn::env(*self) }
+
+	// it's only lent, whoever owns the transaction ends it
+	#[throws]
+	fn commit(self) { throw!(lmdb::Error::BadTxn); }
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::{DbName, Env, RawTable, Transaction, env::Durability};
+
+	struct Blobs;
+
+	impl DbName for Blobs {
+		type Table<'tx, 'env: 'tx, TX: Transaction<'env> + 'tx> = RawTable<'tx, TX>;
+		const NAME: &'static [u8] = b"blobs\0";
+	}
+
+	#[test]
+	fn stronger_durability_syncs() {
+		let env = Env::builder().unwrap().with::<Blobs>().durability(Durability::NoSync).build_temp().unwrap();
+		let syncs = || env.commit_latency().sync.count();
+		let tx = env.write_tx().unwrap();
+		tx.get::<Blobs>().put(b"a", b"1").unwrap();
+		tx.commit().unwrap();
+		assert_eq!(syncs(), 0);
+
+		let tx = env.write_tx().unwrap();
+		tx.set_durability(Durability::NoSync);
+		tx.commit().unwrap();
+		assert_eq!(syncs(), 0);
+
+		let tx = env.write_tx().unwrap();
+		let savepoint = tx.savepoint().unwrap();
+		savepoint.set_durability(Durability::Full);
+		savepoint.get::<Blobs>().put(b"b", b"2").unwrap();
+		savepoint.release().unwrap();
+		tx.commit().unwrap();
+		assert_eq!(syncs(), 1, "set through the savepoint");
+		assert_eq!(env.durability().unwrap(), Durability::NoSync, "the env's flags are left alone");
+	}
+}
@@ -0,0 +1,187 @@
+//! A sled-style facade for small tools that don't care about LMDB details: trees are named at runtime,
+//! keys and values are plain bytes and every operation runs in its own transaction.
+//! ```ignore
+//! let db = batadase::Db::open("data")?;
+//! let users = db.tree("users")?;
+//! users.insert("alice", "admin")?;
+//! assert_eq!(users.get("alice")?.as_deref(), Some(&b"admin"[..]));
+//! // grouping operations into one transaction
+//! db.transaction(|tx| {
+//!     users.insert_in(tx, "bob", "user")?;
+//!     users.remove_in(tx, "alice")
+//! })?;
+//! ```
+//! Write transactions are blocking - don't open trees or run single-operation writes from inside [`Db::transaction`],
+//! they'd wait for the transaction they're called from.
+
+use culpa::{throw, throws};
+use std::collections::HashMap;
+use crate::{Env, Error, Transaction, RwTxn, env::EnvBuilder, lmdb::{self, DbFlags}};
+
+pub struct Db {
+	env: Env,
+	trees: std::sync::Mutex<HashMap<Vec<u8>, lmdb_sys::MDB_dbi>>, // ones created after opening
+}
+
+#[derive(Clone, Copy)]
+pub struct Tree<'db> {
+	db: &'db Db,
+	dbi: lmdb_sys::MDB_dbi,
+}
+
+impl Db {
+	/// Open or create a db in the directory `path` with a 1 GiB map and up to 128 trees.
+	#[throws]
+	pub fn open(path: impl AsRef<std::path::Path>) -> Self {
+		Self::open_with(Env::builder()?.mapsize(1 << 30)?, path)?
+	}
+
+	/// Like [`Db::open`] with a builder configured by the caller.
+	#[throws]
+	pub fn open_with(builder: EnvBuilder, path: impl AsRef<std::path::Path>) -> Self {
+		std::fs::create_dir_all(&path)?;
+		Self { env: builder.build_existing(&crate::path_to_cstring(path.as_ref())?)?, trees: Default::default() }
+	}
+
+	/// the env underneath, for explicit read transactions and everything the facade doesn't cover
+	pub fn env(&self) -> &Env { &self.env }
+
+	/// Open the tree `name`, creating it if it doesn't exist.
+	#[throws]
+	pub fn tree(&self, name: &str) -> Tree<'_> {
+		if name.contains('\0') { throw!(Error::InvalidEntry("tree names can't contain a nul")); }
+		let name = [name.as_bytes(), b"\0"].concat();
+		if let Some(dbi) = self.env.db(&name) { return Tree { db: self, dbi }; }
+
+		let mut trees = self.trees.lock().unwrap();
+		if let Some(&dbi) = trees.get(&name) { return Tree { db: self, dbi }; }
+		let tx = self.env.write_tx()?;
+		let dbi = lmdb::dbi_open(tx.raw(), &name, DbFlags::Create.into())?;
+		tx.commit()?;
+		trees.insert(name, dbi);
+		Tree { db: self, dbi }
+	}
+
+	/// names of every tree, sorted
+	#[throws]
+	pub fn tree_names(&self) -> Vec<String> {
+		let trees = self.trees.lock().unwrap();
+		let mut names = self.env.dbs().into_iter().map(|(name, _)| name).chain(trees.keys().map(Vec::as_slice))
+			.map(crate::db_name_lossy)
+			.collect::<Vec<_>>();
+		names.sort();
+		names
+	}
+
+	/// Run `job` in a write transaction, which is committed if it returns `Ok`.
+	#[throws]
+	pub fn transaction<R>(&self, job: impl FnOnce(&RwTxn) -> Result<R, Error>) -> R {
+		let tx = self.env.write_tx()?;
+		let res = job(&tx)?;
+		tx.commit()?;
+		res
+	}
+}
+
+impl<'db> Tree<'db> {
+	pub fn db(&self) -> &'db Db { self.db }
+
+	#[throws]
+	pub fn get(&self, key: impl AsRef<[u8]>) -> Option<Vec<u8>> {
+		let tx = self.db.env.read_tx()?;
+		self.get_in(&tx, key)?.map(<[u8]>::to_vec)
+	}
+
+	#[throws]
+	pub fn contains_key(&self, key: impl AsRef<[u8]>) -> bool {
+		let tx = self.db.env.read_tx()?;
+		self.get_in(&tx, key)?.is_some()
+	}
+
+	/// returns the previous value
+	#[throws]
+	pub fn insert(&self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Option<Vec<u8>> {
+		self.db.transaction(|tx| {
+			let old = self.get_in(tx, &key)?.map(<[u8]>::to_vec);
+			self.insert_in(tx, key, value)?;
+			Ok(old)
+		})?
+	}
+
+	/// returns the removed value
+	#[throws]
+	pub fn remove(&self, key: impl AsRef<[u8]>) -> Option<Vec<u8>> {
+		self.db.transaction(|tx| {
+			let old = self.get_in(tx, &key)?.map(<[u8]>::to_vec);
+			self.remove_in(tx, key)?;
+			Ok(old)
+		})?
+	}
+
+	#[throws]
+	pub fn len(&self) -> usize {
+		let tx = self.db.env.read_tx()?;
+		tx.stat(self.dbi)?.entries
+	}
+
+	#[expect(unused_braces)]
+	#[throws]
+	pub fn is_empty(&self) -> bool { self.len()? == 0 }
+
+	/// Every entry in key order, read in one transaction - use [`Tree::iter_in`] to walk big trees without collecting them.
+	#[throws]
+	pub fn iter(&self) -> std::vec::IntoIter<(Vec<u8>, Vec<u8>)> {
+		let tx = self.db.env.read_tx()?;
+		self.iter_in(&tx)?.map(|entry| entry.map(|(key, value)| (key.to_vec(), value.to_vec()))).collect::<Result<Vec<_>, _>>()?.into_iter()
+	}
+
+	#[throws]
+	pub fn get_in<'tx, 'env: 'tx>(&self, tx: &'tx impl Transaction<'env>, key: impl AsRef<[u8]>) -> Option<&'tx [u8]> {
+		lmdb::get(tx, self.dbi, &mut key.as_ref().to_vec())?
+	}
+
+	/// [`Tree::get_in`] as a reader, for streaming big values out of the map without copying them
+	#[throws]
+	pub fn open_reader_in<'tx, 'env: 'tx>(&self, tx: &'tx impl Transaction<'env>, key: impl AsRef<[u8]>) -> Option<std::io::Cursor<&'tx [u8]>> {
+		self.get_in(tx, key)?.map(std::io::Cursor::new)
+	}
+
+	#[throws]
+	pub fn insert_in(&self, tx: &RwTxn, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) {
+		lmdb::put(tx, self.dbi, &mut key.as_ref().to_vec(), &mut value.as_ref().to_vec(), enumflags2::BitFlags::empty())?;
+	}
+
+	/// Put a `len` byte value that's streamed into its place in the map, e.g. by an encoder, instead of being built in memory first.
+	///
+	/// # Safety
+	/// The writer points into lmdb's dirty page, which the next write in the transaction can move, reuse or free. It has
+	/// to be dropped before anything else is written in the transaction.
+	#[throws]
+	pub unsafe fn put_writer_in<'tx>(&self, tx: &'tx RwTxn, key: impl AsRef<[u8]>, len: usize) -> lmdb::ValueWriter<'tx> {
+		lmdb::ValueWriter::new(lmdb::put_reserve(tx, self.dbi, &mut key.as_ref().to_vec(), len)?)
+	}
+
+	/// whether there was anything to remove
+	#[throws]
+	pub fn remove_in(&self, tx: &RwTxn, key: impl AsRef<[u8]>) -> bool {
+		lmdb::del(tx, self.dbi, &mut key.as_ref().to_vec())?
+	}
+
+	#[throws]
+	pub fn iter_in<'tx, 'env: 'tx, TX: Transaction<'env>>(&self, tx: &'tx TX) -> Iter<'tx, TX> {
+		Iter(Some(lmdb::Cursor::open(tx, self.dbi)?))
+	}
+}
+
+/// ends after yielding a cursor's error
+pub struct Iter<'tx, TX>(Option<lmdb::Cursor<'tx, TX>>);
+
+impl<'tx, 'env: 'tx, TX: Transaction<'env>> Iterator for Iter<'tx, TX> {
+	type Item = Result<(&'tx [u8], &'tx [u8]), Error>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let next = self.0.as_mut()?.get(lmdb::CursorOp::Next);
+		if next.is_err() { self.0 = None; }
+		next.map_err(Error::from).transpose()
+	}
+}
@@ -0,0 +1,163 @@
+//! Expiry for [`TrackedTable`](crate::TrackedTable)s: a thread that deletes entries `ttl` after they were last modified.
+//! Each pass is a short write transaction looking at a bounded number of entries from where the last one stopped,
+//! so writers are never held up for long, however big the table.
+//! ```ignore
+//! let sweeper = Sweeper::start::<Session>(&ENV, Sessions::NAME, Policy { ttl: Duration::from_secs(3600), ..Policy::default() });
+//! ```
+//! Until a pass gets to them, expired entries are still there - check `modified` on reads that can't tolerate that.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use crate::{Env, Error, RkyvVal, Transaction, lmdb, error::Context, framing::{Framing, Plain}, tracked_table::{self, Tracked}};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+	pub ttl: Duration,
+	/// pause between passes
+	pub interval: Duration,
+	/// entries looked at per pass, at least 1
+	pub max_scan: usize,
+	/// entries deleted per pass, at least 1
+	pub max_purge: usize,
+}
+
+impl Default for Policy {
+	fn default() -> Self { Self { ttl: Duration::from_secs(24 * 3600), interval: Duration::from_secs(1), max_scan: 10_000, max_purge: 1000 } }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SweepStats {
+	pub passes: u64,
+	pub failures: u64,
+	pub scanned: u64,
+	pub purged: u64,
+	/// entries skipped since their values aren't valid, they're logged and left alone
+	pub invalid: u64,
+	pub last_pass: Option<Duration>,
+	/// entries deleted by the last pass
+	pub last_purged: usize,
+}
+
+/// The sweeping thread, stopped when this is dropped.
+pub struct Sweeper {
+	shared: Arc<Shared>,
+	thread: Option<std::thread::JoinHandle<()>>,
+}
+
+struct Shared {
+	stop: Mutex<bool>,
+	wake: Condvar,
+	stats: Mutex<SweepStats>,
+}
+
+impl Sweeper {
+	/// Sweep the table registered as `name` with values `V`, use `start_framed` for tables with framing other than [`Plain`].
+	pub fn start<V>(env: &'static Env, name: &'static [u8], policy: Policy) -> Self where
+		V: rkyv::Archive,
+		rkyv::Archived<Tracked<V>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	{ Self::start_framed::<V, Plain>(env, name, policy) }
+
+	pub fn start_framed<V, F>(env: &'static Env, name: &'static [u8], policy: Policy) -> Self where
+		F: Framing,
+		V: rkyv::Archive,
+		rkyv::Archived<Tracked<V>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+	{
+		assert!(policy.max_scan > 0 && policy.max_purge > 0, "a sweep pass has to look at and delete at least one entry to get anywhere");
+		let shared = Arc::new(Shared { stop: Mutex::new(false), wake: Condvar::new(), stats: Mutex::default() });
+		let thread = std::thread::Builder::new()
+			.name("batadase-ttl-sweeper".to_owned())
+			.spawn({ let shared = Arc::clone(&shared); move || sweeper::<V, F>(env, name, policy, &shared) })
+			.expect("failed to spawn the ttl sweeper thread");
+		Self { shared, thread: Some(thread) }
+	}
+
+	pub fn stats(&self) -> SweepStats { *self.shared.stats.lock().unwrap() }
+}
+
+impl Drop for Sweeper {
+	fn drop(&mut self) {
+		*self.shared.stop.lock().unwrap() = true;
+		self.shared.wake.notify_one();
+		if let Some(thread) = self.thread.take() { let _ = thread.join(); }
+	}
+}
+
+fn sweeper<V, F>(env: &'static Env, name: &'static [u8], policy: Policy, shared: &Shared) where
+	F: Framing,
+	V: rkyv::Archive,
+	rkyv::Archived<Tracked<V>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	let ttl = policy.ttl.as_millis() as u64;
+	let mut resume = None;
+	let mut stop = shared.stop.lock().unwrap();
+	while !*stop {
+		drop(stop);
+		let start = Instant::now();
+		let res = sweep::<V, F>(env, name, tracked_table::now().saturating_sub(ttl), &policy, &mut resume);
+		let mut stats = shared.stats.lock().unwrap();
+		stats.passes += 1;
+		stats.last_pass = Some(start.elapsed());
+		match res {
+			Ok(Pass { scanned, purged, invalid }) => {
+				stats.scanned += scanned as u64;
+				stats.purged += purged as u64;
+				stats.invalid += invalid as u64;
+				stats.last_purged = purged;
+			},
+			Err(e) => {
+				stats.failures += 1;
+				log::error!("ttl sweep of {} failed: {e}", crate::db_name_lossy(name));
+			},
+		}
+		drop(stats);
+		stop = shared.wake.wait_timeout(shared.stop.lock().unwrap(), policy.interval).unwrap().0;
+	}
+}
+
+struct Pass {
+	scanned: usize,
+	purged: usize,
+	invalid: usize,
+}
+
+// One pass deleting entries modified before `before`, starting at `resume` and leaving it where it stopped - only once
+// the pass committed, so a failed one is tried again from the same place.
+fn sweep<V, F>(env: &Env, name: &[u8], before: u64, policy: &Policy, resume: &mut Option<Vec<u8>>) -> Result<Pass, Error> where
+	F: Framing,
+	V: rkyv::Archive,
+	rkyv::Archived<Tracked<V>>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>>,
+{
+	let Some(dbi) = env.db(name) else { return Err(crate::dump::unknown_db(name)) };
+	let tx = env.write_tx()?;
+	let mut cursor = lmdb::Cursor::open(&tx, dbi).context(&tx, dbi, "sweep", None)?;
+	let mut entry = match resume.clone() {
+		Some(mut key) => cursor.get_with_key(&mut key, lmdb::CursorOp::SetRange),
+		None => cursor.get(lmdb::CursorOp::First),
+	}.context(&tx, dbi, "sweep", None)?;
+	let (mut pass, mut next) = (Pass { scanned: 0, purged: 0, invalid: 0 }, None);
+	while let Some((key, value)) = entry {
+		if pass.scanned == policy.max_scan || pass.purged == policy.max_purge {
+			next = Some(key.to_vec());
+			break;
+		}
+		pass.scanned += 1;
+		match F::unframe(value).and_then(|value| Ok(rkyv::access::<rkyv::Archived<Tracked<V>>, rkyv::rancor::Error>(value)?)) {
+			Ok(tracked) if tracked.modified.to_native() < before => {
+				let key = key.to_vec(); // the slice goes stale with the delete
+				cursor.del_current().context(&tx, dbi, "sweep", Some(&key))?;
+				pass.purged += 1;
+			},
+			Ok(_) => {},
+			Err(e) => {
+				log::warn!("ttl sweep of {} skipped an invalid entry b\"{}\": {e}", crate::db_name_lossy(name), key.escape_ascii());
+				pass.invalid += 1;
+			},
+		}
+		entry = cursor.get(lmdb::CursorOp::Next)?;
+	}
+	drop(cursor);
+	tx.commit()?;
+	*resume = next;
+	if pass.purged > 0 { log::debug!("ttl sweep deleted {} of {} entries of {}", pass.purged, pass.scanned, crate::db_name_lossy(name)); }
+	Ok(pass)
+}
@@ -0,0 +1,142 @@
+//! Read-only consistency checks of db contents.
+
+use culpa::{throw, throws};
+use std::cmp::Ordering;
+use crate::{Transaction, RoTxn, Error, lmdb::{self, DbFlags}};
+
+/// checks raw bytes of a single entry, see [`crate::Table::check_entry`]
+pub type CheckEntry = fn(&[u8], &[u8]) -> Result<(), Error>;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DbReport {
+	pub name: String,
+	pub entries: usize,
+	pub misordered: usize, // entries that don't sort after the previous one according to the db's comparator
+	pub invalid: usize,    // entries rejected by the registered table's entry check
+	pub checked: bool,     // whether a table was registered for this db, if not only ordering is verified
+	pub first_error: Option<String>,
+}
+
+impl DbReport {
+	pub fn errors(&self) -> usize { self.misordered + self.invalid }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+	pub txn_id: usize, // of the read transaction all dbs were walked in
+	pub dbs: Vec<DbReport>,
+}
+
+impl Report {
+	pub fn errors(&self) -> usize { self.dbs.iter().map(DbReport::errors).sum() }
+	pub fn is_ok(&self) -> bool { self.errors() == 0 }
+}
+
+// same as mdb_cmp_int, keys are either all c unsigned int or all size_t
+fn cmp_int(a: &[u8], b: &[u8]) -> Ordering {
+	match (<[u8; 4]>::try_from(a), <[u8; 4]>::try_from(b), <[u8; 8]>::try_from(a), <[u8; 8]>::try_from(b)) {
+		(Ok(a), Ok(b), _, _) => u32::from_ne_bytes(a).cmp(&u32::from_ne_bytes(b)),
+		(_, _, Ok(a), Ok(b)) => u64::from_ne_bytes(a).cmp(&u64::from_ne_bytes(b)),
+		_ => a.cmp(b),
+	}
+}
+
+// same as mdb_cmp_memnr
+fn cmp_reverse(a: &[u8], b: &[u8]) -> Ordering { a.iter().rev().cmp(b.iter().rev()) }
+
+fn cmp_keys(flags: enumflags2::BitFlags<DbFlags>, a: &[u8], b: &[u8]) -> Ordering {
+	if flags.contains(DbFlags::IntegerKey) { cmp_int(a, b) }
+	else if flags.contains(DbFlags::ReverseKey) { cmp_reverse(a, b) }
+	else { a.cmp(b) }
+}
+
+
+/// Walk every entry of a db, checking that keys (and duplicates) are strictly ordered
+/// and that entries pass the registered table's check. Nothing is modified.
+#[throws]
+pub fn verify_db<'env>(tx: &impl Transaction<'env>, name: &[u8]) -> DbReport {
+	let Some(dbi) = tx.env().db(name) else { throw!(Error::UnknownDb(crate::db_name_lossy(name))) };
+	let flags = lmdb::dbi_flags(tx.raw(), dbi)?;
+	let check = tx.env().check_entry(name);
+	let mut report = DbReport { name: crate::db_name_lossy(name), checked: check.is_some(), ..DbReport::default() };
+
+	let mut cursor = lmdb::Cursor::open(tx, dbi)?;
+	let mut prev: Option<(&[u8], &[u8])> = None;
+	while let Some((key, value)) = cursor.get(lmdb::CursorOp::Next)? {
+		report.entries += 1;
+		let entry = report.entries;
+		if let Some((prev_key, prev_value)) = prev {
+			let ordered = match cmp_keys(flags, prev_key, key) {
+				Ordering::Less => true,
+				Ordering::Equal => flags.contains(DbFlags::DupSort) && lmdb::dcmp(tx, dbi, prev_value, value) == Ordering::Less,
+				Ordering::Greater => false,
+			};
+			if !ordered {
+				report.misordered += 1;
+				report.first_error.get_or_insert_with(|| format!("entry {entry} is out of order"));
+			}
+		}
+		if let Some(check) = check && let Err(e) = check(key, value) {
+			report.invalid += 1;
+			report.first_error.get_or_insert_with(|| format!("entry {entry}: {e}"));
+		}
+		prev = Some((key, value));
+	}
+	report
+}
+
+/// verify_db every registered db, in order of name
+#[throws]
+pub fn verify_all<'env>(tx: &impl Transaction<'env>) -> Report {
+	let dbs = tx.env().dbs().into_iter()
+		.map(|(name, _)| verify_db(tx, name))
+		.collect::<Result<Vec<_>, _>>()?;
+	Report { txn_id: tx.id(), dbs }
+}
+
+/// An application invariant for [`EnvBuilder::on_open`](crate::env::EnvBuilder::on_open), e.g. that every order's
+/// customer exists. Returns a description of each violation, none if it holds.
+pub type Invariant = fn(&RoTxn<'_>) -> Result<Vec<String>, Error>;
+
+/// What a violated [`Invariant`] does to opening the env.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnViolation {
+	/// building fails with [`Error::Invariants`]
+	Fail,
+	/// the violations are logged as warnings
+	Warn,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvariantReport {
+	pub name: &'static str,
+	pub policy: OnViolation,
+	pub violations: Vec<String>,
+	pub error: Option<String>, // the check itself failed, which counts as violated
+}
+
+impl InvariantReport {
+	pub fn holds(&self) -> bool { self.violations.is_empty() && self.error.is_none() }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InvariantsReport {
+	pub txn_id: usize, // of the read transaction every invariant was checked in
+	pub invariants: Vec<InvariantReport>,
+}
+
+impl InvariantsReport {
+	/// invariants that don't hold, whatever their policy
+	pub fn violated(&self) -> impl Iterator<Item = &InvariantReport> { self.invariants.iter().filter(|x| !x.holds()) }
+	/// violated invariants that fail opening the env
+	pub fn failed(&self) -> usize { self.violated().filter(|x| x.policy == OnViolation::Fail).count() }
+}
+
+/// check every invariant in the same read transaction
+pub fn check_invariants(tx: &RoTxn<'_>, invariants: &[(&'static str, OnViolation, Invariant)]) -> InvariantsReport {
+	let invariants = invariants.iter().map(|&(name, policy, invariant)| match invariant(tx) {
+		Ok(violations) => InvariantReport { name, policy, violations, error: None },
+		Err(e) => InvariantReport { name, policy, violations: Vec::new(), error: Some(e.to_string()) },
+	}).collect();
+	InvariantsReport { txn_id: tx.id(), invariants }
+}
@@ -0,0 +1,124 @@
+//! Writes to an assoc table collected within a write transaction and put all at once, so an updater rewriting the
+//! same key several times per transaction only puts the last version:
+//! ```ignore
+//! let mut batch = tx.get::<Aggregates>().batch();
+//! for event in events {
+//!     let mut aggregate = batch.pending_value(&event.aggregate)?.flatten().unwrap_or_default();
+//!     aggregate.apply(&event);
+//!     batch.put(&event.aggregate, &aggregate)?;
+//! }
+//! let stats = batch.write()?;
+//! log::debug!("{} of {} writes coalesced", stats.coalesced, stats.writes);
+//! ```
+//! Values are serialized when they're added, and written in the order their keys were first written to. Reads of
+//! the table don't see the batch until it's written, [`WriteBatch::pending_value`] does. A batch dropped without
+//! [`WriteBatch::write`] is discarded.
+
+use culpa::throws;
+use enumflags2::BitFlag;
+use std::collections::HashMap;
+use crate::{AssocTable, RwTxn, RkyvSer, RkyvVal, RkyvDe, Error, lmdb, error::Context, framing::{Framing, Plain}};
+
+/// see [`WriteBatch::stats`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchStats {
+	/// puts and deletes added
+	pub writes: usize,
+	/// writes dropped for a later one to the same key
+	pub coalesced: usize,
+}
+
+pub struct WriteBatch<'t, 'tx, K, V, F = Plain> {
+	table: &'t AssocTable<'tx, RwTxn<'tx>, K, V, F>,
+	// serialized key and framed value, None deletes
+	pending: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+	// where each key is in pending, None when not coalescing
+	index: Option<HashMap<Vec<u8>, usize>>,
+	stats: BatchStats,
+}
+
+// RwTxn only, so all methods mutate
+impl<'tx, K, V, F> AssocTable<'tx, RwTxn<'tx>, K, V, F> where
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+{
+	/// a coalescing batch of writes to this table, see [`write_batch`](crate::write_batch)
+	pub fn batch(&self) -> WriteBatch<'_, 'tx, K, V, F> {
+		WriteBatch { table: self, pending: Vec::new(), index: Some(HashMap::new()), stats: BatchStats::default() }
+	}
+}
+
+impl<'tx, K, V, F> WriteBatch<'_, 'tx, K, V, F> where
+	F: Framing,
+	K: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+	V: rkyv::Archive + for <'a> rkyv::Serialize<RkyvSer<'a>>,
+{
+	/// Keep every write instead of only the last one per key, e.g. for dupsort tables where each put adds a value.
+	/// Only for a batch nothing was added to yet.
+	#[must_use]
+	pub fn coalesce(mut self, coalesce: bool) -> Self {
+		debug_assert!(self.pending.is_empty(), "coalescing changed on a batch with writes in it");
+		self.index = coalesce.then(HashMap::new);
+		self
+	}
+
+	#[throws]
+	pub fn put(&mut self, key: &K, value: &V) { self.add(rkyv::to_bytes(key)?.to_vec(), Some(F::frame(rkyv::to_bytes(value)?).as_mut().to_vec())); }
+
+	#[throws]
+	pub fn delete(&mut self, key: &K) { self.add(rkyv::to_bytes(key)?.to_vec(), None); }
+
+	/// The last write to `key` in the batch if there is one - `Some(None)` for a delete.
+	#[throws]
+	pub fn pending_value(&self, key: &K) -> Option<Option<V>> where
+		rkyv::Archived<V>: for <'a> rkyv::bytecheck::CheckBytes<RkyvVal<'a>> + rkyv::Deserialize<V, RkyvDe>,
+	{
+		let key_bytes = rkyv::to_bytes(key)?;
+		let found = match &self.index {
+			Some(index) => index.get(key_bytes.as_slice()).map(|&i| &self.pending[i].1),
+			None => self.pending.iter().rev().find(|(key, _)| *key == key_bytes.as_slice()).map(|(_, value)| value),
+		};
+		let Some(value) = found else { return None };
+		match value {
+			Some(value) => {
+				let archive = F::unframe(value)?;
+				Some(Some(rkyv::deserialize::<V, rkyv::rancor::Error>(rkyv::access::<rkyv::Archived<V>, rkyv::rancor::Error>(archive)?)?))
+			},
+			None => Some(None),
+		}
+	}
+
+	/// keys with a write waiting, or writes when not coalescing
+	pub fn len(&self) -> usize { self.pending.len() }
+	pub fn is_empty(&self) -> bool { self.pending.is_empty() }
+	pub fn stats(&self) -> BatchStats { self.stats }
+
+	/// Put and delete everything in the batch, returns its stats.
+	#[throws]
+	pub fn write(mut self) -> BatchStats {
+		let (tx, dbi) = (self.table.tx, self.table.dbi);
+		for (mut key, value) in std::mem::take(&mut self.pending) {
+			match value {
+				Some(mut value) => lmdb::put(tx, dbi, &mut key, &mut value, lmdb::PutFlags::empty()).context(tx, dbi, "write_batch", Some(&key))?,
+				None => { lmdb::del(tx, dbi, &mut key).context(tx, dbi, "write_batch", Some(&key))?; },
+			}
+		}
+		self.stats
+	}
+
+	fn add(&mut self, key: Vec<u8>, value: Option<Vec<u8>>) {
+		self.stats.writes += 1;
+		let Some(index) = &mut self.index else { self.pending.push((key, value)); return };
+		match index.get(&key) {
+			Some(&i) => { self.pending[i].1 = value; self.stats.coalesced += 1; },
+			None => { index.insert(key.clone(), self.pending.len()); self.pending.push((key, value)); },
+		}
+	}
+}
+
+impl<K, V, F> Drop for WriteBatch<'_, '_, K, V, F> {
+	fn drop(&mut self) {
+		if !self.pending.is_empty() { log::warn!("a write batch of {} writes was dropped without being written", self.pending.len()); }
+	}
+}
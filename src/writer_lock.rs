@@ -0,0 +1,79 @@
+//! Coordinating writers across processes. LMDB serializes write transactions between processes itself,
+//! but a writer waiting on another process just blocks in `mdb_txn_begin` with no way to tell who it's waiting for.
+//! Processes that take the advisory lock in [`Env::lock_writer`] before writing fail with [`Error::WriterLocked`] instead,
+//! naming the pid holding it. It's only advisory - processes that don't take it can still write.
+//!
+//! The lock is `writer.lock` in the env's directory, holding the pid and start time of whoever has it.
+//! The OS releases it when that process dies, so a crashed holder never leaves it taken.
+
+use culpa::{throw, throws};
+use std::io::{Read, Seek, Write};
+use std::time::{Duration, SystemTime};
+use crate::{Env, Error};
+
+// how often a waiting lock_writer retries
+const RETRY: Duration = Duration::from_millis(10);
+
+/// released when dropped
+#[derive(Debug)]
+pub struct WriterLock {
+	file: std::fs::File,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Holder {
+	pub pid: u32,
+	pub since: SystemTime,
+}
+
+#[throws]
+fn open(env: &Env) -> std::fs::File {
+	std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(false).open(env.path()?.join("writer.lock"))?
+}
+
+// None while the holder is still writing it
+fn read_holder(file: &mut std::fs::File) -> Option<Holder> {
+	let mut contents = String::new();
+	file.rewind().ok()?;
+	file.read_to_string(&mut contents).ok()?;
+	let (pid, since) = contents.trim().split_once(' ')?;
+	Some(Holder { pid: pid.parse().ok()?, since: SystemTime::UNIX_EPOCH + Duration::from_millis(since.parse().ok()?) })
+}
+
+impl WriterLock {
+	#[throws]
+	pub(crate) fn acquire(env: &Env, timeout: Duration) -> Self {
+		let mut file = open(env)?;
+		let start = std::time::Instant::now();
+		loop {
+			match file.try_lock() {
+				Ok(()) => break,
+				Err(std::fs::TryLockError::WouldBlock) if start.elapsed() < timeout => std::thread::sleep(RETRY),
+				Err(std::fs::TryLockError::WouldBlock) => throw!(Error::WriterLocked(read_holder(&mut file))),
+				Err(std::fs::TryLockError::Error(e)) => throw!(e),
+			}
+		}
+		let since = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_millis();
+		file.set_len(0)?;
+		file.rewind()?;
+		write!(file, "{} {since}", std::process::id())?;
+		Self { file }
+	}
+}
+
+impl Drop for WriterLock {
+	fn drop(&mut self) {
+		let _ = self.file.set_len(0);
+		let _ = self.file.unlock();
+	}
+}
+
+#[throws]
+pub(crate) fn holder(env: &Env) -> Option<Holder> {
+	let mut file = open(env)?;
+	match file.try_lock_shared() {
+		Ok(()) => { file.unlock()?; None },
+		Err(std::fs::TryLockError::WouldBlock) => read_holder(&mut file),
+		Err(std::fs::TryLockError::Error(e)) => throw!(e),
+	}
+}
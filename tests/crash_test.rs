@@ -0,0 +1,24 @@
+// a binary of its own, crash_test::run's children run every test in it again
+
+use std::time::Duration;
+use batadase::{AssocTable, DbName, Env, Transaction, crash_test::{self, CrashTest}, testutil};
+
+struct Counters;
+
+impl DbName for Counters {
+	type Table<'tx, 'env: 'tx, TX: Transaction<'env> + 'tx> = AssocTable<'tx, TX, u32, u64>;
+	const NAME: &'static [u8] = b"counters\0";
+}
+
+#[test]
+fn counters_survive_crashes() {
+	let test = CrashTest { name: "counters", rounds: 8, max_run: Duration::from_millis(100), ..CrashTest::default() };
+	crash_test::run(&test, || Ok(Env::builder()?.with::<Counters>()), |env, i| {
+		// both in one transaction, so they must always agree
+		testutil::write(env, |tx| { tx.get::<Counters>().put(&0, &i)?; tx.get::<Counters>().put(&1, &i) }).unwrap().unwrap();
+	}, |env| {
+		let tx = env.read_tx().unwrap();
+		let counters = tx.get::<Counters>();
+		assert_eq!(counters.get_unrkyv(&0).unwrap(), counters.get_unrkyv(&1).unwrap());
+	});
+}